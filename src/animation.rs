@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use cgmath::{vec3, Deg, Euler, Quaternion, Vector3};
+use serde::Deserialize;
+
+use crate::scene::Transform;
+use crate::tween::Lerp;
+
+/// Keyframes of one property, sorted by time and interpolated linearly
+/// (spherically for rotations).
+#[derive(Clone, Debug)]
+pub struct Track<V> {
+    pub keys: Vec<(f32, V)>,
+}
+
+impl<V> Default for Track<V> {
+    fn default() -> Track<V> {
+        Track { keys: vec![] }
+    }
+}
+
+impl<V: Lerp> Track<V> {
+    pub fn new(mut keys: Vec<(f32, V)>) -> Track<V> {
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Track { keys }
+    }
+
+    /// The value at `time`, holding the first and last keys outside the
+    /// track. `None` if there are no keys.
+    pub fn sample(&self, time: f32) -> Option<V> {
+        let next = self.keys.partition_point(|&(t, _)| t <= time);
+        let (t0, a) = *self.keys.get(next.max(1) - 1)?;
+        let Some(&(t1, b)) = self.keys.get(next) else {
+            return Some(a);
+        };
+        if next == 0 || t1 <= t0 {
+            return Some(b);
+        }
+        Some(a.lerp(b, (time - t0) / (t1 - t0)))
+    }
+
+    fn end(&self) -> f32 {
+        self.keys.last().map_or(0.0, |&(t, _)| t)
+    }
+}
+
+/// Transform tracks for one node. Properties without keys are left alone.
+#[derive(Clone, Debug, Default)]
+pub struct Clip {
+    pub position: Track<Vector3<f32>>,
+    pub rotation: Track<Quaternion<f32>>,
+    pub scale: Track<Vector3<f32>>,
+    /// Tracks for the joints of the node's skinned mesh, indexed like
+    /// `Skeleton::joints`. Posed by `Skeleton::pose` rather than `apply`.
+    pub joints: Vec<Clip>,
+}
+
+impl Clip {
+    /// Time of the last key, in seconds.
+    pub fn duration(&self) -> f32 {
+        let own = self
+            .position
+            .end()
+            .max(self.rotation.end())
+            .max(self.scale.end());
+        self.joints.iter().map(Clip::duration).fold(own, f32::max)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position.keys.is_empty()
+            && self.rotation.keys.is_empty()
+            && self.scale.keys.is_empty()
+            && self.joints.iter().all(Clip::is_empty)
+    }
+
+    pub fn apply(&self, time: f32, transform: &mut Transform) {
+        if let Some(position) = self.position.sample(time) {
+            transform.position = position;
+        }
+        if let Some(rotation) = self.rotation.sample(time) {
+            transform.rotation = rotation;
+        }
+        if let Some(scale) = self.scale.sample(time) {
+            transform.scale = scale;
+        }
+    }
+}
+
+/// A clip attached to a node, with its playback state.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    /// Shared between copies of the node, e.g. for undo.
+    pub clip: Arc<Clip>,
+    /// Seconds into the clip.
+    pub time: f32,
+    pub playing: bool,
+    /// Start over at the end instead of stopping.
+    pub looping: bool,
+}
+
+impl Animation {
+    pub fn new(clip: Clip) -> Animation {
+        Animation {
+            clip: Arc::new(clip),
+            time: 0.0,
+            playing: true,
+            looping: true,
+        }
+    }
+
+    /// Plays from the current time, or from the start if a one-shot clip
+    /// has already finished.
+    pub fn play(&mut self) {
+        if !self.looping && self.time >= self.clip.duration() {
+            self.time = 0.0;
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Pauses and rewinds to the start.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.time = 0.0;
+    }
+
+    /// Moves `dt` seconds on if playing. Returns whether `time` changed.
+    pub fn advance(&mut self, dt: f32) -> bool {
+        if !self.playing {
+            return false;
+        }
+        let duration = self.clip.duration();
+        self.time += dt;
+        if self.time >= duration {
+            if self.looping && duration > 0.0 {
+                self.time %= duration;
+            } else {
+                self.time = duration;
+                self.playing = false;
+            }
+        }
+        true
+    }
+}
+
+/// An animation as written in prefab and scene files. Keys are
+/// `(time, value)` pairs; positions and scales in the node's parent space,
+/// rotations as Euler angles in degrees like `TransformDef`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimationDef {
+    #[serde(default)]
+    pub position: Vec<(f32, (f32, f32, f32))>,
+    #[serde(default)]
+    pub rotation: Vec<(f32, (f32, f32, f32))>,
+    #[serde(default)]
+    pub scale: Vec<(f32, (f32, f32, f32))>,
+    #[serde(default = "yes")]
+    pub looping: bool,
+    /// Start playing as soon as the node is spawned.
+    #[serde(default = "yes")]
+    pub autoplay: bool,
+}
+
+fn yes() -> bool {
+    true
+}
+
+impl AnimationDef {
+    pub fn animation(&self) -> Animation {
+        let vector = |keys: &[(f32, (f32, f32, f32))]| {
+            Track::new(
+                keys.iter()
+                    .map(|&(t, (x, y, z))| (t, vec3(x, y, z)))
+                    .collect(),
+            )
+        };
+        let clip = Clip {
+            position: vector(&self.position),
+            rotation: Track::new(
+                self.rotation
+                    .iter()
+                    .map(|&(t, (x, y, z))| {
+                        (t, Quaternion::from(Euler::new(Deg(x), Deg(y), Deg(z))))
+                    })
+                    .collect(),
+            ),
+            scale: vector(&self.scale),
+            joints: vec![],
+        };
+        Animation {
+            playing: self.autoplay,
+            looping: self.looping,
+            ..Animation::new(clip)
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use miniquad::*;
+
+use crate::texture::{self, ColorSpace, Image};
+
+/// Texels of border around each image, copied from its edges so filtering
+/// at the edge doesn't pull in the neighbours.
+const BORDER: u32 = 1;
+
+/// A rectangle of a texture: all of it, or one image in an `Atlas`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub texture: TextureId,
+    /// Maps the image's own UVs into the texture: scale in xy, offset in
+    /// zw, as for lightmap tiles.
+    pub scale_offset: [f32; 4],
+}
+
+impl Region {
+    pub fn whole(texture: TextureId) -> Region {
+        Region {
+            texture,
+            scale_offset: [1.0, 1.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// A row of images as tall as the tallest of them.
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the next image in the row goes.
+    x: u32,
+}
+
+/// Packs small images (decals, icons, sprites) into one square texture, so
+/// everything drawn from it shares a binding and can go out in one batch.
+/// Images are placed left to right along shelves, starting a new shelf
+/// below when none has room. Nothing is ever taken out again.
+pub struct Atlas {
+    pub texture: TextureId,
+    size: u32,
+    /// Whether added images are sRGB the GPU can't decode, so they have
+    /// to be decoded on the way in.
+    linearize: bool,
+    shelves: Vec<Shelf>,
+}
+
+impl Atlas {
+    /// An empty atlas for images in `color_space`, stored like
+    /// `texture::upload` stores them.
+    pub fn new(ctx: &mut dyn RenderingBackend, size: u32, color_space: ColorSpace) -> Atlas {
+        let texels = vec![0u8; (size * size * 4) as usize];
+        let texture = ctx.new_texture_from_rgba8(size as u16, size as u16, &texels);
+        let linearize = color_space == ColorSpace::Srgb
+            && !texture::store_srgb(ctx, texture, size, size, &texels);
+        Atlas {
+            texture,
+            size,
+            linearize,
+            shelves: vec![],
+        }
+    }
+
+    /// Copies `image`, in the atlas's color space, into the atlas.
+    /// Images more than a quarter of the atlas wide or tall are refused so
+    /// a few large ones can't crowd out the small ones, and so is anything
+    /// once the atlas is full; both are left to textures of their own.
+    pub fn add(&mut self, ctx: &mut dyn RenderingBackend, image: &Image) -> Option<Region> {
+        if image.width == 0 || image.height == 0 {
+            return None;
+        }
+        if image.width > self.size / 4 || image.height > self.size / 4 {
+            return None;
+        }
+        let (width, height) = (image.width + 2 * BORDER, image.height + 2 * BORDER);
+        let (x, y) = self.allocate(width, height)?;
+
+        let mut texels = Vec::with_capacity((width * height * 4) as usize);
+        for row in 0..height {
+            let source_row = row.saturating_sub(BORDER).min(image.height - 1);
+            for column in 0..width {
+                let source_column = column.saturating_sub(BORDER).min(image.width - 1);
+                let at = ((source_row * image.width + source_column) * 4) as usize;
+                texels.extend_from_slice(&image.rgba[at..at + 4]);
+            }
+        }
+        if self.linearize {
+            texture::linearize(&mut texels);
+        }
+        ctx.texture_update_part(
+            self.texture,
+            x as i32,
+            y as i32,
+            width as i32,
+            height as i32,
+            &texels,
+        );
+
+        let size = self.size as f32;
+        Some(Region {
+            texture: self.texture,
+            scale_offset: [
+                image.width as f32 / size,
+                image.height as f32 / size,
+                (x + BORDER) as f32 / size,
+                (y + BORDER) as f32 / size,
+            ],
+        })
+    }
+
+    /// Finds room for a `width` by `height` rectangle: on the lowest shelf
+    /// it fits on without wasting more than half the shelf's height, else
+    /// on a new shelf, else on any shelf with room left.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let size = self.size;
+        let fits = |shelf: &Shelf| shelf.x + width <= size && height <= shelf.height;
+        let snug = self
+            .shelves
+            .iter()
+            .position(|shelf| fits(shelf) && height * 2 > shelf.height);
+        let top = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        let index = match snug {
+            Some(index) => index,
+            None if top + height <= size && width <= size => {
+                self.shelves.push(Shelf {
+                    y: top,
+                    height,
+                    x: 0,
+                });
+                self.shelves.len() - 1
+            }
+            None => self.shelves.iter().position(fits)?,
+        };
+        let shelf = &mut self.shelves[index];
+        let x = shelf.x;
+        shelf.x += width;
+        Some((x, shelf.y))
+    }
+}
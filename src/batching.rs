@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{vec2, InnerSpace, Matrix3, Matrix4};
+use miniquad::*;
+
+use crate::lightmap::Lightmap;
+use crate::mesh::{GpuMesh, Mesh, MeshId, MeshLibrary, Vertex};
+use crate::scene::{self, Cull, DrawParams, Instance, Material, NodeId, Scene, Winding};
+
+/// Meshes with more vertices than this are left alone: merging copies
+/// every vertex, which only pays off for small props.
+const MAX_MERGED_VERTICES: usize = 1024;
+/// Vertices one batch can address with 16-bit indices.
+const MAX_BATCH_VERTICES: usize = 1 << 16;
+
+/// Small static objects sharing a material, merged into one mesh with
+/// their world transforms applied, so they take a single draw call.
+pub struct StaticBatch {
+    pub gpu: GpuMesh,
+    pub material: Material,
+    /// The merged objects, as they were when baked.
+    members: Vec<Member>,
+}
+
+struct Member {
+    node: NodeId,
+    mesh: MeshId,
+    world: Matrix4<f32>,
+}
+
+/// Draw calls for the scene with and without batching, as reported when
+/// baking.
+pub struct BatchStats {
+    pub objects: usize,
+    pub batches: usize,
+    pub draws_before: usize,
+    pub draws_after: usize,
+}
+
+/// An optional bake step that trades memory for draw calls in prop-heavy
+/// scenes. Batches last until one of their objects is moved, recolored or
+/// removed; then it is broken up and its objects are drawn one by one.
+#[derive(Default)]
+pub struct StaticBatches {
+    batches: Vec<StaticBatch>,
+}
+
+impl StaticBatches {
+    /// Merges every small, unanimated object with others of the same
+    /// material, replacing earlier batches. With a lightmap, each object's
+    /// place in the atlas goes into the merged lightmap coordinates.
+    pub fn bake(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        scene: &Scene,
+        meshes: &MeshLibrary,
+        lightmap: Option<&Lightmap>,
+    ) -> BatchStats {
+        self.clear(ctx);
+        let instances = scene.instances();
+
+        let mut groups: HashMap<([u32; 4], Cull, Winding), Vec<&Instance>> = HashMap::new();
+        for instance in &instances {
+            let mesh = meshes.get(instance.mesh);
+            // Objects with their own draw parameters are likely to change
+            // them, and a batch only has one set. Skinned meshes need their
+            // skeleton to be drawn.
+            let plain = instance.params == DrawParams::default() && mesh.skeleton.is_none();
+            if mesh.mesh.vertices.len() <= MAX_MERGED_VERTICES
+                && plain
+                && !is_animated(scene, instance.node)
+            {
+                let material = &instance.material;
+                let color: [u32; 4] = material.color.map(f32::to_bits).into();
+                let key = (color, material.cull, material.winding);
+                groups.entry(key).or_default().push(instance);
+            }
+        }
+        // Hash map order would shuffle batches between bakes.
+        let mut groups: Vec<_> = groups.into_values().filter(|g| g.len() > 1).collect();
+        groups.sort_by_key(|group| group[0].node);
+
+        for group in groups {
+            let material = group[0].material;
+            let mut mesh = empty_mesh();
+            let mut members = vec![];
+            for instance in group {
+                let source = &meshes.get(instance.mesh).mesh;
+                if mesh.vertices.len() + source.vertices.len() > MAX_BATCH_VERTICES {
+                    self.add(ctx, mesh, material, members);
+                    mesh = empty_mesh();
+                    members = vec![];
+                }
+                let scale_offset = lightmap
+                    .and_then(|l| l.scale_offset.get(&instance.node))
+                    .copied()
+                    .unwrap_or([1.0, 1.0, 0.0, 0.0]);
+                append(&mut mesh, source, instance.world, scale_offset);
+                members.push(Member {
+                    node: instance.node,
+                    mesh: instance.mesh,
+                    world: instance.world,
+                });
+            }
+            self.add(ctx, mesh, material, members);
+        }
+
+        let unbatched = self.unbatched(ctx, &instances);
+        BatchStats {
+            objects: instances.len() - unbatched.len(),
+            batches: self.batches.len(),
+            draws_before: scene::batches(&instances).count(),
+            draws_after: scene::batches(&unbatched).count() + self.batches.len(),
+        }
+    }
+
+    fn add(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        mesh: Mesh,
+        material: Material,
+        members: Vec<Member>,
+    ) {
+        // A lone object gains nothing from a batch of its own.
+        if members.len() > 1 {
+            self.batches.push(StaticBatch {
+                gpu: GpuMesh::new(ctx, mesh),
+                material,
+                members,
+            });
+        }
+    }
+
+    pub fn clear(&mut self, ctx: &mut dyn RenderingBackend) {
+        for batch in self.batches.drain(..) {
+            batch.gpu.delete(ctx);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &StaticBatch> {
+        self.batches.iter()
+    }
+
+    /// The instances no batch draws, after breaking up batches that no
+    /// longer match the scene.
+    pub fn unbatched(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        instances: &[Instance],
+    ) -> Vec<Instance> {
+        if self.batches.is_empty() {
+            return instances.to_vec();
+        }
+        let by_node: HashMap<NodeId, &Instance> = instances.iter().map(|i| (i.node, i)).collect();
+        self.batches.retain(|batch| {
+            let intact = batch.members.iter().all(|member| {
+                by_node.get(&member.node).is_some_and(|instance| {
+                    instance.mesh == member.mesh
+                        && instance.world == member.world
+                        && instance.material == batch.material
+                        && instance.params == DrawParams::default()
+                })
+            });
+            if !intact {
+                println!("Static batch of {} objects broken up", batch.members.len());
+                batch.gpu.delete(ctx);
+            }
+            intact
+        });
+        let batched: HashSet<NodeId> = self
+            .batches
+            .iter()
+            .flat_map(|batch| batch.members.iter().map(|member| member.node))
+            .collect();
+        instances
+            .iter()
+            .filter(|instance| !batched.contains(&instance.node))
+            .copied()
+            .collect()
+    }
+}
+
+/// Whether the node or one of its ancestors has an animation, which would
+/// move it out from under a batch.
+fn is_animated(scene: &Scene, id: NodeId) -> bool {
+    let mut node = scene.get(id);
+    while let Some(n) = node {
+        if n.animation.is_some() {
+            return true;
+        }
+        node = n.parent.and_then(|parent| scene.get(parent));
+    }
+    false
+}
+
+fn empty_mesh() -> Mesh {
+    Mesh {
+        vertices: vec![],
+        indices: vec![],
+    }
+}
+
+/// Adds `source` to `mesh`, moved into world space. Normals are turned
+/// the same way the instanced vertex shader does it.
+fn append(mesh: &mut Mesh, source: &Mesh, world: Matrix4<f32>, [sx, sy, ox, oy]: [f32; 4]) {
+    let linear = Matrix3::from_cols(world.x.truncate(), world.y.truncate(), world.z.truncate());
+    let base = mesh.vertices.len() as u16;
+    mesh.vertices.extend(source.vertices.iter().map(|v| Vertex {
+        pos: (world * v.pos.extend(1.0)).truncate(),
+        normal: (linear * v.normal).normalize(),
+        color: v.color,
+        uv2: vec2(v.uv2.x * sx + ox, v.uv2.y * sy + oy),
+    }));
+    mesh.indices
+        .extend(source.indices.iter().map(|&i| base + i));
+}
@@ -0,0 +1,203 @@
+use std::{fmt::Write as _, fs, time::Instant};
+
+use cgmath::{vec3, Deg, EuclideanSpace, Point3, Rad, VectorSpace};
+use serde::Deserialize;
+
+use crate::camera::Camera;
+use crate::stats::RenderStats;
+
+/// Scene loaded instead of the default one.
+pub const SCENE: &str = "scenes/benchmark.ron";
+const PATH: &str = "benchmark/path.ron";
+const DEFAULT_DURATION: f32 = 20.0;
+const TIMESTEP: f32 = 1.0 / 60.0;
+/// Frames left out of the results while caches and drivers settle.
+const WARMUP_FRAMES: usize = 30;
+const REPORT_JSON: &str = "benchmark.json";
+const REPORT_CSV: &str = "benchmark.csv";
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct Keyframe {
+    time: f32,
+    position: (f32, f32, f32),
+    /// Degrees.
+    yaw: f32,
+    /// Degrees.
+    pitch: f32,
+}
+
+struct Sample {
+    /// Wall-clock time since the previous frame, in milliseconds.
+    frame_time: f32,
+    stats: RenderStats,
+    /// Drawable objects in the scene.
+    objects: usize,
+    /// Objects left out by frustum culling.
+    culled: usize,
+}
+
+/// Flies the camera along a recorded path at a fixed timestep for a set
+/// time, then writes frame time and renderer statistics to
+/// `benchmark.json` (summary) and `benchmark.csv` (per frame) and quits.
+pub struct Benchmark {
+    duration: f32,
+    path: Vec<Keyframe>,
+    /// Simulated time, advanced by exactly `TIMESTEP` per frame.
+    elapsed: f32,
+    last_frame: Option<Instant>,
+    samples: Vec<Sample>,
+}
+
+impl Benchmark {
+    /// Parses `--benchmark [seconds]` from the command line.
+    pub fn from_args() -> Option<Benchmark> {
+        let args: Vec<String> = std::env::args().collect();
+        let i = args.iter().position(|arg| arg == "--benchmark")?;
+        let duration = args
+            .get(i + 1)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(DEFAULT_DURATION);
+        let path = match fs::read_to_string(PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|text| ron::from_str::<Vec<Keyframe>>(&text).map_err(|err| err.to_string()))
+        {
+            Ok(path) if !path.is_empty() => path,
+            Ok(_) => {
+                println!("Camera path {} is empty", PATH);
+                return None;
+            }
+            Err(err) => {
+                println!("Could not load camera path {}: {}", PATH, err);
+                return None;
+            }
+        };
+        println!("Benchmarking for {} seconds", duration);
+        Some(Benchmark {
+            duration,
+            path,
+            elapsed: 0.0,
+            last_frame: None,
+            samples: vec![],
+        })
+    }
+
+    /// Advances one timestep and puts the camera where the path is then.
+    /// Returns the timestep.
+    pub fn step(&mut self, camera: &mut Camera) -> f32 {
+        self.elapsed += TIMESTEP;
+        let end = self.path.last().unwrap().time;
+        let t = if end > 0.0 { self.elapsed % end } else { 0.0 };
+        let next = self.path.iter().position(|k| k.time > t);
+        let (a, b) = match next {
+            Some(0) | None => (self.path[0], self.path[0]),
+            Some(i) => (self.path[i - 1], self.path[i]),
+        };
+        let s = if b.time > a.time {
+            (t - a.time) / (b.time - a.time)
+        } else {
+            0.0
+        };
+        let position = |k: Keyframe| vec3(k.position.0, k.position.1, k.position.2);
+        camera.position = Point3::from_vec(position(a).lerp(position(b), s));
+        camera.yaw = Rad::from(Deg(a.yaw + (b.yaw - a.yaw) * s)).0;
+        camera.pitch = Rad::from(Deg(a.pitch + (b.pitch - a.pitch) * s)).0;
+        TIMESTEP
+    }
+
+    /// Records a finished frame. Writes the report and returns true once
+    /// the benchmark has run its course.
+    pub fn record(&mut self, stats: RenderStats, objects: usize, culled: usize) -> bool {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame.replace(now) {
+            self.samples.push(Sample {
+                frame_time: (now - last).as_secs_f32() * 1000.0,
+                stats,
+                objects,
+                culled,
+            });
+        }
+        if self.elapsed < self.duration {
+            return false;
+        }
+        let samples = self.samples.get(WARMUP_FRAMES..).unwrap_or(&[]);
+        if samples.is_empty() {
+            println!("Benchmark too short to measure anything");
+            return true;
+        }
+        for (path, contents) in [
+            (REPORT_JSON, summary(samples)),
+            (REPORT_CSV, table(samples)),
+        ] {
+            match fs::write(path, contents) {
+                Ok(()) => println!("Wrote {}", path),
+                Err(err) => println!("Could not write {}: {}", path, err),
+            }
+        }
+        true
+    }
+}
+
+fn summary(samples: &[Sample]) -> String {
+    let mut times: Vec<f32> = samples.iter().map(|s| s.frame_time).collect();
+    times.sort_by(f32::total_cmp);
+    let percentile = |p: f32| times[((times.len() - 1) as f32 * p).round() as usize];
+    let mean =
+        |f: &dyn Fn(&Sample) -> f32| samples.iter().map(f).sum::<f32>() / samples.len() as f32;
+    let max = |f: &dyn Fn(&Sample) -> f32| samples.iter().map(f).fold(0.0, f32::max);
+    let frame_time = mean(&|s| s.frame_time);
+    let counter = |name: &str, f: &dyn Fn(&Sample) -> f32| {
+        format!(
+            "  \"{}\": {{ \"mean\": {:.1}, \"max\": {} }}",
+            name,
+            mean(f),
+            max(f)
+        )
+    };
+
+    let fields = [
+        format!("  \"frames\": {}", samples.len()),
+        format!("  \"timestep_ms\": {:.3}", TIMESTEP * 1000.0),
+        format!(
+            "  \"frame_time_ms\": {{ \"mean\": {:.3}, \"min\": {:.3}, \"p50\": {:.3}, \"p95\": {:.3}, \"p99\": {:.3}, \"max\": {:.3} }}",
+            frame_time,
+            times[0],
+            percentile(0.5),
+            percentile(0.95),
+            percentile(0.99),
+            times[times.len() - 1]
+        ),
+        format!("  \"fps\": {:.1}", 1000.0 / frame_time),
+        counter("draw_calls", &|s| s.stats.draw_calls as f32),
+        counter("instances", &|s| s.stats.instances as f32),
+        counter("triangles", &|s| s.stats.triangles as f32),
+        counter("bytes_uploaded", &|s| s.stats.bytes_uploaded as f32),
+        counter("objects", &|s| s.objects as f32),
+        counter("culled", &|s| s.culled as f32),
+        format!(
+            "  \"gpu_memory_bytes\": {}",
+            samples.last().map_or(0, |s| s.stats.buffer_memory + s.stats.texture_memory)
+        ),
+    ];
+    format!("{{\n{}\n}}\n", fields.join(",\n"))
+}
+
+fn table(samples: &[Sample]) -> String {
+    let mut csv = String::from(
+        "frame,frame_time_ms,draw_calls,instances,triangles,bytes_uploaded,objects,culled\n",
+    );
+    for (i, s) in samples.iter().enumerate() {
+        let _ = writeln!(
+            csv,
+            "{},{:.3},{},{},{},{},{},{}",
+            i,
+            s.frame_time,
+            s.stats.draw_calls,
+            s.stats.instances,
+            s.stats.triangles,
+            s.stats.bytes_uploaded,
+            s.objects,
+            s.culled
+        );
+    }
+    csv
+}
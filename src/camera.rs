@@ -0,0 +1,220 @@
+use cgmath::{
+    ortho, perspective, point3, vec3, Angle, Basis3, Deg, EuclideanSpace, InnerSpace, Matrix3,
+    Matrix4, Point3, Rad, Rotation3, SquareMatrix, Vector2, Vector3,
+};
+
+use crate::picking::Ray;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
+/// How view depth maps to the depth buffer. Only affects perspective
+/// projections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    Standard,
+    /// Far plane pushed to infinity; `far` is ignored.
+    InfiniteFar,
+    /// Depth written as log2 of view distance from the vertex shader, for
+    /// far more even precision over huge ranges. Passes that reconstruct
+    /// positions from the depth buffer are given `log_depth_coef` to undo
+    /// it.
+    Logarithmic,
+}
+
+impl DepthMode {
+    pub fn next(self) -> DepthMode {
+        match self {
+            DepthMode::Standard => DepthMode::InfiniteFar,
+            DepthMode::InfiniteFar => DepthMode::Logarithmic,
+            DepthMode::Logarithmic => DepthMode::Standard,
+        }
+    }
+}
+
+/// Range `Camera::ortho_size` is kept in.
+pub const MIN_ORTHO_SIZE: f32 = 0.05;
+pub const MAX_ORTHO_SIZE: f32 = 1000.0;
+
+/// Stand-in for an infinite far plane where a finite one is needed to
+/// linearize depth.
+const INFINITE_FAR: f32 = 1e7;
+
+/// First-person fly camera.
+#[derive(Clone)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    /// Rotation around the X axis, in radians.
+    pub pitch: f32,
+    /// Rotation around the Y axis, in radians.
+    pub yaw: f32,
+    pub projection: Projection,
+    /// Vertical field of view of the perspective projection, in degrees.
+    pub fov: f32,
+    /// Half the height of the orthographic view volume, in world units.
+    pub ortho_size: f32,
+    pub near: f32,
+    pub far: f32,
+    pub depth_mode: DepthMode,
+    /// Width over height of the viewport.
+    pub aspect: f32,
+    /// Flying speed, in units per second.
+    pub speed: f32,
+}
+
+impl Camera {
+    pub fn new(aspect: f32) -> Camera {
+        Camera {
+            position: point3(0.0, 0.0, 1.0),
+            pitch: 0.0,
+            yaw: 0.0,
+            projection: Projection::Perspective,
+            fov: 80.0,
+            ortho_size: 1.0,
+            near: 0.1,
+            far: 100.0,
+            depth_mode: DepthMode::Standard,
+            aspect,
+            speed: 1.0,
+        }
+    }
+
+    /// Horizontal forward direction, ignoring pitch.
+    pub fn forward(&self) -> Vector3<f32> {
+        vec3(-self.yaw.sin(), 0.0, -self.yaw.cos())
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        vec3(self.yaw.cos(), 0.0, -self.yaw.sin())
+    }
+
+    /// Position, yaw, pitch (in degrees) and field of view as one line of
+    /// text, for sharing a viewpoint.
+    pub fn pose(&self) -> String {
+        format!(
+            "pose {:.3} {:.3} {:.3} {:.2} {:.2} {:.1}",
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            Deg::from(Rad(self.yaw)).0,
+            Deg::from(Rad(self.pitch)).0,
+            self.fov
+        )
+    }
+
+    /// Jumps to a viewpoint written by `pose`.
+    pub fn set_pose(&mut self, pose: &str) -> Result<(), String> {
+        let mut words = pose.split_whitespace();
+        if words.next() != Some("pose") {
+            return Err("not a camera pose".to_owned());
+        }
+        let numbers: Vec<f32> = words
+            .map(|w| w.parse().map_err(|_| format!("not a number: {}", w)))
+            .collect::<Result<_, _>>()?;
+        let [x, y, z, yaw, pitch, fov] = numbers[..] else {
+            return Err("expected x y z yaw pitch fov".to_owned());
+        };
+        self.position = point3(x, y, z);
+        self.yaw = Rad::from(Deg(yaw)).0;
+        self.pitch = Rad::from(Deg(pitch)).0;
+        self.fov = fov.clamp(1.0, 179.0);
+        Ok(())
+    }
+
+    /// Camera-to-world transform.
+    pub fn transform(&self) -> Matrix4<f32> {
+        let rotate = Basis3::from_angle_y(Rad(self.yaw)) * Basis3::from_angle_x(Rad(self.pitch));
+        let rotate: Matrix3<f32> = rotate.into();
+        let rotate: Matrix4<f32> = rotate.into();
+        let translate = Matrix4::from_translation(self.position.to_vec());
+        translate * rotate
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        self.transform().invert().unwrap()
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective if self.depth_mode == DepthMode::InfiniteFar => {
+                let mut m = perspective(Deg(self.fov), self.aspect, self.near, self.far);
+                // Limit of the standard matrix as far goes to infinity.
+                m.z.z = -1.0;
+                m.w.z = -2.0 * self.near;
+                m
+            }
+            Projection::Perspective => perspective(Deg(self.fov), self.aspect, self.near, self.far),
+            Projection::Orthographic => {
+                let h = self.ortho_size;
+                let w = h * self.aspect;
+                ortho(-w, w, -h, h, self.near, self.far)
+            }
+        }
+    }
+
+    /// Ray through a point on the screen, given in normalized device
+    /// coordinates (-1 to 1, Y up).
+    pub fn ray(&self, ndc: Vector2<f32>) -> Ray {
+        let transform = self.transform();
+        let right = transform.x.truncate();
+        let up = transform.y.truncate();
+        let back = transform.z.truncate();
+        match self.projection {
+            Projection::Perspective => {
+                let h = (Deg(self.fov) / 2.0).tan();
+                let w = h * self.aspect;
+                Ray {
+                    origin: self.position.to_vec(),
+                    direction: (right * ndc.x * w + up * ndc.y * h - back).normalize(),
+                }
+            }
+            Projection::Orthographic => {
+                let h = self.ortho_size;
+                let w = h * self.aspect;
+                Ray {
+                    origin: self.position.to_vec() + right * ndc.x * w + up * ndc.y * h,
+                    direction: -back,
+                }
+            }
+        }
+    }
+
+    /// Normalized device coordinates of a world point, or `None` if it is
+    /// behind the camera.
+    pub fn project(&self, point: Vector3<f32>) -> Option<Vector2<f32>> {
+        let clip = self.projection_matrix() * self.view() * point.extend(1.0);
+        (clip.w > 1e-6).then(|| clip.truncate().truncate() / clip.w)
+    }
+
+    /// World units per unit of NDC height at `point`, for keeping things
+    /// a constant size on screen.
+    pub fn world_per_ndc(&self, point: Vector3<f32>) -> f32 {
+        match self.projection {
+            Projection::Perspective => {
+                let distance = (point - self.position.to_vec()).magnitude();
+                distance * (Deg(self.fov) / 2.0).tan()
+            }
+            Projection::Orthographic => self.ortho_size,
+        }
+    }
+
+    /// Far distance to linearize depth buffer values with.
+    pub fn depth_far(&self) -> f32 {
+        match (self.projection, self.depth_mode) {
+            (Projection::Perspective, DepthMode::InfiniteFar) => INFINITE_FAR,
+            _ => self.far,
+        }
+    }
+
+    /// Coefficient for the logarithmic depth write in vertex shaders, zero
+    /// when it is off.
+    pub fn log_depth_coef(&self) -> f32 {
+        match (self.projection, self.depth_mode) {
+            (Projection::Perspective, DepthMode::Logarithmic) => 2.0 / (self.far + 1.0).log2(),
+            _ => 0.0,
+        }
+    }
+}
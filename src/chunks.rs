@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use cgmath::{vec2, vec3, vec4, InnerSpace, Matrix4, Point3, Vector3, VectorSpace};
+use miniquad::*;
+
+use crate::color::linear_rgba;
+use crate::mesh::{Mesh, Vertex};
+use crate::scatter::{self, ScatterInstance};
+
+/// Width of a chunk in world units.
+pub const CHUNK_SIZE: f32 = 32.0;
+/// Quads along each side of a chunk.
+const RESOLUTION: usize = 32;
+/// Chunks this many chunk widths from the camera's are loaded...
+const LOAD_RADIUS: i32 = 4;
+/// ...and kept until they are this far, so crossing a border back and
+/// forth doesn't regenerate them.
+const UNLOAD_RADIUS: i32 = LOAD_RADIUS + 1;
+/// Chunks generated at once.
+const MAX_PENDING: usize = 8;
+/// Chunks uploaded per frame.
+const MAX_UPLOADS: usize = 2;
+/// Height of the hand-made scenes' floor. The terrain stays just below it
+/// near the origin, so they sit on level ground.
+const GROUND: f32 = -0.5;
+
+type Coord = (i32, i32);
+
+/// What a worker hands back for one chunk.
+struct Generated {
+    /// The seed it was grown from.
+    seed: u64,
+    coord: Coord,
+    vertices: Vec<Vertex>,
+    scatter: [Vec<ScatterInstance>; scatter::KINDS],
+}
+
+/// The GPU buffers of a chunk, reused for another one once it's unloaded.
+pub struct ChunkBuffers {
+    pub vertices: BufferId,
+    /// Instance buffers of each kind of prop, `scatter::MAX_PER_CHUNK`
+    /// long.
+    pub scatter: [BufferId; scatter::KINDS],
+}
+
+/// A generated tile of terrain on the GPU.
+pub struct Chunk {
+    pub buffers: ChunkBuffers,
+    /// Props of each kind in `buffers.scatter`.
+    pub scatter_counts: [i32; scatter::KINDS],
+    /// Moves the chunk's vertices, which are relative to its corner, into
+    /// place.
+    pub world: Matrix4<f32>,
+    /// World-space box around the terrain and its props, as (min, max).
+    pub bounds: (Vector3<f32>, Vector3<f32>),
+}
+
+/// Streams terrain around the camera: chunks within `LOAD_RADIUS` are
+/// generated on worker threads and uploaded a few per frame, and those
+/// beyond `UNLOAD_RADIUS` are dropped. All chunks share one index buffer,
+/// and the vertex buffers of dropped chunks are refilled by new ones
+/// rather than deleted, so flying around allocates nothing once the
+/// working set is reached.
+pub struct ChunkManager {
+    pub enabled: bool,
+    seed: u64,
+    chunks: HashMap<Coord, Chunk>,
+    /// Being generated.
+    pending: HashSet<Coord>,
+    sender: Sender<Generated>,
+    receiver: Receiver<Generated>,
+    /// Buffers of unloaded chunks, for reuse.
+    free: Vec<ChunkBuffers>,
+    index_buffer: BufferId,
+    index_count: i32,
+    edge_buffer: BufferId,
+    edge_count: i32,
+}
+
+impl ChunkManager {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> ChunkManager {
+        let grid = grid();
+        let edges = grid.edges();
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&grid.indices),
+        );
+        let edge_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&edges),
+        );
+        let (sender, receiver) = channel();
+        ChunkManager {
+            enabled: false,
+            seed: 0,
+            chunks: HashMap::new(),
+            pending: HashSet::new(),
+            sender,
+            receiver,
+            free: vec![],
+            index_buffer,
+            index_count: grid.indices.len() as i32,
+            edge_buffer,
+            edge_count: edges.len() as i32,
+        }
+    }
+
+    /// Turns streaming on with terrain grown from `seed`, or off.
+    pub fn set(&mut self, enabled: bool, seed: u64) {
+        if seed != self.seed || !enabled {
+            self.unload_all();
+        }
+        self.enabled = enabled;
+        self.seed = seed;
+    }
+
+    fn unload_all(&mut self) {
+        self.free
+            .extend(self.chunks.drain().map(|(_, chunk)| chunk.buffers));
+    }
+
+    /// Unloads distant chunks, starts generating missing ones nearest
+    /// first and uploads those that are ready.
+    pub fn update(&mut self, ctx: &mut dyn RenderingBackend, camera: Point3<f32>) {
+        let center = (
+            (camera.x / CHUNK_SIZE).floor() as i32,
+            (camera.z / CHUNK_SIZE).floor() as i32,
+        );
+        let within = |(x, z): Coord, radius: i32| {
+            let (dx, dz) = (x - center.0, z - center.1);
+            dx * dx + dz * dz <= radius * radius
+        };
+
+        let (keep, unload) = self
+            .chunks
+            .drain()
+            .partition(|&(coord, _)| within(coord, UNLOAD_RADIUS));
+        self.chunks = keep;
+        self.free
+            .extend(unload.into_values().map(|chunk: Chunk| chunk.buffers));
+
+        if self.enabled {
+            let mut wanted: Vec<Coord> = (-LOAD_RADIUS..=LOAD_RADIUS)
+                .flat_map(|dz| (-LOAD_RADIUS..=LOAD_RADIUS).map(move |dx| (dx, dz)))
+                .map(|(dx, dz)| (center.0 + dx, center.1 + dz))
+                .filter(|&coord| within(coord, LOAD_RADIUS))
+                .filter(|coord| !self.chunks.contains_key(coord) && !self.pending.contains(coord))
+                .collect();
+            wanted.sort_by_key(|&(x, z)| (x - center.0).pow(2) + (z - center.1).pow(2));
+            let room = MAX_PENDING.saturating_sub(self.pending.len());
+            for coord in wanted.into_iter().take(room) {
+                self.pending.insert(coord);
+                let sender = self.sender.clone();
+                let seed = self.seed;
+                // Chunks take well under a millisecond each, so they can
+                // share rayon's pool with the scene update.
+                rayon::spawn(move || {
+                    let _ = sender.send(Generated {
+                        seed,
+                        coord,
+                        vertices: generate(seed, coord),
+                        scatter: scatter::place(seed, coord),
+                    });
+                });
+            }
+        }
+
+        for generated in self.receiver.try_iter().take(MAX_UPLOADS) {
+            let coord = generated.coord;
+            self.pending.remove(&coord);
+            // Flown away from, turned off or reseeded while it was
+            // generated.
+            if !self.enabled || generated.seed != self.seed || !within(coord, UNLOAD_RADIUS) {
+                continue;
+            }
+            let buffers = self.free.pop().unwrap_or_else(|| ChunkBuffers {
+                vertices: ctx.new_buffer(
+                    BufferType::VertexBuffer,
+                    BufferUsage::Dynamic,
+                    BufferSource::empty::<Vertex>(generated.vertices.len()),
+                ),
+                scatter: [(); scatter::KINDS].map(|_| {
+                    ctx.new_buffer(
+                        BufferType::VertexBuffer,
+                        BufferUsage::Dynamic,
+                        BufferSource::empty::<ScatterInstance>(scatter::MAX_PER_CHUNK),
+                    )
+                }),
+            });
+            ctx.buffer_update(buffers.vertices, BufferSource::slice(&generated.vertices));
+            for (buffer, instances) in buffers.scatter.iter().zip(&generated.scatter) {
+                ctx.buffer_update(*buffer, BufferSource::slice(instances));
+            }
+
+            let corner = vec3(coord.0 as f32, 0.0, coord.1 as f32) * CHUNK_SIZE;
+            let (low, high) = generated
+                .vertices
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(low, high), v| {
+                    (low.min(v.pos.y), high.max(v.pos.y))
+                });
+            let chunk = Chunk {
+                buffers,
+                scatter_counts: generated.scatter.each_ref().map(|i| i.len() as i32),
+                world: Matrix4::from_translation(corner),
+                // Props stand up to about two units tall.
+                bounds: (
+                    corner + vec3(0.0, low, 0.0),
+                    corner + vec3(CHUNK_SIZE, high + 2.0, CHUNK_SIZE),
+                ),
+            };
+            self.chunks.insert(coord, chunk);
+        }
+    }
+
+    /// Height of the terrain at a point, whether or not its chunk is
+    /// loaded, or `None` while streaming is off.
+    pub fn ground(&self, x: f32, z: f32) -> Option<f32> {
+        self.enabled.then(|| height(self.seed, x, z))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+
+    pub fn index_count(&self) -> i32 {
+        self.index_count
+    }
+
+    pub fn edge_count(&self) -> i32 {
+        self.edge_count
+    }
+
+    pub fn bindings(&self, chunk: &Chunk, image: TextureId) -> Bindings {
+        Bindings {
+            vertex_buffers: vec![chunk.buffers.vertices],
+            index_buffer: self.index_buffer,
+            images: vec![image],
+        }
+    }
+
+    pub fn edge_bindings(&self, chunk: &Chunk, image: TextureId) -> Bindings {
+        Bindings {
+            index_buffer: self.edge_buffer,
+            ..self.bindings(chunk, image)
+        }
+    }
+}
+
+/// The flat grid every chunk deforms; only its indices are used.
+fn grid() -> Mesh {
+    let side = RESOLUTION + 1;
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for z in 0..side {
+        for x in 0..side {
+            vertices.push(Vertex {
+                pos: vec3(x as f32, 0.0, z as f32),
+                normal: vec3(0.0, 1.0, 0.0),
+                color: vec4(1.0, 1.0, 1.0, 1.0),
+                uv2: vec2(0.0, 0.0),
+            });
+        }
+    }
+    for z in 0..RESOLUTION {
+        for x in 0..RESOLUTION {
+            let i = (z * side + x) as u16;
+            let below = i + side as u16;
+            indices.extend([i, below, i + 1, i + 1, below, below + 1]);
+        }
+    }
+    Mesh { vertices, indices }
+}
+
+/// Vertices of one chunk, relative to its corner, in `grid` order.
+fn generate(seed: u64, (cx, cz): Coord) -> Vec<Vertex> {
+    let step = CHUNK_SIZE / RESOLUTION as f32;
+    let grass = linear_rgba(vec4(0.32, 0.45, 0.2, 1.0));
+    let rock = linear_rgba(vec4(0.5, 0.47, 0.43, 1.0));
+    let snow = linear_rgba(vec4(0.9, 0.9, 0.92, 1.0));
+    let mut vertices = Vec::with_capacity((RESOLUTION + 1).pow(2));
+    for z in 0..=RESOLUTION {
+        for x in 0..=RESOLUTION {
+            let local = vec2(x as f32, z as f32) * step;
+            let wx = cx as f32 * CHUNK_SIZE + local.x;
+            let wz = cz as f32 * CHUNK_SIZE + local.y;
+            let y = height(seed, wx, wz);
+            // Sampled from the height field rather than the grid, so
+            // normals match along chunk borders.
+            let e = step * 0.5;
+            let normal = vec3(
+                height(seed, wx - e, wz) - height(seed, wx + e, wz),
+                2.0 * e,
+                height(seed, wx, wz - e) - height(seed, wx, wz + e),
+            )
+            .normalize();
+            let steep = smoothstep(0.85, 0.7, normal.y);
+            let high = smoothstep(10.0, 14.0, y);
+            let color = grass.lerp(rock, steep).lerp(snow, high * (1.0 - steep));
+            vertices.push(Vertex {
+                pos: vec3(local.x, y, local.y),
+                normal,
+                color,
+                uv2: vec2(0.0, 0.0),
+            });
+        }
+    }
+    vertices
+}
+
+/// Rolling hills of value noise, flattened to just under `GROUND` near the
+/// origin.
+pub fn height(seed: u64, x: f32, z: f32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 8.0;
+    let mut frequency = 1.0 / 64.0;
+    for octave in 0..5 {
+        sum += amplitude * value_noise(seed.wrapping_add(octave), x * frequency, z * frequency);
+        amplitude *= 0.45;
+        frequency *= 2.0;
+    }
+    let flatten = smoothstep(16.0, 48.0, vec2(x, z).magnitude());
+    GROUND - 0.05 + (sum + 4.0) * flatten
+}
+
+/// Smoothly interpolated random values at integer points, in [-1, 1].
+pub fn value_noise(seed: u64, x: f32, z: f32) -> f32 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (fx, fz) = (smoothstep(0.0, 1.0, x - x0), smoothstep(0.0, 1.0, z - z0));
+    let (ix, iz) = (x0 as i32, z0 as i32);
+    let top = lerp(hash(seed, ix, iz), hash(seed, ix + 1, iz), fx);
+    let bottom = lerp(hash(seed, ix, iz + 1), hash(seed, ix + 1, iz + 1), fx);
+    lerp(top, bottom, fz)
+}
+
+pub fn hash(seed: u64, x: i32, z: i32) -> f32 {
+    let mut h = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (z as u32 as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    (h >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
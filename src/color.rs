@@ -0,0 +1,21 @@
+use cgmath::{vec4, Vector4};
+
+/// Decodes a single sRGB-encoded channel into linear light.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Colors are authored in sRGB (as picked in any color picker), but all
+/// shading happens in linear space. Alpha is never gamma-encoded.
+pub fn linear_rgba(srgb: Vector4<f32>) -> Vector4<f32> {
+    vec4(
+        srgb_to_linear(srgb.x),
+        srgb_to_linear(srgb.y),
+        srgb_to_linear(srgb.z),
+        srgb.w,
+    )
+}
@@ -0,0 +1,924 @@
+use cgmath::{vec3, vec4};
+
+use crate::camera::{MAX_ORTHO_SIZE, MIN_ORTHO_SIZE};
+use crate::color::linear_rgba;
+use crate::console::{Command, Console};
+use crate::environment::{self, Cubemap, Environment};
+use crate::generate::{self, Generation};
+use crate::history::Edit;
+use crate::level;
+use crate::palette::{ColorScheme, Palette};
+use crate::pip::PipSource;
+use crate::prefab;
+use crate::scene::DrawParams;
+use crate::settings::Transparency;
+use crate::walk;
+use crate::Stage;
+
+pub fn register(console: &mut Console<Stage>) {
+    console.register(Command {
+        name: "help",
+        usage: "",
+        help: "list commands",
+        handler: help,
+    });
+    console.register(Command {
+        name: "clear",
+        usage: "",
+        help: "clear the console",
+        handler: |stage, _| {
+            stage.console.clear();
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "spawn",
+        usage: "<prefab>",
+        help: "place a prefab in front of the camera",
+        handler: |stage, args| {
+            let [name] = args else {
+                return Err("usage: spawn <prefab>".to_owned());
+            };
+            stage
+                .spawn_prefab(name)
+                .map(|id| format!("Spawned {}", stage.scene.get(id).unwrap().name))
+        },
+    });
+    console.register(Command {
+        name: "delete",
+        usage: "",
+        help: "delete the selected object",
+        handler: |stage, _| match stage.gizmo.selected {
+            Some(_) => {
+                stage.delete_selected();
+                Ok(String::new())
+            }
+            None => Err("nothing selected".to_owned()),
+        },
+    });
+    console.register(Command {
+        name: "color",
+        usage: "<r> <g> <b>",
+        help: "tint the selected object (sRGB, 0-1)",
+        handler: color,
+    });
+    console.register(Command {
+        name: "tint",
+        usage: "<r> <g> <b> [a]|off",
+        help: "multiply the selected object's color without changing its material",
+        handler: tint,
+    });
+    console.register(Command {
+        name: "emissive",
+        usage: "<factor>",
+        help: "make the selected object glow with its own color",
+        handler: |stage, args| {
+            let [factor] = args else {
+                return Err("usage: emissive <factor>".to_owned());
+            };
+            let factor: f32 = factor
+                .parse()
+                .map_err(|_| format!("not a number: {}", factor))?;
+            set_params(stage, |params| params.emissive = factor.max(0.0))
+        },
+    });
+    console.register(Command {
+        name: "undo",
+        usage: "",
+        help: "revert the last edit",
+        handler: |stage, _| {
+            stage.undo();
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "redo",
+        usage: "",
+        help: "reapply the last undone edit",
+        handler: |stage, _| {
+            stage.redo();
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "set",
+        usage: "<variable> <value>",
+        help:
+            "set fov, near, far, fog, sensitivity, time (hours), daylength (seconds), timescale, mapsize (units), pipfov, orthosize (units) or scatterfade (units)",
+        handler: set,
+    });
+    console.register(Command {
+        name: "wireframe",
+        usage: "on|off",
+        help: "draw triangle edges only",
+        handler: |stage, args| {
+            let enabled = parse_switch(args)?;
+            stage.debug.set(stage.views.wireframe, enabled);
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "anim",
+        usage: "play|pause|stop|loop|once",
+        help: "control the animations of the selection",
+        handler: anim,
+    });
+    console.register(Command {
+        name: "debug",
+        usage: "[<flag> on|off]",
+        help: "list or switch debug views",
+        handler: debug,
+    });
+    console.register(Command {
+        name: "load",
+        usage: "scene|level <file>",
+        help: "replace the scene with one from scenes/ or a level from levels/",
+        handler: load,
+    });
+    console.register(Command {
+        name: "spawnpoint",
+        usage: "[<index>]",
+        help: "go to a spawn point of the loaded level",
+        handler: |stage, args| {
+            let index = match args {
+                [] => 0,
+                [index] => index
+                    .parse()
+                    .map_err(|_| format!("not an index: {}", index))?,
+                _ => return Err("usage: spawnpoint [<index>]".to_owned()),
+            };
+            go_to_spawn(stage, index)
+        },
+    });
+    console.register(Command {
+        name: "generate",
+        usage: "<seed> [objects]",
+        help: "replace the scene with props scattered from a seed",
+        handler: generate,
+    });
+    console.register(Command {
+        name: "terrain",
+        usage: "on [seed]|off",
+        help: "stream endless terrain around the camera",
+        handler: terrain,
+    });
+    console.register(Command {
+        name: "voxels",
+        usage: "on|off",
+        help: "show the block world; click to dig, right-click to build",
+        handler: |stage, args| {
+            stage.voxels.enabled = parse_switch(args)?;
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "import",
+        usage: "<file>|<directory>",
+        help: "load models, images or environment maps in the background",
+        handler: import,
+    });
+    console.register(Command {
+        name: "sky",
+        usage: "<file>|default",
+        help: "light the scene with an .hdr or .exr panorama",
+        handler: sky,
+    });
+    console.register(Command {
+        name: "raymarch",
+        usage: "on|off",
+        help: "draw signed distance field shapes among the meshes",
+        handler: |stage, args| {
+            stage.raymarcher.enabled = parse_switch(args)?;
+            Ok(String::new())
+        },
+    });
+    #[cfg(feature = "networking")]
+    console.register(Command {
+        name: "net",
+        usage: "start <port> [<host:port>...]|stop|status",
+        help: "share the camera with other viewers over UDP",
+        handler: net,
+    });
+    #[cfg(feature = "remote")]
+    console.register(Command {
+        name: "remote",
+        usage: "start <port>|stop|status",
+        help: "take JSON commands from other programs on localhost",
+        handler: remote,
+    });
+    console.register(Command {
+        name: "screenshot",
+        usage: "[<file>]",
+        help: "save the next frame, without overlays, to screenshots/",
+        handler: |stage, args| {
+            let file = match args {
+                [] => None,
+                [file] => Some(*file),
+                _ => return Err("usage: screenshot [<file>]".to_owned()),
+            };
+            stage.screenshot = Some(screenshot_file(file)?);
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "walk",
+        usage: "on|off",
+        help: "walk over the scene and terrain instead of flying",
+        handler: |stage, args| {
+            let enabled = parse_switch(args)?;
+            stage.walker.set(enabled, &stage.camera);
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "scatter",
+        usage: "on|off",
+        help: "grow grass and rocks on the terrain",
+        handler: |stage, args| {
+            stage.scatter.enabled = parse_switch(args)?;
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "batch",
+        usage: "on|off",
+        help: "merge small static objects that share a material",
+        handler: |stage, args| {
+            if parse_switch(args)? {
+                Ok(stage.bake_static_batches())
+            } else {
+                stage.static_batches.clear(stage.ctx.as_mut());
+                Ok(String::new())
+            }
+        },
+    });
+    console.register(Command {
+        name: "parallel",
+        usage: "on|off",
+        help: "update the scene on all cores, or on one to compare",
+        handler: |stage, args| {
+            stage.settings.parallel_update = parse_switch(args)?;
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "transparency",
+        usage: "sorted|weighted",
+        help: "blend transparent objects back to front, or order-independently",
+        handler: |stage, args| {
+            let [name] = args else {
+                return Err("usage: transparency sorted|weighted".to_owned());
+            };
+            stage.settings.transparency =
+                Transparency::find(name).ok_or(format!("unknown mode {}", name))?;
+            stage.apply_settings();
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "pip",
+        usage: "off|rear|sun|selected|<node>",
+        help: "show a second view at the top of the screen, from behind, the sun or a node",
+        handler: pip,
+    });
+    console.register(Command {
+        name: "palette",
+        usage: "[standard|redgreen|blueyellow]",
+        help: "show or pick colors for the debug views, gizmo, HUD and generated props",
+        handler: palette,
+    });
+    console.register(Command {
+        name: "contrast",
+        usage: "on|off",
+        help: "brighter lines and text on solid backgrounds",
+        handler: |stage, args| {
+            stage.settings.high_contrast = parse_switch(args)?;
+            stage.palette = Palette::new(stage.settings.color_scheme, stage.settings.high_contrast);
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "session",
+        usage: "on|off",
+        help: "restore the camera, settings and debug views at startup",
+        handler: |stage, args| {
+            stage.settings.restore_session = parse_switch(args)?;
+            Ok(String::new())
+        },
+    });
+    console.register(Command {
+        name: "quit",
+        usage: "",
+        help: "exit",
+        handler: |_, _| {
+            miniquad::window::request_quit();
+            Ok(String::new())
+        },
+    });
+}
+
+fn help(stage: &mut Stage, _: &[&str]) -> Result<String, String> {
+    Ok(stage
+        .console
+        .commands()
+        .iter()
+        .map(|c| format!("{} {} - {}", c.name, c.usage, c.help))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn set(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let [variable, value] = args else {
+        return Err("usage: set <variable> <value>".to_owned());
+    };
+    let value: f32 = value
+        .parse()
+        .map_err(|_| format!("not a number: {}", value))?;
+    match *variable {
+        "fov" => stage.set_fov(value.clamp(1.0, 179.0)),
+        "near" => stage.camera.near = value.max(1e-4),
+        "far" => stage.camera.far = value.max(stage.camera.near),
+        "fog" => stage.lighting.fog_density = value.max(0.0),
+        "sensitivity" => stage.settings.mouse_sensitivity = value.max(0.0),
+        "time" => stage.day_night.time_of_day = (value / 24.0).rem_euclid(1.0),
+        "daylength" => stage.day_night.cycle_length = value.max(1.0),
+        "timescale" => stage.time.scale = value.max(0.0),
+        "mapsize" => stage.minimap.radius = value.max(1.0),
+        "pipfov" => stage.pip.fov = value.clamp(1.0, 179.0),
+        "orthosize" => stage.camera.ortho_size = value.clamp(MIN_ORTHO_SIZE, MAX_ORTHO_SIZE),
+        "scatterfade" => {
+            stage.scatter.fade_end = value.max(1.0);
+            stage.scatter.fade_start = stage.scatter.fade_end * 2.0 / 3.0;
+        }
+        _ => return Err(format!("unknown variable {}", variable)),
+    }
+    Ok(String::new())
+}
+
+fn color(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let [r, g, b] = args else {
+        return Err("usage: color <r> <g> <b>".to_owned());
+    };
+    let parse = |c: &str| c.parse::<f32>().map_err(|_| format!("not a number: {}", c));
+    let color = linear_rgba(vec4(parse(r)?, parse(g)?, parse(b)?, 1.0));
+    let root = stage.gizmo.selected.ok_or("nothing selected")?;
+
+    let mut changes = vec![];
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        let Some(node) = stage.scene.get_mut(id) else {
+            continue;
+        };
+        pending.extend(&node.children);
+        if node.mesh.is_some() {
+            let before = node.material;
+            node.material.color = color;
+            changes.push((id, before, node.material));
+        }
+    }
+    stage.history.record(Edit::Material { changes });
+    Ok(String::new())
+}
+
+fn tint(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let parse = |c: &str| c.parse::<f32>().map_err(|_| format!("not a number: {}", c));
+    let tint = match args {
+        ["off"] => DrawParams::default().tint,
+        [r, g, b] => linear_rgba(vec4(parse(r)?, parse(g)?, parse(b)?, 1.0)),
+        [r, g, b, a] => linear_rgba(vec4(parse(r)?, parse(g)?, parse(b)?, parse(a)?)),
+        _ => return Err("usage: tint <r> <g> <b> [a]|off".to_owned()),
+    };
+    set_params(stage, |params| params.tint = tint)
+}
+
+/// Changes the draw parameters of the selection and its children.
+fn set_params(stage: &mut Stage, f: impl Fn(&mut DrawParams)) -> Result<String, String> {
+    let root = stage.gizmo.selected.ok_or("nothing selected")?;
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        let Some(node) = stage.scene.get_mut(id) else {
+            continue;
+        };
+        pending.extend(&node.children);
+        f(&mut node.params);
+    }
+    Ok(String::new())
+}
+
+fn anim(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let [command] = args else {
+        return Err("usage: anim play|pause|stop|loop|once".to_owned());
+    };
+    let root = stage.gizmo.selected.ok_or("nothing selected")?;
+    let mut count = 0;
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        let Some(node) = stage.scene.get_mut(id) else {
+            continue;
+        };
+        pending.extend(&node.children);
+        let Some(animation) = &mut node.animation else {
+            continue;
+        };
+        match *command {
+            "play" => animation.play(),
+            "pause" => animation.pause(),
+            "stop" => {
+                animation.stop();
+                animation.clip.apply(0.0, &mut node.transform);
+            }
+            "loop" => animation.looping = true,
+            "once" => animation.looping = false,
+            _ => return Err(format!("unknown animation command {}", command)),
+        }
+        count += 1;
+    }
+    match count {
+        0 => Err("the selection has no animations".to_owned()),
+        _ => Ok(String::new()),
+    }
+}
+
+fn debug(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let Some((name, switch)) = args.split_first() else {
+        return Ok(stage
+            .debug
+            .iter()
+            .map(|flag| {
+                let state = if stage.debug.enabled(flag) {
+                    "on"
+                } else {
+                    "off"
+                };
+                format!("{} {}", stage.debug.name(flag), state)
+            })
+            .collect::<Vec<_>>()
+            .join("\n"));
+    };
+    let flag = stage
+        .debug
+        .find(name)
+        .ok_or_else(|| format!("unknown debug flag {}", name))?;
+    stage.debug.set(flag, parse_switch(switch)?);
+    Ok(String::new())
+}
+
+#[cfg(feature = "networking")]
+fn net(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["start", port, peers @ ..] => {
+            let port = port.parse().map_err(|_| format!("not a port: {}", port))?;
+            if let Some(network) = stage.network.take() {
+                network.stop(&mut stage.scene);
+            }
+            let network = crate::net::Network::start(port, peers)?;
+            let status = network.status();
+            stage.network = Some(network);
+            Ok(status)
+        }
+        ["stop"] => {
+            let network = stage.network.take().ok_or("not connected")?;
+            network.stop(&mut stage.scene);
+            Ok(String::new())
+        }
+        ["status"] => Ok(stage
+            .network
+            .as_ref()
+            .map_or_else(|| "not connected".to_owned(), |network| network.status())),
+        _ => Err("usage: net start <port> [<host:port>...]|stop|status".to_owned()),
+    }
+}
+
+#[cfg(feature = "remote")]
+fn remote(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["start", port] => {
+            let port = port.parse().map_err(|_| format!("not a port: {}", port))?;
+            // Drop the old server first, in case it holds the same port.
+            stage.remote = None;
+            let server = crate::remote::Server::start(port)?;
+            let status = server.status();
+            stage.remote = Some(server);
+            Ok(status)
+        }
+        ["stop"] => {
+            stage.remote.take().ok_or("not listening")?;
+            Ok(String::new())
+        }
+        ["status"] => Ok(stage
+            .remote
+            .as_ref()
+            .map_or_else(|| "not listening".to_owned(), |server| server.status())),
+        _ => Err("usage: remote start <port>|stop|status".to_owned()),
+    }
+}
+
+/// Console commands remote clients may run. Those that read or write
+/// files, open connections or exit are left out; scenes and screenshots
+/// have their own checked remote commands.
+#[cfg(feature = "remote")]
+const REMOTE_CONSOLE: &[&str] = &[
+    "help",
+    "clear",
+    "spawn",
+    "delete",
+    "color",
+    "tint",
+    "emissive",
+    "undo",
+    "redo",
+    "set",
+    "wireframe",
+    "anim",
+    "debug",
+    "spawnpoint",
+    "generate",
+    "terrain",
+    "voxels",
+    "raymarch",
+    "walk",
+    "scatter",
+    "batch",
+    "parallel",
+    "transparency",
+    "pip",
+    "palette",
+    "contrast",
+];
+
+/// Answers the commands remote clients sent since the last frame.
+/// Screenshots are answered once the frame is drawn.
+#[cfg(feature = "remote")]
+pub fn serve_remote(stage: &mut Stage) {
+    use crate::remote::Command;
+
+    let Some(server) = &mut stage.remote else {
+        return;
+    };
+    for request in server.poll() {
+        let result = match request.command {
+            Ok(Command::Screenshot { file }) => match screenshot_file(file.as_deref()) {
+                Ok(file) => {
+                    stage.screenshot = Some(file);
+                    if let Some(server) = &mut stage.remote {
+                        server.wait_for_screenshot(request.client, request.id);
+                    }
+                    continue;
+                }
+                Err(err) => Err(err),
+            },
+            command => command.and_then(|command| remote_command(stage, command)),
+        };
+        // A console command may have stopped the server.
+        if let Some(server) = &mut stage.remote {
+            server.reply(request.client, &request.id, result);
+        }
+    }
+}
+
+#[cfg(feature = "remote")]
+fn remote_command(
+    stage: &mut Stage,
+    command: crate::remote::Command,
+) -> Result<serde_json::Value, String> {
+    use crate::remote::Command;
+    use cgmath::{point3, Deg, Rad};
+    use serde_json::{json, Value};
+
+    // Console messages are for people; an empty one means nothing to say.
+    let message = |result: Result<String, String>| {
+        result.map(|message| match message.is_empty() {
+            true => Value::Null,
+            false => json!({ "message": message }),
+        })
+    };
+    match command {
+        Command::Camera {
+            position,
+            yaw,
+            pitch,
+            fov,
+        } => {
+            // A jump in progress would carry on from the new pose.
+            let moved = position.is_some() || yaw.is_some() || pitch.is_some() || fov.is_some();
+            if moved {
+                for property in ["position", "yaw", "pitch", "fov"] {
+                    stage.tweens.cancel(property);
+                }
+            }
+            let camera = &mut stage.camera;
+            if let Some([x, y, z]) = position {
+                camera.position = point3(x, y, z);
+            }
+            if let Some(yaw) = yaw {
+                camera.yaw = Rad::from(Deg(yaw)).0;
+            }
+            if let Some(pitch) = pitch {
+                camera.pitch = Rad::from(Deg(pitch)).0;
+            }
+            if let Some(fov) = fov {
+                camera.fov = fov.clamp(1.0, 179.0);
+                stage.unzoomed_fov = None;
+            }
+            if moved {
+                stage.walker.reset(&stage.camera);
+            }
+            let camera = &stage.camera;
+            Ok(json!({
+                "position": [camera.position.x, camera.position.y, camera.position.z],
+                "yaw": Deg::from(Rad(camera.yaw)).0,
+                "pitch": Deg::from(Rad(camera.pitch)).0,
+                "fov": camera.fov,
+            }))
+        }
+        Command::Load { file, level } => {
+            let kind = if level { "level" } else { "scene" };
+            message(load(stage, &[kind, &file]))
+        }
+        Command::Screenshot { .. } => unreachable!("screenshots are answered after drawing"),
+        Command::Debug { flag: None, .. } => Ok(stage
+            .debug
+            .iter()
+            .map(|flag| {
+                (
+                    stage.debug.name(flag).to_owned(),
+                    stage.debug.enabled(flag).into(),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()),
+        Command::Debug {
+            flag: Some(name),
+            on,
+        } => {
+            let flag = stage
+                .debug
+                .find(&name)
+                .ok_or_else(|| format!("unknown debug flag {}", name))?;
+            if let Some(on) = on {
+                stage.debug.set(flag, on);
+            }
+            Ok(json!({ name: stage.debug.enabled(flag) }))
+        }
+        Command::Console { line } => {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((name, args)) = words.split_first() else {
+                return Err("empty command".to_owned());
+            };
+            if !REMOTE_CONSOLE.contains(name) {
+                return Err(format!("{} is not available remotely", name));
+            }
+            let handler = stage
+                .console
+                .commands()
+                .iter()
+                .find(|command| command.name == *name)
+                .map(|command| command.handler)
+                .ok_or_else(|| format!("unknown command {:?}", name))?;
+            message(handler(stage, args))
+        }
+    }
+}
+
+/// Where in `screenshots/` to save to: `file` as given, or named after
+/// the current time.
+fn screenshot_file(file: Option<&str>) -> Result<String, String> {
+    let Some(file) = file else {
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        return Ok(format!("screenshot-{}.png", seconds));
+    };
+    check_file_name(file)?;
+    if file.ends_with(".png") {
+        Ok(file.to_owned())
+    } else {
+        Ok(format!("{}.png", file))
+    }
+}
+
+/// Refuses anything but a plain name, so that a path joined onto a
+/// directory stays inside it.
+fn check_file_name(file: &str) -> Result<(), String> {
+    if file.contains(['/', '\\']) || file.starts_with('.') {
+        return Err(format!("not a file name: {}", file));
+    }
+    Ok(())
+}
+
+fn palette(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let names = || ColorScheme::ALL.map(ColorScheme::name).join(", ");
+    match args {
+        [] => Ok(format!(
+            "{} (of {}), high contrast {}",
+            stage.settings.color_scheme.name(),
+            names(),
+            if stage.settings.high_contrast {
+                "on"
+            } else {
+                "off"
+            }
+        )),
+        [name] => {
+            let scheme = ColorScheme::find(name)
+                .ok_or_else(|| format!("unknown palette {}, try {}", name, names()))?;
+            stage.settings.color_scheme = scheme;
+            stage.palette = Palette::new(scheme, stage.settings.high_contrast);
+            Ok(String::new())
+        }
+        _ => Err("usage: palette [standard|redgreen|blueyellow]".to_owned()),
+    }
+}
+
+fn pip(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let source = match args {
+        ["off"] => {
+            stage.pip.enabled = false;
+            return Ok(String::new());
+        }
+        ["rear"] => PipSource::Rear,
+        ["sun"] => PipSource::Sun,
+        ["selected"] => PipSource::Node(stage.gizmo.selected.ok_or("nothing selected")?),
+        [] => return Err("usage: pip off|rear|sun|selected|<node>".to_owned()),
+        name => {
+            let name = name.join(" ");
+            let (id, _) = stage
+                .scene
+                .iter()
+                .find(|(_, node)| node.name == name)
+                .ok_or_else(|| format!("no node named {}", name))?;
+            PipSource::Node(id)
+        }
+    };
+    match source {
+        PipSource::Node(node) => stage.pip.attach(node),
+        source => {
+            stage.pip.source = source;
+            stage.pip.enabled = true;
+        }
+    }
+    Ok(String::new())
+}
+
+fn load(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["scene", file] => {
+            check_file_name(file)?;
+            let path = format!("scenes/{}", file);
+            if !std::path::Path::new(&path).is_file() {
+                return Err(format!("no such scene: {}", path));
+            }
+            let def = prefab::read_scene(&path)?;
+            clear_scene(stage);
+            prefab::place_scene(def, &path, &stage.prefabs, &mut stage.scene, &stage.meshes);
+            Ok(format!("Loaded {}", path))
+        }
+        ["level", file] => {
+            check_file_name(file)?;
+            let path = format!("{}/{}", level::LEVEL_DIR, file);
+            if !std::path::Path::new(&path).is_file() {
+                return Err(format!("no such level: {}", path));
+            }
+            let def = level::read_level(file)?;
+            clear_scene(stage);
+            let level =
+                level::build_level(file, def, &stage.prefabs, &mut stage.scene, &stage.meshes)?;
+            let lighting = level.lighting;
+            if let Some(hours) = lighting.time {
+                stage.day_night.time_of_day = (hours / 24.0).rem_euclid(1.0);
+            }
+            if let Some(cycle) = lighting.cycle {
+                stage.day_night.running = cycle;
+            }
+            if let Some(fog) = lighting.fog {
+                stage.lighting.fog_density = fog.max(0.0);
+            }
+            stage.spawns = level.spawns;
+            if !stage.spawns.is_empty() {
+                go_to_spawn(stage, 0)?;
+            }
+            Ok(format!(
+                "Loaded {}, {} spawn points",
+                path,
+                stage.spawns.len()
+            ))
+        }
+        _ => Err("usage: load scene|level <file>".to_owned()),
+    }
+}
+
+/// Puts the camera at eye height over a spawn point, looking level.
+fn go_to_spawn(stage: &mut Stage, index: usize) -> Result<String, String> {
+    let spawn = stage
+        .spawns
+        .get(index)
+        .ok_or_else(|| format!("no spawn point {} ({} loaded)", index, stage.spawns.len()))?;
+    stage.camera.position = spawn.position + vec3(0.0, walk::EYE_HEIGHT, 0.0);
+    stage.camera.yaw = spawn.yaw;
+    stage.camera.pitch = 0.0;
+    stage.walker.reset(&stage.camera);
+    Ok(String::new())
+}
+
+fn generate(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let usage = || "usage: generate <seed> [objects]".to_owned();
+    let (seed, objects) = match args {
+        [seed] => (seed, None),
+        [seed, objects] => (seed, Some(objects)),
+        _ => return Err(usage()),
+    };
+    let generation = Generation {
+        seed: seed.parse().map_err(|_| usage())?,
+        objects: match objects {
+            Some(objects) => objects.parse().map_err(|_| usage())?,
+            None => generate::DEFAULT_OBJECTS,
+        },
+    };
+    clear_scene(stage);
+    generation.populate(
+        &mut stage.scene,
+        &stage.prefabs,
+        &stage.meshes,
+        &stage.palette,
+    );
+    Ok(String::new())
+}
+
+fn terrain(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["on"] => stage.chunks.set(true, 0),
+        ["on", seed] => {
+            let seed = seed
+                .parse()
+                .map_err(|_| "usage: terrain on [seed]|off".to_owned())?;
+            stage.chunks.set(true, seed);
+        }
+        ["off"] => stage.chunks.set(false, 0),
+        _ => return Err("usage: terrain on [seed]|off".to_owned()),
+    }
+    Ok(String::new())
+}
+
+/// Empties the scene along with everything tied to what was in it.
+fn clear_scene(stage: &mut Stage) {
+    // A baked lightmap only fits the scene it was baked for.
+    if stage.lightmap.is_some() {
+        stage.toggle_lightmap();
+    }
+    stage.static_batches.clear(stage.ctx.as_mut());
+    stage.scene.clear();
+    stage.history.clear();
+    stage.gizmo.selected = None;
+    stage.spawns.clear();
+}
+
+fn import(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let [path] = args else {
+        return Err("usage: import <file>|<directory>".to_owned());
+    };
+    let path = std::path::Path::new(path);
+    if path.is_dir() {
+        let count = stage
+            .loader
+            .request_dir(path)
+            .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+        Ok(format!(
+            "Importing {} files, {} in progress",
+            count,
+            stage.loader.pending()
+        ))
+    } else if path.is_file() {
+        stage.loader.request(path.to_owned());
+        Ok(format!("{} in progress", stage.loader.pending()))
+    } else {
+        Err(format!("no such file: {}", path.display()))
+    }
+}
+
+fn sky(stage: &mut Stage, args: &[&str]) -> Result<String, String> {
+    let [file] = args else {
+        return Err("usage: sky <file>|default".to_owned());
+    };
+    if *file == "default" {
+        stage.sky.set_environment(stage.ctx.as_mut(), None);
+        return Ok(String::new());
+    }
+    let image = environment::load(std::path::Path::new(file))
+        .map_err(|err| format!("could not load {}: {}", file, err))?;
+    let environment = Environment::new(stage.ctx.as_mut(), &Cubemap::from_equirect(&image));
+    stage
+        .sky
+        .set_environment(stage.ctx.as_mut(), Some(environment));
+    Ok(format!(
+        "Loaded {}x{} environment",
+        image.width, image.height
+    ))
+}
+
+fn parse_switch(args: &[&str]) -> Result<bool, String> {
+    match args {
+        ["on"] | ["1"] => Ok(true),
+        ["off"] | ["0"] => Ok(false),
+        _ => Err("expected on or off".to_owned()),
+    }
+}
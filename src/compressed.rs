@@ -0,0 +1,412 @@
+use std::ffi::CStr;
+
+use miniquad::*;
+
+use crate::texture::{self, ColorSpace, Image};
+
+/// Block-compressed texel encodings, all in 4x4 blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// RGB with 1-bit alpha.
+    Bc1,
+    /// RGB with explicit 4-bit alpha.
+    Bc2,
+    /// RGB with interpolated alpha.
+    Bc3,
+    /// One channel.
+    Bc4 { signed: bool },
+    /// Two channels, e.g. normal map XY.
+    Bc5 { signed: bool },
+    /// HDR RGB.
+    Bc6h { signed: bool },
+    /// High quality RGBA.
+    Bc7,
+}
+
+impl BlockFormat {
+    fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc4 { .. } => 8,
+            _ => 16,
+        }
+    }
+
+    /// Bytes taken by one image of this size.
+    /// Saturates rather than overflowing for the sizes of malformed files,
+    /// which then read as truncated.
+    fn size(self, width: u32, height: u32) -> usize {
+        (width.div_ceil(4) as usize)
+            .saturating_mul(height.div_ceil(4) as usize)
+            .saturating_mul(self.block_bytes())
+    }
+}
+
+/// A block-compressed image with its mip chain, as stored in a DDS or KTX2
+/// file.
+pub struct CompressedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: BlockFormat,
+    /// Set when the container says whether the texels are sRGB; legacy DDS
+    /// files don't.
+    pub color_space: Option<ColorSpace>,
+    /// Largest first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    fn from_levels(
+        width: u32,
+        height: u32,
+        format: BlockFormat,
+        color_space: Option<ColorSpace>,
+        count: usize,
+        mut level: impl FnMut(usize, usize) -> Option<Vec<u8>>,
+    ) -> Result<CompressedImage, String> {
+        if width == 0 || height == 0 {
+            return Err("empty image".to_owned());
+        }
+        let mut levels = vec![];
+        for i in 0..count.max(1) {
+            let (w, h) = ((width >> i).max(1), (height >> i).max(1));
+            levels.push(level(i, format.size(w, h)).ok_or("file is truncated")?);
+            if w == 1 && h == 1 {
+                break;
+            }
+        }
+        Ok(CompressedImage {
+            width,
+            height,
+            format,
+            color_space,
+            levels,
+        })
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    let value = u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().unwrap());
+    usize::try_from(value).ok()
+}
+
+/// DirectDraw Surface, with a legacy FourCC or DX10 header. Only the first
+/// image of arrays and cube maps is read.
+pub fn decode_dds(bytes: &[u8]) -> Result<CompressedImage, String> {
+    const MIPMAP_COUNT: u32 = 0x20000;
+    if bytes.get(..4) != Some(b"DDS ") {
+        return Err("not a DDS file".to_owned());
+    }
+    let field = |offset: usize| u32_at(bytes, offset).ok_or("file is truncated");
+    let height = field(12)?;
+    let width = field(16)?;
+    let levels = if field(8)? & MIPMAP_COUNT != 0 {
+        field(28)? as usize
+    } else {
+        1
+    };
+    let four_cc = bytes.get(84..88).ok_or("file is truncated")?;
+    let mut data = 128;
+    let (format, color_space) = match four_cc {
+        b"DXT1" => (BlockFormat::Bc1, None),
+        b"DXT2" | b"DXT3" => (BlockFormat::Bc2, None),
+        b"DXT4" | b"DXT5" => (BlockFormat::Bc3, None),
+        b"ATI1" | b"BC4U" => (BlockFormat::Bc4 { signed: false }, None),
+        b"BC4S" => (BlockFormat::Bc4 { signed: true }, None),
+        b"ATI2" | b"BC5U" => (BlockFormat::Bc5 { signed: false }, None),
+        b"BC5S" => (BlockFormat::Bc5 { signed: true }, None),
+        b"DX10" => {
+            data = 148;
+            let srgb = Some(ColorSpace::Srgb);
+            let linear = Some(ColorSpace::Linear);
+            // DXGI_FORMAT values; typeless ones are read as UNORM.
+            match field(128)? {
+                70 | 71 => (BlockFormat::Bc1, linear),
+                72 => (BlockFormat::Bc1, srgb),
+                73 | 74 => (BlockFormat::Bc2, linear),
+                75 => (BlockFormat::Bc2, srgb),
+                76 | 77 => (BlockFormat::Bc3, linear),
+                78 => (BlockFormat::Bc3, srgb),
+                79 | 80 => (BlockFormat::Bc4 { signed: false }, linear),
+                81 => (BlockFormat::Bc4 { signed: true }, linear),
+                82 | 83 => (BlockFormat::Bc5 { signed: false }, linear),
+                84 => (BlockFormat::Bc5 { signed: true }, linear),
+                94 | 95 => (BlockFormat::Bc6h { signed: false }, linear),
+                96 => (BlockFormat::Bc6h { signed: true }, linear),
+                97 | 98 => (BlockFormat::Bc7, linear),
+                99 => (BlockFormat::Bc7, srgb),
+                format => return Err(format!("unsupported DXGI format {}", format)),
+            }
+        }
+        _ => {
+            return Err(format!(
+                "unsupported DDS format {}",
+                String::from_utf8_lossy(four_cc)
+            ))
+        }
+    };
+    let mut offset: usize = data;
+    CompressedImage::from_levels(width, height, format, color_space, levels, |_, size| {
+        let end = offset.checked_add(size)?;
+        let level = bytes.get(offset..end)?.to_vec();
+        offset = end;
+        Some(level)
+    })
+}
+
+/// Khronos KTX 2.0 with a BCn `vkFormat`. Supercompressed files (Basis,
+/// Zstandard) aren't supported. Only the first image of arrays and cube
+/// maps is read.
+pub fn decode_ktx2(bytes: &[u8]) -> Result<CompressedImage, String> {
+    const IDENTIFIER: [u8; 12] = [
+        0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+    ];
+    if bytes.get(..12) != Some(&IDENTIFIER[..]) {
+        return Err("not a KTX2 file".to_owned());
+    }
+    let field = |offset: usize| u32_at(bytes, offset).ok_or("file is truncated");
+    let srgb = Some(ColorSpace::Srgb);
+    let linear = Some(ColorSpace::Linear);
+    let (format, color_space) = match field(12)? {
+        131 | 133 => (BlockFormat::Bc1, linear),
+        132 | 134 => (BlockFormat::Bc1, srgb),
+        135 => (BlockFormat::Bc2, linear),
+        136 => (BlockFormat::Bc2, srgb),
+        137 => (BlockFormat::Bc3, linear),
+        138 => (BlockFormat::Bc3, srgb),
+        139 => (BlockFormat::Bc4 { signed: false }, linear),
+        140 => (BlockFormat::Bc4 { signed: true }, linear),
+        141 => (BlockFormat::Bc5 { signed: false }, linear),
+        142 => (BlockFormat::Bc5 { signed: true }, linear),
+        143 => (BlockFormat::Bc6h { signed: false }, linear),
+        144 => (BlockFormat::Bc6h { signed: true }, linear),
+        145 => (BlockFormat::Bc7, linear),
+        146 => (BlockFormat::Bc7, srgb),
+        0 => return Err("Basis Universal KTX2 files are not supported".to_owned()),
+        format => return Err(format!("unsupported vkFormat {}", format)),
+    };
+    if field(44)? != 0 {
+        return Err("supercompressed KTX2 files are not supported".to_owned());
+    }
+    let (width, height) = (field(20)?, field(24)?);
+    let levels = field(40)? as usize;
+    CompressedImage::from_levels(width, height, format, color_space, levels, |i, size| {
+        // Level index: offset, length and uncompressed length per level.
+        let offset = u64_at(bytes, 80 + i * 24)?;
+        Some(bytes.get(offset..offset.checked_add(size)?)?.to_vec())
+    })
+}
+
+const GL_NUM_EXTENSIONS: u32 = 0x821D;
+const GL_TEXTURE_BINDING_2D: u32 = 0x8069;
+const GL_ACTIVE_TEXTURE: u32 = 0x84E0;
+
+/// OpenGL internal format for `format`, if the driver can sample it.
+fn gl_format(format: BlockFormat, color_space: ColorSpace) -> Option<u32> {
+    let extensions: Vec<String> = unsafe {
+        let mut count = 0;
+        gl::glGetIntegerv(GL_NUM_EXTENSIONS, &mut count);
+        (0..count.max(0) as u32)
+            .filter_map(|i| {
+                let name = gl::glGetStringi(gl::GL_EXTENSIONS, i);
+                (!name.is_null()).then(|| CStr::from_ptr(name as _).to_string_lossy().into_owned())
+            })
+            .collect()
+    };
+    let has = |name: &str| extensions.iter().any(|e| e == name);
+    let srgb = color_space == ColorSpace::Srgb;
+    let s3tc = has("GL_EXT_texture_compression_s3tc")
+        && (!srgb || has("GL_EXT_texture_sRGB") || has("GL_EXT_texture_compression_s3tc_srgb"));
+    let bptc = has("GL_ARB_texture_compression_bptc");
+    match format {
+        BlockFormat::Bc1 if s3tc => Some(if srgb { 0x8C4D } else { 0x83F1 }),
+        BlockFormat::Bc2 if s3tc => Some(if srgb { 0x8C4E } else { 0x83F2 }),
+        BlockFormat::Bc3 if s3tc => Some(if srgb { 0x8C4F } else { 0x83F3 }),
+        // RGTC is core since OpenGL 3.0.
+        BlockFormat::Bc4 { signed } => Some(if signed { 0x8DBC } else { 0x8DBB }),
+        BlockFormat::Bc5 { signed } => Some(if signed { 0x8DBE } else { 0x8DBD }),
+        BlockFormat::Bc6h { signed } if bptc => Some(if signed { 0x8E8E } else { 0x8E8F }),
+        BlockFormat::Bc7 if bptc => Some(if srgb { 0x8E8D } else { 0x8E8C }),
+        _ => None,
+    }
+}
+
+/// Uploads the image as-is where the GPU supports its format, and decodes
+/// the top level on the CPU otherwise. `color_space` applies if the file
+/// doesn't say. Either way shaders sample linear values.
+pub fn upload(
+    ctx: &mut dyn RenderingBackend,
+    image: &CompressedImage,
+    color_space: ColorSpace,
+) -> TextureId {
+    let color_space = image.color_space.unwrap_or(color_space);
+    if ctx.info().backend == Backend::OpenGl {
+        if let Some(internal_format) = gl_format(image.format, color_space) {
+            if let Some(texture) = upload_compressed(ctx, image, internal_format) {
+                return texture;
+            }
+        }
+    }
+    println!(
+        "{:?} textures are not supported by the GPU, decoding on the CPU",
+        image.format
+    );
+    decode(ctx, image, color_space)
+}
+
+/// Replaces the storage of a fresh miniquad texture with the compressed
+/// levels, so the rest of the renderer can use it like any other.
+/// miniquad still believes it is RGBA8, and so does the stats overlay.
+fn upload_compressed(
+    ctx: &mut dyn RenderingBackend,
+    image: &CompressedImage,
+    internal_format: u32,
+) -> Option<TextureId> {
+    let texture = ctx.new_texture(
+        TextureAccess::Static,
+        TextureSource::Empty,
+        TextureParams {
+            width: image.width,
+            height: image.height,
+            format: TextureFormat::RGBA8,
+            ..Default::default()
+        },
+    );
+    // Only Apple targets have other kinds of id.
+    #[allow(irrefutable_let_patterns)]
+    let RawId::OpenGl(raw) = (unsafe { ctx.texture_raw_id(texture) }) else {
+        ctx.delete_texture(texture);
+        return None;
+    };
+    let ok = unsafe {
+        // Leave the bindings as miniquad's state cache remembers them.
+        let (mut active, mut bound) = (0, 0);
+        gl::glGetIntegerv(GL_ACTIVE_TEXTURE, &mut active);
+        gl::glActiveTexture(gl::GL_TEXTURE0);
+        gl::glGetIntegerv(GL_TEXTURE_BINDING_2D, &mut bound);
+        while gl::glGetError() != gl::GL_NO_ERROR {}
+
+        gl::glBindTexture(gl::GL_TEXTURE_2D, raw);
+        for (i, level) in image.levels.iter().enumerate() {
+            gl::glCompressedTexImage2D(
+                gl::GL_TEXTURE_2D,
+                i as i32,
+                internal_format,
+                (image.width >> i).max(1) as i32,
+                (image.height >> i).max(1) as i32,
+                0,
+                level.len() as i32,
+                level.as_ptr() as *const _,
+            );
+        }
+        gl::glTexParameteri(
+            gl::GL_TEXTURE_2D,
+            gl::GL_TEXTURE_MAX_LEVEL,
+            image.levels.len() as i32 - 1,
+        );
+        let ok = gl::glGetError() == gl::GL_NO_ERROR;
+
+        gl::glBindTexture(gl::GL_TEXTURE_2D, bound as u32);
+        gl::glActiveTexture(active as u32);
+        ok
+    };
+    if !ok {
+        ctx.delete_texture(texture);
+        return None;
+    }
+    if image.levels.len() > 1 {
+        ctx.texture_set_min_filter(texture, FilterMode::Linear, MipmapFilterMode::Linear);
+    }
+    Some(texture)
+}
+
+/// Decodes the top level. Channels missing from the format read as they
+/// would from the GPU: 0 for color, 1 for alpha.
+fn decode(
+    ctx: &mut dyn RenderingBackend,
+    image: &CompressedImage,
+    color_space: ColorSpace,
+) -> TextureId {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let blocks = image.levels[0].chunks_exact(image.format.block_bytes());
+    let blocks_wide = width.div_ceil(4);
+
+    if let BlockFormat::Bc6h { signed } = image.format {
+        const HALF_ONE: u16 = 0x3C00;
+        let mut rgba = vec![HALF_ONE; width * height * 4];
+        let mut block = [0; 4 * 4 * 3];
+        for (i, compressed) in blocks.enumerate() {
+            bcdec_rs::bc6h_half(compressed, &mut block, 4 * 3, signed);
+            copy_block(i, blocks_wide, width, height, |x, y, texel| {
+                let dst = (y * width + x) * 4;
+                rgba[dst..dst + 3].copy_from_slice(&block[texel * 3..texel * 3 + 3]);
+            });
+        }
+        let bytes: Vec<u8> = rgba.iter().flat_map(|h| h.to_ne_bytes()).collect();
+        return ctx.new_texture_from_data_and_format(
+            &bytes,
+            TextureParams {
+                width: image.width,
+                height: image.height,
+                format: TextureFormat::RGBA16F,
+                ..Default::default()
+            },
+        );
+    }
+
+    let mut rgba = vec![0; width * height * 4];
+    let mut block = [0; 4 * 4 * 4];
+    for (i, compressed) in blocks.enumerate() {
+        match image.format {
+            BlockFormat::Bc1 => bcdec_rs::bc1(compressed, &mut block, 4 * 4),
+            BlockFormat::Bc2 => bcdec_rs::bc2(compressed, &mut block, 4 * 4),
+            BlockFormat::Bc3 => bcdec_rs::bc3(compressed, &mut block, 4 * 4),
+            BlockFormat::Bc7 => bcdec_rs::bc7(compressed, &mut block, 4 * 4),
+            BlockFormat::Bc4 { signed } => bcdec_rs::bc4(compressed, &mut block, 4, signed),
+            BlockFormat::Bc5 { signed } => bcdec_rs::bc5(compressed, &mut block, 4 * 2, signed),
+            BlockFormat::Bc6h { .. } => unreachable!(),
+        }
+        let channels = match image.format {
+            BlockFormat::Bc4 { .. } => 1,
+            BlockFormat::Bc5 { .. } => 2,
+            _ => 4,
+        };
+        copy_block(i, blocks_wide, width, height, |x, y, texel| {
+            let dst = (y * width + x) * 4;
+            rgba[dst..dst + channels]
+                .copy_from_slice(&block[texel * channels..(texel + 1) * channels]);
+            if channels < 4 {
+                rgba[dst + 3] = 255;
+            }
+        });
+    }
+    let image = Image {
+        width: image.width,
+        height: image.height,
+        rgba,
+    };
+    texture::upload(ctx, image, color_space)
+}
+
+/// Calls `f(x, y, texel)` for each texel of block `i` inside the image.
+fn copy_block(
+    i: usize,
+    blocks_wide: usize,
+    width: usize,
+    height: usize,
+    mut f: impl FnMut(usize, usize, usize),
+) {
+    let (bx, by) = (i % blocks_wide * 4, i / blocks_wide * 4);
+    for ty in 0..4 {
+        for tx in 0..4 {
+            if bx + tx < width && by + ty < height {
+                f(bx + tx, by + ty, ty * 4 + tx);
+            }
+        }
+    }
+}
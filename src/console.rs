@@ -0,0 +1,157 @@
+use crate::text::TextRenderer;
+
+/// Runs a command against `T` with the words following its name. The `Ok`
+/// message, if not empty, and the `Err` message are shown in the console.
+pub type Handler<T> = fn(&mut T, &[&str]) -> Result<String, String>;
+
+pub struct Command<T> {
+    pub name: &'static str,
+    /// Argument synopsis, e.g. `<prefab>`.
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub handler: Handler<T>,
+}
+
+/// Lines kept in the scrollback.
+const SCROLLBACK: usize = 200;
+/// Fraction of the screen the console covers when open.
+const HEIGHT: f32 = 0.4;
+
+/// Quake-style drop-down console. Commands are registered by whoever owns
+/// the state they act on; the console itself only edits and dispatches
+/// lines.
+pub struct Console<T> {
+    pub open: bool,
+    input: String,
+    log: Vec<(String, [f32; 4])>,
+    history: Vec<String>,
+    /// Position while browsing `history`; equal to its length otherwise.
+    history_pos: usize,
+    commands: Vec<Command<T>>,
+}
+
+const INPUT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const OUTPUT_COLOR: [f32; 4] = [0.75, 0.75, 0.75, 1.0];
+const ERROR_COLOR: [f32; 4] = [1.0, 0.45, 0.4, 1.0];
+
+impl<T> Console<T> {
+    pub fn new() -> Console<T> {
+        Console {
+            open: false,
+            input: String::new(),
+            log: vec![],
+            history: vec![],
+            history_pos: 0,
+            commands: vec![],
+        }
+    }
+
+    pub fn register(&mut self, command: Command<T>) {
+        self.commands.retain(|c| c.name != command.name);
+        self.commands.push(command);
+        self.commands.sort_by_key(|c| c.name);
+    }
+
+    pub fn commands(&self) -> &[Command<T>] {
+        &self.commands
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        // The toggle key arrives as a character too.
+        if c != '`' && c != '~' && !c.is_control() {
+            self.input.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+
+    /// Steps through previously submitted lines; `back` goes further into
+    /// the past.
+    pub fn browse_history(&mut self, back: bool) {
+        if back {
+            self.history_pos = self.history_pos.saturating_sub(1);
+        } else {
+            self.history_pos = (self.history_pos + 1).min(self.history.len());
+        }
+        self.input = self
+            .history
+            .get(self.history_pos)
+            .cloned()
+            .unwrap_or_default();
+    }
+
+    /// Takes the current input line and looks up its command. Returns the
+    /// handler and the whole line; the caller runs the handler on the
+    /// line's words, then hands the result to `print_result`.
+    pub fn submit(&mut self) -> Option<(Handler<T>, String)> {
+        let line = std::mem::take(&mut self.input);
+        let name = line.split_whitespace().next()?.to_owned();
+        self.print(format!("> {}", line), INPUT_COLOR);
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.history_pos = self.history.len();
+
+        match self.commands.iter().find(|c| c.name == name) {
+            Some(command) => Some((command.handler, line)),
+            None => {
+                self.print(
+                    format!("Unknown command {:?}, try \"help\"", name),
+                    ERROR_COLOR,
+                );
+                None
+            }
+        }
+    }
+
+    pub fn print_result(&mut self, result: Result<String, String>) {
+        match result {
+            Ok(message) => {
+                for line in message.lines() {
+                    self.print(line.to_owned(), OUTPUT_COLOR);
+                }
+            }
+            Err(message) => self.print(message, ERROR_COLOR),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.log.clear();
+    }
+
+    fn print(&mut self, line: String, color: [f32; 4]) {
+        self.log.push((line, color));
+        if self.log.len() > SCROLLBACK {
+            self.log.remove(0);
+        }
+    }
+
+    /// Queues the console's background, scrollback and input line.
+    pub fn draw(&self, text: &mut TextRenderer) {
+        if !self.open {
+            return;
+        }
+        let (width, height) = miniquad::window::screen_size();
+        let bottom = (height * HEIGHT).floor();
+        let line = text.line_height();
+        let margin = text.scale * 2.0;
+        text.rect(0.0, 0.0, width, bottom, [0.05, 0.05, 0.08, 0.85]);
+        text.rect(0.0, bottom, width, text.scale, [0.4, 0.4, 0.5, 1.0]);
+
+        let mut y = bottom - line;
+        text.print(margin, y, INPUT_COLOR, &format!("> {}_", self.input));
+        for (entry, color) in self.log.iter().rev() {
+            y -= line;
+            if y < 0.0 {
+                break;
+            }
+            text.print(margin, y, *color, entry);
+        }
+    }
+}
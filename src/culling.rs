@@ -0,0 +1,60 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Vector3, Vector4};
+use rayon::prelude::*;
+
+use crate::mesh::MeshLibrary;
+use crate::scene::Instance;
+
+/// The six planes bounding what a camera sees, pointing inwards.
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix. They are left
+    /// unnormalized, which is enough to tell sides apart and keeps the far
+    /// plane of an infinite projection (all zeros but w) from dividing by
+    /// zero.
+    pub fn new(view_proj: Matrix4<f32>) -> Frustum {
+        let m = view_proj.transpose();
+        Frustum {
+            planes: [
+                m.w + m.x,
+                m.w - m.x,
+                m.w + m.y,
+                m.w - m.y,
+                m.w + m.z,
+                m.w - m.z,
+            ],
+        }
+    }
+
+    /// Whether a box given in local space by its corners, placed by
+    /// `world`, is at least partly inside. Conservative: boxes near a
+    /// corner of the frustum may pass without being visible.
+    pub fn intersects(
+        &self,
+        (min, max): (Vector3<f32>, Vector3<f32>),
+        world: Matrix4<f32>,
+    ) -> bool {
+        let center = (min + max) * 0.5;
+        let half = (max - min) * 0.5;
+        let center = (world * center.extend(1.0)).truncate();
+        // Extents of the transformed box along the world axes.
+        let abs = |v: Vector4<f32>| v.truncate().map(f32::abs);
+        let extent = abs(world.x) * half.x + abs(world.y) * half.y + abs(world.z) * half.z;
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let radius = normal.map(f32::abs).dot(extent);
+            normal.dot(center) + plane.w + radius >= 0.0
+        })
+    }
+}
+
+/// The instances whose bounds touch the frustum, in their original order.
+pub fn cull(frustum: &Frustum, instances: &[Instance], meshes: &MeshLibrary) -> Vec<Instance> {
+    instances
+        .par_iter()
+        .filter(|instance| frustum.intersects(meshes.get(instance.mesh).bounds, instance.world))
+        .copied()
+        .collect()
+}
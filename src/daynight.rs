@@ -0,0 +1,74 @@
+use std::f32::consts::TAU;
+
+use cgmath::{vec3, InnerSpace, Vector3};
+
+use crate::light::Lighting;
+
+/// Tilt of the sun's path away from straight overhead, towards +Z.
+const SUN_TILT: f32 = 0.5;
+
+/// Drives the sun, ambient light and fog from a time of day.
+pub struct DayNight {
+    /// Fraction of a day: 0 is midnight, 0.25 sunrise, 0.5 noon.
+    pub time_of_day: f32,
+    /// Real seconds for a full day.
+    pub cycle_length: f32,
+    pub running: bool,
+}
+
+impl Default for DayNight {
+    fn default() -> DayNight {
+        DayNight {
+            time_of_day: 0.35,
+            cycle_length: 120.0,
+            running: true,
+        }
+    }
+}
+
+impl DayNight {
+    pub fn advance(&mut self, seconds: f32) {
+        if self.running {
+            self.scrub(seconds / self.cycle_length);
+        }
+    }
+
+    /// Moves the clock by a fraction of a day, in either direction.
+    pub fn scrub(&mut self, days: f32) {
+        self.time_of_day = (self.time_of_day + days).rem_euclid(1.0);
+    }
+
+    pub fn apply(&self, lighting: &mut Lighting) {
+        let angle = (self.time_of_day - 0.25) * TAU;
+        let direction = vec3(
+            angle.cos(),
+            angle.sin() * SUN_TILT.cos(),
+            angle.sin() * SUN_TILT.sin(),
+        )
+        .normalize();
+        let elevation = direction.y;
+
+        // Reddens close to the horizon, where light crosses more atmosphere.
+        let warmth = 1.0 - smoothstep(0.0, 0.4, elevation);
+        let day = smoothstep(-0.1, 0.15, elevation);
+
+        lighting.sun.direction = direction;
+        lighting.sun.color = lerp(vec3(1.0, 0.95, 0.85), vec3(1.0, 0.45, 0.15), warmth);
+        lighting.sun.intensity = smoothstep(-0.05, 0.1, elevation);
+        lighting.ambient = lerp(vec3(0.01, 0.012, 0.03), vec3(0.12, 0.15, 0.2), day);
+        lighting.fog_color = lerp(
+            vec3(0.005, 0.007, 0.015),
+            lerp(vec3(0.5, 0.6, 0.75), vec3(0.7, 0.45, 0.3), warmth),
+            day,
+        );
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: Vector3<f32>, b: Vector3<f32>, t: f32) -> Vector3<f32> {
+    a + (b - a) * t
+}
@@ -0,0 +1,125 @@
+use miniquad::KeyCode;
+
+use crate::palette::Palette;
+use crate::text::TextRenderer;
+
+/// Handle to a registered flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebugFlag(usize);
+
+struct Entry {
+    name: &'static str,
+    key: Option<KeyCode>,
+    enabled: bool,
+}
+
+/// Central registry of debug views. Subsystems register the flags they
+/// honor and check them by handle; toggling (keys, console) and the
+/// on-screen list are handled here for all of them.
+#[derive(Default)]
+pub struct DebugFlags {
+    entries: Vec<Entry>,
+}
+
+impl DebugFlags {
+    /// Registers a flag, off by default. `key` toggles it.
+    pub fn register(&mut self, name: &'static str, key: Option<KeyCode>) -> DebugFlag {
+        assert!(
+            self.find(name).is_none(),
+            "debug flag {} registered twice",
+            name
+        );
+        self.entries.push(Entry {
+            name,
+            key,
+            enabled: false,
+        });
+        DebugFlag(self.entries.len() - 1)
+    }
+
+    pub fn enabled(&self, flag: DebugFlag) -> bool {
+        self.entries[flag.0].enabled
+    }
+
+    pub fn set(&mut self, flag: DebugFlag, enabled: bool) {
+        self.entries[flag.0].enabled = enabled;
+    }
+
+    pub fn find(&self, name: &str) -> Option<DebugFlag> {
+        self.entries
+            .iter()
+            .position(|e| e.name == name)
+            .map(DebugFlag)
+    }
+
+    pub fn name(&self, flag: DebugFlag) -> &'static str {
+        self.entries[flag.0].name
+    }
+
+    pub fn key(&self, flag: DebugFlag) -> Option<KeyCode> {
+        self.entries[flag.0].key
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = DebugFlag> {
+        (0..self.entries.len()).map(DebugFlag)
+    }
+
+    /// Toggles the flag bound to `key`, if any.
+    pub fn handle_key(&mut self, key: KeyCode) -> Option<DebugFlag> {
+        let i = self.entries.iter().position(|e| e.key == Some(key))?;
+        let entry = &mut self.entries[i];
+        entry.enabled = !entry.enabled;
+        println!(
+            "Debug {}: {}",
+            entry.name,
+            if entry.enabled { "on" } else { "off" }
+        );
+        Some(DebugFlag(i))
+    }
+
+    /// Lists the active flags down the top right corner.
+    pub fn draw(&self, text: &mut TextRenderer, palette: &Palette) {
+        let (width, _) = miniquad::window::screen_size();
+        let margin = text.scale * 2.0;
+        let mut y = margin;
+        for entry in self.entries.iter().filter(|e| e.enabled) {
+            let label = match entry.key {
+                Some(key) => format!("{:?} {}", key, entry.name),
+                None => entry.name.to_owned(),
+            };
+            let x = width - margin - label.len() as f32 * text.char_width();
+            text.print(x, y, palette.debug_text, &label);
+            y += text.line_height();
+        }
+    }
+}
+
+/// The stage's built-in debug views.
+pub struct DebugViews {
+    /// Draw triangle edges only.
+    pub wireframe: DebugFlag,
+    /// World-space bounding box of every instance.
+    pub bounds: DebugFlag,
+    /// Vertex normals as short lines.
+    pub normals: DebugFlag,
+    /// Outline of the culling camera's view volume.
+    pub frustum: DebugFlag,
+    /// Stops the culling camera from following the view, so the frozen
+    /// frustum can be inspected from outside.
+    pub freeze_culling: DebugFlag,
+    /// Draw calls, triangles and memory of the last frame.
+    pub stats: DebugFlag,
+}
+
+impl DebugViews {
+    pub fn register(flags: &mut DebugFlags) -> DebugViews {
+        DebugViews {
+            wireframe: flags.register("wireframe", Some(KeyCode::F2)),
+            bounds: flags.register("bounds", Some(KeyCode::F3)),
+            normals: flags.register("normals", Some(KeyCode::F4)),
+            frustum: flags.register("frustum", Some(KeyCode::F5)),
+            freeze_culling: flags.register("freeze_culling", Some(KeyCode::F6)),
+            stats: flags.register("stats", Some(KeyCode::F7)),
+        }
+    }
+}
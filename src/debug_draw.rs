@@ -0,0 +1,167 @@
+use cgmath::{vec4, Matrix4, SquareMatrix, Vector3};
+use miniquad::*;
+
+use crate::dynamic_buffer::DynamicBuffer;
+use crate::gfx::compile_shader;
+
+/// Line vertices drawn in one batch; more are drawn in several.
+const MAX_VERTICES: usize = 1 << 16;
+
+#[repr(C)]
+struct Vertex {
+    pos: Vector3<f32>,
+    color: [f32; 4],
+}
+
+/// Immediate-mode world-space lines, collected during the frame and drawn
+/// depth-tested into the scene. Colors are linear, like the scene.
+pub struct DebugDraw {
+    pipeline: Pipeline,
+    bindings: Bindings,
+    vertices: DynamicBuffer<Vertex>,
+}
+
+impl DebugDraw {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> DebugDraw {
+        DebugDraw::with_depth_test(ctx, Comparison::LessOrEqual)
+    }
+
+    /// Lines drawn over everything, for handles that must stay visible.
+    pub fn overlay(ctx: &mut dyn RenderingBackend) -> DebugDraw {
+        DebugDraw::with_depth_test(ctx, Comparison::Always)
+    }
+
+    fn with_depth_test(ctx: &mut dyn RenderingBackend, depth_test: Comparison) -> DebugDraw {
+        let vertices = DynamicBuffer::new(ctx, 4096, MAX_VERTICES, 2);
+        let indices: Vec<u16> = (0..MAX_VERTICES).map(|i| i as u16).collect();
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+
+        let shader = compile_shader(ctx, shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float3),
+                VertexAttribute::new("in_color", VertexFormat::Float4),
+            ],
+            shader,
+            PipelineParams {
+                primitive_type: PrimitiveType::Lines,
+                depth_test,
+                ..Default::default()
+            },
+        );
+
+        DebugDraw {
+            pipeline,
+            bindings: Bindings {
+                vertex_buffers: vec![],
+                index_buffer,
+                images: vec![],
+            },
+            vertices,
+        }
+    }
+
+    pub fn line(&mut self, a: Vector3<f32>, b: Vector3<f32>, color: [f32; 4]) {
+        self.vertices.push(Vertex { pos: a, color });
+        self.vertices.push(Vertex { pos: b, color });
+    }
+
+    /// Outlines the box `min`..`max`, transformed by `world`.
+    pub fn aabb(
+        &mut self,
+        min: Vector3<f32>,
+        max: Vector3<f32>,
+        world: Matrix4<f32>,
+        color: [f32; 4],
+    ) {
+        let corners: [Vector3<f32>; 8] = std::array::from_fn(|i: usize| {
+            let pick = |bit: usize, lo: f32, hi: f32| if i & bit == 0 { lo } else { hi };
+            (world
+                * vec4(
+                    pick(1, min.x, max.x),
+                    pick(2, min.y, max.y),
+                    pick(4, min.z, max.z),
+                    1.0,
+                ))
+            .truncate()
+        });
+        self.box_edges(&corners, color);
+    }
+
+    /// Outlines the volume a view-projection matrix maps to clip space.
+    pub fn frustum(&mut self, view_proj: Matrix4<f32>, color: [f32; 4]) {
+        let Some(inverse) = view_proj.invert() else {
+            return;
+        };
+        let corners: [Vector3<f32>; 8] = std::array::from_fn(|i: usize| {
+            let ndc = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            let p = inverse * vec4(ndc(1), ndc(2), ndc(4), 1.0);
+            p.truncate() / p.w
+        });
+        self.box_edges(&corners, color);
+    }
+
+    fn box_edges(&mut self, corners: &[Vector3<f32>; 8], color: [f32; 4]) {
+        // Corner index bits are x, y, z; edges join corners one bit apart.
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// Draws and clears everything collected, into the current pass.
+    pub fn draw(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        view_proj: Matrix4<f32>,
+        log_depth_coef: f32,
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            view_proj,
+            log_depth_coef,
+        }));
+        let bindings = &mut self.bindings;
+        self.vertices.flush(ctx, |ctx, buffer, count| {
+            bindings.vertex_buffers = vec![buffer];
+            ctx.apply_bindings(bindings);
+            ctx.draw(0, count as i32, 1);
+        });
+    }
+}
+
+mod shader {
+    use cgmath::Matrix4;
+    use miniquad::*;
+
+    pub const VERTEX: &str = include_str!("shaders/debug.vert");
+
+    pub const FRAGMENT: &str = include_str!("shaders/debug.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("view_proj", UniformType::Mat4),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+            ] },
+        }
+    }
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub view_proj: Matrix4<f32>,
+        pub log_depth_coef: f32,
+    }
+}
@@ -0,0 +1,217 @@
+use cgmath::{Matrix4, SquareMatrix};
+use miniquad::*;
+
+use crate::atlas::{Atlas, Region};
+use crate::gfx::compile_shader;
+use crate::texture::{self, decode_png, ColorSpace, Image};
+
+const MAX_DECALS: usize = 64;
+/// Side of the atlas decal images share.
+const ATLAS_SIZE: u32 = 1024;
+
+/// Screen-space decals: every decal is an oriented unit box whose back faces
+/// are rasterized over the lit scene. Each covered pixel is reconstructed
+/// from the depth buffer and, if it falls inside the box, takes its color
+/// from the decal texture projected along the box's local Z axis.
+///
+/// Decal images are packed into an atlas, so runs of decals only bind it
+/// once; images too big for it, and compressed ones, keep a texture each.
+pub struct Decals {
+    pipeline: Pipeline,
+    bindings: Bindings,
+    /// Writes into the scene color without a depth attachment, so the depth
+    /// texture can be sampled at the same time.
+    pass: RenderPass,
+    atlas: Atlas,
+    /// Image new decals are placed with, and its width over height.
+    image: Region,
+    aspect: f32,
+    decals: Vec<(Matrix4<f32>, Region)>,
+}
+
+impl Decals {
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        scene_color: TextureId,
+        scene_depth: TextureId,
+    ) -> Decals {
+        #[rustfmt::skip]
+        let vertices: [[f32; 3]; 8] = [
+            [-0.5, -0.5, -0.5], [ 0.5, -0.5, -0.5], [ 0.5,  0.5, -0.5], [-0.5,  0.5, -0.5],
+            [-0.5, -0.5,  0.5], [ 0.5, -0.5,  0.5], [ 0.5,  0.5,  0.5], [-0.5,  0.5,  0.5],
+        ];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+
+        #[rustfmt::skip]
+        let indices: [u16; 36] = [
+            0, 2, 1, 0, 3, 2, // -z
+            4, 5, 6, 4, 6, 7, // +z
+            0, 1, 5, 0, 5, 4, // -y
+            3, 7, 6, 3, 6, 2, // +y
+            0, 4, 7, 0, 7, 3, // -x
+            1, 2, 6, 1, 6, 5, // +x
+        ];
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+
+        let mut atlas = Atlas::new(ctx, ATLAS_SIZE, ColorSpace::Srgb);
+        let target = decode_png(include_bytes!("../assets/decals/target.png"))
+            .expect("decal texture is a valid png");
+        let image = atlas
+            .add(ctx, &target)
+            .expect("decal texture fits the atlas");
+
+        let bindings = Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![scene_depth, atlas.texture],
+        };
+
+        let shader = compile_shader(ctx, shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("in_pos", VertexFormat::Float3)],
+            shader,
+            PipelineParams {
+                // Back faces stay visible while the camera is inside a box.
+                cull_face: CullFace::Front,
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                alpha_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Zero,
+                    BlendFactor::One,
+                )),
+                ..Default::default()
+            },
+        );
+
+        let pass = ctx.new_render_pass(scene_color, None);
+
+        Decals {
+            pipeline,
+            bindings,
+            pass,
+            atlas,
+            image,
+            aspect: 1.0,
+            decals: vec![],
+        }
+    }
+
+    /// Switches the image for decals placed from now on. Decals already
+    /// placed keep theirs.
+    pub fn set_image(&mut self, ctx: &mut dyn RenderingBackend, image: Image) {
+        self.aspect = image.width as f32 / image.height as f32;
+        self.image = match self.atlas.add(ctx, &image) {
+            Some(region) => region,
+            None => Region::whole(texture::upload(ctx, image, ColorSpace::Srgb)),
+        };
+    }
+
+    /// Like `set_image`, for a texture that is already uploaded.
+    pub fn set_texture(&mut self, texture: TextureId, aspect: f32) {
+        self.image = Region::whole(texture);
+        self.aspect = aspect;
+    }
+
+    /// Width over height of the current texture, for sizing new decals.
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    /// Adds a decal, dropping the oldest once the limit is reached.
+    pub fn place(&mut self, world: Matrix4<f32>) {
+        if self.decals.len() == MAX_DECALS {
+            self.decals.remove(0);
+        }
+        self.decals.push((world, self.image));
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        projection: Matrix4<f32>,
+        view: Matrix4<f32>,
+        log_depth_coef: f32,
+        width: f32,
+        height: f32,
+    ) {
+        if self.decals.is_empty() {
+            return;
+        }
+        let view_proj = projection * view;
+        let inv_view_proj = view_proj.invert().unwrap();
+
+        ctx.begin_pass(Some(self.pass), PassAction::Nothing);
+        ctx.apply_pipeline(&self.pipeline);
+        let mut bindings = self.bindings.clone();
+        let mut bound = None;
+        for &(world, image) in &self.decals {
+            if bound != Some(image.texture) {
+                bindings.images[1] = image.texture;
+                ctx.apply_bindings(&bindings);
+                bound = Some(image.texture);
+            }
+            ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+                view_proj,
+                inv_view_proj,
+                projection,
+                log_depth_coef,
+                world,
+                inv_world: world.invert().unwrap(),
+                screen_size: [width, height],
+                scale_offset: image.scale_offset,
+            }));
+            ctx.draw(0, 36, 1);
+        }
+        ctx.end_render_pass();
+    }
+}
+
+mod shader {
+    use cgmath::Matrix4;
+    use miniquad::*;
+
+    pub const VERTEX: &str = include_str!("shaders/decal.vert");
+
+    pub const FRAGMENT: &str = include_str!("shaders/decal.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["depth".to_owned(), "decal".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("view_proj", UniformType::Mat4),
+                UniformDesc::new("inv_view_proj", UniformType::Mat4),
+                UniformDesc::new("projection", UniformType::Mat4),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+                UniformDesc::new("world", UniformType::Mat4),
+                UniformDesc::new("inv_world", UniformType::Mat4),
+                UniformDesc::new("screen_size", UniformType::Float2),
+                UniformDesc::new("scale_offset", UniformType::Float4),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub view_proj: Matrix4<f32>,
+        pub inv_view_proj: Matrix4<f32>,
+        pub projection: Matrix4<f32>,
+        pub log_depth_coef: f32,
+        pub world: Matrix4<f32>,
+        pub inv_world: Matrix4<f32>,
+        pub screen_size: [f32; 2],
+        /// Where the image is in its texture, see `Region`.
+        pub scale_offset: [f32; 4],
+    }
+}
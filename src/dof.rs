@@ -0,0 +1,98 @@
+use miniquad::*;
+
+use crate::post::{Effect, Frame, Quad};
+
+/// Depth of field: pixels away from the focus distance are blurred with a
+/// separable gather, horizontally then vertically, with a radius given by
+/// their circle of confusion.
+pub struct DepthOfField {
+    pipeline: Pipeline,
+    pub enabled: bool,
+    /// Focus on whatever is under the crosshair instead of `focus_distance`.
+    pub autofocus: bool,
+    pub focus_distance: f32,
+    pub aperture: f32,
+    /// Largest blur radius, in pixels.
+    pub max_radius: f32,
+}
+
+impl DepthOfField {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> DepthOfField {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+        DepthOfField {
+            pipeline,
+            enabled: false,
+            autofocus: true,
+            focus_distance: 1.0,
+            aperture: 0.5,
+            max_radius: 8.0,
+        }
+    }
+}
+
+impl Effect for DepthOfField {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn passes(&self) -> usize {
+        2
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        frame: &Frame,
+        pass: usize,
+    ) {
+        let direction = if pass == 0 { [1.0, 0.0] } else { [0.0, 1.0] };
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source, frame.depth]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            direction,
+            near: frame.near,
+            far: frame.far,
+            log_depth_coef: frame.log_depth_coef,
+            focus_distance: self.focus_distance,
+            autofocus: if self.autofocus { 1.0 } else { 0.0 },
+            aperture: self.aperture,
+            max_radius: self.max_radius,
+        }));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/dof.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["source".to_owned(), "depth".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("direction", UniformType::Float2),
+                UniformDesc::new("near", UniformType::Float1),
+                UniformDesc::new("far", UniformType::Float1),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+                UniformDesc::new("focus_distance", UniformType::Float1),
+                UniformDesc::new("autofocus", UniformType::Float1),
+                UniformDesc::new("aperture", UniformType::Float1),
+                UniformDesc::new("max_radius", UniformType::Float1),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub direction: [f32; 2],
+        pub near: f32,
+        pub far: f32,
+        pub log_depth_coef: f32,
+        pub focus_distance: f32,
+        pub autofocus: f32,
+        pub aperture: f32,
+        pub max_radius: f32,
+    }
+}
@@ -0,0 +1,80 @@
+use miniquad::*;
+
+/// Vertices written on the CPU during a frame and streamed to the GPU when
+/// drawn, for immediate-mode renderers.
+///
+/// The GPU side is two `Stream` buffers used in turn, so an upload doesn't
+/// have to wait for the draw before it to finish reading. They grow to fit
+/// a frame's vertices up to `max_vertices`; anything beyond that wraps
+/// around, drawn in several batches that each start the buffer over.
+pub struct DynamicBuffer<V> {
+    vertices: Vec<V>,
+    buffers: [BufferId; 2],
+    next: usize,
+    /// Vertices each GPU buffer has room for.
+    capacity: usize,
+    max_vertices: usize,
+    /// Vertices per primitive, which a batch never splits.
+    group: usize,
+}
+
+impl<V> DynamicBuffer<V> {
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        capacity: usize,
+        max_vertices: usize,
+        group: usize,
+    ) -> DynamicBuffer<V> {
+        let capacity = capacity.min(max_vertices);
+        DynamicBuffer {
+            vertices: vec![],
+            buffers: [(); 2].map(|_| stream_buffer::<V>(ctx, capacity)),
+            next: 0,
+            capacity,
+            max_vertices,
+            group,
+        }
+    }
+
+    pub fn push(&mut self, vertex: V) {
+        self.vertices.push(vertex);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Uploads everything pushed since the last flush and calls `draw` once
+    /// per batch with the buffer to bind and how many vertices it holds.
+    /// Starts the next frame empty.
+    pub fn flush(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        mut draw: impl FnMut(&mut dyn RenderingBackend, BufferId, usize),
+    ) {
+        if self.vertices.len() > self.capacity && self.capacity < self.max_vertices {
+            let capacity = self.vertices.len().next_power_of_two().min(self.max_vertices);
+            for buffer in &mut self.buffers {
+                ctx.delete_buffer(*buffer);
+                *buffer = stream_buffer::<V>(ctx, capacity);
+            }
+            self.capacity = capacity;
+        }
+        let batch = (self.capacity / self.group * self.group).max(self.group);
+        for vertices in self.vertices.chunks(batch) {
+            let buffer = self.buffers[self.next];
+            self.next = 1 - self.next;
+            ctx.buffer_update(buffer, BufferSource::slice(vertices));
+            draw(ctx, buffer, vertices.len());
+        }
+        self.vertices.clear();
+    }
+}
+
+fn stream_buffer<V>(ctx: &mut dyn RenderingBackend, capacity: usize) -> BufferId {
+    ctx.new_buffer(
+        BufferType::VertexBuffer,
+        BufferUsage::Stream,
+        BufferSource::empty::<V>(capacity),
+    )
+}
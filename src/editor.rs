@@ -0,0 +1,333 @@
+use cgmath::{Deg, Euler, Quaternion};
+use egui::collapsing_header::CollapsingState;
+use egui_miniquad::EguiMq;
+use miniquad::RenderingBackend;
+
+use crate::history::{Edit, History};
+use crate::mesh::MeshLibrary;
+use crate::scene::{Cull, Material, NodeId, Scene, Transform, Winding};
+
+/// The state the panels show and edit.
+pub struct Context<'a> {
+    pub scene: &'a mut Scene,
+    pub meshes: &'a MeshLibrary,
+    pub history: &'a mut History,
+    pub selected: &'a mut Option<NodeId>,
+}
+
+/// A node's editable state before the inspector started changing it.
+struct Pending {
+    node: NodeId,
+    transform: Transform,
+    material: Material,
+}
+
+/// Edit mode's egui panels: the scene hierarchy on the left, where nodes
+/// can be selected and dragged onto each other to reparent them, and an
+/// inspector for the selected node on the right.
+pub struct EditorPanels {
+    egui: EguiMq,
+    /// Inspector changes are recorded as one edit per drag or text entry
+    /// rather than one per frame, so this holds the state from before the
+    /// widget was grabbed until it is let go.
+    pending: Option<Pending>,
+}
+
+impl EditorPanels {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> EditorPanels {
+        EditorPanels {
+            egui: EguiMq::new(ctx),
+            pending: None,
+        }
+    }
+
+    /// For forwarding input events.
+    pub fn input(&mut self) -> &mut EguiMq {
+        &mut self.egui
+    }
+
+    /// Whether the mouse is over a panel or busy with one of its widgets,
+    /// so clicks shouldn't reach the viewport.
+    pub fn wants_pointer(&self) -> bool {
+        self.egui.egui_ctx().wants_pointer_input()
+    }
+
+    /// Whether a text field has focus, so keys shouldn't reach the stage.
+    pub fn wants_keyboard(&self) -> bool {
+        self.egui.egui_ctx().wants_keyboard_input()
+    }
+
+    /// Lays out this frame's panels, applying whatever was edited.
+    pub fn run(&mut self, ctx: &mut dyn RenderingBackend, state: Context) {
+        let mut reparent = None;
+        let mut changed = false;
+        let pending = &mut self.pending;
+        self.egui.run(ctx, |_, egui_ctx| {
+            egui::SidePanel::left("hierarchy")
+                .default_width(180.0)
+                .show(egui_ctx, |ui| {
+                    ui.heading("Hierarchy");
+                    ui.separator();
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        let roots: Vec<NodeId> = state
+                            .scene
+                            .iter()
+                            .filter(|(_, node)| node.parent.is_none())
+                            .map(|(id, _)| id)
+                            .collect();
+                        for id in roots {
+                            tree(ui, state.scene, id, state.selected, &mut reparent);
+                        }
+                        // Dropping below the tree moves a node to the top level.
+                        let rest = ui.allocate_response(ui.available_size(), egui::Sense::hover());
+                        if let Some(dragged) = rest.dnd_release_payload::<NodeId>() {
+                            reparent = Some((*dragged, None));
+                        }
+                    });
+                });
+            egui::SidePanel::right("inspector")
+                .default_width(240.0)
+                .show(egui_ctx, |ui| {
+                    ui.heading("Inspector");
+                    ui.separator();
+                    let Some(id) = state.selected.filter(|&id| state.scene.get(id).is_some())
+                    else {
+                        ui.label("Nothing selected");
+                        return;
+                    };
+                    if pending.as_ref().is_some_and(|p| p.node != id) {
+                        commit(pending, state.scene, state.history);
+                    }
+                    let node = state.scene.get(id).unwrap();
+                    let before = Pending {
+                        node: id,
+                        transform: node.transform,
+                        material: node.material,
+                    };
+                    changed = inspector(ui, state.scene, state.meshes, id);
+                    if changed && pending.is_none() {
+                        *pending = Some(before);
+                    }
+                });
+        });
+
+        let ctx = self.egui.egui_ctx();
+        let released = !ctx.is_using_pointer() && ctx.memory(|m| m.focused().is_none());
+        if released && !changed {
+            commit(&mut self.pending, state.scene, state.history);
+        }
+        if let Some((node, parent)) = reparent {
+            let before = state.scene.get(node).and_then(|n| n.parent);
+            if before != parent && state.scene.set_parent(node, parent) {
+                state.history.record(Edit::Reparent {
+                    node,
+                    before,
+                    after: parent,
+                });
+            }
+        }
+    }
+
+    /// Paints the panels laid out by `run` over the default framebuffer.
+    pub fn draw(&mut self, ctx: &mut dyn RenderingBackend) {
+        self.egui.draw(ctx);
+    }
+}
+
+/// Records a finished inspector edit, if it changed anything.
+fn commit(pending: &mut Option<Pending>, scene: &Scene, history: &mut History) {
+    let Some(before) = pending.take() else {
+        return;
+    };
+    let Some(node) = scene.get(before.node) else {
+        return;
+    };
+    if node.transform != before.transform {
+        history.record(Edit::Transform {
+            node: before.node,
+            before: before.transform,
+            after: node.transform,
+        });
+    }
+    if node.material != before.material {
+        history.record(Edit::Material {
+            changes: vec![(before.node, before.material, node.material)],
+        });
+    }
+}
+
+/// One node of the hierarchy and, below it, its children.
+fn tree(
+    ui: &mut egui::Ui,
+    scene: &Scene,
+    id: NodeId,
+    selected: &mut Option<NodeId>,
+    reparent: &mut Option<(NodeId, Option<NodeId>)>,
+) {
+    let node = scene.get(id).unwrap();
+    if node.children.is_empty() {
+        label(ui, &node.name, id, selected, reparent);
+        return;
+    }
+    CollapsingState::load_with_default_open(ui.ctx(), egui::Id::new(("tree", id)), false)
+        .show_header(ui, |ui| label(ui, &node.name, id, selected, reparent))
+        .body(|ui| {
+            for &child in &node.children {
+                tree(ui, scene, child, selected, reparent);
+            }
+        });
+}
+
+/// A node's entry in the hierarchy, which selects it when clicked and
+/// takes it as the new parent when another node is dropped on it.
+fn label(
+    ui: &mut egui::Ui,
+    name: &str,
+    id: NodeId,
+    selected: &mut Option<NodeId>,
+    reparent: &mut Option<(NodeId, Option<NodeId>)>,
+) {
+    let egui::InnerResponse { inner, response } =
+        ui.dnd_drag_source(egui::Id::new(("node", id)), id, |ui| {
+            ui.selectable_label(*selected == Some(id), name)
+        });
+    if inner.clicked() {
+        *selected = Some(id);
+    }
+    if response.dnd_hover_payload::<NodeId>().is_some() {
+        ui.painter().rect_stroke(
+            response.rect,
+            2.0,
+            ui.visuals().selection.stroke,
+            egui::StrokeKind::Outside,
+        );
+    }
+    if let Some(dragged) = response.dnd_release_payload::<NodeId>() {
+        *reparent = Some((*dragged, Some(id)));
+    }
+}
+
+/// The selected node's properties. Returns whether any were changed.
+fn inspector(ui: &mut egui::Ui, scene: &mut Scene, meshes: &MeshLibrary, id: NodeId) -> bool {
+    let parent = match scene.get(id).unwrap().parent {
+        Some(parent) => scene.get(parent).unwrap().name.clone(),
+        None => "none".to_owned(),
+    };
+    let node = scene.get_mut(id).unwrap();
+    let mut changed = false;
+
+    ui.strong(&node.name);
+    ui.label(format!("Parent: {}", parent));
+    ui.label(format!("Children: {}", node.children.len()));
+
+    ui.collapsing("Transform", |ui| {
+        let transform = &mut node.transform;
+        let euler = Euler::from(transform.rotation);
+        let mut rotation = [
+            Deg::from(euler.x).0,
+            Deg::from(euler.y).0,
+            Deg::from(euler.z).0,
+        ];
+        egui::Grid::new("transform").num_columns(4).show(ui, |ui| {
+            changed |= vector_row(ui, "Position", transform.position.as_mut(), 0.01, "");
+            if vector_row(ui, "Rotation", &mut rotation, 1.0, "°") {
+                transform.rotation = Quaternion::from(Euler::new(
+                    Deg(rotation[0]),
+                    Deg(rotation[1]),
+                    Deg(rotation[2]),
+                ));
+                changed = true;
+            }
+            changed |= vector_row(ui, "Scale", transform.scale.as_mut(), 0.01, "");
+        });
+    })
+    .header_response
+    .context_menu(|ui| {
+        if ui.button("Reset").clicked() {
+            node.transform = Transform::default();
+            changed = true;
+            ui.close_menu();
+        }
+    });
+
+    if let Some(mesh) = node.mesh {
+        ui.collapsing("Mesh", |ui| {
+            let gpu = meshes.get(mesh);
+            ui.label(format!("Mesh: {}", meshes.name(mesh).unwrap_or("?")));
+            ui.label(format!("Vertices: {}", gpu.mesh.vertices.len()));
+            ui.label(format!("Triangles: {}", gpu.index_count() / 3));
+        });
+        ui.collapsing("Material", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Color");
+                let mut color: [f32; 4] = node.material.color.into();
+                if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                    node.material.color = color.into();
+                    changed = true;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Cull");
+                for (cull, name) in [
+                    (Cull::Back, "Back"),
+                    (Cull::Front, "Front"),
+                    (Cull::None, "None"),
+                ] {
+                    changed |= ui
+                        .radio_value(&mut node.material.cull, cull, name)
+                        .changed();
+                }
+            });
+            let mut clockwise = node.material.winding == Winding::Clockwise;
+            if ui.checkbox(&mut clockwise, "Clockwise winding").changed() {
+                node.material.winding = match clockwise {
+                    true => Winding::Clockwise,
+                    false => Winding::CounterClockwise,
+                };
+                changed = true;
+            }
+        });
+    }
+
+    // Playback isn't an edit, so none of this goes into the history.
+    if let Some(animation) = &mut node.animation {
+        ui.collapsing("Animation", |ui| {
+            ui.horizontal(|ui| {
+                let label = if animation.playing { "Pause" } else { "Play" };
+                if ui.button(label).clicked() {
+                    if animation.playing {
+                        animation.pause();
+                    } else {
+                        animation.play();
+                    }
+                }
+                ui.checkbox(&mut animation.looping, "Loop");
+            });
+            let duration = animation.clip.duration();
+            let slider = egui::Slider::new(&mut animation.time, 0.0..=duration).suffix(" s");
+            if ui.add(slider).changed() {
+                animation.clip.apply(animation.time, &mut node.transform);
+            }
+        });
+    }
+    changed
+}
+
+/// A labelled row of three drag values.
+fn vector_row(
+    ui: &mut egui::Ui,
+    label: &str,
+    values: &mut [f32; 3],
+    speed: f64,
+    suffix: &str,
+) -> bool {
+    ui.label(label);
+    let mut changed = false;
+    for value in values {
+        changed |= ui
+            .add(egui::DragValue::new(value).speed(speed).suffix(suffix))
+            .changed();
+    }
+    ui.end_row();
+    changed
+}
@@ -0,0 +1,556 @@
+use std::{f32::consts::PI, fs, io::Read, path::Path};
+
+use cgmath::{vec3, InnerSpace, Vector3, Zero};
+use miniquad::*;
+
+/// Cubemap faces are at most this many texels across.
+const MAX_FACE_SIZE: usize = 512;
+/// Largest finite half float.
+const HALF_MAX: f32 = 65504.0;
+
+/// A floating point image in linear RGB, rows top to bottom.
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<Vector3<f32>>,
+}
+
+/// Decodes a Radiance `.hdr` or OpenEXR `.exr` file by extension.
+pub fn load(path: &Path) -> Result<HdrImage, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let extension = path.extension().and_then(|e| e.to_str());
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("hdr") => decode_hdr(&bytes),
+        Some("exr") => decode_exr(&bytes),
+        _ => Err("not an .hdr or .exr file".to_owned()),
+    }
+}
+
+/// Radiance RGBE, flat or run-length encoded, in the usual `-Y h +X w`
+/// orientation.
+pub fn decode_hdr(bytes: &[u8]) -> Result<HdrImage, String> {
+    let mut pos = 0;
+    let mut line = || {
+        let end = bytes[pos..].iter().position(|&b| b == b'\n')? + pos;
+        let text = String::from_utf8_lossy(&bytes[pos..end]).into_owned();
+        pos = end + 1;
+        Some(text)
+    };
+    let magic = line().ok_or("file is truncated")?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err("not a Radiance HDR file".to_owned());
+    }
+    loop {
+        let header = line().ok_or("file is truncated")?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some(format) = header.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format {}", format));
+            }
+        }
+    }
+    let resolution = line().ok_or("file is truncated")?;
+    let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => (
+            w.parse::<usize>().map_err(|_| "bad width")?,
+            h.parse::<usize>().map_err(|_| "bad height")?,
+        ),
+        _ => return Err(format!("unsupported orientation {:?}", resolution)),
+    };
+
+    let mut data = &bytes[pos..];
+    let mut rgb = Vec::with_capacity(width * height);
+    let mut scanline = vec![0; width * 4];
+    for _ in 0..height {
+        data = read_scanline(data, &mut scanline).ok_or("file is truncated")?;
+        rgb.extend(scanline.chunks_exact(4).map(|p| {
+            if p[3] == 0 {
+                return Vector3::zero();
+            }
+            let scale = 2f32.powi(p[3] as i32 - 136);
+            vec3(p[0] as f32 + 0.5, p[1] as f32 + 0.5, p[2] as f32 + 0.5) * scale
+        }));
+    }
+    Ok(HdrImage {
+        width: width as u32,
+        height: height as u32,
+        rgb,
+    })
+}
+
+/// Reads one row of RGBE pixels, returning the rest of the data. New-style
+/// run-length encoded rows store each channel separately.
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [u8]) -> Option<&'a [u8]> {
+    let width = scanline.len() / 4;
+    let encoded = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && u16::from_be_bytes([data[2], data[3]]) as usize == width;
+    if !encoded {
+        scanline.copy_from_slice(data.get(..scanline.len())?);
+        return Some(&data[scanline.len()..]);
+    }
+    let mut data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first()?;
+            if count > 128 {
+                let run = (count - 128) as usize;
+                let (&value, rest) = rest.split_first()?;
+                for i in x..(x + run).min(width) {
+                    scanline[i * 4 + channel] = value;
+                }
+                x += run;
+                data = rest;
+            } else {
+                let count = (count as usize).max(1);
+                let values = rest.get(..count)?;
+                for (i, &value) in (x..width).zip(values) {
+                    scanline[i * 4 + channel] = value;
+                }
+                x += count;
+                data = &rest[count..];
+            }
+        }
+    }
+    Some(data)
+}
+
+/// Single-part scanline OpenEXR with half or float R, G and B (or Y)
+/// channels, uncompressed, RLE or zip compressed. Other channels are
+/// ignored.
+pub fn decode_exr(bytes: &[u8]) -> Result<HdrImage, String> {
+    if bytes.get(..4) != Some(&[0x76, 0x2f, 0x31, 0x01][..]) {
+        return Err("not an OpenEXR file".to_owned());
+    }
+    let flags = u32_at(bytes, 4).ok_or("file is truncated")?;
+    if flags & 0x1a00 != 0 {
+        return Err("tiled, deep and multi-part files are not supported".to_owned());
+    }
+
+    let mut pos = 8;
+    let mut channels = vec![];
+    let mut compression = None;
+    let mut window = None;
+    loop {
+        let name = c_string(bytes, &mut pos).ok_or("file is truncated")?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = c_string(bytes, &mut pos).ok_or("file is truncated")?;
+        let size = u32_at(bytes, pos).ok_or("file is truncated")? as usize;
+        let value = bytes
+            .get(pos + 4..pos + 4 + size)
+            .ok_or("file is truncated")?;
+        pos += 4 + size;
+        match name.as_str() {
+            "channels" => channels = exr_channels(value).ok_or("bad channel list")?,
+            "compression" => compression = value.first().copied(),
+            "dataWindow" => {
+                let corner = |i: usize| u32_at(value, i * 4).map(|v| v as i32);
+                window = Some((
+                    corner(0).ok_or("bad data window")?,
+                    corner(1).ok_or("bad data window")?,
+                    corner(2).ok_or("bad data window")?,
+                    corner(3).ok_or("bad data window")?,
+                ));
+            }
+            _ => {}
+        }
+    }
+    let (x_min, y_min, x_max, y_max) = window.ok_or("no data window")?;
+    if x_max < x_min || y_max < y_min {
+        return Err("empty image".to_owned());
+    }
+    let (width, height) = ((x_max - x_min + 1) as usize, (y_max - y_min + 1) as usize);
+    let lines_per_chunk = match compression {
+        Some(0..=2) => 1,
+        Some(3) => 16,
+        Some(other) => return Err(format!("unsupported compression {}", other)),
+        None => return Err("no compression attribute".to_owned()),
+    };
+    if channels.iter().any(|c| c.sampling != (1, 1)) {
+        return Err("subsampled channels are not supported".to_owned());
+    }
+    let target = |name: &str| match name.rsplit('.').next() {
+        Some("R") => Some(0..1),
+        Some("G") => Some(1..2),
+        Some("B") => Some(2..3),
+        Some("Y") => Some(0..3),
+        _ => None,
+    };
+    if !channels.iter().any(|c| target(&c.name).is_some()) {
+        return Err("no color channels".to_owned());
+    }
+    let line_bytes: usize = channels.iter().map(|c| c.kind.size() * width).sum();
+
+    let mut rgb = vec![Vector3::zero(); width * height];
+    let chunks = height.div_ceil(lines_per_chunk);
+    for chunk in 0..chunks {
+        let offset = u64_at(bytes, pos + chunk * 8).ok_or("file is truncated")?;
+        let y = u32_at(bytes, offset).ok_or("file is truncated")? as i32;
+        let size = u32_at(bytes, offset + 4).ok_or("file is truncated")? as usize;
+        let data = bytes
+            .get(offset + 8..offset + 8 + size)
+            .ok_or("file is truncated")?;
+        let first = y
+            .checked_sub(y_min)
+            .filter(|&row| (row as usize) < height)
+            .ok_or("chunk outside the data window")? as usize;
+        let lines = lines_per_chunk.min(height - first);
+        let expected = line_bytes * lines;
+        let data = match compression {
+            // Chunks that wouldn't get smaller are stored as they are.
+            _ if size == expected => data.to_vec(),
+            Some(1) => unpredict(&exr_rle(data, expected).ok_or("bad RLE data")?),
+            Some(2 | 3) => {
+                let mut inflated = Vec::with_capacity(expected);
+                flate2::read::ZlibDecoder::new(data)
+                    .read_to_end(&mut inflated)
+                    .map_err(|err| err.to_string())?;
+                unpredict(&inflated)
+            }
+            _ => data.to_vec(),
+        };
+        if data.len() < expected {
+            return Err("chunk is truncated".to_owned());
+        }
+
+        let mut values = data.as_slice();
+        for line in first..first + lines {
+            let row = &mut rgb[line * width..(line + 1) * width];
+            for channel in &channels {
+                let (samples, rest) = values.split_at(channel.kind.size() * width);
+                values = rest;
+                let Some(range) = target(&channel.name) else {
+                    continue;
+                };
+                for (pixel, sample) in row
+                    .iter_mut()
+                    .zip(samples.chunks_exact(channel.kind.size()))
+                {
+                    let value = channel.kind.read(sample);
+                    for c in range.clone() {
+                        pixel[c] = value;
+                    }
+                }
+            }
+        }
+    }
+    Ok(HdrImage {
+        width: width as u32,
+        height: height as u32,
+        rgb,
+    })
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ExrType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl ExrType {
+    fn size(self) -> usize {
+        match self {
+            ExrType::Half => 2,
+            _ => 4,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> f32 {
+        match self {
+            ExrType::Uint => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+            ExrType::Half => half_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])),
+            ExrType::Float => f32::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+}
+
+struct ExrChannel {
+    name: String,
+    kind: ExrType,
+    sampling: (u32, u32),
+}
+
+/// The `chlist` attribute, which the file keeps sorted by name, the order
+/// channels are stored in.
+fn exr_channels(value: &[u8]) -> Option<Vec<ExrChannel>> {
+    let mut channels = vec![];
+    let mut pos = 0;
+    loop {
+        let name = c_string(value, &mut pos)?;
+        if name.is_empty() {
+            return Some(channels);
+        }
+        let kind = match u32_at(value, pos)? {
+            0 => ExrType::Uint,
+            1 => ExrType::Half,
+            2 => ExrType::Float,
+            _ => return None,
+        };
+        let sampling = (u32_at(value, pos + 8)?, u32_at(value, pos + 12)?);
+        pos += 16;
+        channels.push(ExrChannel {
+            name,
+            kind,
+            sampling,
+        });
+    }
+}
+
+/// Expands EXR run-length encoding: a negative count is followed by that
+/// many literal bytes, any other by one byte repeated count + 1 times.
+fn exr_rle(mut data: &[u8], expected: usize) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(expected);
+    while let Some((&count, rest)) = data.split_first() {
+        let count = count as i8;
+        if count < 0 {
+            let literal = rest.get(..-(count as i32) as usize)?;
+            out.extend_from_slice(literal);
+            data = &rest[literal.len()..];
+        } else {
+            let (&value, rest) = rest.split_first()?;
+            out.extend(std::iter::repeat_n(value, count as usize + 1));
+            data = rest;
+        }
+    }
+    Some(out)
+}
+
+/// Undoes the delta predictor and byte split RLE and zip data is stored
+/// with.
+fn unpredict(data: &[u8]) -> Vec<u8> {
+    let mut deltas = data.to_vec();
+    for i in 1..deltas.len() {
+        deltas[i] = deltas[i - 1].wrapping_add(deltas[i]).wrapping_sub(128);
+    }
+    let (first, second) = deltas.split_at(deltas.len().div_ceil(2));
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in first.iter().enumerate() {
+        out.push(byte);
+        out.extend(second.get(i));
+    }
+    out
+}
+
+fn c_string(bytes: &[u8], pos: &mut usize) -> Option<String> {
+    let end = bytes.get(*pos..)?.iter().position(|&b| b == 0)? + *pos;
+    let text = String::from_utf8_lossy(&bytes[*pos..end]).into_owned();
+    *pos = end + 1;
+    Some(text)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    let value = u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().unwrap());
+    value.try_into().ok()
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent as i32 - 15),
+    }
+}
+
+/// Rounds to the nearest half float. Only meant for values already in
+/// 0..=`HALF_MAX`.
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent <= 0 {
+        if exponent < -10 {
+            return 0;
+        }
+        let mantissa = (mantissa | 0x80_0000) >> (1 - exponent);
+        return ((mantissa + 0x1000) >> 13) as u16;
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    (half + ((mantissa >> 12) & 1)) as u16
+}
+
+/// Six square faces in GL order: +X, -X, +Y, -Y, +Z, -Z.
+pub struct Cubemap {
+    pub size: usize,
+    pub faces: [Vec<Vector3<f32>>; 6],
+}
+
+impl Cubemap {
+    /// Resamples an equirectangular (latitude-longitude) panorama, whose
+    /// center looks down -Z and whose top row is straight up.
+    pub fn from_equirect(image: &HdrImage) -> Cubemap {
+        let size = (image.width as usize / 4).clamp(1, MAX_FACE_SIZE);
+        let faces = std::array::from_fn(|face| {
+            let mut texels = Vec::with_capacity(size * size);
+            for y in 0..size {
+                for x in 0..size {
+                    let dir = face_direction(face, size, x, y).normalize();
+                    let u = 0.5 + dir.x.atan2(-dir.z) / (2.0 * PI);
+                    let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+                    texels.push(sample_bilinear(image, u, v));
+                }
+            }
+            texels
+        });
+        Cubemap { size, faces }
+    }
+
+    /// Diffuse lighting from the whole environment as second-order
+    /// spherical harmonics, prescaled so that the basic shader gets
+    /// irradiance over pi for a normal, like `ambient`, by weighting
+    /// `sh_basis` with them.
+    pub fn irradiance(&self) -> [Vector3<f32>; 9] {
+        let mut sh = [Vector3::zero(); 9];
+        for (face, texels) in self.faces.iter().enumerate() {
+            for y in 0..self.size {
+                for x in 0..self.size {
+                    let dir = face_direction(face, self.size, x, y);
+                    // Texels nearer the corners cover less of the sphere.
+                    let texel = 2.0 / self.size as f32;
+                    let solid_angle = texel * texel / dir.magnitude2().powf(1.5);
+                    let radiance = texels[y * self.size + x] * solid_angle;
+                    for (c, basis) in sh.iter_mut().zip(sh_basis(dir.normalize())) {
+                        *c += radiance * basis;
+                    }
+                }
+            }
+        }
+        // Projecting and evaluating each multiply by the basis constant,
+        // and convolving with the cosine lobe scales each band.
+        const CONSTANT: [f32; 9] = [
+            0.282095, 0.488603, 0.488603, 0.488603, 1.092548, 1.092548, 0.315392, 1.092548,
+            0.546274,
+        ];
+        const BAND: [f32; 9] = [
+            1.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+        ];
+        for i in 0..9 {
+            sh[i] *= CONSTANT[i] * CONSTANT[i] * BAND[i];
+        }
+        sh
+    }
+}
+
+/// The polynomial part of the first nine real spherical harmonics.
+fn sh_basis(n: Vector3<f32>) -> [f32; 9] {
+    [
+        1.0,
+        n.y,
+        n.z,
+        n.x,
+        n.x * n.y,
+        n.y * n.z,
+        3.0 * n.z * n.z - 1.0,
+        n.x * n.z,
+        n.x * n.x - n.y * n.y,
+    ]
+}
+
+/// Unnormalized direction through the center of a face texel. Row 0 is
+/// the top of the face as GL samples it.
+fn face_direction(face: usize, size: usize, x: usize, y: usize) -> Vector3<f32> {
+    let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    match face {
+        0 => vec3(1.0, -t, -s),
+        1 => vec3(-1.0, -t, s),
+        2 => vec3(s, 1.0, t),
+        3 => vec3(s, -1.0, -t),
+        4 => vec3(s, -t, 1.0),
+        _ => vec3(-s, -t, -1.0),
+    }
+}
+
+/// Filters between texel centers, wrapping around horizontally.
+fn sample_bilinear(image: &HdrImage, u: f32, v: f32) -> Vector3<f32> {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (fx, fy) = (x - x.floor(), y - y.floor());
+    let x0 = (x.floor() as isize).rem_euclid(width as isize) as usize;
+    let x1 = (x0 + 1) % width;
+    let y0 = y as usize;
+    let y1 = (y0 + 1).min(height - 1);
+    let texel = |x: usize, y: usize| image.rgb[y * width + x];
+    let top = texel(x0, y0) * (1.0 - fx) + texel(x1, y0) * fx;
+    let bottom = texel(x0, y1) * (1.0 - fx) + texel(x1, y1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// A loaded environment map: the sky to draw and the light it casts.
+pub struct Environment {
+    pub cubemap: TextureId,
+    pub irradiance: [Vector3<f32>; 9],
+}
+
+impl Environment {
+    /// Uploads a cubemap made with `Cubemap::from_equirect`, which is the
+    /// slow part and can be done off the main thread.
+    pub fn new(ctx: &mut dyn RenderingBackend, cubemap: &Cubemap) -> Environment {
+        let faces: Vec<Vec<u8>> = cubemap
+            .faces
+            .iter()
+            .map(|texels| {
+                texels
+                    .iter()
+                    .flat_map(|c| [c.x, c.y, c.z, 1.0])
+                    .map(|v| {
+                        f32_to_half(if v.is_nan() {
+                            0.0
+                        } else {
+                            v.clamp(0.0, HALF_MAX)
+                        })
+                    })
+                    .flat_map(u16::to_ne_bytes)
+                    .collect()
+            })
+            .collect();
+        let levels: Vec<[&[u8]; 1]> = faces.iter().map(|face| [face.as_slice()]).collect();
+        let levels: Vec<&[&[u8]]> = levels.iter().map(|level| &level[..]).collect();
+        let texture = ctx.new_texture(
+            TextureAccess::Static,
+            TextureSource::Array(&levels),
+            TextureParams {
+                kind: TextureKind::CubeMap,
+                width: cubemap.size as u32,
+                height: cubemap.size as u32,
+                format: TextureFormat::RGBA16F,
+                wrap: TextureWrap::Clamp,
+                ..Default::default()
+            },
+        );
+        Environment {
+            cubemap: texture,
+            irradiance: cubemap.irradiance(),
+        }
+    }
+
+    pub fn delete(&self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_texture(self.cubemap);
+    }
+}
@@ -0,0 +1,108 @@
+use miniquad::*;
+
+use crate::post::{Effect, Frame, Quad};
+
+/// Darkens the image towards the corners.
+pub struct Vignette {
+    pipeline: Pipeline,
+    pub enabled: bool,
+    pub intensity: f32,
+}
+
+impl Vignette {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Vignette {
+        let pipeline = quad.pipeline(ctx, shader::VIGNETTE, shader::meta());
+        Vignette {
+            pipeline,
+            enabled: false,
+            intensity: 0.5,
+        }
+    }
+}
+
+impl Effect for Vignette {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        _frame: &Frame,
+        _pass: usize,
+    ) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            intensity: self.intensity,
+            time: 0.0,
+        }));
+        quad.draw(ctx);
+    }
+}
+
+/// Animated luminance noise, re-seeded every frame.
+pub struct Grain {
+    pipeline: Pipeline,
+    pub enabled: bool,
+    pub intensity: f32,
+}
+
+impl Grain {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Grain {
+        let pipeline = quad.pipeline(ctx, shader::GRAIN, shader::meta());
+        Grain {
+            pipeline,
+            enabled: false,
+            intensity: 0.05,
+        }
+    }
+}
+
+impl Effect for Grain {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        frame: &Frame,
+        _pass: usize,
+    ) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            intensity: self.intensity,
+            time: frame.time,
+        }));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const VIGNETTE: &str = include_str!("shaders/vignette.frag");
+
+    pub const GRAIN: &str = include_str!("shaders/grain.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["source".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("intensity", UniformType::Float1),
+                UniformDesc::new("time", UniformType::Float1),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub intensity: f32,
+        pub time: f32,
+    }
+}
@@ -0,0 +1,52 @@
+use miniquad::*;
+
+use crate::post::{Effect, Frame, Quad};
+
+/// Fast approximate anti-aliasing: blurs along the edge direction found
+/// from the luma gradient of the final image.
+pub struct Fxaa {
+    pipeline: Pipeline,
+    pub enabled: bool,
+}
+
+impl Fxaa {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Fxaa {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+        Fxaa {
+            pipeline,
+            enabled: false,
+        }
+    }
+}
+
+impl Effect for Fxaa {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        _frame: &Frame,
+        _pass: usize,
+    ) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source]));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/fxaa.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["source".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![] },
+        }
+    }
+}
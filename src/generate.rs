@@ -0,0 +1,165 @@
+use cgmath::{vec3, vec4, Deg, Quaternion, Rotation3};
+
+use crate::animation::{Animation, Clip, Track};
+use crate::color::linear_rgba;
+use crate::mesh::MeshLibrary;
+use crate::palette::Palette;
+use crate::prefab::PrefabLibrary;
+use crate::scene::{Material, Node, Scene, Transform};
+
+pub const DEFAULT_OBJECTS: usize = 1000;
+/// Ground area per object, in square units.
+const AREA_PER_OBJECT: f32 = 4.0;
+/// Height of the ground, matching the hand-made scenes.
+const GROUND: f32 = -0.5;
+
+/// A procedural scene: a square of ground scattered with props, the same
+/// for the same seed and object count.
+#[derive(Clone, Copy, Debug)]
+pub struct Generation {
+    pub seed: u64,
+    pub objects: usize,
+}
+
+impl Generation {
+    /// Parses `--seed <n> [objects]` from the command line.
+    pub fn from_args() -> Option<Generation> {
+        let args: Vec<String> = std::env::args().collect();
+        let i = args.iter().position(|arg| arg == "--seed")?;
+        let Some(seed) = args.get(i + 1).and_then(|arg| arg.parse().ok()) else {
+            println!("--seed needs a number");
+            return None;
+        };
+        let objects = args
+            .get(i + 2)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(DEFAULT_OBJECTS);
+        Some(Generation { seed, objects })
+    }
+
+    /// Adds the ground and the props to the scene. Most props are crates
+    /// of varied proportions; some are pillars, some spin and some are
+    /// see-through. Their colors come from `palette`.
+    pub fn populate(
+        &self,
+        scene: &mut Scene,
+        prefabs: &PrefabLibrary,
+        meshes: &MeshLibrary,
+        palette: &Palette,
+    ) {
+        let mut rng = Rng::new(self.seed);
+        let half = (self.objects as f32 * AREA_PER_OBJECT).sqrt() / 2.0;
+        let cube = meshes.find("cube");
+
+        let mut ground = Node::new("ground");
+        ground.mesh = meshes.find("plane");
+        ground.material = srgb(0.4, 0.4, 0.38, 1.0);
+        ground.transform = Transform {
+            position: vec3(0.0, GROUND, 0.0),
+            scale: vec3(half * 2.0, 1.0, half * 2.0),
+            ..Transform::default()
+        };
+        scene.add(ground, None);
+
+        for i in 0..self.objects {
+            let position = vec3(rng.range(-half, half), GROUND, rng.range(-half, half));
+            let rotation = Quaternion::from_angle_y(Deg(rng.range(0.0, 360.0)));
+            if rng.chance(0.1) {
+                let transform = Transform {
+                    position,
+                    rotation,
+                    ..Transform::default()
+                };
+                if let Err(err) =
+                    prefabs.instantiate("pillar", scene, meshes, Some(transform), None)
+                {
+                    println!("Could not place a pillar: {}", err);
+                }
+                continue;
+            }
+
+            let size = rng.range(0.2, 1.0);
+            let scale = vec3(
+                size * rng.range(0.5, 2.0),
+                size * rng.range(0.5, 2.0),
+                size * rng.range(0.5, 2.0),
+            );
+            let (r, g, b) = palette.props[rng.below(palette.props.len())];
+            let shade = rng.range(0.8, 1.2);
+            let alpha = if rng.chance(0.05) { 0.5 } else { 1.0 };
+            let mut node = Node::new(&format!("prop {}", i));
+            node.mesh = cube;
+            node.material = srgb(
+                (r * shade).min(1.0),
+                (g * shade).min(1.0),
+                (b * shade).min(1.0),
+                alpha,
+            );
+            node.transform = Transform {
+                position: position + vec3(0.0, scale.y / 2.0, 0.0),
+                rotation,
+                scale,
+            };
+            if rng.chance(0.05) {
+                let period = rng.range(2.0, 8.0);
+                let start = rng.range(0.0, 360.0);
+                let keys = (0..4)
+                    .map(|k| {
+                        let angle = Deg(start + k as f32 * 120.0);
+                        (k as f32 * period / 3.0, Quaternion::from_angle_y(angle))
+                    })
+                    .collect();
+                node.animation = Some(Animation::new(Clip {
+                    rotation: Track::new(keys),
+                    ..Clip::default()
+                }));
+            }
+            scene.add(node, None);
+        }
+        println!("Generated {} objects from seed {}", self.objects, self.seed);
+    }
+}
+
+fn srgb(r: f32, g: f32, b: f32, a: f32) -> Material {
+    Material {
+        color: linear_rgba(vec4(r, g, b, a)),
+        ..Material::default()
+    }
+}
+
+/// SplitMix64: small, fast and the same on every platform, which is all a
+/// reproducible scene needs.
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    fn unit(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.unit()
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn chance(&mut self, p: f32) -> bool {
+        self.unit() < p
+    }
+}
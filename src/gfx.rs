@@ -0,0 +1,27 @@
+use miniquad::*;
+
+pub fn compile_shader(
+    ctx: &mut dyn RenderingBackend,
+    vertex: &str,
+    fragment: &str,
+    meta: ShaderMeta,
+) -> ShaderId {
+    ctx.new_shader(
+        match ctx.info().backend {
+            Backend::OpenGl => ShaderSource::Glsl { vertex, fragment },
+            _ => unreachable!(),
+        },
+        meta,
+    )
+    .unwrap_or_else(|err| match err {
+        ShaderError::CompilationError {
+            shader_type,
+            error_message,
+        } => {
+            println!("A {:?} error has occured:", shader_type);
+            println!("{}", error_message);
+            panic!()
+        }
+        _ => panic!("{:?}", err),
+    })
+}
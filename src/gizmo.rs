@@ -0,0 +1,356 @@
+use cgmath::{
+    vec2, vec3, InnerSpace, Matrix3, Matrix4, MetricSpace, Quaternion, Rad, Rotation, Rotation3,
+    SquareMatrix, Vector2, Vector3,
+};
+
+use crate::camera::Camera;
+use crate::debug_draw::DebugDraw;
+use crate::history::Edit;
+use crate::mesh::MeshLibrary;
+use crate::palette::Palette;
+use crate::picking::{self, Ray};
+use crate::scene::{Instance, NodeId, Scene, Transform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+const AXES: [Vector3<f32>; 3] = [
+    vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0),
+];
+/// Emissive added to the object under the pointer...
+const HOVER_GLOW: f32 = 0.15;
+/// ...and to the selected one.
+const SELECTED_GLOW: f32 = 0.3;
+/// Handle length as a fraction of the screen height, whatever the
+/// distance to the camera.
+const SIZE: f32 = 0.2;
+/// Distance in pixels within which the cursor grabs a handle.
+const GRAB_RADIUS: f32 = 8.0;
+const RING_SEGMENTS: usize = 48;
+const MIN_SCALE: f32 = 0.01;
+
+/// Where a node sits in the world, for placing handles on nodes nested in
+/// a hierarchy.
+#[derive(Clone, Copy)]
+struct Frame {
+    /// The node's origin in world space.
+    center: Vector3<f32>,
+    transform: Transform,
+    /// World transform of the parent, or identity at the top level.
+    parent: Matrix4<f32>,
+    parent_rotation: Quaternion<f32>,
+}
+
+impl Frame {
+    fn of(scene: &Scene, node: NodeId) -> Frame {
+        let parent = scene
+            .get(node)
+            .unwrap()
+            .parent
+            .map_or(Matrix4::identity(), |p| scene.world_transform(p));
+        let linear = Matrix3::from_cols(
+            parent.x.truncate().normalize(),
+            parent.y.truncate().normalize(),
+            parent.z.truncate().normalize(),
+        );
+        Frame {
+            center: scene.world_transform(node).w.truncate(),
+            transform: scene.get(node).unwrap().transform,
+            parent,
+            parent_rotation: Quaternion::from(linear),
+        }
+    }
+
+    /// World space direction of a handle.
+    fn axis(&self, mode: GizmoMode, axis: usize) -> Vector3<f32> {
+        match mode {
+            GizmoMode::Translate | GizmoMode::Rotate => AXES[axis],
+            GizmoMode::Scale => {
+                (self.parent_rotation * self.transform.rotation).rotate_vector(AXES[axis])
+            }
+        }
+    }
+}
+
+struct Drag {
+    axis: usize,
+    start: Frame,
+    /// Where the cursor first hit the handle: the distance along the axis
+    /// for translate and scale, the offset from the center for rotate.
+    grab: Vector3<f32>,
+}
+
+/// Translate/rotate/scale handles for the selected node. Clicking in the
+/// viewport selects whole objects, so clicking any part of a prefab
+/// instance selects its root; nested nodes can be selected from the
+/// hierarchy panel. Translation and rotation happen along world axes,
+/// scaling along the node's own axes.
+///
+/// Outside edit mode the object under the crosshair is highlighted and
+/// clicking selects it.
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub selected: Option<NodeId>,
+    /// The object under the pointer, found by `hover`.
+    pub hovered: Option<NodeId>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new() -> Gizmo {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            selected: None,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    /// Grabs a handle under the cursor or, failing that, selects whatever
+    /// is under it. `cursor` is in pixels.
+    pub fn press(
+        &mut self,
+        cursor: Vector2<f32>,
+        camera: &Camera,
+        scene: &Scene,
+        instances: &[Instance],
+        meshes: &MeshLibrary,
+    ) {
+        if let Some(node) = self.selected.filter(|&id| scene.get(id).is_some()) {
+            let frame = Frame::of(scene, node);
+            if let Some(axis) = self.handle_at(cursor, camera, &frame) {
+                let ray = camera.ray(to_ndc(cursor));
+                if let Some(grab) = self.grab_point(&ray, &frame, axis) {
+                    self.drag = Some(Drag {
+                        axis,
+                        start: frame,
+                        grab,
+                    });
+                    return;
+                }
+            }
+        }
+        let ray = camera.ray(to_ndc(cursor));
+        self.selected = picking::pick(&ray, instances, meshes).map(|(id, _)| scene.root(id));
+    }
+
+    /// Finds the object under `cursor` (in pixels), or under the middle of
+    /// the screen without one.
+    pub fn hover(
+        &mut self,
+        cursor: Option<Vector2<f32>>,
+        camera: &Camera,
+        scene: &Scene,
+        instances: &[Instance],
+        meshes: &MeshLibrary,
+    ) {
+        let ray = camera.ray(cursor.map_or(vec2(0.0, 0.0), to_ndc));
+        self.hovered = picking::pick(&ray, instances, meshes).map(|(id, _)| scene.root(id));
+    }
+
+    /// Selects the hovered object, or nothing if there isn't one.
+    pub fn select_hovered(&mut self) {
+        self.selected = self.hovered;
+    }
+
+    /// Brightens the instances of the hovered and the selected object.
+    /// Only their own entries change, so the rest of an instanced draw is
+    /// unaffected.
+    pub fn highlight(&self, scene: &Scene, instances: &mut [Instance]) {
+        if self.hovered.is_none() && self.selected.is_none() {
+            return;
+        }
+        for instance in instances {
+            let within = |root: Option<NodeId>| {
+                root.is_some_and(|root| scene.is_ancestor(root, instance.node))
+            };
+            if within(self.selected) {
+                instance.params.emissive += SELECTED_GLOW;
+            } else if within(self.hovered) {
+                instance.params.emissive += HOVER_GLOW;
+            }
+        }
+    }
+
+    /// Moves the grabbed handle to follow the cursor.
+    pub fn motion(&mut self, cursor: Vector2<f32>, camera: &Camera, scene: &mut Scene) {
+        let (Some(drag), Some(node)) = (&self.drag, self.selected) else {
+            return;
+        };
+        let Some(target) = scene.get_mut(node) else {
+            self.drag = None;
+            return;
+        };
+        let ray = camera.ray(to_ndc(cursor));
+        let Some(now) = self.grab_point(&ray, &drag.start, drag.axis) else {
+            return;
+        };
+        let axis = AXES[drag.axis];
+        let start = &drag.start;
+        let mut transform = start.transform;
+        match self.mode {
+            GizmoMode::Translate => {
+                let parent_inverse = start.parent.invert().unwrap_or(Matrix4::identity());
+                let offset = axis * (now.x - drag.grab.x);
+                transform.position += (parent_inverse * offset.extend(0.0)).truncate();
+            }
+            GizmoMode::Rotate => {
+                let angle = drag.grab.cross(now).dot(axis).atan2(drag.grab.dot(now));
+                let world = Quaternion::from_axis_angle(axis, Rad(angle));
+                transform.rotation = start.parent_rotation.invert()
+                    * world
+                    * start.parent_rotation
+                    * start.transform.rotation;
+            }
+            GizmoMode::Scale => {
+                if drag.grab.x.abs() > 1e-6 {
+                    let factor = now.x / drag.grab.x;
+                    transform.scale[drag.axis] =
+                        (start.transform.scale[drag.axis] * factor).max(MIN_SCALE);
+                }
+            }
+        }
+        target.transform = transform;
+    }
+
+    /// Ends a drag, returning it as an undoable edit if anything moved.
+    pub fn release(&mut self, scene: &Scene) -> Option<Edit> {
+        let drag = self.drag.take()?;
+        let node = self.selected?;
+        let after = scene.get(node)?.transform;
+        (after != drag.start.transform).then_some(Edit::Transform {
+            node,
+            before: drag.start.transform,
+            after,
+        })
+    }
+
+    /// Outlines the selection and draws the handles for the current mode.
+    pub fn draw(
+        &self,
+        lines: &mut DebugDraw,
+        camera: &Camera,
+        scene: &Scene,
+        instances: &[Instance],
+        meshes: &MeshLibrary,
+        palette: &Palette,
+    ) {
+        let Some(node) = self.selected.filter(|&id| scene.get(id).is_some()) else {
+            return;
+        };
+        for instance in instances.iter().filter(|i| scene.is_ancestor(node, i.node)) {
+            let (min, max) = meshes.get(instance.mesh).bounds;
+            lines.aabb(min, max, instance.world, palette.selection);
+        }
+
+        let frame = Frame::of(scene, node);
+        let size = camera.world_per_ndc(frame.center) * SIZE;
+        for (axis, &axis_color) in palette.axes.iter().enumerate() {
+            let active = self.drag.as_ref().is_some_and(|d| d.axis == axis);
+            let color = if active { palette.active } else { axis_color };
+            let points = self.handle(&frame, axis, size);
+            for pair in points.windows(2) {
+                lines.line(pair[0], pair[1], color);
+            }
+            if self.mode == GizmoMode::Scale {
+                let half = vec3(1.0, 1.0, 1.0) * size * 0.06;
+                let end = Matrix4::from_translation(points[1]);
+                lines.aabb(-half, half, end, color);
+            }
+        }
+    }
+
+    /// Polyline of one handle, in world space.
+    fn handle(&self, frame: &Frame, axis: usize, size: f32) -> Vec<Vector3<f32>> {
+        let center = frame.center;
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                vec![center, center + frame.axis(self.mode, axis) * size]
+            }
+            GizmoMode::Rotate => {
+                let (u, v) = (AXES[(axis + 1) % 3], AXES[(axis + 2) % 3]);
+                (0..=RING_SEGMENTS)
+                    .map(|i| {
+                        let a = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        center + (u * a.cos() + v * a.sin()) * size
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The handle closest to the cursor, if within grabbing distance.
+    fn handle_at(&self, cursor: Vector2<f32>, camera: &Camera, frame: &Frame) -> Option<usize> {
+        let size = camera.world_per_ndc(frame.center) * SIZE;
+        (0..3)
+            .filter_map(|axis| {
+                let points: Option<Vec<Vector2<f32>>> = self
+                    .handle(frame, axis, size)
+                    .into_iter()
+                    .map(|p| camera.project(p).map(to_pixels))
+                    .collect();
+                let distance = points?
+                    .windows(2)
+                    .map(|s| segment_distance(cursor, s[0], s[1]))
+                    .fold(f32::MAX, f32::min);
+                Some((axis, distance))
+            })
+            .filter(|&(_, distance)| distance < GRAB_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(axis, _)| axis)
+    }
+
+    /// Where the cursor ray meets a handle's drag space: the distance along
+    /// the axis (in `x`) for translate and scale, or the offset from the
+    /// center within the ring's plane for rotate.
+    fn grab_point(&self, ray: &Ray, frame: &Frame, axis: usize) -> Option<Vector3<f32>> {
+        let center = frame.center;
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                closest_on_axis(ray, center, frame.axis(self.mode, axis)).map(|t| vec3(t, 0.0, 0.0))
+            }
+            GizmoMode::Rotate => {
+                let normal = AXES[axis];
+                let facing = ray.direction.dot(normal);
+                if facing.abs() < 1e-4 {
+                    return None;
+                }
+                let t = (center - ray.origin).dot(normal) / facing;
+                Some(ray.at(t) - center)
+            }
+        }
+    }
+}
+
+/// Parameter of the point on the line `origin + axis*t` closest to the ray.
+fn closest_on_axis(ray: &Ray, origin: Vector3<f32>, axis: Vector3<f32>) -> Option<f32> {
+    let w = origin - ray.origin;
+    let b = axis.dot(ray.direction);
+    let denom = 1.0 - b * b;
+    if denom < 1e-4 {
+        return None;
+    }
+    Some((b * ray.direction.dot(w) - axis.dot(w)) / denom)
+}
+
+fn segment_distance(p: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.magnitude2().max(1e-6)).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+fn to_ndc(pixels: Vector2<f32>) -> Vector2<f32> {
+    let (width, height) = miniquad::window::screen_size();
+    vec2(pixels.x / width * 2.0 - 1.0, 1.0 - pixels.y / height * 2.0)
+}
+
+fn to_pixels(ndc: Vector2<f32>) -> Vector2<f32> {
+    let (width, height) = miniquad::window::screen_size();
+    vec2((ndc.x + 1.0) * 0.5 * width, (1.0 - ndc.y) * 0.5 * height)
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use crate::scene::{Material, Node, NodeId, Scene, Transform};
+
+/// Edits kept for undoing; the oldest are dropped first.
+const MAX_EDITS: usize = 100;
+
+/// A reversible scene edit.
+pub enum Edit {
+    /// A node tree was added. Holds the tree while the spawn is undone.
+    Spawn {
+        root: NodeId,
+        detached: Vec<(NodeId, Node)>,
+    },
+    /// A node tree was removed. Holds the tree while the deletion stands.
+    Delete {
+        root: NodeId,
+        detached: Vec<(NodeId, Node)>,
+    },
+    Transform {
+        node: NodeId,
+        before: Transform,
+        after: Transform,
+    },
+    Material {
+        /// Node, material before, material after.
+        changes: Vec<(NodeId, Material, Material)>,
+    },
+    Reparent {
+        node: NodeId,
+        before: Option<NodeId>,
+        after: Option<NodeId>,
+    },
+}
+
+impl Edit {
+    fn undo(&mut self, scene: &mut Scene) {
+        match self {
+            Edit::Spawn { root, detached } => *detached = scene.detach(*root),
+            Edit::Delete { detached, .. } => scene.restore(std::mem::take(detached)),
+            Edit::Transform { node, before, .. } => {
+                if let Some(node) = scene.get_mut(*node) {
+                    node.transform = *before;
+                }
+            }
+            Edit::Material { changes } => {
+                for (id, before, _) in changes {
+                    if let Some(node) = scene.get_mut(*id) {
+                        node.material = *before;
+                    }
+                }
+            }
+            Edit::Reparent { node, before, .. } => {
+                scene.set_parent(*node, *before);
+            }
+        }
+    }
+
+    fn redo(&mut self, scene: &mut Scene) {
+        match self {
+            Edit::Spawn { detached, .. } => scene.restore(std::mem::take(detached)),
+            Edit::Delete { root, detached } => *detached = scene.detach(*root),
+            Edit::Transform { node, after, .. } => {
+                if let Some(node) = scene.get_mut(*node) {
+                    node.transform = *after;
+                }
+            }
+            Edit::Material { changes } => {
+                for (id, _, after) in changes {
+                    if let Some(node) = scene.get_mut(*id) {
+                        node.material = *after;
+                    }
+                }
+            }
+            Edit::Reparent { node, after, .. } => {
+                scene.set_parent(*node, *after);
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Edit::Spawn { .. } => "spawn",
+            Edit::Delete { .. } => "delete",
+            Edit::Transform { .. } => "transform",
+            Edit::Material { .. } => "material",
+            Edit::Reparent { .. } => "reparent",
+        }
+    }
+}
+
+/// Undo/redo stacks for editor operations. Edits are recorded after they
+/// have been applied to the scene, except deletions, which go through
+/// `delete` so the removed nodes are kept.
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+        self.undo.push_back(edit);
+        if self.undo.len() > MAX_EDITS {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Removes a node tree from the scene as an undoable edit.
+    pub fn delete(&mut self, scene: &mut Scene, root: NodeId) {
+        let detached = scene.detach(root);
+        if !detached.is_empty() {
+            self.record(Edit::Delete { root, detached });
+        }
+    }
+
+    /// Reverts the most recent edit, returning its name.
+    pub fn undo(&mut self, scene: &mut Scene) -> Option<&'static str> {
+        let mut edit = self.undo.pop_back()?;
+        edit.undo(scene);
+        let name = edit.name();
+        self.redo.push(edit);
+        Some(name)
+    }
+
+    /// Reapplies the most recently undone edit, returning its name.
+    pub fn redo(&mut self, scene: &mut Scene) -> Option<&'static str> {
+        let mut edit = self.redo.pop()?;
+        edit.redo(scene);
+        let name = edit.name();
+        self.undo.push_back(edit);
+        Some(name)
+    }
+
+    /// Forgets everything, for when the scene is replaced.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
@@ -0,0 +1,135 @@
+use cgmath::{MetricSpace, Point3};
+
+use crate::palette::Palette;
+use crate::text::TextRenderer;
+
+/// Seconds a notification stays up, the last of which it fades out over.
+const TOAST_LIFETIME: f32 = 4.0;
+/// Notifications shown at once; older ones are dropped.
+const MAX_TOASTS: usize = 5;
+/// Half the length of a crosshair arm and the gap left in its middle, in
+/// font pixels.
+const CROSSHAIR_SIZE: f32 = 5.0;
+const CROSSHAIR_GAP: f32 = 2.0;
+
+struct Toast {
+    message: String,
+    age: f32,
+}
+
+/// The heads-up display: a crosshair in the middle of the screen, the
+/// camera's speed and position in the bottom left corner and notifications
+/// stacked in the top right. It is queued into the screen-space text
+/// layer, which is drawn over the finished frame without depth testing.
+pub struct Hud {
+    pub enabled: bool,
+    toasts: Vec<Toast>,
+    last_position: Option<Point3<f32>>,
+    /// Smoothed speed of the camera, in units per second.
+    speed: f32,
+}
+
+impl Default for Hud {
+    fn default() -> Hud {
+        Hud {
+            enabled: true,
+            toasts: vec![],
+            last_position: None,
+            speed: 0.0,
+        }
+    }
+}
+
+impl Hud {
+    /// Shows `message` for a few seconds. It is printed as well, so it
+    /// ends up in the log when the HUD is hidden.
+    pub fn notify(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        println!("{}", message);
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.remove(0);
+        }
+        self.toasts.push(Toast { message, age: 0.0 });
+    }
+
+    /// Ages the notifications and measures how fast the camera moved.
+    /// `delta` is real time, which the camera flies in.
+    pub fn update(&mut self, delta: f32, position: Point3<f32>) {
+        for toast in &mut self.toasts {
+            toast.age += delta;
+        }
+        self.toasts.retain(|toast| toast.age < TOAST_LIFETIME);
+
+        if let Some(last) = self.last_position.replace(position) {
+            if delta > 0.0 {
+                let measured = last.distance(position) / delta;
+                // Keyboard movement is per frame, so smooth out the jitter.
+                self.speed += (measured - self.speed) * (delta * 10.0).min(1.0);
+            }
+        }
+    }
+
+    /// Queues the HUD into `text`. The crosshair is left out while the
+    /// mouse cursor is shown.
+    pub fn draw(
+        &self,
+        text: &mut TextRenderer,
+        width: f32,
+        height: f32,
+        position: Point3<f32>,
+        crosshair: bool,
+        palette: &Palette,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let (white, shadow) = (palette.text, palette.shadow);
+        if crosshair {
+            let (cx, cy) = ((width / 2.0).floor(), (height / 2.0).floor());
+            let thickness = text.scale;
+            let size = CROSSHAIR_SIZE * text.scale;
+            let gap = CROSSHAIR_GAP * text.scale;
+            let arm = size - gap;
+            // Outlined by a slightly larger dark copy, so it shows on
+            // bright and dark backgrounds alike.
+            for (grow, color) in [(1.0, shadow), (0.0, white)] {
+                let (thick, long) = (thickness + grow * 2.0, arm + grow * 2.0);
+                let half = thick / 2.0;
+                text.rect(cx - size - grow, cy - half, long, thick, color);
+                text.rect(cx + gap - grow, cy - half, long, thick, color);
+                text.rect(cx - half, cy - size - grow, thick, long, color);
+                text.rect(cx - half, cy + gap - grow, thick, long, color);
+            }
+        }
+
+        let margin = text.scale * 4.0;
+        let lines = [
+            format!("speed {:6.2} u/s", self.speed),
+            format!(
+                "pos   {:.1} {:.1} {:.1}",
+                position.x, position.y, position.z
+            ),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            let y = height - margin - (lines.len() - i) as f32 * text.line_height();
+            text.print(margin + text.scale, y + text.scale, shadow, line);
+            text.print(margin, y, white, line);
+        }
+
+        for (i, toast) in self.toasts.iter().rev().enumerate() {
+            let alpha = (TOAST_LIFETIME - toast.age).min(1.0);
+            let fade = |[r, g, b, a]: [f32; 4]| [r, g, b, a * alpha];
+            let x = width - margin - toast.message.len() as f32 * text.char_width();
+            let y = margin + i as f32 * text.line_height() * 1.5;
+            let pad = text.scale * 2.0;
+            text.rect(
+                x - pad,
+                y - pad,
+                toast.message.len() as f32 * text.char_width() + pad * 2.0,
+                text.char_width() + pad * 2.0,
+                fade(palette.panel),
+            );
+            text.print(x, y, fade(palette.text), &toast.message);
+        }
+    }
+}
@@ -0,0 +1,927 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use cgmath::{
+    vec2, vec3, vec4, ElementWise, InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix,
+    Vector3, Vector4, Zero,
+};
+use gltf::animation::{util::ReadOutputs, Interpolation};
+
+use crate::animation::{Clip, Track};
+use crate::color::linear_rgba;
+use crate::compressed::{decode_dds, decode_ktx2, CompressedImage};
+use crate::environment::{self, Cubemap};
+use crate::mesh::{Mesh, Vertex};
+use crate::scene::Transform;
+use crate::skin::{Joint, Skeleton, Skin, SkinVertex, MAX_JOINTS};
+use crate::texture::{decode_png, Image};
+use crate::tween::Lerp;
+
+/// A file decoded for use in the scene.
+pub enum Asset {
+    /// `animation` poses the whole model, which is in its animated node's
+    /// space, or with a `skin`, its joints.
+    Model {
+        mesh: Mesh,
+        skin: Option<Skin>,
+        animation: Option<Clip>,
+    },
+    Image(Image),
+    /// Block-compressed, for uploading without decoding.
+    Texture(CompressedImage),
+    /// An equirectangular panorama already projected, for the sky.
+    Environment(Cubemap),
+}
+
+impl Asset {
+    /// Roughly how many bytes uploading it sends to the GPU.
+    pub fn upload_size(&self) -> usize {
+        match self {
+            // The edge list has about twice as many indices as the
+            // triangles.
+            Asset::Model { mesh, .. } => {
+                mesh.vertices.len() * std::mem::size_of::<Vertex>() + mesh.indices.len() * 2 * 3
+            }
+            Asset::Image(image) => image.rgba.len(),
+            Asset::Texture(image) => image.levels.iter().map(Vec::len).sum(),
+            // Half floats.
+            Asset::Environment(cubemap) => cubemap.size * cubemap.size * 6 * 8,
+        }
+    }
+}
+
+/// File extensions `load` understands.
+pub const EXTENSIONS: &[&str] = &[
+    "obj", "ply", "stl", "gltf", "glb", "png", "dds", "ktx2", "hdr", "exr",
+];
+
+/// Decodes a model (`.obj`, `.ply`, `.stl`, `.gltf`, `.glb`), image
+/// (`.png`, `.dds`, `.ktx2`) or environment map (`.hdr`, `.exr`) by file
+/// extension.
+pub fn load(path: &Path) -> Result<Asset, String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("obj") => {
+            let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+            let mesh = load_obj(&text)?;
+            Ok(Asset::Model {
+                mesh,
+                skin: None,
+                animation: None,
+            })
+        }
+        Some("ply") | Some("stl") => {
+            let bytes = fs::read(path).map_err(|err| err.to_string())?;
+            let mesh = match extension.as_deref() {
+                Some("ply") => load_ply(&bytes)?,
+                _ => load_stl(&bytes)?,
+            };
+            Ok(Asset::Model {
+                mesh,
+                skin: None,
+                animation: None,
+            })
+        }
+        Some("gltf") | Some("glb") => {
+            let (mesh, skin, animation) = load_gltf(path)?;
+            Ok(Asset::Model {
+                mesh,
+                skin,
+                animation,
+            })
+        }
+        Some("png") => {
+            let bytes = fs::read(path).map_err(|err| err.to_string())?;
+            decode_png(&bytes)
+                .map(Asset::Image)
+                .map_err(|err| err.to_string())
+        }
+        Some("dds") | Some("ktx2") => {
+            let bytes = fs::read(path).map_err(|err| err.to_string())?;
+            let image = match extension.as_deref() {
+                Some("dds") => decode_dds(&bytes)?,
+                _ => decode_ktx2(&bytes)?,
+            };
+            Ok(Asset::Texture(image))
+        }
+        Some("hdr") | Some("exr") => {
+            let image = environment::load(path)?;
+            Ok(Asset::Environment(Cubemap::from_equirect(&image)))
+        }
+        _ => Err("unsupported file type".to_owned()),
+    }
+}
+
+/// Wavefront OBJ: positions (optionally followed by an RGB color), normals
+/// and polygonal faces. Texture coordinates, groups and materials are
+/// ignored. Faces without normals get smooth ones computed.
+pub fn load_obj(text: &str) -> Result<Mesh, String> {
+    let mut positions: Vec<(Vector3<f32>, Vector4<f32>)> = vec![];
+    let mut normals: Vec<Vector3<f32>> = vec![];
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    // Vertices are shared between faces when they use the same position
+    // and normal.
+    let mut shared: HashMap<(usize, Option<usize>), u16> = HashMap::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let mut words = line.split_whitespace();
+        let floats = |words: std::str::SplitWhitespace| -> Result<Vec<f32>, String> {
+            words
+                .map(|w| w.parse::<f32>().map_err(|_| error("bad number")))
+                .collect()
+        };
+        match words.next() {
+            Some("v") => match floats(words)?[..] {
+                [x, y, z] => positions.push((vec3(x, y, z), vec4(1.0, 1.0, 1.0, 1.0))),
+                // Colors are written in sRGB, like everything picked by eye.
+                [x, y, z, r, g, b, ..] => {
+                    positions.push((vec3(x, y, z), linear_rgba(vec4(r, g, b, 1.0))))
+                }
+                _ => return Err(error("expected x y z")),
+            },
+            Some("vn") => match floats(words)?[..] {
+                [x, y, z] => normals.push(vec3(x, y, z)),
+                _ => return Err(error("expected x y z")),
+            },
+            Some("f") => {
+                let mut face = vec![];
+                for corner in words {
+                    let mut parts = corner.split('/');
+                    let position = obj_index(parts.next(), positions.len())
+                        .ok_or_else(|| error("bad vertex index"))?;
+                    let normal = match parts.nth(1) {
+                        Some("") | None => None,
+                        index => Some(
+                            obj_index(index, normals.len())
+                                .ok_or_else(|| error("bad normal index"))?,
+                        ),
+                    };
+                    let index = match shared.get(&(position, normal)) {
+                        Some(&index) => index,
+                        None => {
+                            let index = u16::try_from(mesh.vertices.len())
+                                .map_err(|_| "too many vertices".to_owned())?;
+                            let (pos, color) = positions[position];
+                            mesh.vertices.push(Vertex {
+                                pos,
+                                normal: normal.map_or(Vector3::zero(), |n| normals[n]),
+                                color,
+                                uv2: vec2(0.0, 0.0),
+                            });
+                            shared.insert((position, normal), index);
+                            index
+                        }
+                    };
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    return Err(error("face with fewer than 3 corners"));
+                }
+                for i in 1..face.len() - 1 {
+                    mesh.indices.extend([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => (),
+        }
+    }
+    if mesh.indices.is_empty() {
+        return Err("no faces".to_owned());
+    }
+    smooth_normals(&mut mesh);
+    Ok(mesh)
+}
+
+/// Resolves a 1-based (or, if negative, end-relative) OBJ index.
+fn obj_index(word: Option<&str>, count: usize) -> Option<usize> {
+    let index: isize = word?.parse().ok()?;
+    let index = if index < 0 {
+        count as isize + index
+    } else {
+        index - 1
+    };
+    (0..count as isize)
+        .contains(&index)
+        .then_some(index as usize)
+}
+
+/// STL, binary or ASCII. Triangles keep their facet normal, so only
+/// coplanar neighbours share vertices; facets without a normal get one
+/// computed.
+pub fn load_stl(bytes: &[u8]) -> Result<Mesh, String> {
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    let mut shared = HashMap::new();
+    let mut add_facet = |normal: Vector3<f32>, corners: [Vector3<f32>; 3]| -> Result<(), String> {
+        for pos in corners {
+            let index = shared_vertex(
+                &mut mesh,
+                &mut shared,
+                pos,
+                normal,
+                vec4(1.0, 1.0, 1.0, 1.0),
+            )?;
+            mesh.indices.push(index);
+        }
+        Ok(())
+    };
+
+    // A binary file may also start with "solid", so go by its size first.
+    let count = bytes
+        .get(80..84)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+    if count.is_some_and(|count| bytes.len() == 84 + count * 50) {
+        for facet in bytes[84..].chunks_exact(50) {
+            let vector = |i: usize| {
+                let f = |j: usize| {
+                    let start = i * 12 + j * 4;
+                    f32::from_le_bytes(facet[start..start + 4].try_into().unwrap())
+                };
+                vec3(f(0), f(1), f(2))
+            };
+            add_facet(vector(0), [vector(1), vector(2), vector(3)])?;
+        }
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| "not a valid STL file".to_owned())?;
+        if !text.trim_start().starts_with("solid") {
+            return Err("not a valid STL file".to_owned());
+        }
+        let mut normal = Vector3::zero();
+        let mut corners = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {}", number + 1, message);
+            let mut words = line.split_whitespace();
+            let vector = |words: std::str::SplitWhitespace| -> Result<Vector3<f32>, String> {
+                let floats: Vec<f32> = words
+                    .map(|w| w.parse().map_err(|_| error("bad number")))
+                    .collect::<Result<_, _>>()?;
+                match floats[..] {
+                    [x, y, z] => Ok(vec3(x, y, z)),
+                    _ => Err(error("expected x y z")),
+                }
+            };
+            match words.next() {
+                Some("facet") => {
+                    corners.clear();
+                    normal = match words.next() {
+                        Some("normal") => vector(words)?,
+                        _ => Vector3::zero(),
+                    };
+                }
+                Some("vertex") => corners.push(vector(words)?),
+                Some("endfacet") => match corners[..] {
+                    [a, b, c] => add_facet(normal, [a, b, c])?,
+                    _ => return Err(error("facet without 3 vertices")),
+                },
+                _ => (),
+            }
+        }
+    }
+    if mesh.indices.is_empty() {
+        return Err("no facets".to_owned());
+    }
+    smooth_normals(&mut mesh);
+    Ok(mesh)
+}
+
+/// Index of a vertex with exactly this position and normal, added if there
+/// is none yet.
+fn shared_vertex(
+    mesh: &mut Mesh,
+    shared: &mut HashMap<[u32; 6], u16>,
+    pos: Vector3<f32>,
+    normal: Vector3<f32>,
+    color: Vector4<f32>,
+) -> Result<u16, String> {
+    let key = [pos.x, pos.y, pos.z, normal.x, normal.y, normal.z].map(f32::to_bits);
+    if let Some(&index) = shared.get(&key) {
+        return Ok(index);
+    }
+    let index = u16::try_from(mesh.vertices.len()).map_err(|_| "too many vertices".to_owned())?;
+    mesh.vertices.push(Vertex {
+        pos,
+        normal,
+        color,
+        uv2: vec2(0.0, 0.0),
+    });
+    shared.insert(key, index);
+    Ok(index)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Result<PlyType, String> {
+        Ok(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return Err(format!("unknown PLY type {}", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    /// Type of the length prefix, for list properties.
+    list: Option<PlyType>,
+    value: PlyType,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reads PLY values, whatever their encoding, as `f64`.
+struct PlyReader<'a> {
+    format: PlyFormat,
+    bytes: &'a [u8],
+    words: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl PlyReader<'_> {
+    fn read(&mut self, ty: PlyType) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            let word = self.words.next().ok_or("unexpected end of file")?;
+            return word.parse().map_err(|_| format!("bad number {}", word));
+        }
+        let size = ty.size();
+        if self.bytes.len() < size {
+            return Err("unexpected end of file".to_owned());
+        }
+        let (value, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        let mut raw = [0; 8];
+        raw[..size].copy_from_slice(value);
+        if self.format == PlyFormat::BigEndian {
+            raw[..size].reverse();
+        }
+        let two = [raw[0], raw[1]];
+        let four = [raw[0], raw[1], raw[2], raw[3]];
+        Ok(match ty {
+            PlyType::I8 => raw[0] as i8 as f64,
+            PlyType::U8 => raw[0] as f64,
+            PlyType::I16 => i16::from_le_bytes(two) as f64,
+            PlyType::U16 => u16::from_le_bytes(two) as f64,
+            PlyType::I32 => i32::from_le_bytes(four) as f64,
+            PlyType::U32 => u32::from_le_bytes(four) as f64,
+            PlyType::F32 => f32::from_le_bytes(four) as f64,
+            PlyType::F64 => f64::from_le_bytes(raw),
+        })
+    }
+}
+
+/// Stanford PLY, ASCII or binary: vertex positions with optional normals
+/// and colors, and polygonal faces. Other elements are skipped.
+pub fn load_ply(bytes: &[u8]) -> Result<Mesh, String> {
+    const END: &[u8] = b"end_header";
+    let header_end = bytes
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or("not a PLY file")?;
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |i| header_end + i + 1);
+    let header = std::str::from_utf8(&bytes[..header_end]).map_err(|_| "bad PLY header")?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err("not a PLY file".to_owned());
+    }
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::LittleEndian,
+                    "binary_big_endian" => PlyFormat::BigEndian,
+                    _ => return Err(format!("unknown PLY format {}", name)),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_owned(),
+                count: count.parse().map_err(|_| "bad element count")?,
+                properties: vec![],
+            }),
+            ["property", "list", count, value, name] => {
+                let element = elements.last_mut().ok_or("property outside an element")?;
+                element.properties.push(PlyProperty {
+                    name: name.to_owned(),
+                    list: Some(PlyType::parse(count)?),
+                    value: PlyType::parse(value)?,
+                });
+            }
+            ["property", value, name] => {
+                let element = elements.last_mut().ok_or("property outside an element")?;
+                element.properties.push(PlyProperty {
+                    name: name.to_owned(),
+                    list: None,
+                    value: PlyType::parse(value)?,
+                });
+            }
+            _ => (),
+        }
+    }
+    let format = format.ok_or("PLY header has no format")?;
+    let body = &bytes[body_start..];
+    let mut reader = PlyReader {
+        format,
+        bytes: body,
+        words: match format {
+            PlyFormat::Ascii => std::str::from_utf8(body)
+                .map_err(|_| "bad PLY data")?
+                .split_ascii_whitespace(),
+            _ => "".split_ascii_whitespace(),
+        },
+    };
+
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut values: HashMap<&str, f64> = HashMap::new();
+            let mut indices = vec![];
+            for property in &element.properties {
+                match property.list {
+                    Some(count) => {
+                        let count = reader.read(count)? as usize;
+                        for _ in 0..count {
+                            indices.push(reader.read(property.value)?);
+                        }
+                    }
+                    None => {
+                        let mut value = reader.read(property.value)?;
+                        // Integer colors run to the type's maximum.
+                        if matches!(property.value, PlyType::U8)
+                            && ["red", "green", "blue", "alpha"].contains(&property.name.as_str())
+                        {
+                            value /= 255.0;
+                        }
+                        values.insert(&property.name, value);
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => {
+                    let get = |name: &str, default: f64| {
+                        values.get(name).copied().unwrap_or(default) as f32
+                    };
+                    if mesh.vertices.len() > u16::MAX as usize {
+                        return Err("too many vertices".to_owned());
+                    }
+                    mesh.vertices.push(Vertex {
+                        pos: vec3(get("x", 0.0), get("y", 0.0), get("z", 0.0)),
+                        normal: vec3(get("nx", 0.0), get("ny", 0.0), get("nz", 0.0)),
+                        // Colors are sRGB, as for OBJ.
+                        color: linear_rgba(vec4(
+                            get("red", 1.0),
+                            get("green", 1.0),
+                            get("blue", 1.0),
+                            get("alpha", 1.0),
+                        )),
+                        uv2: vec2(0.0, 0.0),
+                    });
+                }
+                "face" => {
+                    let count = mesh.vertices.len() as f64;
+                    if indices.iter().any(|&i| i < 0.0 || i >= count) {
+                        return Err("face refers to a missing vertex".to_owned());
+                    }
+                    for i in 1..indices.len().saturating_sub(1) {
+                        mesh.indices
+                            .extend([indices[0], indices[i], indices[i + 1]].map(|i| i as u16));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    if mesh.indices.is_empty() {
+        return Err("no faces".to_owned());
+    }
+    smooth_normals(&mut mesh);
+    Ok(mesh)
+}
+
+/// glTF 2.0, text or binary. Every triangle primitive in the default scene
+/// is flattened into one mesh, colored by its material's base color.
+///
+/// Since the nodes don't survive flattening, only the first animation's
+/// tracks for one top-level node are kept; the mesh is then built in that
+/// node's space so the clip can move all of it.
+///
+/// Models with a skin are instead skinned by its joints, which the first
+/// animation's tracks for them then move. Only the first skin used in the
+/// scene is kept. Parts that aren't skinned follow the joint they hang
+/// from, if any.
+pub fn load_gltf(path: &Path) -> Result<(Mesh, Option<Skin>, Option<Clip>), String> {
+    let (document, buffers, _) = gltf::import(path).map_err(|err| err.to_string())?;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or("no scene")?;
+    let mut rest = HashMap::new();
+    for node in scene.nodes() {
+        rest_transforms(&node, None, Matrix4::identity(), &mut rest);
+    }
+    let skin = document
+        .nodes()
+        .filter(|node| rest.contains_key(&node.index()))
+        .find_map(|node| node.skin());
+    if let Some(skin) = skin {
+        return load_gltf_skinned(&document, &scene, &skin, &rest, &buffers);
+    }
+
+    let animated = document.animations().next().and_then(|animation| {
+        let node = animation
+            .channels()
+            .map(|channel| channel.target().node())
+            .find(|node| scene.nodes().any(|n| n.index() == node.index()))?;
+        Some((node.index(), gltf_clip(&animation, node.index(), &buffers)))
+    });
+
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    for node in scene.nodes() {
+        let mut transform = Matrix4::identity();
+        if animated
+            .as_ref()
+            .is_some_and(|(index, _)| *index != node.index())
+        {
+            // Static parts stay where they are relative to the animated one.
+            let (index, _) = animated.as_ref().unwrap();
+            let animated_node = document.nodes().nth(*index).unwrap();
+            let rest = Matrix4::from(animated_node.transform().matrix());
+            transform = rest.invert().unwrap_or(transform);
+        }
+        let root = animated.as_ref().map(|(index, _)| *index);
+        add_gltf_node(&node, transform, root, &buffers, &mut mesh, None)?;
+    }
+    if mesh.indices.is_empty() {
+        return Err("no triangles".to_owned());
+    }
+    smooth_normals(&mut mesh);
+    Ok((mesh, None, animated.map(|(_, clip)| clip)))
+}
+
+/// Where a node sits in the scene at rest: its parent, and its transform
+/// relative to the scene.
+struct RestNode {
+    parent: Option<usize>,
+    global: Matrix4<f32>,
+}
+
+fn rest_transforms(
+    node: &gltf::Node,
+    parent: Option<usize>,
+    parent_global: Matrix4<f32>,
+    out: &mut HashMap<usize, RestNode>,
+) {
+    let global = parent_global * Matrix4::from(node.transform().matrix());
+    out.insert(node.index(), RestNode { parent, global });
+    for child in node.children() {
+        rest_transforms(&child, Some(node.index()), global, out);
+    }
+}
+
+/// Joints and weights of the vertices being added, for `add_gltf_node`.
+struct SkinBuilder {
+    skin: usize,
+    /// Joint index of each joint node.
+    joints: HashMap<usize, usize>,
+    vertices: Vec<SkinVertex>,
+}
+
+fn load_gltf_skinned(
+    document: &gltf::Document,
+    scene: &gltf::Scene,
+    skin: &gltf::Skin,
+    rest: &HashMap<usize, RestNode>,
+    buffers: &[gltf::buffer::Data],
+) -> Result<(Mesh, Option<Skin>, Option<Clip>), String> {
+    let nodes: Vec<gltf::Node> = skin.joints().collect();
+    if nodes.len() > MAX_JOINTS {
+        return Err(format!(
+            "skin has {} joints, at most {} are supported",
+            nodes.len(),
+            MAX_JOINTS
+        ));
+    }
+    let index: HashMap<usize, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.index(), i))
+        .collect();
+    let inverse_binds: Vec<Matrix4<f32>> = skin
+        .reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map_or(vec![], |matrices| matrices.map(Matrix4::from).collect());
+
+    let global = |node: Option<usize>| {
+        node.and_then(|node| rest.get(&node))
+            .map_or(Matrix4::identity(), |rest| rest.global)
+    };
+    let mut joints = vec![];
+    let mut depths = vec![];
+    for (i, node) in nodes.iter().enumerate() {
+        // The nearest ancestor that is a joint too, and how deep it is.
+        let hangs_from = rest.get(&node.index()).and_then(|r| r.parent);
+        let mut parent = hangs_from;
+        let mut depth = 0;
+        while let Some(p) = parent {
+            if index.contains_key(&p) {
+                depth += 1;
+            }
+            parent = rest.get(&p).and_then(|r| r.parent);
+        }
+        let mut parent = hangs_from;
+        while let Some(p) = parent.filter(|p| !index.contains_key(p)) {
+            parent = rest.get(&p).and_then(|r| r.parent);
+        }
+        let offset = global(parent).invert().unwrap_or(Matrix4::identity()) * global(hangs_from);
+        let (position, [x, y, z, w], scale) = node.transform().decomposed();
+        joints.push(Joint {
+            parent: parent.map(|p| index[&p]),
+            offset,
+            rest: Transform {
+                position: position.into(),
+                rotation: Quaternion::new(w, x, y, z),
+                scale: scale.into(),
+            },
+            inverse_bind: inverse_binds.get(i).copied().unwrap_or(Matrix4::identity()),
+        });
+        depths.push(depth);
+    }
+    let mut order: Vec<usize> = (0..joints.len()).collect();
+    order.sort_by_key(|&i| depths[i]);
+
+    let mut mesh = Mesh {
+        vertices: vec![],
+        indices: vec![],
+    };
+    let mut builder = SkinBuilder {
+        skin: skin.index(),
+        joints: index,
+        vertices: vec![],
+    };
+    for node in scene.nodes() {
+        add_gltf_node(
+            &node,
+            Matrix4::identity(),
+            None,
+            buffers,
+            &mut mesh,
+            Some((&mut builder, None)),
+        )?;
+    }
+    if mesh.indices.is_empty() {
+        return Err("no triangles".to_owned());
+    }
+    smooth_normals(&mut mesh);
+
+    let animation = document.animations().next().map(|animation| Clip {
+        joints: nodes
+            .iter()
+            .map(|node| gltf_clip(&animation, node.index(), buffers))
+            .collect(),
+        ..Clip::default()
+    });
+    let skin = Skin {
+        vertices: builder.vertices,
+        skeleton: Skeleton { joints, order },
+    };
+    Ok((mesh, Some(skin), animation))
+}
+
+/// The tracks of `animation` that move node `target`.
+fn gltf_clip(animation: &gltf::Animation, target: usize, buffers: &[gltf::buffer::Data]) -> Clip {
+    let mut clip = Clip::default();
+    for channel in animation.channels() {
+        if channel.target().node().index() != target {
+            continue;
+        }
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+        let times: Vec<f32> = inputs.collect();
+        let interpolation = channel.sampler().interpolation();
+        match outputs {
+            ReadOutputs::Translations(values) => {
+                clip.position = gltf_track(&times, values.map(Vector3::from), interpolation)
+            }
+            ReadOutputs::Rotations(values) => {
+                let values = values
+                    .into_f32()
+                    .map(|[x, y, z, w]| Quaternion::new(w, x, y, z));
+                clip.rotation = gltf_track(&times, values, interpolation)
+            }
+            ReadOutputs::Scales(values) => {
+                clip.scale = gltf_track(&times, values.map(Vector3::from), interpolation)
+            }
+            ReadOutputs::MorphTargetWeights(_) => (),
+        }
+    }
+    clip
+}
+
+fn gltf_track<V: Lerp>(
+    times: &[f32],
+    values: impl Iterator<Item = V>,
+    interpolation: Interpolation,
+) -> Track<V> {
+    // Cubic splines store an in-tangent, value and out-tangent per key;
+    // they are played back linearly between the values.
+    let (skip, stride) = match interpolation {
+        Interpolation::CubicSpline => (1, 3),
+        _ => (0, 1),
+    };
+    let values = values.skip(skip).step_by(stride);
+    Track::new(times.iter().copied().zip(values).collect())
+}
+
+/// Adds a node and its children, transformed by `parent`. The node with
+/// index `animated`, if given, is left at its own origin. With a `skin`,
+/// joints and weights are added for every vertex: meshes with the skin
+/// stay in the space they were bound in, others are bound to the joint
+/// given with it, the nearest above them.
+fn add_gltf_node(
+    node: &gltf::Node,
+    parent: Matrix4<f32>,
+    animated: Option<usize>,
+    buffers: &[gltf::buffer::Data],
+    out: &mut Mesh,
+    mut skin: Option<(&mut SkinBuilder, Option<usize>)>,
+) -> Result<(), String> {
+    let local = match animated == Some(node.index()) {
+        true => Matrix4::identity(),
+        false => Matrix4::from(node.transform().matrix()),
+    };
+    let world = parent * local;
+    if let Some((builder, joint)) = &mut skin {
+        if let Some(&index) = builder.joints.get(&node.index()) {
+            *joint = Some(index);
+        }
+    }
+    let skinned = skin
+        .as_ref()
+        .is_some_and(|(builder, _)| node.skin().is_some_and(|s| s.index() == builder.skin));
+    // Skinned meshes ignore the transform of their node.
+    let world = if skinned { Matrix4::identity() } else { world };
+    let linear = Matrix3::from_cols(world.x.truncate(), world.y.truncate(), world.z.truncate());
+    let normal_matrix = linear
+        .invert()
+        .map_or(Matrix3::identity(), |m| m.transpose());
+
+    let primitives = node.mesh().into_iter().flat_map(|mesh| mesh.primitives());
+    for primitive in primitives.filter(|p| p.mode() == gltf::mesh::Mode::Triangles) {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let positions: Vec<[f32; 3]> = positions.collect();
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+        let colors: Option<Vec<[f32; 4]>> =
+            reader.read_colors(0).map(|c| c.into_rgba_f32().collect());
+        let weighted: Option<Vec<([u16; 4], [f32; 4])>> = match skinned {
+            true => reader
+                .read_joints(0)
+                .zip(reader.read_weights(0))
+                .map(|(j, w)| j.into_u16().zip(w.into_f32()).collect()),
+            false => None,
+        };
+        let base_color = Vector4::from(
+            primitive
+                .material()
+                .pbr_metallic_roughness()
+                .base_color_factor(),
+        );
+
+        let offset = out.vertices.len();
+        if offset + positions.len() > u16::MAX as usize + 1 {
+            return Err("too many vertices".to_owned());
+        }
+        for (i, &position) in positions.iter().enumerate() {
+            let normal = normals.as_ref().map_or(Vector3::zero(), |n| {
+                (normal_matrix * Vector3::from(n[i])).normalize()
+            });
+            let color = colors
+                .as_ref()
+                .map_or(vec4(1.0, 1.0, 1.0, 1.0), |c| c[i].into());
+            out.vertices.push(Vertex {
+                pos: (world * Vector3::from(position).extend(1.0)).truncate(),
+                normal,
+                // glTF colors are linear, like ours.
+                color: color.mul_element_wise(base_color),
+                uv2: vec2(0.0, 0.0),
+            });
+            if let Some((builder, joint)) = &mut skin {
+                builder.vertices.push(match (&weighted, *joint) {
+                    (Some(weighted), _) => {
+                        let (joints, weights) = weighted[i];
+                        SkinVertex {
+                            joints: joints.map(f32::from),
+                            weights,
+                        }
+                    }
+                    (None, Some(joint)) => SkinVertex {
+                        joints: [joint as f32, 0.0, 0.0, 0.0],
+                        weights: [1.0, 0.0, 0.0, 0.0],
+                    },
+                    (None, None) => SkinVertex::default(),
+                });
+            }
+        }
+        let start = out.indices.len();
+        match reader.read_indices() {
+            Some(indices) => out
+                .indices
+                .extend(indices.into_u32().map(|i| (offset + i as usize) as u16)),
+            None => out
+                .indices
+                .extend((0..positions.len()).map(|i| (offset + i) as u16)),
+        }
+        if world.determinant() < 0.0 {
+            // A mirroring transform turns the winding inside out.
+            for triangle in out.indices[start..].chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    for child in node.children() {
+        let skin = skin
+            .as_mut()
+            .map(|(builder, joint)| (&mut **builder, *joint));
+        add_gltf_node(&child, parent * local, animated, buffers, out, skin)?;
+    }
+    Ok(())
+}
+
+/// Gives vertices without a normal the area-weighted average normal of
+/// the triangles around them.
+fn smooth_normals(mesh: &mut Mesh) {
+    let missing: Vec<bool> = mesh.vertices.iter().map(|v| v.normal.is_zero()).collect();
+    let mut sums = vec![Vector3::zero(); mesh.vertices.len()];
+    for triangle in mesh.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+        let v = &mesh.vertices;
+        let normal = (v[b].pos - v[a].pos).cross(v[c].pos - v[a].pos);
+        for i in [a, b, c] {
+            sums[i] += normal;
+        }
+    }
+    for (i, vertex) in mesh.vertices.iter_mut().enumerate() {
+        if missing[i] && sums[i].magnitude2() > 0.0 {
+            vertex.normal = sums[i].normalize();
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashSet;
+
+use miniquad::{KeyCode, KeyMods};
+
+use crate::debug::DebugFlags;
+use crate::text::TextRenderer;
+
+/// Everything the stage can be told to do from the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveForward,
+    MoveLeft,
+    MoveBack,
+    MoveRight,
+    ScrubBack,
+    ScrubForward,
+    Help,
+    Quit,
+    ToggleConsole,
+    ToggleEditing,
+    Translate,
+    Rotate,
+    Scale,
+    Delete,
+    Undo,
+    Redo,
+    SpawnPillar,
+    PlaceDecal,
+    CopyPose,
+    PastePose,
+    CycleGrading,
+    ToggleVignette,
+    ToggleGrain,
+    ToggleDof,
+    ToggleAutofocus,
+    FocusNearer,
+    FocusFarther,
+    ToggleWater,
+    ToggleReflections,
+    ToggleLightmap,
+    ToggleProjection,
+    CycleDepthMode,
+    ToggleDayNight,
+    ToggleMotionBlur,
+    ToggleMinimap,
+    CyclePip,
+    ToggleHud,
+    ToggleWalk,
+    CycleAntialiasing,
+    Zoom,
+    WiderFov,
+    NarrowerFov,
+    TogglePause,
+    StepFrame,
+}
+
+pub struct Binding {
+    pub key: KeyCode,
+    pub ctrl: bool,
+    /// Only active in edit mode.
+    pub editing: bool,
+    pub action: Action,
+    pub description: &'static str,
+}
+
+const fn bind(key: KeyCode, action: Action, description: &'static str) -> Binding {
+    Binding {
+        key,
+        ctrl: false,
+        editing: false,
+        action,
+        description,
+    }
+}
+
+const fn ctrl(key: KeyCode, action: Action, description: &'static str) -> Binding {
+    Binding {
+        ctrl: true,
+        ..bind(key, action, description)
+    }
+}
+
+const fn editing(key: KeyCode, action: Action, description: &'static str) -> Binding {
+    Binding {
+        editing: true,
+        ..bind(key, action, description)
+    }
+}
+
+/// The key map. Movement and scrubbing act while their keys are held,
+/// everything else once per press. Debug views bind their own keys in
+/// `DebugFlags`.
+pub const BINDINGS: &[Binding] = &[
+    bind(KeyCode::W, Action::MoveForward, "move forward"),
+    bind(KeyCode::A, Action::MoveLeft, "move left"),
+    bind(KeyCode::S, Action::MoveBack, "move back"),
+    bind(KeyCode::D, Action::MoveRight, "move right"),
+    bind(KeyCode::Comma, Action::ScrubBack, "scrub time of day back"),
+    bind(
+        KeyCode::Period,
+        Action::ScrubForward,
+        "scrub time of day forward",
+    ),
+    bind(KeyCode::F1, Action::Help, "show this help"),
+    bind(KeyCode::Escape, Action::Quit, "quit"),
+    bind(KeyCode::GraveAccent, Action::ToggleConsole, "console"),
+    bind(KeyCode::Tab, Action::ToggleEditing, "edit mode"),
+    editing(KeyCode::Key1, Action::Translate, "translate gizmo"),
+    editing(KeyCode::Key2, Action::Rotate, "rotate gizmo"),
+    editing(KeyCode::Key3, Action::Scale, "scale gizmo"),
+    editing(KeyCode::Delete, Action::Delete, "delete selection"),
+    ctrl(KeyCode::Z, Action::Undo, "undo"),
+    ctrl(KeyCode::Y, Action::Redo, "redo"),
+    bind(KeyCode::J, Action::SpawnPillar, "spawn a pillar"),
+    bind(KeyCode::P, Action::PlaceDecal, "place a decal"),
+    ctrl(KeyCode::C, Action::CopyPose, "copy camera pose"),
+    ctrl(KeyCode::V, Action::PastePose, "paste camera pose"),
+    bind(KeyCode::L, Action::CycleGrading, "cycle color grading"),
+    bind(KeyCode::V, Action::ToggleVignette, "vignette"),
+    bind(KeyCode::G, Action::ToggleGrain, "film grain"),
+    bind(KeyCode::B, Action::ToggleDof, "depth of field"),
+    bind(KeyCode::N, Action::ToggleAutofocus, "autofocus"),
+    bind(KeyCode::LeftBracket, Action::FocusNearer, "focus nearer"),
+    bind(KeyCode::RightBracket, Action::FocusFarther, "focus farther"),
+    bind(KeyCode::H, Action::ToggleWater, "water"),
+    bind(
+        KeyCode::R,
+        Action::ToggleReflections,
+        "screen-space reflections",
+    ),
+    bind(KeyCode::K, Action::ToggleLightmap, "bake/drop lightmap"),
+    bind(
+        KeyCode::O,
+        Action::ToggleProjection,
+        "perspective/orthographic",
+    ),
+    bind(KeyCode::I, Action::CycleDepthMode, "cycle depth mode"),
+    bind(KeyCode::T, Action::ToggleDayNight, "pause day/night cycle"),
+    bind(KeyCode::M, Action::ToggleMotionBlur, "motion blur"),
+    bind(KeyCode::U, Action::ToggleMinimap, "minimap"),
+    bind(
+        KeyCode::Y,
+        Action::CyclePip,
+        "cycle picture-in-picture view",
+    ),
+    bind(KeyCode::X, Action::ToggleHud, "HUD"),
+    bind(KeyCode::C, Action::ToggleWalk, "walk/fly"),
+    bind(KeyCode::F, Action::CycleAntialiasing, "cycle anti-aliasing"),
+    bind(KeyCode::Z, Action::Zoom, "zoom in/out"),
+    bind(KeyCode::Equal, Action::WiderFov, "widen field of view"),
+    bind(KeyCode::Minus, Action::NarrowerFov, "narrow field of view"),
+    bind(
+        KeyCode::Space,
+        Action::TogglePause,
+        "pause/resume simulation",
+    ),
+    bind(
+        KeyCode::Slash,
+        Action::StepFrame,
+        "step one frame while paused",
+    ),
+];
+
+/// The action a key press triggers, if any. With Ctrl held a Ctrl binding
+/// wins over a plain one on the same key.
+pub fn action(key: KeyCode, mods: KeyMods, edit_mode: bool) -> Option<Action> {
+    let mut matching = BINDINGS
+        .iter()
+        .filter(|b| b.key == key && (mods.ctrl || !b.ctrl) && (edit_mode || !b.editing));
+    let first = matching.next()?;
+    Some(matching.find(|b| b.ctrl).unwrap_or(first).action)
+}
+
+/// Whether any key bound to a held action is down.
+pub fn held(keys_down: &HashSet<KeyCode>, action: Action) -> bool {
+    BINDINGS
+        .iter()
+        .any(|b| b.action == action && keys_down.contains(&b.key))
+}
+
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Key1 => "1".to_owned(),
+        KeyCode::Key2 => "2".to_owned(),
+        KeyCode::Key3 => "3".to_owned(),
+        KeyCode::Comma => ",".to_owned(),
+        KeyCode::Period => ".".to_owned(),
+        KeyCode::LeftBracket => "[".to_owned(),
+        KeyCode::RightBracket => "]".to_owned(),
+        KeyCode::Slash => "/".to_owned(),
+        KeyCode::GraveAccent => "`".to_owned(),
+        KeyCode::Escape => "Esc".to_owned(),
+        _ => format!("{:?}", key),
+    }
+}
+
+/// Lists every binding, including the debug views', in columns over the
+/// middle of the screen. `offset` slides the panel up, by its full height
+/// plus the space above it at 1.
+pub fn draw_help(text: &mut TextRenderer, debug: &DebugFlags, offset: f32) {
+    let mut lines: Vec<(String, String)> = BINDINGS
+        .iter()
+        .map(|b| {
+            let mut key = key_name(b.key);
+            if b.ctrl {
+                key = format!("Ctrl+{}", key);
+            }
+            let mut description = b.description.to_owned();
+            if b.editing {
+                description += " (edit mode)";
+            }
+            (key, description)
+        })
+        .collect();
+    lines.extend(debug.iter().filter_map(|flag| {
+        let key = debug.key(flag)?;
+        Some((key_name(key), format!("debug: {}", debug.name(flag))))
+    }));
+
+    let (width, height) = miniquad::window::screen_size();
+    let margin = text.scale * 4.0;
+    let line = text.line_height();
+    let key_width = lines.iter().map(|(k, _)| k.len()).max().unwrap_or(0) + 2;
+    let column_chars = key_width + lines.iter().map(|(_, d)| d.len()).max().unwrap_or(0) + 4;
+    let column_width = column_chars as f32 * text.char_width();
+    let rows = (((height - margin * 2.0) / line).floor() as usize).max(1);
+    let columns = lines.len().div_ceil(rows);
+    let rows = lines.len().div_ceil(columns.max(1));
+
+    let panel_width = columns as f32 * column_width + margin * 2.0;
+    let panel_height = rows as f32 * line + margin * 2.0;
+    let left = ((width - panel_width) / 2.0).max(0.0);
+    let top = ((height - panel_height) / 2.0).max(0.0);
+    let top = top - offset * (top + panel_height);
+    text.rect(
+        left,
+        top,
+        panel_width,
+        panel_height,
+        [0.05, 0.05, 0.08, 0.85],
+    );
+    for (i, (key, description)) in lines.iter().enumerate() {
+        let x = left + margin + (i / rows) as f32 * column_width;
+        let y = top + margin + (i % rows) as f32 * line;
+        text.print(x, y, [1.0, 0.85, 0.3, 1.0], key);
+        let x = x + key_width as f32 * text.char_width();
+        text.print(x, y, [0.85, 0.85, 0.85, 1.0], description);
+    }
+}
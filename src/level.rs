@@ -0,0 +1,269 @@
+use std::{collections::HashMap, fs};
+
+use cgmath::{vec3, EuclideanSpace, Point3, Vector3, Zero};
+use serde::Deserialize;
+
+use crate::mesh::{MeshId, MeshLibrary};
+use crate::prefab::{MaterialDef, PrefabLibrary, TransformDef};
+use crate::scene::{Node, NodeId, Scene, Transform};
+
+pub const LEVEL_DIR: &str = "levels";
+/// Floors are slabs this thick, with their top at the cell's height.
+const FLOOR_THICKNESS: f32 = 0.2;
+
+/// A level file: a grid of characters extruded into floors and walls,
+/// with props and spawn points placed on it and the lighting to show it
+/// in. Row 0 is the northern (-Z) edge and the grid is centered on the
+/// origin. Characters:
+///
+/// - `#` a wall, `wall_height` tall
+/// - `.` floor
+/// - `1` to `9` floor raised by that many `step_height`s
+/// - `^`, `>`, `v`, `<` floor with a spawn point facing north, east,
+///   south or west
+/// - a space, nothing
+/// - anything in `legend`, floor with that prop in the middle of the cell
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct LevelDef {
+    /// Width of a grid cell in world units.
+    pub cell_size: f32,
+    pub wall_height: f32,
+    pub step_height: f32,
+    pub floor: MaterialDef,
+    pub wall: MaterialDef,
+    pub map: Vec<String>,
+    pub legend: HashMap<char, PropDef>,
+    /// Props placed anywhere, relative to the level's origin rather than
+    /// to a cell.
+    pub props: Vec<PropDef>,
+    pub lighting: LightingDef,
+}
+
+impl Default for LevelDef {
+    fn default() -> LevelDef {
+        LevelDef {
+            cell_size: 2.0,
+            wall_height: 3.0,
+            step_height: 0.25,
+            floor: MaterialDef {
+                color: (0.55, 0.55, 0.52, 1.0),
+                ..MaterialDef::default()
+            },
+            wall: MaterialDef {
+                color: (0.75, 0.72, 0.66, 1.0),
+                ..MaterialDef::default()
+            },
+            map: vec![],
+            legend: HashMap::new(),
+            props: vec![],
+            lighting: LightingDef::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PropDef {
+    pub prefab: String,
+    /// Relative to the floor in the middle of the cell, for props in the
+    /// legend.
+    #[serde(default)]
+    pub transform: TransformDef,
+    /// Glow added to the prop's lighting; the renderer has no point
+    /// lights, so lamps are props that glow.
+    #[serde(default)]
+    pub emissive: f32,
+}
+
+/// The lighting the level is shown in. Missing values leave the current
+/// lighting alone.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LightingDef {
+    /// Time of day in hours, which places the sun.
+    pub time: Option<f32>,
+    /// Whether the day/night cycle keeps running.
+    pub cycle: Option<bool>,
+    pub fog: Option<f32>,
+}
+
+/// Where the player can start, feet on the floor.
+#[derive(Clone, Copy, Debug)]
+pub struct Spawn {
+    pub position: Point3<f32>,
+    pub yaw: f32,
+}
+
+pub struct Level {
+    pub spawns: Vec<Spawn>,
+    pub lighting: LightingDef,
+}
+
+/// A floor or wall block, merged across a run of equal cells in a row.
+#[derive(PartialEq)]
+struct Block {
+    bottom: f32,
+    top: f32,
+    wall: bool,
+}
+
+/// Reads and parses `levels/<file>` without touching any scene, so a
+/// caller can check it before clearing what is loaded.
+pub fn read_level(file: &str) -> Result<LevelDef, String> {
+    let path = format!("{}/{}", LEVEL_DIR, file);
+    let text =
+        fs::read_to_string(&path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    ron::from_str(&text).map_err(|err| format!("could not parse {}: {}", path, err))
+}
+
+/// Builds a parsed level into the scene, under one root node named after
+/// `file`. Props that fail to spawn are reported and left out.
+pub fn build_level(
+    file: &str,
+    def: LevelDef,
+    prefabs: &PrefabLibrary,
+    scene: &mut Scene,
+    meshes: &MeshLibrary,
+) -> Result<Level, String> {
+    let path = format!("{}/{}", LEVEL_DIR, file);
+    let cube = meshes.find("cube").ok_or("no cube mesh")?;
+
+    let root = scene.add(Node::new(file.trim_end_matches(".ron")), None);
+    let size = def.cell_size;
+    let columns = def
+        .map
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0);
+    let origin = vec3(
+        -(columns as f32) * size / 2.0,
+        0.0,
+        -(def.map.len() as f32) * size / 2.0,
+    );
+    let center = |column: usize, row: usize, height: f32| {
+        origin
+            + vec3(
+                (column as f32 + 0.5) * size,
+                height,
+                (row as f32 + 0.5) * size,
+            )
+    };
+
+    let mut spawns = vec![];
+    let mut props = vec![];
+    for (row, line) in def.map.iter().enumerate() {
+        let mut run: Option<(usize, Block)> = None;
+        let cells = line.chars().map(Some).chain([None]);
+        for (column, cell) in cells.enumerate() {
+            let floor = Block {
+                bottom: -FLOOR_THICKNESS,
+                top: 0.0,
+                wall: false,
+            };
+            let block = match cell {
+                None | Some(' ') => None,
+                Some('#') => Some(Block {
+                    top: def.wall_height,
+                    wall: true,
+                    ..floor
+                }),
+                Some(c @ '1'..='9') => Some(Block {
+                    top: c.to_digit(10).unwrap() as f32 * def.step_height,
+                    ..floor
+                }),
+                Some(c) => {
+                    let yaw = match c {
+                        '^' => Some(0.0),
+                        '<' => Some(90.0f32.to_radians()),
+                        'v' => Some(180.0f32.to_radians()),
+                        '>' => Some(-90.0f32.to_radians()),
+                        _ => None,
+                    };
+                    if let Some(yaw) = yaw {
+                        spawns.push(Spawn {
+                            position: Point3::from_vec(center(column, row, 0.0)),
+                            yaw,
+                        });
+                    } else if let Some(prop) = def.legend.get(&c) {
+                        props.push((prop, center(column, row, 0.0)));
+                    } else if c != '.' {
+                        println!("Unknown cell {:?} in {}, row {}", c, path, row + 1);
+                    }
+                    Some(floor)
+                }
+            };
+            // Close the run when this cell differs from it.
+            if run.as_ref().map(|(_, b)| b) != block.as_ref() {
+                if let Some((start, block)) = run.take() {
+                    add_block(
+                        scene,
+                        root,
+                        &def,
+                        cube,
+                        (center(start, row, 0.0), center(column - 1, row, 0.0)),
+                        &block,
+                    );
+                }
+                run = block.map(|block| (column, block));
+            }
+        }
+    }
+
+    let placed = props
+        .into_iter()
+        .chain(def.props.iter().map(|prop| (prop, Vector3::zero())));
+    for (prop, offset) in placed {
+        let mut transform = prop.transform.transform();
+        transform.position += offset;
+        match prefabs.instantiate(&prop.prefab, scene, meshes, Some(transform), Some(root)) {
+            Ok(id) if prop.emissive != 0.0 => set_emissive(scene, id, prop.emissive),
+            Ok(_) => (),
+            Err(err) => println!("Skipping prop in {}: {}", path, err),
+        }
+    }
+
+    Ok(Level {
+        spawns,
+        lighting: def.lighting,
+    })
+}
+
+/// One box spanning the cells whose centers are `first` and `last`.
+fn add_block(
+    scene: &mut Scene,
+    root: NodeId,
+    def: &LevelDef,
+    cube: MeshId,
+    (first, last): (Vector3<f32>, Vector3<f32>),
+    block: &Block,
+) {
+    let length = last.x - first.x + def.cell_size;
+    let height = block.top - block.bottom;
+    let position = vec3(
+        (first.x + last.x) / 2.0,
+        (block.top + block.bottom) / 2.0,
+        first.z,
+    );
+    let node = Node {
+        mesh: Some(cube),
+        material: if block.wall { &def.wall } else { &def.floor }.material(),
+        transform: Transform {
+            scale: vec3(length, height, def.cell_size),
+            ..Transform::from_position(position)
+        },
+        ..Node::new(if block.wall { "wall" } else { "floor" })
+    };
+    scene.add(node, Some(root));
+}
+
+fn set_emissive(scene: &mut Scene, root: NodeId, emissive: f32) {
+    let mut pending = vec![root];
+    while let Some(id) = pending.pop() {
+        let Some(node) = scene.get_mut(id) else {
+            continue;
+        };
+        pending.extend(&node.children);
+        node.params.emissive = emissive;
+    }
+}
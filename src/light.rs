@@ -0,0 +1,40 @@
+use cgmath::{vec3, InnerSpace, Vector3};
+
+pub struct DirectionalLight {
+    /// Unit vector pointing towards the light.
+    pub direction: Vector3<f32>,
+    /// Linear color, multiplied by `intensity`.
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+impl DirectionalLight {
+    pub fn radiance(&self) -> Vector3<f32> {
+        self.color * self.intensity
+    }
+}
+
+/// Light shared by the scene shading and the sky.
+pub struct Lighting {
+    pub sun: DirectionalLight,
+    /// Flat fill light standing in for the sky's contribution.
+    pub ambient: Vector3<f32>,
+    pub fog_color: Vector3<f32>,
+    /// Exponential-squared fog density, per world unit.
+    pub fog_density: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Lighting {
+        Lighting {
+            sun: DirectionalLight {
+                direction: vec3(0.4, 0.6, 0.7).normalize(),
+                color: vec3(1.0, 0.95, 0.85),
+                intensity: 1.0,
+            },
+            ambient: vec3(0.12, 0.15, 0.2),
+            fog_color: vec3(0.5, 0.6, 0.75),
+            fog_density: 0.05,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use cgmath::{vec2, InnerSpace, Matrix, Matrix3, Matrix4, SquareMatrix, Vector2, Vector3};
+use miniquad::*;
+
+use crate::light::DirectionalLight;
+use crate::mesh::MeshLibrary;
+use crate::picking::{ray_triangle, Ray};
+use crate::scene::{Instance, NodeId};
+
+/// Texels per instance tile, including a one texel border against bleeding.
+const TILE: usize = 32;
+/// Baked irradiance is stored divided by this, to leave headroom in 8 bits.
+pub const RANGE: f32 = 2.0;
+const SHADOW_BIAS: f32 = 1e-3;
+
+/// Baked direct sunlight for static instances. Every instance gets its own
+/// tile in the atlas; `scale_offset` maps the mesh's `uv2` into it.
+pub struct Lightmap {
+    pub texture: TextureId,
+    pub scale_offset: HashMap<NodeId, [f32; 4]>,
+}
+
+struct WorldTriangle {
+    p: [Vector3<f32>; 3],
+    n: [Vector3<f32>; 3],
+    uv: [Vector2<f32>; 3],
+}
+
+/// Bakes sun light, with shadows cast by every instance onto every other,
+/// by tracing one ray per texel.
+pub fn bake(
+    ctx: &mut dyn RenderingBackend,
+    meshes: &MeshLibrary,
+    instances: &[Instance],
+    sun: &DirectionalLight,
+) -> Lightmap {
+    let per_instance: Vec<Vec<WorldTriangle>> = instances
+        .iter()
+        .map(|instance| {
+            let world = &instance.world;
+            let normal_matrix: Matrix3<f32> = world_normal_matrix(world);
+            meshes
+                .get(instance.mesh)
+                .mesh
+                .triangles()
+                .map(|t| WorldTriangle {
+                    p: t.map(|v| (world * v.pos.extend(1.0)).truncate()),
+                    n: t.map(|v| (normal_matrix * v.normal).normalize()),
+                    uv: t.map(|v| v.uv2),
+                })
+                .collect()
+        })
+        .collect();
+    let occluders: Vec<&WorldTriangle> = per_instance.iter().flatten().collect();
+
+    let tiles = (instances.len() as f32).sqrt().ceil().max(1.0) as usize;
+    let size = tiles * TILE;
+    let mut texels = vec![0u8; size * size * 4];
+    let mut scale_offset = HashMap::with_capacity(instances.len());
+
+    for (i, triangles) in per_instance.iter().enumerate() {
+        let (tile_x, tile_y) = (i % tiles, i / tiles);
+        let inner = (TILE - 2) as f32;
+        scale_offset.insert(
+            instances[i].node,
+            [
+                inner / size as f32,
+                inner / size as f32,
+                (tile_x * TILE + 1) as f32 / size as f32,
+                (tile_y * TILE + 1) as f32 / size as f32,
+            ],
+        );
+
+        for y in 0..TILE {
+            for x in 0..TILE {
+                // Border texels take the value of the nearest inner one.
+                let u = ((x as f32 - 1.0).clamp(0.0, inner - 1.0) + 0.5) / inner;
+                let v = ((y as f32 - 1.0).clamp(0.0, inner - 1.0) + 0.5) / inner;
+                let Some((pos, normal)) = surface_at(triangles, vec2(u, v)) else {
+                    continue;
+                };
+
+                let n_dot_l = normal.dot(sun.direction).max(0.0);
+                let lit = n_dot_l > 0.0
+                    && !occluders.iter().any(|t| {
+                        let ray = Ray {
+                            origin: pos + normal * SHADOW_BIAS,
+                            direction: sun.direction,
+                        };
+                        ray_triangle(&ray, t.p).is_some_and(|d| d > SHADOW_BIAS)
+                    });
+                let irradiance = if lit {
+                    sun.radiance() * n_dot_l
+                } else {
+                    Vector3::new(0.0, 0.0, 0.0)
+                };
+
+                let at = ((tile_y * TILE + y) * size + tile_x * TILE + x) * 4;
+                for (c, value) in [irradiance.x, irradiance.y, irradiance.z]
+                    .iter()
+                    .enumerate()
+                {
+                    texels[at + c] = ((value / RANGE).clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+                texels[at + 3] = 255;
+            }
+        }
+    }
+
+    let texture = ctx.new_texture_from_rgba8(size as u16, size as u16, &texels);
+    Lightmap {
+        texture,
+        scale_offset,
+    }
+}
+
+fn world_normal_matrix(world: &Matrix4<f32>) -> Matrix3<f32> {
+    let m = Matrix3::from_cols(world.x.truncate(), world.y.truncate(), world.z.truncate());
+    m.invert().unwrap_or(m).transpose()
+}
+
+/// Finds the point whose lightmap coordinate is `uv`. Texels slightly
+/// outside every triangle snap to the closest one, so edges don't go dark
+/// under bilinear filtering.
+fn surface_at(
+    triangles: &[WorldTriangle],
+    uv: Vector2<f32>,
+) -> Option<(Vector3<f32>, Vector3<f32>)> {
+    let (t, bary) = triangles
+        .iter()
+        .filter_map(|t| barycentric(t.uv, uv).map(|b| (t, b)))
+        .max_by(|(_, a), (_, b)| min3(*a).total_cmp(&min3(*b)))?;
+    if min3(bary) < -0.1 {
+        return None;
+    }
+    let clamped = bary.map(|w| w.max(0.0));
+    let sum: f32 = clamped.iter().sum();
+    let w = clamped.map(|w| w / sum);
+    let pos = t.p[0] * w[0] + t.p[1] * w[1] + t.p[2] * w[2];
+    let normal = (t.n[0] * w[0] + t.n[1] * w[1] + t.n[2] * w[2]).normalize();
+    Some((pos, normal))
+}
+
+fn min3(v: [f32; 3]) -> f32 {
+    v[0].min(v[1]).min(v[2])
+}
+
+fn barycentric(t: [Vector2<f32>; 3], p: Vector2<f32>) -> Option<[f32; 3]> {
+    let (e0, e1, e2) = (t[1] - t[0], t[2] - t[0], p - t[0]);
+    let det = e0.x * e1.y - e1.x * e0.y;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let v = (e2.x * e1.y - e1.x * e2.y) / det;
+    let w = (e0.x * e2.y - e2.x * e0.y) / det;
+    Some([1.0 - v - w, v, w])
+}
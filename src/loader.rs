@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::import::{self, Asset};
+
+/// Bytes of decoded assets handed over for upload in one frame. The first
+/// asset of a frame always goes, however big.
+const UPLOAD_BUDGET: usize = 16 << 20;
+
+/// A file decoded by a worker.
+pub struct Loaded {
+    pub path: PathBuf,
+    pub asset: Result<Asset, String>,
+}
+
+/// Reads and decodes files on worker threads, so the main thread only
+/// uploads the results, a few per frame.
+///
+/// The workers are a pool of their own rather than rayon's global one, so
+/// a long decode never holds up the per-frame parallel work.
+pub struct Loader {
+    pool: ThreadPool,
+    sender: Sender<Loaded>,
+    receiver: Receiver<Loaded>,
+    /// Decoded but not yet handed over, in the order they finished.
+    ready: VecDeque<Loaded>,
+    /// Requested but not yet handed over.
+    pending: usize,
+}
+
+impl Loader {
+    pub fn new() -> Loader {
+        let pool = ThreadPoolBuilder::new()
+            .thread_name(|i| format!("loader {}", i))
+            .build()
+            .expect("could not start loader threads");
+        let (sender, receiver) = channel();
+        Loader {
+            pool,
+            sender,
+            receiver,
+            ready: VecDeque::new(),
+            pending: 0,
+        }
+    }
+
+    /// Starts decoding a file.
+    pub fn request(&mut self, path: PathBuf) {
+        let sender = self.sender.clone();
+        self.pending += 1;
+        self.pool.spawn(move || {
+            let asset = import::load(&path);
+            // The loader is gone only when the program is exiting.
+            let _ = sender.send(Loaded { path, asset });
+        });
+    }
+
+    /// Starts decoding every file in a directory that `import::load`
+    /// understands, in name order. Returns how many there are.
+    pub fn request_dir(&mut self, dir: &Path) -> Result<usize, String> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .map_err(|err| err.to_string())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_supported(path))
+            .collect();
+        paths.sort();
+        let count = paths.len();
+        for path in paths {
+            self.request(path);
+        }
+        Ok(count)
+    }
+
+    /// Files requested and not yet returned by `poll`.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// The decoded files to upload this frame, within the upload budget.
+    pub fn poll(&mut self) -> Vec<Loaded> {
+        self.ready.extend(self.receiver.try_iter());
+        let mut budget = UPLOAD_BUDGET;
+        let mut batch = vec![];
+        while let Some(next) = self.ready.front() {
+            let size = next.asset.as_ref().map_or(0, Asset::upload_size);
+            if !batch.is_empty() && size > budget {
+                break;
+            }
+            budget = budget.saturating_sub(size);
+            batch.extend(self.ready.pop_front());
+        }
+        self.pending -= batch.len();
+        batch
+    }
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| import::EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
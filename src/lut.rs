@@ -0,0 +1,99 @@
+use std::{fs, path::Path};
+
+use miniquad::*;
+
+use crate::post::{Effect, Frame, Quad};
+use crate::texture::{load_png, ColorSpace};
+
+const LUT_DIR: &str = "luts";
+
+/// Color grading through 16x16x16 LUTs stored as 256x16 strip PNGs, the
+/// format most grading tools export. The first entry is "no grading".
+pub struct ColorGrading {
+    pipeline: Pipeline,
+    luts: Vec<(String, TextureId)>,
+    current: usize,
+}
+
+impl ColorGrading {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> ColorGrading {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+
+        let mut paths: Vec<_> = fs::read_dir(LUT_DIR)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|e| e == "png"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        let luts = paths
+            .iter()
+            .filter_map(|path| load_lut(ctx, path))
+            .collect();
+
+        ColorGrading {
+            pipeline,
+            luts,
+            current: 0,
+        }
+    }
+
+    pub fn cycle(&mut self) {
+        self.current = (self.current + 1) % (self.luts.len() + 1);
+        match self.current {
+            0 => println!("Color grading: off"),
+            i => println!("Color grading: {}", self.luts[i - 1].0),
+        }
+    }
+}
+
+fn load_lut(ctx: &mut dyn RenderingBackend, path: &Path) -> Option<(String, TextureId)> {
+    let bytes = fs::read(path)
+        .map_err(|err| println!("Could not read LUT {}: {}", path.display(), err))
+        .ok()?;
+    let texture = load_png(ctx, &bytes, ColorSpace::Linear)
+        .map_err(|err| println!("Could not decode LUT {}: {}", path.display(), err))
+        .ok()?;
+    if ctx.texture_size(texture) != (256, 16) {
+        println!("Skipping LUT {}: expected a 256x16 strip", path.display());
+        ctx.delete_texture(texture);
+        return None;
+    }
+    let name = path.file_stem()?.to_string_lossy().into_owned();
+    Some((name, texture))
+}
+
+impl Effect for ColorGrading {
+    fn enabled(&self) -> bool {
+        self.current != 0
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        _frame: &Frame,
+        _pass: usize,
+    ) {
+        let lut = self.luts[self.current - 1].1;
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source, lut]));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/lut.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["source".to_owned(), "lut".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![] },
+        }
+    }
+}
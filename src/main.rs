@@ -1,242 +1,1569 @@
-use std::{collections::HashSet, time::Instant};
+use std::{collections::{HashMap, HashSet}, f32::consts::{PI, TAU}, time::Instant};
 
 use miniquad::{*};
-use cgmath::{Vector3, Vector4, vec4, Matrix4, SquareMatrix, vec3, perspective, Deg, Point3, point3, Matrix3, EuclideanSpace, Rad, Basis3, Rotation3};
+use cgmath::{Vector2, Vector3, Vector4, Zero, vec2, vec4, Matrix4, SquareMatrix, vec3, Point3, EuclideanSpace, InnerSpace, MetricSpace};
 use shader::Uniforms;
 
-#[repr(C)]
-struct Vertex {
-    pos: Vector3<f32>,
-    color: Vector4<f32>,
-}
+mod animation;
+mod atlas;
+mod batching;
+mod benchmark;
+mod camera;
+mod chunks;
+mod color;
+mod commands;
+mod compressed;
+mod console;
+mod culling;
+mod daynight;
+mod debug;
+mod debug_draw;
+mod decal;
+mod editor;
+mod environment;
+mod dof;
+mod dynamic_buffer;
+mod film;
+mod fxaa;
+mod generate;
+mod gizmo;
+mod history;
+mod hud;
+mod import;
+mod input;
+mod gfx;
+mod level;
+mod light;
+mod lightmap;
+mod loader;
+mod lut;
+mod mesh;
+#[cfg(feature = "networking")]
+mod net;
+mod minimap;
+mod motion_blur;
+mod oit;
+mod palette;
+mod picking;
+mod pip;
+mod pipelines;
+mod post;
+mod prefab;
+mod raymarch;
+mod render_queue;
+#[cfg(feature = "remote")]
+mod remote;
+mod scatter;
+mod scene;
+#[cfg(feature = "scripting")]
+mod script;
+mod session;
+mod settings;
+mod sky;
+mod skin;
+mod ssr;
+mod stats;
+mod taa;
+mod text;
+mod time;
+mod texture;
+mod tween;
+mod velocity;
+mod voxel;
+mod walk;
+mod water;
+
+use animation::Animation;
+use batching::StaticBatches;
+use benchmark::Benchmark;
+use camera::{Camera, DepthMode, Projection, MIN_ORTHO_SIZE, MAX_ORTHO_SIZE};
+use chunks::ChunkManager;
+use console::Console;
+use culling::Frustum;
+use daynight::DayNight;
+use debug::{DebugFlags, DebugViews};
+use debug_draw::DebugDraw;
+use decal::Decals;
+use editor::EditorPanels;
+use environment::Environment;
+use dof::DepthOfField;
+use film::{Grain, Vignette};
+use fxaa::Fxaa;
+use generate::Generation;
+use gfx::compile_shader;
+use gizmo::{Gizmo, GizmoMode};
+use input::Action;
+use history::{Edit, History};
+use hud::Hud;
+use level::Spawn;
+use light::Lighting;
+use lightmap::Lightmap;
+use loader::Loader;
+use lut::ColorGrading;
+use mesh::{Mesh, MeshLibrary};
+use minimap::Minimap;
+use motion_blur::MotionBlur;
+use oit::Oit;
+use palette::{ColorScheme, Palette};
+use pip::Pip;
+use pipelines::{Blend, ScenePipelines};
+use post::{Chain, Frame, Present, Quad, RenderTarget};
+use prefab::PrefabLibrary;
+use raymarch::Raymarcher;
+use render_queue::{DrawCommand, RenderQueue};
+use scatter::Scatter;
+use scene::{Instance, Material, NodeId, Scene, Transform, MAX_INSTANCES};
+use skin::MAX_JOINTS;
+use session::{CameraState, Session};
+use settings::{Antialiasing, Settings, Transparency};
+use sky::Sky;
+use ssr::Reflections;
+use stats::{CountingBackend, SceneTimings};
+use taa::Taa;
+use text::TextRenderer;
+use time::Time;
+use tween::{Ease, Tween, Tweens};
+use velocity::VelocityPass;
+use voxel::VoxelWorld;
+use walk::Walker;
+use water::Water;
+
+/// Factor the camera speed changes by per mouse wheel notch.
+const SPEED_STEP: f32 = 1.25;
+const MIN_SPEED: f32 = 0.01;
+const MAX_SPEED: f32 = 1000.0;
+/// Factor the orthographic view size changes by per mouse wheel notch.
+const ORTHO_STEP: f32 = 1.25;
+/// Seconds the camera speed stays on screen after a change, fading out.
+const SPEED_DISPLAY_TIME: f32 = 1.5;
+/// Field of view is divided by this while zoomed in.
+const ZOOM: f32 = 4.0;
+const ZOOM_TIME: f32 = 0.25;
+/// Degrees the field of view changes by per key press, within the range
+/// the keys reach. The console can go further.
+const FOV_STEP: f32 = 5.0;
+const MIN_FOV: f32 = 60.0;
+const MAX_FOV: f32 = 110.0;
+const FOV_TIME: f32 = 0.3;
+/// Seconds a pasted camera pose takes to fly to.
+const TRANSITION_TIME: f32 = 0.8;
+const SCREENSHOT_DIR: &str = "screenshots";
 
 struct Stage {
-    pipeline: Pipeline,
-    bindings: Bindings,
-    ctx: Box<dyn RenderingBackend>,
-    perspective: Matrix4<f32>,
-    camera_pos: Point3<f32>,
-    view: Matrix4<f32>,
+    pipelines: ScenePipelines,
+    meshes: MeshLibrary,
+    prefabs: PrefabLibrary,
+    scene: Scene,
+    /// Imports being decoded in the background.
+    loader: Loader,
+    white: TextureId,
+    lightmap: Option<Lightmap>,
+    /// Small static objects merged to save draw calls, if baked.
+    static_batches: StaticBatches,
+    /// Terrain streamed around the camera, when turned on.
+    chunks: ChunkManager,
+    /// Editable blocks, when turned on.
+    voxels: VoxelWorld,
+    /// Scene draw calls, sorted before they are submitted.
+    render_queue: RenderQueue<Uniforms>,
+    debug: DebugFlags,
+    views: DebugViews,
+    debug_draw: DebugDraw,
+    /// Lines drawn over the final image, like gizmo handles.
+    overlay_lines: DebugDraw,
+    /// Edit mode frees the cursor for selecting and moving objects.
+    editing: bool,
+    gizmo: Gizmo,
+    history: History,
+    /// Hierarchy and inspector, shown in edit mode.
+    panels: EditorPanels,
+    /// Mouse position in pixels.
+    cursor: Vector2<f32>,
+    /// Counts draws and uploads for the stats overlay.
+    ctx: Box<CountingBackend>,
+    scene_timings: SceneTimings,
+    /// A single worker, to run the scene update on when parallel updates
+    /// are turned off, for comparison.
+    serial_pool: rayon::ThreadPool,
+    scene_target: RenderTarget,
+    decals: Decals,
+    water: Water,
+    velocity: VelocityPass,
+    oit: Oit,
+    taa: Taa,
+    quad: Quad,
+    sky: Sky,
+    /// Signed distance field shapes drawn among the meshes, when turned on.
+    raymarcher: Raymarcher,
+    /// Instanced grass and rocks on the terrain, when turned on.
+    scatter: Scatter,
+    lighting: Lighting,
+    day_night: DayNight,
+    post: Chain,
+    reflections: Reflections,
+    dof: DepthOfField,
+    motion_blur: MotionBlur,
+    grading: ColorGrading,
+    fxaa: Fxaa,
+    vignette: Vignette,
+    grain: Grain,
+    present: Present,
+    /// Top-down view around the camera, in a corner of the screen.
+    minimap: Minimap,
+    /// A second camera's view, at the top of the screen.
+    pip: Pip,
+    text: TextRenderer,
+    hud: Hud,
+    walker: Walker,
+    /// Spawn points of the loaded level.
+    spawns: Vec<Spawn>,
+    console: Console<Stage>,
+    settings: Settings,
+    /// Follows the color settings.
+    palette: Palette,
+    camera: Camera,
+    /// Follows `camera` unless the freeze_culling debug flag is set.
+    cull_camera: Camera,
+    prev_view_proj: Matrix4<f32>,
+    /// World transforms of the previous frame, for motion vectors.
+    prev_world: HashMap<NodeId, Matrix4<f32>>,
     keys_down: HashSet<KeyCode>,
-    last_frame: Instant,
-    rotate_x: f32,
-    rotate_y: f32,
-    fov: f32,
-    near: f32,
-    far: f32
+    show_help: bool,
+    /// How far the help overlay is slid up out of view, 1 being hidden.
+    help_offset: f32,
+    #[cfg(feature = "scripting")]
+    scripts: script::Scripts,
+    /// Camera sharing, while connected.
+    #[cfg(feature = "networking")]
+    network: Option<net::Network>,
+    /// Commands from other programs, while listening.
+    #[cfg(feature = "remote")]
+    remote: Option<remote::Server>,
+    /// File in `screenshots/` to save the next frame to.
+    screenshot: Option<String>,
+    tweens: Tweens<Stage>,
+    /// Opacity of the camera speed label, fully opaque from 1 up.
+    speed_label: f32,
+    /// Field of view to go back to, while zoomed in.
+    unzoomed_fov: Option<f32>,
+    /// Set when running with `--benchmark`.
+    benchmark: Option<Benchmark>,
+    time: Time,
 }
 
 impl Stage {
-    pub fn new() -> Stage {
-        let mut ctx: Box<dyn RenderingBackend> = window::new_rendering_backend();
+    pub fn new(benchmark: Option<Benchmark>, generation: Option<Generation>) -> Stage {
+        let mut ctx = Box::new(CountingBackend::new(window::new_rendering_backend()));
 
         window::show_mouse(false);
         window::set_cursor_grab(true);
 
-        #[rustfmt::skip]
-        let vertices: [Vertex; 3] = [
-            Vertex { pos : vec3(-0.5, -0.5, 0.0), color: vec4(1., 0., 0., 1.) },
-            Vertex { pos : vec3( 0.5, -0.5, 0.0), color: vec4(0., 1., 0., 1.) },
-            Vertex { pos : vec3( 0.0,  0.5, 0.0), color: vec4(0., 0., 1., 1.) },
-        ];
-        let vertex_buffer = ctx.new_buffer(
-            BufferType::VertexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&vertices),
-        );
+        let mut meshes = MeshLibrary::default();
+        meshes.add(ctx.as_mut(), "triangle", Mesh::triangle());
+        meshes.add(ctx.as_mut(), "cube", Mesh::cube());
+        meshes.add(ctx.as_mut(), "plane", Mesh::plane());
 
-        let indices: [u16; 3] = [0, 1, 2];
-        let index_buffer = ctx.new_buffer(
-            BufferType::IndexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&indices),
-        );
+        let prefabs = PrefabLibrary::load();
+        let mut scene = Scene::default();
+        // Generated scenes are colored from the palette, so they wait for
+        // the settings.
+        if generation.is_none() {
+            let scene_path = if benchmark.is_some() { benchmark::SCENE } else { "scenes/default.ron" };
+            if let Err(err) = prefab::load_scene(scene_path, &prefabs, &mut scene, &meshes) {
+                println!("Could not load scene: {}", err);
+            }
+        }
+
+        // Bound in place of a lightmap while none is baked.
+        let white = ctx.new_texture_from_rgba8(1, 1, &[255, 255, 255, 255]);
+
+        let shader = compile_shader(ctx.as_mut(), shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let skinned_shader = compile_shader(ctx.as_mut(), shader::SKINNED_VERTEX, shader::FRAGMENT, shader::meta());
+
+        let params = PipelineParams{
+            depth_write: true,
+            depth_test: Comparison::LessOrEqual,
+            cull_face: CullFace::Back,
+            ..Default::default()
+        };
+        let transparent_params = PipelineParams{
+            depth_write: false,
+            color_blend: Some(BlendState::new(Equation::Add, BlendFactor::Value(BlendValue::SourceAlpha), BlendFactor::OneMinusValue(BlendValue::SourceAlpha))),
+            // The target's alpha stays opaque.
+            alpha_blend: Some(BlendState::new(Equation::Add, BlendFactor::Zero, BlendFactor::One)),
+            ..params
+        };
+        let pipelines = ScenePipelines::new(ctx.as_mut(), shader, skinned_shader, params, transparent_params);
+
+        let screen_size = window::screen_size();
+
+        let scene_target = RenderTarget::new(ctx.as_mut(), screen_size.0 as u32, screen_size.1 as u32);
+        let decals = Decals::new(ctx.as_mut(), scene_target.color, scene_target.depth.unwrap());
+        let water = Water::new(ctx.as_mut(), screen_size.0, screen_size.1);
+        let quad = Quad::new(ctx.as_mut());
+        let velocity = VelocityPass::new(ctx.as_mut(), screen_size.0 as u32, screen_size.1 as u32, scene_target.depth.unwrap());
+        let oit = Oit::new(ctx.as_mut(), &quad, screen_size.0 as u32, screen_size.1 as u32, scene_target.depth.unwrap());
+        let taa = Taa::new(ctx.as_mut(), &quad, screen_size.0 as u32, screen_size.1 as u32);
+        let sky = Sky::new(ctx.as_mut(), &quad);
+        let raymarcher = Raymarcher::new(ctx.as_mut(), &quad);
+        let scatter = Scatter::new(ctx.as_mut());
+        let post = Chain::new(ctx.as_mut(), screen_size.0 as u32, screen_size.1 as u32);
+        let reflections = Reflections::new(ctx.as_mut(), &quad);
+        let dof = DepthOfField::new(ctx.as_mut(), &quad);
+        let motion_blur = MotionBlur::new(ctx.as_mut(), &quad);
+        let grading = ColorGrading::new(ctx.as_mut(), &quad);
+        let fxaa = Fxaa::new(ctx.as_mut(), &quad);
+        let vignette = Vignette::new(ctx.as_mut(), &quad);
+        let grain = Grain::new(ctx.as_mut(), &quad);
+        let present = Present::new(ctx.as_mut(), &quad);
+        let minimap = Minimap::new(ctx.as_mut());
+        let pip = Pip::new(ctx.as_mut());
+        let text = TextRenderer::new(ctx.as_mut());
+        let debug_draw = DebugDraw::new(ctx.as_mut());
+        let overlay_lines = DebugDraw::overlay(ctx.as_mut());
+        let panels = EditorPanels::new(ctx.as_mut());
+        let mut debug = DebugFlags::default();
+        let views = DebugViews::register(&mut debug);
+        let camera = Camera::new(screen_size.0/screen_size.1);
 
-        let bindings = Bindings {
-            vertex_buffers: vec![vertex_buffer],
-            index_buffer,
-            images: vec![],
+        let mut stage = Stage {
+            pipelines,
+            meshes,
+            prefabs,
+            scene,
+            loader: Loader::new(),
+            white,
+            lightmap: None,
+            render_queue: RenderQueue::default(),
+            static_batches: StaticBatches::default(),
+            chunks: ChunkManager::new(ctx.as_mut()),
+            voxels: VoxelWorld::new(),
+            debug,
+            views,
+            debug_draw,
+            overlay_lines,
+            editing: false,
+            gizmo: Gizmo::new(),
+            history: History::default(),
+            panels,
+            cursor: vec2(0.0, 0.0),
+            ctx,
+            scene_timings: SceneTimings::default(),
+            serial_pool: rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap(),
+            scene_target,
+            decals,
+            water,
+            velocity,
+            oit,
+            taa,
+            quad,
+            sky,
+            raymarcher,
+            scatter,
+            lighting: Lighting::default(),
+            day_night: DayNight::default(),
+            post,
+            reflections,
+            dof,
+            motion_blur,
+            grading,
+            fxaa,
+            vignette,
+            grain,
+            present,
+            minimap,
+            pip,
+            text,
+            hud: Hud::default(),
+            walker: Walker::default(),
+            spawns: vec![],
+            console: Console::new(),
+            settings: Settings::default(),
+            palette: Palette::new(ColorScheme::Standard, false),
+            cull_camera: camera.clone(),
+            camera,
+            prev_view_proj: Matrix4::identity(),
+            prev_world: HashMap::new(),
+            keys_down: HashSet::new(),
+            show_help: false,
+            help_offset: 1.0,
+            #[cfg(feature = "scripting")]
+            scripts: script::Scripts::new(),
+            #[cfg(feature = "networking")]
+            network: None,
+            #[cfg(feature = "remote")]
+            remote: remote::Server::from_args(),
+            screenshot: None,
+            tweens: Tweens::default(),
+            speed_label: 0.0,
+            unzoomed_fov: None,
+            benchmark,
+            time: Time::new(),
         };
+        // A benchmark flies its own path from a known state.
+        if stage.benchmark.is_none() {
+            stage.restore_session();
+        }
+        stage.apply_settings();
+        if let Some(generation) = generation {
+            generation.populate(&mut stage.scene, &stage.prefabs, &stage.meshes, &stage.palette);
+        }
+        commands::register(&mut stage.console);
+        stage
+    }
+
+    /// Draws the sky and the scene objects into the currently active pass.
+    /// `mirrored` views, seen through a reflection, have their winding
+    /// flipped. `log_depth_coef` belongs to the camera `perspective` is
+    /// from, which isn't always the player's. With `Transparency::Weighted`
+    /// transparent objects are left in the render queue for `draw_weighted`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_geometry(&mut self, mirrored: bool, transparency: Transparency, instances: &[Instance], perspective: Matrix4<f32>, view: Matrix4<f32>, clip_plane: Vector4<f32>, log_depth_coef: f32) {
+        let camera_pos = Point3::from_vec(view.invert().unwrap().w.truncate());
+        self.sky.draw(self.ctx.as_mut(), &self.quad, perspective*view, camera_pos, &self.lighting.sun);
 
-        let shader = ctx
-            .new_shader(
-                match ctx.info().backend {
-                    Backend::OpenGl => ShaderSource::Glsl {
-                        vertex: shader::VERTEX,
-                        fragment: shader::FRAGMENT,
-                    },
-                    _ => unreachable!()
-                },
-                shader::meta(),
-            )
-            .unwrap_or_else(|err|{
-                match err {
-                    ShaderError::CompilationError { shader_type, error_message } => {
-                        println!("A {:?} error has occured:", shader_type);
-                        println!("{}", error_message);
-                        panic!()
-                    },
-                    _ => panic!("{:?}", err)
+        let wireframe = self.debug.enabled(self.views.wireframe);
+        let mut pipeline = |ctx: &mut dyn RenderingBackend, material: &Material, transparent, skinned| {
+            let blend = match (transparent, transparency) {
+                (false, _) => Blend::Opaque,
+                (true, Transparency::Sorted) => Blend::Sorted,
+                (true, Transparency::Weighted) => Blend::Weighted,
+            };
+            match wireframe {
+                true => self.pipelines.wireframe(skinned),
+                false => self.pipelines.get(ctx, material, blend, mirrored, skinned),
+            }
+        };
+        let lightmap_texture = self.lightmap.as_ref().map_or(self.white, |lightmap| lightmap.texture);
+        // An environment map lights the scene in place of the flat ambient.
+        let (ambient, irradiance) = match self.sky.irradiance() {
+            Some(irradiance) => (Vector3::zero(), irradiance),
+            None => (self.lighting.ambient, [Vector3::zero(); 9]),
+        };
+        let shared = Uniforms{
+            perspective,
+            view,
+            world: [Matrix4::identity(); MAX_INSTANCES],
+            lightmap_scale_offset: [[0.0; 4]; MAX_INSTANCES],
+            instance_color: [vec4(1.0, 1.0, 1.0, 1.0); MAX_INSTANCES],
+            instance_params: [Vector4::zero(); MAX_INSTANCES],
+            joints: [[0.0; 4]; 3*MAX_JOINTS],
+            use_lightmap: if self.lightmap.is_some() { 1.0 } else { 0.0 },
+            lightmap_range: lightmap::RANGE,
+            log_depth_coef,
+            clip_plane,
+            sun_direction: self.lighting.sun.direction,
+            sun_radiance: self.lighting.sun.radiance(),
+            ambient,
+            irradiance,
+            fog_color: self.lighting.fog_color,
+            fog_density: self.lighting.fog_density,
+            oit_output: oit::OUTPUT_BLENDED,
+        };
+        if self.raymarcher.enabled {
+            let view_proj = perspective*view;
+            self.raymarcher.draw(self.ctx.as_mut(), &self.quad, &raymarch::Uniforms{
+                inv_view_proj: view_proj.invert().unwrap(),
+                view_proj,
+                camera_pos: camera_pos.to_vec(),
+                log_depth_coef: shared.log_depth_coef,
+                clip_plane,
+                sun_direction: shared.sun_direction,
+                sun_radiance: shared.sun_radiance,
+                ambient,
+                irradiance,
+                fog_color: shared.fog_color,
+                fog_density: shared.fog_density,
+                time: self.time.elapsed(),
+            });
+        }
+        let distance = |world: Matrix4<f32>| camera_pos.distance(Point3::from_vec(world.w.truncate()));
+
+        // Transparent objects are ordered one by one, so they aren't drawn
+        // instanced, and neither are skinned ones, which each have a pose.
+        let (single, opaque): (Vec<Instance>, Vec<Instance>) = instances.iter().partition(|instance| {
+            instance.color().w < 1.0 || self.meshes.get(instance.mesh).skeleton.is_some()
+        });
+        for batch in scene::batches(&opaque).chain(single.chunks(1)) {
+            let mesh = self.meshes.get(batch[0].mesh);
+            let mut uniforms = shared;
+            if let Some(skeleton) = &mesh.skeleton {
+                let animation = self.scene.get(batch[0].node).and_then(|node| node.animation.as_ref());
+                uniforms.joints = match animation {
+                    Some(animation) => skeleton.pose(Some(&animation.clip), animation.time),
+                    None => skeleton.rest_pose(),
+                };
+            }
+            for (i, instance) in batch.iter().enumerate() {
+                uniforms.world[i] = instance.world;
+                uniforms.instance_color[i] = instance.color();
+                uniforms.instance_params[i] = instance.params.packed();
+                if let Some(lightmap) = &self.lightmap {
+                    uniforms.lightmap_scale_offset[i] = lightmap.scale_offset.get(&instance.node).copied().unwrap_or_default();
                 }
+            }
+            let transparent = batch[0].color().w < 1.0;
+            self.render_queue.push(DrawCommand{
+                pipeline: pipeline(self.ctx.as_mut(), &batch[0].material, transparent, mesh.skeleton.is_some()),
+                bindings: if wireframe { mesh.edge_bindings(lightmap_texture) } else { mesh.bindings(lightmap_texture) },
+                uniforms,
+                elements: if wireframe { mesh.edge_count() } else { mesh.index_count() },
+                instances: batch.len() as i32,
+                depth: batch.iter().map(|instance| distance(instance.world)).fold(f32::MAX, f32::min),
+                transparent,
             });
+        }
+
+        for batch in self.static_batches.iter() {
+            let mut uniforms = Uniforms{
+                // Batches have the atlas placement baked in.
+                lightmap_scale_offset: [[1.0, 1.0, 0.0, 0.0]; MAX_INSTANCES],
+                ..shared
+            };
+            uniforms.instance_color[0] = batch.material.color;
+            let transparent = batch.material.color.w < 1.0;
+            let (min, max) = batch.gpu.bounds;
+            self.render_queue.push(DrawCommand{
+                pipeline: pipeline(self.ctx.as_mut(), &batch.material, transparent, false),
+                bindings: if wireframe { batch.gpu.edge_bindings(lightmap_texture) } else { batch.gpu.bindings(lightmap_texture) },
+                uniforms,
+                elements: if wireframe { batch.gpu.edge_count() } else { batch.gpu.index_count() },
+                instances: 1,
+                depth: camera_pos.distance(Point3::from_vec((min + max)*0.5)),
+                transparent,
+            });
+        }
+
+        // Terrain and voxels aren't part of the lightmap.
+        let terrain = self.chunks.iter().map(|chunk| match wireframe {
+            true => (chunk.world, self.chunks.edge_bindings(chunk, lightmap_texture), self.chunks.edge_count()),
+            false => (chunk.world, self.chunks.bindings(chunk, lightmap_texture), self.chunks.index_count()),
+        });
+        let voxels = self.voxels.meshes().map(|mesh| match wireframe {
+            true => (mesh.world, mesh.edge_bindings(lightmap_texture), mesh.edge_count()),
+            false => (mesh.world, mesh.bindings(lightmap_texture), mesh.index_count()),
+        });
+        for (world, bindings, elements) in terrain.chain(voxels) {
+            let mut uniforms = Uniforms{
+                use_lightmap: 0.0,
+                ..shared
+            };
+            uniforms.world[0] = world;
+            self.render_queue.push(DrawCommand{
+                pipeline: pipeline(self.ctx.as_mut(), &Material::default(), false, false),
+                bindings,
+                uniforms,
+                elements,
+                instances: 1,
+                depth: distance(world),
+                transparent: false,
+            });
+        }
+        // Drawn straight away, so the queued transparent objects blend
+        // over the props.
+        self.scatter.draw(self.ctx.as_mut(), &self.chunks, lightmap_texture, scatter::Uniforms{
+            perspective,
+            view,
+            log_depth_coef,
+            camera_pos: camera_pos.to_vec(),
+            fade_start: 0.0,
+            fade_end: 0.0,
+            use_lightmap: 0.0,
+            lightmap_range: lightmap::RANGE,
+            clip_plane,
+            sun_direction: shared.sun_direction,
+            sun_radiance: shared.sun_radiance,
+            ambient,
+            irradiance,
+            fog_color: shared.fog_color,
+            fog_density: shared.fog_density,
+        });
+        match transparency {
+            Transparency::Sorted => self.render_queue.submit(self.ctx.as_mut()),
+            Transparency::Weighted => self.render_queue.submit_opaque(self.ctx.as_mut()),
+        }
+    }
+
+    /// Draws the transparent objects `draw_geometry` left queued into the
+    /// order-independent targets, then blends them over `target`, whose
+    /// depth they were tested against.
+    fn draw_weighted(&mut self, target: RenderPass) {
+        self.oit.begin_accumulation(self.ctx.as_mut());
+        self.render_queue.replay(self.ctx.as_mut(), |uniforms| uniforms.oit_output = oit::OUTPUT_ACCUMULATION);
+        self.ctx.end_render_pass();
+        self.oit.begin_revealage(self.ctx.as_mut());
+        self.render_queue.replay(self.ctx.as_mut(), |uniforms| uniforms.oit_output = oit::OUTPUT_REVEALAGE);
+        self.ctx.end_render_pass();
+        self.render_queue.clear();
+
+        self.ctx.begin_pass(Some(target), PassAction::Nothing);
+        self.oit.composite(self.ctx.as_mut(), &self.quad);
+    }
+
+    /// Collects the lines of the enabled debug views.
+    fn collect_debug_lines(&mut self, instances: &[Instance]) {
+        if self.debug.enabled(self.views.bounds) {
+            for instance in instances {
+                let (min, max) = self.meshes.get(instance.mesh).bounds;
+                self.debug_draw.aabb(min, max, instance.world, self.palette.bounds);
+            }
+        }
+        if self.debug.enabled(self.views.normals) {
+            for instance in instances {
+                for vertex in &self.meshes.get(instance.mesh).mesh.vertices {
+                    let pos = (instance.world*vertex.pos.extend(1.0)).truncate();
+                    let normal = (instance.world*vertex.normal.extend(0.0)).truncate().normalize();
+                    self.debug_draw.line(pos, pos + normal*0.1, self.palette.normals);
+                }
+            }
+        }
+        if self.debug.enabled(self.views.frustum) {
+            // Log depth and an infinite far plane have no far face to draw.
+            let mut camera = self.cull_camera.clone();
+            camera.depth_mode = DepthMode::Standard;
+            self.debug_draw.frustum(camera.projection_matrix()*camera.view(), self.palette.frustum);
+        }
+    }
 
-        let pipeline = ctx.new_pipeline_with_params(
-            &[BufferLayout::default()],
-            &[
-                VertexAttribute::new("in_pos", VertexFormat::Float3),
-                VertexAttribute::new("in_color", VertexFormat::Float4),
-            ],
-            shader,
-            PipelineParams{
-                depth_write: true,
-                depth_test: Comparison::LessOrEqual,
-                cull_face: CullFace::Back,
-                ..Default::default()
+    /// Draws every instance's motion since the previous frame. Skinned
+    /// meshes are left out: the velocity shader can't pose them, so they
+    /// read as still.
+    fn draw_velocity(&mut self, instances: &[Instance], projection_view: Matrix4<f32>, view_proj: Matrix4<f32>) {
+        self.velocity.begin(self.ctx.as_mut());
+        let rigid: Vec<Instance> = instances.iter().filter(|instance| self.meshes.get(instance.mesh).skeleton.is_none()).copied().collect();
+        for batch in scene::batches(&rigid) {
+            let mesh = self.meshes.get(batch[0].mesh);
+            let mut uniforms = velocity::Uniforms {
+                projection_view,
+                view_proj,
+                prev_view_proj: self.prev_view_proj,
+                world: [Matrix4::identity(); MAX_INSTANCES],
+                prev_world: [Matrix4::identity(); MAX_INSTANCES],
+                log_depth_coef: self.camera.log_depth_coef(),
+            };
+            for (i, instance) in batch.iter().enumerate() {
+                uniforms.world[i] = instance.world;
+                uniforms.prev_world[i] = self.prev_world.get(&instance.node).copied().unwrap_or(instance.world);
+            }
+            self.velocity.draw(self.ctx.as_mut(), &mesh.bindings(self.white), &uniforms, mesh.index_count(), batch.len() as i32);
+        }
+        for batch in self.static_batches.iter() {
+            let uniforms = velocity::Uniforms {
+                projection_view,
+                view_proj,
+                prev_view_proj: self.prev_view_proj,
+                world: [Matrix4::identity(); MAX_INSTANCES],
+                prev_world: [Matrix4::identity(); MAX_INSTANCES],
+                log_depth_coef: self.camera.log_depth_coef(),
+            };
+            self.velocity.draw(self.ctx.as_mut(), &batch.gpu.bindings(self.white), &uniforms, batch.gpu.index_count(), 1);
+        }
+        let terrain = self.chunks.iter().map(|chunk| (chunk.world, self.chunks.bindings(chunk, self.white), self.chunks.index_count()));
+        let voxels = self.voxels.meshes().map(|mesh| (mesh.world, mesh.bindings(self.white), mesh.index_count()));
+        for (world, bindings, elements) in terrain.chain(voxels) {
+            let mut uniforms = velocity::Uniforms {
+                projection_view,
+                view_proj,
+                prev_view_proj: self.prev_view_proj,
+                world: [Matrix4::identity(); MAX_INSTANCES],
+                prev_world: [Matrix4::identity(); MAX_INSTANCES],
+                log_depth_coef: self.camera.log_depth_coef(),
+            };
+            uniforms.world[0] = world;
+            uniforms.prev_world[0] = world;
+            self.velocity.draw(self.ctx.as_mut(), &bindings, &uniforms, elements, 1);
+        }
+        self.ctx.end_render_pass();
+    }
+
+    /// Bakes the current sunlight into a lightmap, or drops back to
+    /// dynamic lighting if one is in use.
+    fn toggle_lightmap(&mut self) {
+        match self.lightmap.take() {
+            Some(lightmap) => {
+                self.ctx.delete_texture(lightmap.texture);
+                self.hud.notify("Lightmap: off");
+            }
+            None => {
+                let baked = Instant::now();
+                let lightmap = lightmap::bake(self.ctx.as_mut(), &self.meshes, &self.scene.instances(), &self.lighting.sun);
+                self.lightmap = Some(lightmap);
+                self.hud.notify(format!("Lightmap: baked in {:?}", baked.elapsed()));
             }
+        }
+        // Batches carry the lightmap placement of their objects.
+        if !self.static_batches.is_empty() {
+            self.bake_static_batches();
+        }
+    }
+
+    /// Merges small static objects that share a material and reports how
+    /// many draw calls that saves.
+    fn bake_static_batches(&mut self) -> String {
+        let stats = self.static_batches.bake(self.ctx.as_mut(), &self.scene, &self.meshes, self.lightmap.as_ref());
+        let report = format!(
+            "Static batching: {} objects in {} batches, {} draw calls instead of {}",
+            stats.objects, stats.batches, stats.draws_after, stats.draws_before
         );
+        println!("{}", report);
+        report
+    }
 
-        let screen_size = window::screen_size();
+    /// Stamps a decal onto whatever is in front of the camera, projected
+    /// along the view direction.
+    fn place_decal(&mut self) {
+        let world = self.camera.transform()
+            *Matrix4::from_translation(vec3(0.0, 0.0, -2.0))
+            *Matrix4::from_nonuniform_scale(0.2*self.decals.aspect(), 0.2, 4.0);
+        self.decals.place(world);
+    }
 
-        let fov = 80.0;
-        let near = 0.1;
-        let far = 100.0;
+    /// Digs out the block in the middle of the view with the left button,
+    /// or builds one of the same kind against it with the right.
+    fn edit_voxel(&mut self, button: MouseButton) {
+        let Some((hit, front)) = self.voxels.raycast(&self.camera.ray(vec2(0.0, 0.0))) else {
+            return;
+        };
+        match button {
+            MouseButton::Left => self.voxels.set(hit, voxel::AIR),
+            MouseButton::Right => self.voxels.set(front, self.voxels.get(hit)),
+            _ => (),
+        }
+    }
 
-        Stage {
-            pipeline,
-            bindings,
-            ctx,
-            camera_pos: point3(0.0, 0.0, 1.0),
-            perspective: perspective(Deg(fov), screen_size.0/screen_size.1, near, far),
-            view: Matrix4::identity(),
-            keys_down: HashSet::new(),
-            last_frame: Instant::now(),
-            rotate_x: 0.0,
-            rotate_y: 0.0,
-            fov,
-            near,
-            far,
+    /// Runs per-object scene work on every core or, with parallel updates
+    /// turned off, on a single one.
+    fn on_scene<R: Send>(&mut self, f: impl FnOnce(&mut Scene) -> R + Send) -> R {
+        self.scene_timings.threads = if self.settings.parallel_update { rayon::current_num_threads() } else { 1 };
+        let scene = &mut self.scene;
+        if self.settings.parallel_update {
+            f(scene)
+        } else {
+            self.serial_pool.install(|| f(scene))
+        }
+    }
+
+    /// Spawns a decoded model in front of the camera, scaled to about a
+    /// unit across and skinned if it has a skin, projects an image there
+    /// as a decal, or makes an HDR environment map the sky.
+    fn place_asset(&mut self, path: &std::path::Path, asset: import::Asset) {
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("import");
+        match asset {
+            import::Asset::Model{ mesh, skin, animation } => {
+                let (min, max) = mesh.bounds();
+                let size = (max - min).x.max((max - min).y).max((max - min).z);
+                let scale = if size > 0.0 { 1.0/size } else { 1.0 };
+                let mut mesh_name = name.to_owned();
+                for n in 2.. {
+                    if self.meshes.find(&mesh_name).is_none() {
+                        break;
+                    }
+                    mesh_name = format!("{} {}", name, n);
+                }
+                let mesh_id = match skin {
+                    Some(skin) => self.meshes.add_skinned(self.ctx.as_mut(), &mesh_name, mesh, skin),
+                    None => self.meshes.add(self.ctx.as_mut(), &mesh_name, mesh),
+                };
+
+                // Centered in front of the camera, resting on the ground.
+                let center = (min + max)*0.5;
+                let mut position = self.camera.position.to_vec() + self.camera.forward()*2.0 - center*scale;
+                position.y = -0.5 - min.y*scale;
+                let mut node = scene::Node::new(name);
+                node.transform = Transform {
+                    position,
+                    scale: vec3(scale, scale, scale),
+                    ..Transform::default()
+                };
+                let root = match animation.filter(|clip| !clip.is_empty()) {
+                    // The clip replaces the transform, so it goes on a child
+                    // of the placed node.
+                    Some(clip) => {
+                        let root = self.scene.add(node, None);
+                        let mut child = scene::Node::new(&mesh_name);
+                        child.mesh = Some(mesh_id);
+                        let animation = Animation::new(clip);
+                        animation.clip.apply(animation.time, &mut child.transform);
+                        child.animation = Some(animation);
+                        self.scene.add(child, Some(root));
+                        root
+                    }
+                    None => {
+                        node.mesh = Some(mesh_id);
+                        self.scene.add(node, None)
+                    }
+                };
+                self.history.record(Edit::Spawn{ root, detached: vec![] });
+                self.gizmo.selected = Some(root);
+            }
+            import::Asset::Image(image) => {
+                self.decals.set_image(self.ctx.as_mut(), image);
+                self.place_decal();
+            }
+            import::Asset::Texture(image) => {
+                let aspect = image.width as f32/image.height as f32;
+                let texture = compressed::upload(self.ctx.as_mut(), &image, texture::ColorSpace::Srgb);
+                self.decals.set_texture(texture, aspect);
+                self.place_decal();
+            }
+            import::Asset::Environment(cubemap) => {
+                let environment = Environment::new(self.ctx.as_mut(), &cubemap);
+                self.sky.set_environment(self.ctx.as_mut(), Some(environment));
+            }
+        }
+    }
+
+    /// Drops a copy of a prefab on the ground in front of the camera.
+    fn spawn_prefab(&mut self, name: &str) -> Result<NodeId, String> {
+        let mut position = self.camera.position + self.camera.forward()*2.0;
+        position.y = -0.5;
+        let root = self.prefabs.instantiate(name, &mut self.scene, &self.meshes, Some(Transform::from_position(position.to_vec())), None)?;
+        self.history.record(Edit::Spawn{ root, detached: vec![] });
+        Ok(root)
+    }
+
+    fn delete_selected(&mut self) {
+        if let Some(node) = self.gizmo.selected.take() {
+            self.release_gizmo();
+            self.history.delete(&mut self.scene, node);
+        }
+    }
+
+    fn release_gizmo(&mut self) {
+        if let Some(edit) = self.gizmo.release(&self.scene) {
+            self.history.record(edit);
+        }
+    }
+
+    fn undo(&mut self) {
+        match self.history.undo(&mut self.scene) {
+            Some(edit) => self.hud.notify(format!("Undo {}", edit)),
+            None => self.hud.notify("Nothing to undo"),
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.history.redo(&mut self.scene) {
+            Some(edit) => self.hud.notify(format!("Redo {}", edit)),
+            None => self.hud.notify("Nothing to redo"),
+        }
+    }
+
+    /// Writes the finished frame in `source`, without the HUD or any other
+    /// overlay, to `screenshots/<file>` and returns its path.
+    fn save_screenshot(&mut self, source: TextureId, file: &str, width: u32, height: u32) -> Result<String, String> {
+        std::fs::create_dir_all(SCREENSHOT_DIR).map_err(|err| format!("could not create {}: {}", SCREENSHOT_DIR, err))?;
+        let path = format!("{}/{}", SCREENSHOT_DIR, file);
+        let pixels = self.present.capture(self.ctx.as_mut(), &self.quad, source, width, height);
+        texture::save_png(&path, width, height, &pixels)?;
+        Ok(path)
+    }
+
+    /// Keys while the console is open edit its input line instead of
+    /// controlling the stage.
+    fn console_key(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::GraveAccent | KeyCode::Escape => self.console.toggle(),
+            KeyCode::Backspace => self.console.backspace(),
+            KeyCode::Up => self.console.browse_history(true),
+            KeyCode::Down => self.console.browse_history(false),
+            KeyCode::Enter | KeyCode::KpEnter => {
+                if let Some((handler, line)) = self.console.submit() {
+                    let args: Vec<&str> = line.split_whitespace().skip(1).collect();
+                    let result = handler(self, &args);
+                    self.console.print_result(result);
+                }
+            }
+            _ => ()
+        }
+    }
+
+    fn toggle_editing(&mut self) {
+        self.editing = !self.editing;
+        self.release_gizmo();
+        window::show_mouse(self.editing);
+        window::set_cursor_grab(!self.editing);
+        self.hud.notify(format!("Edit mode: {}", if self.editing { "on" } else { "off" }));
+    }
+
+    fn apply_settings(&mut self) {
+        self.fxaa.enabled = self.settings.antialiasing == Antialiasing::Fxaa;
+        self.taa.enabled = self.settings.antialiasing == Antialiasing::Taa;
+        self.taa.invalidate();
+        self.oit.enabled = self.settings.transparency == Transparency::Weighted;
+        self.palette = Palette::new(self.settings.color_scheme, self.settings.high_contrast);
+    }
+
+    /// Picks up where the last run left off, unless that was turned off.
+    fn restore_session(&mut self) {
+        let Some(session) = Session::load() else {
+            return;
+        };
+        if !session.settings.restore_session {
+            self.settings.restore_session = false;
+            return;
+        }
+        self.settings = session.settings;
+        if let Some(camera) = session.camera {
+            if let Err(err) = self.camera.set_pose(&camera.pose) {
+                println!("Could not restore camera: {}", err);
+            }
+            self.camera.speed = camera.speed;
+            if let Some(size) = camera.ortho_size {
+                self.camera.ortho_size = size.clamp(MIN_ORTHO_SIZE, MAX_ORTHO_SIZE);
+            }
+            self.cull_camera = self.camera.clone();
+        }
+        self.camera.fov = self.settings.fov;
+        for name in &session.debug {
+            match self.debug.find(name) {
+                Some(flag) => self.debug.set(flag, true),
+                None => println!("Unknown debug flag {} in session", name),
+            }
+        }
+    }
+
+    fn save_session(&self) {
+        let mut camera = self.camera.clone();
+        // Zooming is only held for a moment.
+        if let Some(fov) = self.unzoomed_fov {
+            camera.fov = fov;
+        }
+        Session {
+            camera: Some(CameraState{ pose: camera.pose(), speed: camera.speed, ortho_size: Some(camera.ortho_size) }),
+            settings: self.settings.clone(),
+            debug: self.debug.iter().filter(|&flag| self.debug.enabled(flag)).map(|flag| self.debug.name(flag).to_owned()).collect(),
+        }.save();
+    }
+
+    /// Smoothly moves the camera to the pose of `target`.
+    fn fly_to(&mut self, target: &Camera) {
+        let ease = Ease::CubicInOut;
+        let position = Tween::new(self.camera.position.to_vec(), target.position.to_vec(), TRANSITION_TIME, ease);
+        // Turn the short way round.
+        let yaw = self.camera.yaw + (target.yaw - self.camera.yaw + PI).rem_euclid(TAU) - PI;
+        self.tweens.animate("position", position, |stage, p| stage.camera.position = Point3::from_vec(p))
+            .then(|stage| stage.hud.notify(format!("Jumped to {}", stage.camera.pose())));
+        self.tweens.animate("yaw", Tween::new(self.camera.yaw, yaw, TRANSITION_TIME, ease), |stage, yaw| stage.camera.yaw = yaw);
+        self.tweens.animate("pitch", Tween::new(self.camera.pitch, target.pitch, TRANSITION_TIME, ease), |stage, pitch| stage.camera.pitch = pitch);
+        self.tweens.animate("fov", Tween::new(self.camera.fov, target.fov, TRANSITION_TIME, ease), |stage, fov| stage.camera.fov = fov);
+        self.unzoomed_fov = None;
+    }
+
+    fn toggle_zoom(&mut self) {
+        let to = match self.unzoomed_fov.take() {
+            Some(fov) => fov,
+            None => {
+                self.unzoomed_fov = Some(self.camera.fov);
+                self.camera.fov/ZOOM
+            }
+        };
+        let tween = Tween::new(self.camera.fov, to, ZOOM_TIME, Ease::CubicOut);
+        self.tweens.animate("fov", tween, |stage, fov| stage.camera.fov = fov);
+    }
+
+    /// Eases the field of view to `fov` and keeps it as the setting. While
+    /// zoomed in, the zoomed view follows and zooming out lands on `fov`.
+    fn set_fov(&mut self, fov: f32) {
+        self.settings.fov = fov;
+        let to = match &mut self.unzoomed_fov {
+            Some(unzoomed) => {
+                *unzoomed = fov;
+                fov/ZOOM
+            }
+            None => fov,
+        };
+        let tween = Tween::new(self.camera.fov, to, FOV_TIME, Ease::CubicOut);
+        self.tweens.animate("fov", tween, |stage, fov| stage.camera.fov = fov);
+        self.hud.notify(format!("Field of view: {:.0} degrees", fov));
+    }
+
+    /// Carries out a key press action. Held actions are polled in
+    /// `update` instead.
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Help => {
+                self.show_help = !self.show_help;
+                let tween = match self.show_help {
+                    true => Tween::new(self.help_offset, 0.0, 0.6, Ease::Elastic),
+                    false => Tween::new(self.help_offset, 1.0, 0.2, Ease::CubicIn),
+                };
+                self.tweens.animate("help_offset", tween, |stage, offset| stage.help_offset = offset);
+            }
+            Action::Quit => window::request_quit(),
+            Action::CycleGrading => self.grading.cycle(),
+            Action::CopyPose => {
+                let pose = self.camera.pose();
+                window::clipboard_set(&pose);
+                self.hud.notify(format!("Copied {}", pose));
+            }
+            Action::PastePose => {
+                let text = window::clipboard_get().unwrap_or_default();
+                let mut target = self.camera.clone();
+                match target.set_pose(text.trim()) {
+                    Ok(()) => self.fly_to(&target),
+                    Err(err) => self.hud.notify(format!("Could not paste camera pose: {}", err)),
+                }
+            }
+            Action::Zoom => self.toggle_zoom(),
+            Action::WiderFov => self.set_fov((self.settings.fov + FOV_STEP).clamp(MIN_FOV, MAX_FOV)),
+            Action::NarrowerFov => self.set_fov((self.settings.fov - FOV_STEP).clamp(MIN_FOV, MAX_FOV)),
+            Action::TogglePause => self.time.toggle_pause(),
+            Action::StepFrame => self.time.step(),
+            Action::ToggleVignette => self.vignette.enabled = !self.vignette.enabled,
+            Action::ToggleGrain => self.grain.enabled = !self.grain.enabled,
+            Action::ToggleDof => self.dof.enabled = !self.dof.enabled,
+            Action::ToggleAutofocus => {
+                self.dof.autofocus = !self.dof.autofocus;
+                self.hud.notify(format!("Autofocus: {}", self.dof.autofocus));
+            }
+            Action::FocusNearer => self.dof.focus_distance = (self.dof.focus_distance/1.25).max(self.camera.near),
+            Action::FocusFarther => self.dof.focus_distance = (self.dof.focus_distance*1.25).min(self.camera.far),
+            Action::PlaceDecal => self.place_decal(),
+            Action::ToggleEditing => self.toggle_editing(),
+            Action::Translate => self.gizmo.mode = GizmoMode::Translate,
+            Action::Rotate => self.gizmo.mode = GizmoMode::Rotate,
+            Action::Scale => self.gizmo.mode = GizmoMode::Scale,
+            Action::Delete => self.delete_selected(),
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::SpawnPillar => match self.spawn_prefab("pillar") {
+                Ok(id) => self.hud.notify(format!("Spawned {}", self.scene.get(id).unwrap().name)),
+                Err(err) => self.hud.notify(format!("Could not spawn pillar: {}", err)),
+            },
+            Action::ToggleConsole => self.console.toggle(),
+            Action::ToggleWater => self.water.enabled = !self.water.enabled,
+            Action::ToggleReflections => self.reflections.enabled = !self.reflections.enabled,
+            Action::ToggleLightmap => self.toggle_lightmap(),
+            Action::ToggleProjection => {
+                self.camera.projection = match self.camera.projection {
+                    Projection::Perspective => Projection::Orthographic,
+                    Projection::Orthographic => Projection::Perspective,
+                };
+                self.hud.notify(format!("Projection: {:?}", self.camera.projection));
+            }
+            Action::CycleDepthMode => {
+                self.camera.depth_mode = self.camera.depth_mode.next();
+                self.hud.notify(format!("Depth: {:?}", self.camera.depth_mode));
+            }
+            Action::ToggleDayNight => {
+                self.day_night.running = !self.day_night.running;
+                self.hud.notify(format!("Day/night cycle: {}", if self.day_night.running { "running" } else { "paused" }));
+            }
+            Action::ToggleMotionBlur => self.motion_blur.enabled = !self.motion_blur.enabled,
+            Action::ToggleMinimap => self.minimap.enabled = !self.minimap.enabled,
+            Action::CyclePip => self.pip.cycle(self.gizmo.selected),
+            Action::ToggleHud => self.hud.enabled = !self.hud.enabled,
+            Action::ToggleWalk => {
+                self.walker.set(!self.walker.enabled, &self.camera);
+                self.hud.notify(if self.walker.enabled { "Walking" } else { "Flying" });
+            }
+            Action::CycleAntialiasing => {
+                self.settings.antialiasing = self.settings.antialiasing.next();
+                self.apply_settings();
+                self.hud.notify(format!("Anti-aliasing: {:?}", self.settings.antialiasing));
+            }
+            Action::MoveForward | Action::MoveLeft | Action::MoveBack | Action::MoveRight
+                | Action::ScrubBack | Action::ScrubForward => (),
         }
     }
 }
 
 impl EventHandler for Stage {
     fn update(&mut self) {
+        let fixed = self.benchmark.as_mut().map(|benchmark| benchmark.step(&mut self.camera));
+        self.time.tick(fixed);
+        let real_delta = self.time.real_delta();
+        let delta = self.time.delta();
+        Tweens::update(self, |stage| &mut stage.tweens, real_delta);
 
-        let delta_time = self.last_frame.elapsed();
-        self.last_frame = Instant::now();
+        for loaded in self.loader.poll() {
+            match loaded.asset {
+                Ok(asset) => {
+                    self.place_asset(&loaded.path, asset);
+                    self.hud.notify(format!("Imported {}", loaded.path.display()));
+                }
+                Err(err) => self.hud.notify(format!("Could not import {}: {}", loaded.path.display(), err)),
+            }
+        }
 
-        let forward = vec3(-self.rotate_y.sin(), 0.0, -self.rotate_y.cos());
-        let right = vec3(self.rotate_y.cos(), 0.0, -self.rotate_y.sin());
+        let forward = self.camera.forward();
+        let right = self.camera.right();
 
-        if self.keys_down.contains(&KeyCode::W) {
-            self.camera_pos += forward*delta_time.as_secs_f32();
+        // Scrubbing moves through the day at an hour per second.
+        if input::held(&self.keys_down, Action::ScrubBack) {
+            self.day_night.scrub(-real_delta/24.0);
         }
-
-        if self.keys_down.contains(&KeyCode::A) {
-            self.camera_pos += -right*delta_time.as_secs_f32();
+        if input::held(&self.keys_down, Action::ScrubForward) {
+            self.day_night.scrub(real_delta/24.0);
         }
+        self.day_night.advance(delta);
+        self.day_night.apply(&mut self.lighting);
+        let start = Instant::now();
+        self.on_scene(|scene| scene.animate(delta));
+        self.scene_timings.animation = start.elapsed();
 
-        if self.keys_down.contains(&KeyCode::S) {
-            self.camera_pos += -forward*delta_time.as_secs_f32();
+        if !self.debug.enabled(self.views.freeze_culling) {
+            self.cull_camera = self.camera.clone();
         }
 
-        if self.keys_down.contains(&KeyCode::D) {
-            self.camera_pos += right*delta_time.as_secs_f32();
+        #[cfg(feature = "scripting")]
+        self.scripts.update(delta, script::Context {
+            scene: &mut self.scene,
+            prefabs: &mut self.prefabs,
+            meshes: &mut self.meshes,
+            lighting: &mut self.lighting,
+            keys_down: &self.keys_down,
+        });
+
+        // The camera flies in real time, so it can look around a paused
+        // scene.
+        let mut direction = Vector3::zero();
+        if input::held(&self.keys_down, Action::MoveForward) {
+            direction += forward;
         }
 
-        let rotate = Basis3::from_angle_y(Rad(self.rotate_y))*Basis3::from_angle_x(Rad(self.rotate_x));
-        let rotate: Matrix3<f32> = rotate.into();
-        let rotate: Matrix4<f32> = rotate.into();
-        let translate  = Matrix4::from_translation(self.camera_pos.to_vec());
-        self.view = (translate*rotate).invert().unwrap();
+        if input::held(&self.keys_down, Action::MoveLeft) {
+            direction += -right;
+        }
 
+        if input::held(&self.keys_down, Action::MoveBack) {
+            direction += -forward;
+        }
 
+        if input::held(&self.keys_down, Action::MoveRight) {
+            direction += right;
+        }
+        if self.walker.enabled {
+            let direction = if direction.is_zero() { direction } else { direction.normalize() };
+            let instances = self.scene.instances();
+            self.walker.update(&mut self.camera, direction, real_delta, &instances, &self.meshes, &self.chunks);
+        } else {
+            self.camera.position += direction*real_delta*self.camera.speed;
+        }
+        self.chunks.update(self.ctx.as_mut(), self.camera.position);
+        self.voxels.update(self.ctx.as_mut());
+        self.hud.update(real_delta, self.camera.position);
+        #[cfg(feature = "networking")]
+        if let Some(network) = &mut self.network {
+            network.update(&self.camera, &mut self.scene, &self.meshes);
+        }
+        #[cfg(feature = "remote")]
+        commands::serve_remote(self);
 
-        
-        
     }
 
     fn key_down_event(&mut self, _keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        if self.console.open {
+            self.console_key(_keycode);
+            return;
+        }
+        if self.editing && self.panels.wants_keyboard() {
+            self.panels.input().key_down_event(_keycode, _keymods);
+            return;
+        }
         if _repeat {
             return;
         }
-        match _keycode{
-            KeyCode::Escape => {
-                window::quit();
+        match input::action(_keycode, _keymods, self.editing) {
+            Some(Action::ToggleConsole) => {
+                self.console.toggle();
+                // Keys held now won't see their release.
+                self.keys_down.clear();
+                return;
+            }
+            Some(action) => self.perform(action),
+            None => {
+                self.debug.handle_key(_keycode);
             }
-            _ => ()
         }
         self.keys_down.insert(_keycode);
     }
 
+    fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
+        if self.console.open {
+            self.console.type_char(character);
+        } else if self.editing {
+            self.panels.input().char_event(character);
+        }
+    }
+
     fn key_up_event(&mut self, _keycode: KeyCode, _keymods: KeyMods) {
+        if self.editing {
+            self.panels.input().key_up_event(_keycode, _keymods);
+        }
         self.keys_down.remove(&_keycode);
     }
 
+    fn files_dropped_event(&mut self) {
+        for i in 0..window::dropped_file_count() {
+            let Some(path) = window::dropped_file_path(i) else {
+                continue;
+            };
+            self.loader.request(path);
+        }
+    }
+
     fn resize_event(&mut self, width: f32, height: f32) {
-        self.perspective = perspective(Deg(self.fov), width/height, self.near, self.far);
+        self.camera.aspect = width/height;
+        self.scene_target.resize(self.ctx.as_mut(), width as u32, height as u32);
+        self.post.resize(self.ctx.as_mut(), width as u32, height as u32);
+        self.velocity.target.resize(self.ctx.as_mut(), width as u32, height as u32);
+        self.oit.resize(self.ctx.as_mut(), width as u32, height as u32);
+        self.taa.resize(self.ctx.as_mut(), width as u32, height as u32);
+        self.water.resize(self.ctx.as_mut(), width, height);
+    }
+
+    /// Closing the window, Escape and the console's `quit` all end up
+    /// here; a finished benchmark quits directly and leaves no session.
+    fn quit_requested_event(&mut self) {
+        self.save_session();
+    }
+
+    fn mouse_motion_event(&mut self, x: f32, y: f32) {
+        self.cursor = vec2(x, y);
+        if self.editing {
+            self.panels.input().mouse_motion_event(x, y);
+            self.gizmo.motion(self.cursor, &self.camera, &mut self.scene);
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, x: f32, y: f32) {
+        if self.editing {
+            self.panels.input().mouse_wheel_event(x, y);
+            if self.panels.wants_pointer() {
+                return;
+            }
+        }
+        // Platforms disagree on how far one notch scrolls, so only the
+        // direction counts. Orthographic views zoom instead, since moving
+        // closer doesn't make anything bigger.
+        if y != 0.0 && self.camera.projection == Projection::Orthographic {
+            self.camera.ortho_size = (self.camera.ortho_size/ORTHO_STEP.powf(y.signum())).clamp(MIN_ORTHO_SIZE, MAX_ORTHO_SIZE);
+        } else if y != 0.0 {
+            self.camera.speed = (self.camera.speed*SPEED_STEP.powf(y.signum())).clamp(MIN_SPEED, MAX_SPEED);
+            // Fully visible for the first half, then fading.
+            let tween = Tween::new(2.0, 0.0, SPEED_DISPLAY_TIME, Ease::Linear);
+            self.tweens.animate("speed_label", tween, |stage, alpha| stage.speed_label = alpha);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        if !self.editing {
+            if self.voxels.enabled {
+                self.edit_voxel(button);
+            } else if button == MouseButton::Left {
+                self.gizmo.select_hovered();
+            }
+            return;
+        }
+        self.panels.input().mouse_button_down_event(button, x, y);
+        if button == MouseButton::Left && !self.panels.wants_pointer() {
+            self.cursor = vec2(x, y);
+            let instances = self.scene.instances();
+            self.gizmo.press(self.cursor, &self.camera, &self.scene, &instances, &self.meshes);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, _x: f32, _y: f32) {
+        if self.editing {
+            self.panels.input().mouse_button_up_event(button, _x, _y);
+        }
+        if button == MouseButton::Left {
+            self.release_gizmo();
+        }
     }
 
     fn raw_mouse_motion(&mut self, dx: f32, dy: f32) {
+        // Looking around would fight a camera transition.
+        if self.editing || self.tweens.is_animating("yaw") {
+            return;
+        }
         println!("{}, {}", dx, dy);
-        self.rotate_x += -dy*0.01;
-        self.rotate_y += -dx*0.01;
+        self.camera.pitch += -dy*self.settings.mouse_sensitivity;
+        self.camera.yaw += -dx*self.settings.mouse_sensitivity;
     }
 
     fn draw(&mut self) {
-        self.ctx.begin_default_pass(PassAction::Clear { color: Some((0.0, 0.0, 0.0, 1.0)), depth: Some(1.0), stencil: None});
-
-        
-        self.ctx.apply_pipeline(&self.pipeline);
-        self.ctx.apply_bindings(&self.bindings);
-        
-        let uniforms = Uniforms{
-            perspective: self.perspective,
-            view: self.view,
-            world: [
-                Matrix4::from_translation(vec3(0.0, 0.0, -0.3)),
-                Matrix4::from_translation(vec3(0.0, 0.0, -0.5))
-            ]
+        if self.editing {
+            self.panels.run(self.ctx.as_mut(), editor::Context {
+                scene: &mut self.scene,
+                meshes: &self.meshes,
+                history: &mut self.history,
+                selected: &mut self.gizmo.selected,
+            });
+        }
+
+        let (width, height) = window::screen_size();
+        let view = self.camera.view();
+        let unjittered = self.camera.projection_matrix();
+        let view_proj = unjittered*view;
+        let projection = if self.taa.enabled {
+            self.taa.jitter(width, height)*unjittered
+        } else {
+            unjittered
         };
-        self.ctx.apply_uniforms(UniformsSource::table(&uniforms));
 
-        self.ctx.draw(0, 3, 2);
+        let start = Instant::now();
+        let all_instances = self.on_scene(|scene| scene.instances());
+        self.scene_timings.transforms = start.elapsed();
+        let mut instances = self.static_batches.unbatched(self.ctx.as_mut(), &all_instances);
+        // Objects merged into a static batch are picked but can't be
+        // highlighted on their own.
+        if self.editing && self.panels.wants_pointer() {
+            self.gizmo.hovered = None;
+        } else {
+            let cursor = self.editing.then_some(self.cursor);
+            self.gizmo.hover(cursor, &self.camera, &self.scene, &all_instances, &self.meshes);
+        }
+        self.gizmo.highlight(&self.scene, &mut instances);
+
+        // Reflections, the minimap and the picture-in-picture view look
+        // elsewhere, so only the main view is culled.
+        let start = Instant::now();
+        let frustum = Frustum::new(self.cull_camera.projection_matrix()*self.cull_camera.view());
+        let cull = || culling::cull(&frustum, &instances, &self.meshes);
+        let visible = if self.settings.parallel_update { cull() } else { self.serial_pool.install(cull) };
+        self.scene_timings.culling = start.elapsed();
+        self.scene_timings.culled = instances.len() - visible.len();
+        let clear = || PassAction::clear_color(0.0, 0.0, 0.0, 1.0);
+
+        if self.water.enabled {
+            self.ctx.begin_pass(Some(self.water.reflection.pass), clear());
+            self.draw_geometry(true, Transparency::Sorted, &instances, projection, view*self.water.mirror(), self.water.above(), self.camera.log_depth_coef());
+            self.ctx.end_render_pass();
+
+            self.ctx.begin_pass(Some(self.water.refraction.pass), clear());
+            self.draw_geometry(false, Transparency::Sorted, &instances, projection, view, self.water.below(), self.camera.log_depth_coef());
+            self.ctx.end_render_pass();
+        }
 
+        // Reflections and the insets keep sorting: they are small, and
+        // the wireframe view has no coverage to weigh.
+        let transparency = match self.oit.enabled && !self.debug.enabled(self.views.wireframe) {
+            true => Transparency::Weighted,
+            false => Transparency::Sorted,
+        };
+        self.ctx.begin_pass(Some(self.scene_target.pass), clear());
+        self.draw_geometry(false, transparency, &visible, projection, view, vec4(0.0, 0.0, 0.0, 1.0), self.camera.log_depth_coef());
+        if transparency == Transparency::Weighted {
+            self.ctx.end_render_pass();
+            self.draw_weighted(self.scene_target.pass);
+        }
+        if self.water.enabled {
+            self.water.draw(self.ctx.as_mut(), projection*view, &self.camera, self.time.elapsed());
+        }
+        self.collect_debug_lines(&all_instances);
+        self.debug_draw.draw(self.ctx.as_mut(), projection*view, self.camera.log_depth_coef());
         self.ctx.end_render_pass();
 
+        self.decals.draw(self.ctx.as_mut(), projection, view, self.camera.log_depth_coef(), width, height);
+
+        if self.minimap.enabled {
+            let map = self.minimap.camera(&self.camera);
+            let map_view_proj = map.projection_matrix()*map.view();
+            self.ctx.begin_pass(Some(self.minimap.pass()), clear());
+            self.draw_geometry(false, Transparency::Sorted, &instances, map.projection_matrix(), map.view(), vec4(0.0, 0.0, 0.0, 1.0), map.log_depth_coef());
+            self.minimap.marker(&self.camera, &mut self.overlay_lines, &self.palette);
+            self.overlay_lines.draw(self.ctx.as_mut(), map_view_proj, 0.0);
+            self.ctx.end_render_pass();
+        }
+
+        if self.pip.enabled {
+            match self.pip.view(&self.camera, &self.scene, self.lighting.sun.direction) {
+                Some((pip_projection, pip_view)) => {
+                    self.ctx.begin_pass(Some(self.pip.pass()), clear());
+                    self.draw_geometry(false, Transparency::Sorted, &instances, pip_projection, pip_view, vec4(0.0, 0.0, 0.0, 1.0), 0.0);
+                    self.ctx.end_render_pass();
+                }
+                // The node it was attached to was removed.
+                None => self.pip.enabled = false,
+            }
+        }
+
+        if self.taa.enabled || self.motion_blur.enabled {
+            self.draw_velocity(&visible, projection*view, view_proj);
+        }
+        let mut scene = self.scene_target.color;
+        if self.taa.enabled {
+            scene = self.taa.resolve(self.ctx.as_mut(), &self.quad, scene, self.velocity.target.color);
+        }
+        self.prev_view_proj = view_proj;
+        self.prev_world = all_instances.iter().map(|instance| (instance.node, instance.world)).collect();
+
+        let frame = Frame {
+            time: self.time.real_elapsed(),
+            depth: self.scene_target.depth.unwrap(),
+            velocity: self.velocity.target.color,
+            near: self.camera.near,
+            far: self.camera.depth_far(),
+            projection: unjittered,
+            view,
+            log_depth_coef: self.camera.log_depth_coef(),
+            environment: self.sky.environment(),
+        };
+        let output = self.post.run(
+            self.ctx.as_mut(),
+            &self.quad,
+            scene,
+            &frame,
+            &[&self.reflections, &self.dof, &self.motion_blur, &self.grading, &self.fxaa, &self.vignette, &self.grain],
+        );
+        self.present.draw(self.ctx.as_mut(), &self.quad, output);
+        if let Some(file) = self.screenshot.take() {
+            let result = self.save_screenshot(output, &file, width as u32, height as u32);
+            match &result {
+                Ok(path) => self.hud.notify(format!("Saved {}", path)),
+                Err(err) => self.hud.notify(err.as_str()),
+            }
+            #[cfg(feature = "remote")]
+            if let Some(server) = &mut self.remote {
+                server.screenshot_taken(&result);
+            }
+        }
+
+        self.ctx.begin_default_pass(PassAction::Nothing);
+        if self.minimap.enabled {
+            self.present.draw_inset(self.ctx.as_mut(), &self.quad, self.minimap.texture(), self.minimap.viewport(width, height));
+        }
+        if self.pip.enabled {
+            self.present.draw_inset(self.ctx.as_mut(), &self.quad, self.pip.texture(), self.pip.viewport(width, height));
+        }
+        if self.editing {
+            self.gizmo.draw(&mut self.overlay_lines, &self.camera, &self.scene, &instances, &self.meshes, &self.palette);
+            self.overlay_lines.draw(self.ctx.as_mut(), view_proj, 0.0);
+        }
+        self.hud.draw(&mut self.text, width, height, self.camera.position, !self.editing, &self.palette);
+        if self.debug.enabled(self.views.stats) {
+            self.ctx.stats().draw(&self.scene_timings, &mut self.text, &self.palette);
+        }
+        if self.speed_label > 0.0 {
+            let label = format!("Speed {:.2}", self.camera.speed);
+            let x = (width - label.len() as f32*self.text.char_width())/2.0;
+            let y = height - self.text.line_height()*3.0;
+            self.text.print(x, y, [1.0, 1.0, 1.0, self.speed_label.min(1.0)], &label);
+        }
+        if self.time.is_paused() || self.time.scale != 1.0 {
+            let label = if self.time.is_paused() { "Paused".to_owned() } else { format!("Time x{}", self.time.scale) };
+            let x = (width - label.len() as f32*self.text.char_width())/2.0;
+            self.text.print(x, self.text.line_height(), [1.0, 1.0, 1.0, 1.0], &label);
+        }
+        self.debug.draw(&mut self.text, &self.palette);
+        if self.help_offset < 1.0 {
+            input::draw_help(&mut self.text, &self.debug, self.help_offset);
+        }
+        self.console.draw(&mut self.text);
+        self.text.draw(self.ctx.as_mut());
+        self.ctx.end_render_pass();
+        if self.editing {
+            self.panels.draw(self.ctx.as_mut());
+        }
+
         self.ctx.commit_frame();
+
+        if let Some(benchmark) = &mut self.benchmark {
+            if benchmark.record(self.ctx.stats(), instances.len(), self.scene_timings.culled) {
+                window::quit();
+            }
+        }
     }
 }
 
+/// The stage grabs and hides the cursor, so a panic would otherwise leave
+/// the desktop without a usable mouse. The message is printed first, in
+/// case releasing the window fails too.
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        window::set_cursor_grab(false);
+        window::show_mouse(true);
+        window::set_fullscreen(false);
+        std::process::abort();
+    }));
+}
+
 fn main() {
+    install_panic_hook();
+
     let mut conf = conf::Conf::default();
     conf.platform.apple_gfx_api = conf::AppleGfxApi::OpenGl;
 
-    miniquad::start(conf, move || Box::new(Stage::new()));
+    let benchmark = Benchmark::from_args();
+    let generation = Generation::from_args();
+    if benchmark.is_some() {
+        // Measure how fast frames can be made, not the display's refresh rate.
+        conf.platform.swap_interval = Some(0);
+    }
+
+    miniquad::start(conf, move || Box::new(Stage::new(benchmark, generation)));
 }
 
 mod shader {
-    use cgmath::Matrix4;
+    use cgmath::{Matrix4, Vector3, Vector4};
     use miniquad::*;
 
+    use crate::scene::MAX_INSTANCES;
+    use crate::skin::{JointRows, MAX_JOINTS};
+
     pub const VERTEX: &str = include_str!("shaders/instanced.vert");
 
+    pub const SKINNED_VERTEX: &str = include_str!("shaders/skinned.vert");
+
     pub const FRAGMENT: &str = include_str!("shaders/basic.frag");
 
     pub fn meta() -> ShaderMeta {
         ShaderMeta {
-            images: vec![],
+            images: vec!["lightmap".to_owned()],
             uniforms: UniformBlockLayout { uniforms: vec![
                 UniformDesc{array_count: 1, name: "perspective".to_owned(), uniform_type: UniformType::Mat4},
                 UniformDesc{array_count: 1, name: "view".to_owned(), uniform_type: UniformType::Mat4},
-                UniformDesc{array_count: 2, name: "world".to_owned(), uniform_type: UniformType::Mat4}
+                UniformDesc{array_count: MAX_INSTANCES, name: "world".to_owned(), uniform_type: UniformType::Mat4},
+                UniformDesc{array_count: MAX_INSTANCES, name: "lightmap_scale_offset".to_owned(), uniform_type: UniformType::Float4},
+                UniformDesc{array_count: MAX_INSTANCES, name: "instance_color".to_owned(), uniform_type: UniformType::Float4},
+                UniformDesc{array_count: MAX_INSTANCES, name: "instance_params".to_owned(), uniform_type: UniformType::Float4},
+                UniformDesc{array_count: 1, name: "use_lightmap".to_owned(), uniform_type: UniformType::Float1},
+                UniformDesc{array_count: 1, name: "lightmap_range".to_owned(), uniform_type: UniformType::Float1},
+                UniformDesc{array_count: 1, name: "log_depth_coef".to_owned(), uniform_type: UniformType::Float1},
+                UniformDesc{array_count: 1, name: "clip_plane".to_owned(), uniform_type: UniformType::Float4},
+                UniformDesc{array_count: 1, name: "sun_direction".to_owned(), uniform_type: UniformType::Float3},
+                UniformDesc{array_count: 1, name: "sun_radiance".to_owned(), uniform_type: UniformType::Float3},
+                UniformDesc{array_count: 1, name: "ambient".to_owned(), uniform_type: UniformType::Float3},
+                UniformDesc{array_count: 9, name: "irradiance".to_owned(), uniform_type: UniformType::Float3},
+                UniformDesc{array_count: 1, name: "fog_color".to_owned(), uniform_type: UniformType::Float3},
+                UniformDesc{array_count: 1, name: "fog_density".to_owned(), uniform_type: UniformType::Float1},
+                UniformDesc{array_count: 1, name: "oit_output".to_owned(), uniform_type: UniformType::Float1},
+                UniformDesc{array_count: 3*MAX_JOINTS, name: "joints".to_owned(), uniform_type: UniformType::Float4}
             ] },
         }
     }
     #[repr(C)]
+    #[derive(Clone, Copy)]
     pub struct Uniforms{
         pub perspective: Matrix4<f32>,
         pub view: Matrix4<f32>,
-        pub world: [Matrix4<f32>; 2]
+        pub world: [Matrix4<f32>; MAX_INSTANCES],
+        /// Per instance `uv2` scale (xy) and offset (zw) into the lightmap atlas.
+        pub lightmap_scale_offset: [[f32; 4]; MAX_INSTANCES],
+        /// Per instance material color, in linear space, tinted.
+        pub instance_color: [Vector4<f32>; MAX_INSTANCES],
+        /// Per instance `DrawParams::packed`.
+        pub instance_params: [Vector4<f32>; MAX_INSTANCES],
+        pub use_lightmap: f32,
+        pub lightmap_range: f32,
+        pub log_depth_coef: f32,
+        /// Fragments on the negative side of this plane are discarded.
+        pub clip_plane: Vector4<f32>,
+        pub sun_direction: Vector3<f32>,
+        pub sun_radiance: Vector3<f32>,
+        pub ambient: Vector3<f32>,
+        /// Spherical harmonics from `Cubemap::irradiance`.
+        pub irradiance: [Vector3<f32>; 9],
+        pub fog_color: Vector3<f32>,
+        pub fog_density: f32,
+        /// One of the `oit::OUTPUT_*` values.
+        pub oit_output: f32,
+        /// For the skinned vertex shader, see `Skeleton::pose`.
+        pub joints: JointRows
     }
 }
\ No newline at end of file
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use cgmath::{vec2, vec3, vec4, Vector2, Vector3, Vector4};
+use miniquad::*;
+
+use crate::color::linear_rgba;
+use crate::skin::{Skeleton, Skin};
+
+#[repr(C)]
+pub struct Vertex {
+    pub pos: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub color: Vector4<f32>,
+    /// Lightmap coordinates: a non-overlapping unwrap of the mesh in [0, 1].
+    pub uv2: Vector2<f32>,
+}
+
+/// Layout of `Vertex`, shared by every pipeline that draws scene geometry.
+pub fn vertex_attributes() -> [VertexAttribute; 4] {
+    [
+        VertexAttribute::new("in_pos", VertexFormat::Float3),
+        VertexAttribute::new("in_normal", VertexFormat::Float3),
+        VertexAttribute::new("in_color", VertexFormat::Float4),
+        VertexAttribute::new("in_uv2", VertexFormat::Float2),
+    ]
+}
+
+/// CPU-side copy of a mesh, kept around for work that needs the triangles
+/// themselves, like lightmap baking.
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl Mesh {
+    pub fn upload(&self, ctx: &mut dyn RenderingBackend) -> (BufferId, BufferId) {
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&self.vertices),
+        );
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&self.indices),
+        );
+        (vertex_buffer, index_buffer)
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [&Vertex; 3]> {
+        self.indices.chunks_exact(3).map(|t| {
+            [
+                &self.vertices[t[0] as usize],
+                &self.vertices[t[1] as usize],
+                &self.vertices[t[2] as usize],
+            ]
+        })
+    }
+
+    /// Axis-aligned bounding box, as (min, max).
+    pub fn bounds(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let mut min = vec3(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = vec3(f32::MIN, f32::MIN, f32::MIN);
+        for v in &self.vertices {
+            min = vec3(min.x.min(v.pos.x), min.y.min(v.pos.y), min.z.min(v.pos.z));
+            max = vec3(max.x.max(v.pos.x), max.y.max(v.pos.y), max.z.max(v.pos.z));
+        }
+        (min, max)
+    }
+
+    /// Every triangle edge once, as pairs of indices for line drawing.
+    pub fn edges(&self) -> Vec<u16> {
+        let mut edges: Vec<(u16, u16)> = self
+            .indices
+            .chunks_exact(3)
+            .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        edges.into_iter().flat_map(|(a, b)| [a, b]).collect()
+    }
+
+    /// The original RGB triangle.
+    #[rustfmt::skip]
+    pub fn triangle() -> Mesh {
+        Mesh {
+            vertices: vec![
+                Vertex { pos : vec3(-0.5, -0.5, 0.0), normal: vec3(0., 0., 1.), color: linear_rgba(vec4(1., 0., 0., 1.)), uv2: vec2(0.0, 0.0) },
+                Vertex { pos : vec3( 0.5, -0.5, 0.0), normal: vec3(0., 0., 1.), color: linear_rgba(vec4(0., 1., 0., 1.)), uv2: vec2(1.0, 0.0) },
+                Vertex { pos : vec3( 0.0,  0.5, 0.0), normal: vec3(0., 0., 1.), color: linear_rgba(vec4(0., 0., 1., 1.)), uv2: vec2(0.5, 1.0) },
+            ],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    /// Unit cube centered on the origin, each face its own tile of a 3x2
+    /// lightmap unwrap.
+    pub fn cube() -> Mesh {
+        let faces: [(Vector3<f32>, Vector3<f32>, Vector3<f32>); 6] = [
+            (
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 0.0, -1.0),
+                vec3(0.0, 1.0, 0.0),
+            ),
+            (
+                vec3(-1.0, 0.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+                vec3(0.0, 1.0, 0.0),
+            ),
+            (
+                vec3(0.0, 1.0, 0.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 0.0, -1.0),
+            ),
+            (
+                vec3(0.0, -1.0, 0.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+            ),
+            (
+                vec3(0.0, 0.0, 1.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+            ),
+            (
+                vec3(0.0, 0.0, -1.0),
+                vec3(-1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+            ),
+        ];
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for (i, (normal, u, v)) in faces.into_iter().enumerate() {
+            let tile = vec2((i % 3) as f32, (i / 3) as f32);
+            let base = vertices.len() as u16;
+            for corner in [
+                vec2(0.0, 0.0),
+                vec2(1.0, 0.0),
+                vec2(1.0, 1.0),
+                vec2(0.0, 1.0),
+            ] {
+                vertices.push(Vertex {
+                    pos: normal * 0.5 + u * (corner.x - 0.5) + v * (corner.y - 0.5),
+                    normal,
+                    color: vec4(1.0, 1.0, 1.0, 1.0),
+                    uv2: vec2(
+                        (tile.x + 0.05 + corner.x * 0.9) / 3.0,
+                        (tile.y + 0.05 + corner.y * 0.9) / 2.0,
+                    ),
+                });
+            }
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        Mesh { vertices, indices }
+    }
+
+    /// Unit square in the XZ plane, facing up.
+    pub fn plane() -> Mesh {
+        let corners = [
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(1.0, 1.0),
+            vec2(0.0, 1.0),
+        ];
+        Mesh {
+            vertices: corners
+                .iter()
+                .map(|c| Vertex {
+                    pos: vec3(c.x - 0.5, 0.0, 0.5 - c.y),
+                    normal: vec3(0.0, 1.0, 0.0),
+                    color: vec4(1.0, 1.0, 1.0, 1.0),
+                    uv2: *c,
+                })
+                .collect(),
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(usize);
+
+pub struct GpuMesh {
+    pub mesh: Mesh,
+    pub vertex_buffer: BufferId,
+    pub index_buffer: BufferId,
+    /// Line list of the mesh's edges, for wireframe drawing.
+    pub edge_buffer: BufferId,
+    edge_count: i32,
+    /// Local-space bounding box, as (min, max).
+    pub bounds: (Vector3<f32>, Vector3<f32>),
+    /// `SkinVertex`es for skinned meshes, bound after the vertex buffer.
+    skin_buffer: Option<BufferId>,
+    /// What skinned meshes are deformed by. Bounds, picking and the CPU
+    /// copy are all of the mesh as it was bound.
+    pub skeleton: Option<Skeleton>,
+}
+
+impl GpuMesh {
+    pub fn new(ctx: &mut dyn RenderingBackend, mesh: Mesh) -> GpuMesh {
+        let (vertex_buffer, index_buffer) = mesh.upload(ctx);
+        let edges = mesh.edges();
+        let edge_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&edges),
+        );
+        GpuMesh {
+            bounds: mesh.bounds(),
+            mesh,
+            vertex_buffer,
+            index_buffer,
+            edge_buffer,
+            edge_count: edges.len() as i32,
+            skin_buffer: None,
+            skeleton: None,
+        }
+    }
+
+    pub fn skinned(ctx: &mut dyn RenderingBackend, mesh: Mesh, skin: Skin) -> GpuMesh {
+        let skin_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&skin.vertices),
+        );
+        GpuMesh {
+            skin_buffer: Some(skin_buffer),
+            skeleton: Some(skin.skeleton),
+            ..GpuMesh::new(ctx, mesh)
+        }
+    }
+
+    pub fn delete(&self, ctx: &mut dyn RenderingBackend) {
+        ctx.delete_buffer(self.vertex_buffer);
+        ctx.delete_buffer(self.index_buffer);
+        ctx.delete_buffer(self.edge_buffer);
+        if let Some(buffer) = self.skin_buffer {
+            ctx.delete_buffer(buffer);
+        }
+    }
+
+    pub fn index_count(&self) -> i32 {
+        self.mesh.indices.len() as i32
+    }
+
+    pub fn edge_count(&self) -> i32 {
+        self.edge_count
+    }
+
+    pub fn bindings(&self, image: TextureId) -> Bindings {
+        Bindings {
+            vertex_buffers: [self.vertex_buffer]
+                .into_iter()
+                .chain(self.skin_buffer)
+                .collect(),
+            index_buffer: self.index_buffer,
+            images: vec![image],
+        }
+    }
+
+    pub fn edge_bindings(&self, image: TextureId) -> Bindings {
+        Bindings {
+            index_buffer: self.edge_buffer,
+            ..self.bindings(image)
+        }
+    }
+}
+
+/// Every mesh uploaded to the GPU, addressable by name.
+#[derive(Default)]
+pub struct MeshLibrary {
+    meshes: Vec<GpuMesh>,
+    names: HashMap<String, MeshId>,
+}
+
+impl MeshLibrary {
+    pub fn add(&mut self, ctx: &mut dyn RenderingBackend, name: &str, mesh: Mesh) -> MeshId {
+        self.insert(name, GpuMesh::new(ctx, mesh))
+    }
+
+    pub fn add_skinned(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        name: &str,
+        mesh: Mesh,
+        skin: Skin,
+    ) -> MeshId {
+        self.insert(name, GpuMesh::skinned(ctx, mesh, skin))
+    }
+
+    fn insert(&mut self, name: &str, mesh: GpuMesh) -> MeshId {
+        let id = MeshId(self.meshes.len());
+        self.meshes.push(mesh);
+        self.names.insert(name.to_owned(), id);
+        id
+    }
+
+    pub fn get(&self, id: MeshId) -> &GpuMesh {
+        &self.meshes[id.0]
+    }
+
+    pub fn find(&self, name: &str) -> Option<MeshId> {
+        self.names.get(name).copied()
+    }
+
+    /// Name a mesh was added under.
+    pub fn name(&self, id: MeshId) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|&(_, &mesh)| mesh == id)
+            .map(|(name, _)| name.as_str())
+    }
+}
@@ -0,0 +1,92 @@
+use std::f32::consts::FRAC_PI_2;
+
+use cgmath::{vec3, Angle, Deg, EuclideanSpace};
+use miniquad::*;
+
+use crate::camera::{Camera, Projection};
+use crate::debug_draw::DebugDraw;
+use crate::palette::Palette;
+use crate::post::RenderTarget;
+
+/// Side of the offscreen map, in pixels.
+const RESOLUTION: u32 = 256;
+/// Side of the map on screen, as a fraction of the window height.
+const SCREEN_FRACTION: f32 = 0.3;
+/// Gap between the map and the window corner, in pixels.
+const MARGIN: f32 = 16.0;
+/// How far above the player the map camera hovers. Anything higher is cut
+/// off, so the map shows what's around the player rather than rooftops.
+const HEIGHT: f32 = 200.0;
+
+/// A top-down orthographic view of the scene around the player, rendered
+/// into a small texture and shown in the bottom right corner. North (-Z)
+/// is up; a marker shows where the player is and which way they look.
+pub struct Minimap {
+    pub enabled: bool,
+    /// Half the width of the area shown, in world units.
+    pub radius: f32,
+    target: RenderTarget,
+}
+
+impl Minimap {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Minimap {
+        Minimap {
+            enabled: false,
+            radius: 40.0,
+            target: RenderTarget::new(ctx, RESOLUTION, RESOLUTION),
+        }
+    }
+
+    pub fn pass(&self) -> RenderPass {
+        self.target.pass
+    }
+
+    pub fn texture(&self) -> TextureId {
+        self.target.color
+    }
+
+    /// The camera the map is rendered with, centered on `player`.
+    pub fn camera(&self, player: &Camera) -> Camera {
+        Camera {
+            position: player.position + vec3(0.0, HEIGHT, 0.0),
+            pitch: -FRAC_PI_2,
+            yaw: 0.0,
+            projection: Projection::Orthographic,
+            ortho_size: self.radius,
+            near: 1.0,
+            far: HEIGHT * 2.0,
+            ..Camera::new(1.0)
+        }
+    }
+
+    /// An arrow at the player's position pointing where they look, and the
+    /// edges of their field of view.
+    pub fn marker(&self, player: &Camera, lines: &mut DebugDraw, palette: &Palette) {
+        let size = self.radius * 0.06;
+        let pos = player.position.to_vec();
+        let forward = player.forward();
+        let right = player.right();
+        let tip = pos + forward * size;
+        let back = pos - forward * size * 0.6;
+        let (left, right_corner) = (back - right * size * 0.6, back + right * size * 0.6);
+        lines.line(tip, left, palette.marker);
+        lines.line(tip, right_corner, palette.marker);
+        lines.line(left, right_corner, palette.marker);
+
+        let half_fov = ((Deg(player.fov) / 2.0).tan() * player.aspect).atan();
+        let (sin, cos) = half_fov.sin_cos();
+        let reach = self.radius * 0.4;
+        for side in [-1.0, 1.0] {
+            let edge = (forward * cos + right * sin * side) * reach;
+            lines.line(pos, pos + edge, palette.view_edges);
+        }
+    }
+
+    /// Where the map goes on screen, as x, y, width and height in pixels
+    /// from the bottom left.
+    pub fn viewport(&self, screen_width: f32, screen_height: f32) -> [i32; 4] {
+        let side = screen_height * SCREEN_FRACTION;
+        let x = screen_width - side - MARGIN;
+        [x as i32, MARGIN as i32, side as i32, side as i32]
+    }
+}
@@ -0,0 +1,63 @@
+use miniquad::*;
+
+use crate::post::{Effect, Frame, Quad};
+
+/// Smears every pixel along its screen-space velocity.
+pub struct MotionBlur {
+    pipeline: Pipeline,
+    pub enabled: bool,
+    /// Fraction of the frame the virtual shutter stays open.
+    pub shutter: f32,
+}
+
+impl MotionBlur {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> MotionBlur {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+        MotionBlur {
+            pipeline,
+            enabled: false,
+            shutter: 0.5,
+        }
+    }
+}
+
+impl Effect for MotionBlur {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        frame: &Frame,
+        _pass: usize,
+    ) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source, frame.velocity]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            shutter: self.shutter,
+        }));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/motion_blur.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["source".to_owned(), "velocity".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("shutter", UniformType::Float1),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub shutter: f32,
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::{PI, TAU};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use cgmath::{
+    vec3, vec4, EuclideanSpace, Quaternion, Rad, Rotation3, Vector3, Vector4, VectorSpace, Zero,
+};
+
+use crate::camera::Camera;
+use crate::color::linear_rgba;
+use crate::mesh::MeshLibrary;
+use crate::scene::{Material, Node, NodeId, Scene, Transform};
+
+const MAGIC: [u8; 4] = *b"MQT1";
+const PACKET_SIZE: usize = 36;
+/// Flag bits of a packet.
+const RELAYED: u8 = 1;
+const LEAVING: u8 = 2;
+/// How often the local camera is sent, 20 times a second.
+const SEND_INTERVAL: Duration = Duration::from_millis(50);
+/// Remote cameras are shown this far in the past, so there is almost
+/// always a later update to interpolate towards.
+const INTERPOLATION_DELAY: Duration = Duration::from_millis(100);
+/// Users not heard from for this long are dropped.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug)]
+struct Pose {
+    position: Vector3<f32>,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl Pose {
+    fn lerp(self, other: Pose, t: f32) -> Pose {
+        // Turn the short way round.
+        let yaw = (other.yaw - self.yaw + PI).rem_euclid(TAU) - PI;
+        Pose {
+            position: self.position.lerp(other.position, t),
+            yaw: self.yaw + yaw * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+        }
+    }
+}
+
+/// One camera update on the wire: magic, sender id, sequence number,
+/// flags, three bytes of padding, then position, yaw and pitch, all
+/// little-endian.
+struct Packet {
+    id: u32,
+    seq: u32,
+    flags: u8,
+    pose: Pose,
+}
+
+impl Packet {
+    fn encode(&self) -> [u8; PACKET_SIZE] {
+        let mut bytes = [0; PACKET_SIZE];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&self.id.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.seq.to_le_bytes());
+        bytes[12] = self.flags;
+        let p = self.pose;
+        let floats = [p.position.x, p.position.y, p.position.z, p.yaw, p.pitch];
+        for (i, value) in floats.iter().enumerate() {
+            bytes[16 + i * 4..20 + i * 4].copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Packet> {
+        if bytes.len() != PACKET_SIZE || bytes[0..4] != MAGIC {
+            return None;
+        }
+        let word = |at: usize| <[u8; 4]>::try_from(&bytes[at..at + 4]).unwrap();
+        let float = |i: usize| f32::from_le_bytes(word(16 + i * 4));
+        let pose = Pose {
+            position: vec3(float(0), float(1), float(2)),
+            yaw: float(3),
+            pitch: float(4),
+        };
+        let finite = [
+            pose.position.x,
+            pose.position.y,
+            pose.position.z,
+            pose.yaw,
+            pose.pitch,
+        ]
+        .iter()
+        .all(|v| v.is_finite());
+        finite.then_some(Packet {
+            id: u32::from_le_bytes(word(4)),
+            seq: u32::from_le_bytes(word(8)),
+            flags: bytes[12],
+            pose,
+        })
+    }
+}
+
+struct Remote {
+    last_seq: u32,
+    last_heard: Instant,
+    /// Received poses with their arrival times, oldest first.
+    snapshots: VecDeque<(Instant, Pose)>,
+    avatar: Option<NodeId>,
+}
+
+impl Remote {
+    /// Where the user was `INTERPOLATION_DELAY` ago, between the two
+    /// updates around that time. Past the newest update it stays there
+    /// rather than guessing ahead.
+    fn pose(&mut self, now: Instant) -> Pose {
+        let at = now.checked_sub(INTERPOLATION_DELAY).unwrap_or(now);
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= at {
+            self.snapshots.pop_front();
+        }
+        let (t0, a) = self.snapshots[0];
+        match self.snapshots.get(1) {
+            Some(&(t1, b)) if at > t0 => {
+                let span = (t1 - t0).as_secs_f32().max(1e-4);
+                a.lerp(b, ((at - t0).as_secs_f32() / span).min(1.0))
+            }
+            _ => a,
+        }
+    }
+}
+
+/// Shares camera positions between viewers over UDP, so several people
+/// can walk through the same scene and see each other. Every viewer sends
+/// its camera to the peers it knows, learns peers from whoever sends to
+/// it until they leave or go quiet, and passes first-hand updates on to
+/// its other peers, so everyone who joins through the same viewer sees
+/// everyone else. Other users are
+/// shown as simple avatars, added to the scene as nodes and moved
+/// smoothly between updates.
+pub struct Network {
+    socket: UdpSocket,
+    id: u32,
+    seq: u32,
+    /// Peers given when starting, kept for as long as it runs.
+    peers: Vec<SocketAddr>,
+    /// Peers learned from their packets, with when they last sent one.
+    /// Dropped when they leave or time out.
+    learned: HashMap<SocketAddr, Instant>,
+    remotes: HashMap<u32, Remote>,
+    last_sent: Option<Instant>,
+}
+
+impl Network {
+    /// Listens on `port` and starts sending to `peers`, given as
+    /// `host:port`.
+    pub fn start(port: u16, peers: &[&str]) -> Result<Network, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .map_err(|err| format!("could not listen on port {}: {}", port, err))?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        let peers = peers
+            .iter()
+            .map(|peer| {
+                peer.to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addrs| addrs.next())
+                    .ok_or_else(|| format!("could not resolve {}", peer))
+            })
+            .collect::<Result<_, _>>()?;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        Ok(Network {
+            socket,
+            id: nanos ^ std::process::id().rotate_left(16),
+            seq: 0,
+            peers,
+            learned: HashMap::new(),
+            remotes: HashMap::new(),
+            last_sent: None,
+        })
+    }
+
+    /// Receives pending updates, sends the camera when it's due and moves
+    /// the avatars.
+    pub fn update(&mut self, camera: &Camera, scene: &mut Scene, meshes: &MeshLibrary) {
+        let now = Instant::now();
+        let mut buffer = [0; 64];
+        while let Ok((len, from)) = self.socket.recv_from(&mut buffer) {
+            if let Some(packet) = Packet::decode(&buffer[..len]) {
+                self.receive(packet, from, now, scene);
+            }
+        }
+
+        if self
+            .last_sent
+            .is_none_or(|sent| now - sent >= SEND_INTERVAL)
+        {
+            self.last_sent = Some(now);
+            self.seq = self.seq.wrapping_add(1);
+            let pose = Pose {
+                position: camera.position.to_vec(),
+                yaw: camera.yaw,
+                pitch: camera.pitch,
+            };
+            self.send(&Packet {
+                id: self.id,
+                seq: self.seq,
+                flags: 0,
+                pose,
+            });
+        }
+
+        self.learned.retain(|_, heard| now - *heard < TIMEOUT);
+        self.remotes.retain(|_, remote| {
+            let alive = now - remote.last_heard < TIMEOUT;
+            if !alive {
+                if let Some(avatar) = remote.avatar {
+                    scene.remove(avatar);
+                }
+            }
+            alive
+        });
+        for (&id, remote) in &mut self.remotes {
+            let pose = remote.pose(now);
+            // Spawned again if the scene was cleared.
+            let avatar = match remote.avatar.filter(|&avatar| scene.get(avatar).is_some()) {
+                Some(avatar) => avatar,
+                None => match spawn_avatar(id, scene, meshes) {
+                    Some(avatar) => *remote.avatar.insert(avatar),
+                    None => continue,
+                },
+            };
+            pose_avatar(scene, avatar, pose);
+        }
+    }
+
+    fn receive(&mut self, packet: Packet, from: SocketAddr, now: Instant, scene: &mut Scene) {
+        if packet.id == self.id {
+            return;
+        }
+        if packet.flags & RELAYED == 0 {
+            if !self.peers.contains(&from) {
+                self.learned.insert(from, now);
+            }
+            let relayed = Packet {
+                flags: packet.flags | RELAYED,
+                ..packet
+            };
+            for peer in self.targets().filter(|&&peer| peer != from) {
+                let _ = self.socket.send_to(&relayed.encode(), peer);
+            }
+            if packet.flags & LEAVING != 0 {
+                self.learned.remove(&from);
+            }
+        }
+        if packet.flags & LEAVING != 0 {
+            if let Some(avatar) = self.remotes.remove(&packet.id).and_then(|r| r.avatar) {
+                scene.remove(avatar);
+            }
+            return;
+        }
+        let remote = self.remotes.entry(packet.id).or_insert_with(|| Remote {
+            last_seq: packet.seq.wrapping_sub(1),
+            last_heard: now,
+            snapshots: VecDeque::new(),
+            avatar: None,
+        });
+        // Relayed copies and reordered datagrams arrive late or twice.
+        if (packet.seq.wrapping_sub(remote.last_seq) as i32) <= 0 {
+            return;
+        }
+        remote.last_seq = packet.seq;
+        remote.last_heard = now;
+        remote.snapshots.push_back((now, packet.pose));
+    }
+
+    fn send(&self, packet: &Packet) {
+        let bytes = packet.encode();
+        for peer in self.targets() {
+            let _ = self.socket.send_to(&bytes, peer);
+        }
+    }
+
+    /// Everyone updates go to: the given peers, then the learned ones.
+    fn targets(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.peers.iter().chain(self.learned.keys())
+    }
+
+    /// Says goodbye to the peers and removes the avatars.
+    pub fn stop(self, scene: &mut Scene) {
+        self.send(&Packet {
+            id: self.id,
+            seq: self.seq.wrapping_add(1),
+            flags: LEAVING,
+            pose: Pose {
+                position: Vector3::zero(),
+                yaw: 0.0,
+                pitch: 0.0,
+            },
+        });
+        for remote in self.remotes.values() {
+            if let Some(avatar) = remote.avatar {
+                scene.remove(avatar);
+            }
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let address = self
+            .socket
+            .local_addr()
+            .map_or_else(|err| err.to_string(), |a| a.to_string());
+        let peers: Vec<String> = self.targets().map(SocketAddr::to_string).collect();
+        format!(
+            "listening on {} as {:08x}, peers: {}, users seen: {}",
+            address,
+            self.id,
+            if peers.is_empty() {
+                "none".to_owned()
+            } else {
+                peers.join(", ")
+            },
+            self.remotes.len()
+        )
+    }
+}
+
+/// A body, a head and a dark visor showing where the user looks, colored
+/// after their id. The root sits at the user's eye.
+fn spawn_avatar(id: u32, scene: &mut Scene, meshes: &MeshLibrary) -> Option<NodeId> {
+    let cube = meshes.find("cube")?;
+    let hue = (id % 360) as f32;
+    let color = linear_rgba(hsv(hue, 0.6, 0.9));
+    let part = |name: &str, position: Vector3<f32>, scale: Vector3<f32>, color| Node {
+        mesh: Some(cube),
+        material: Material {
+            color,
+            ..Material::default()
+        },
+        transform: Transform {
+            scale,
+            ..Transform::from_position(position)
+        },
+        ..Node::new(name)
+    };
+    let root = scene.add(Node::new(&format!("user {:08x}", id)), None);
+    scene.add(
+        part("body", vec3(0.0, -0.9, 0.0), vec3(0.5, 1.2, 0.3), color),
+        Some(root),
+    );
+    let head = scene.add(
+        part("head", Vector3::zero(), vec3(0.35, 0.35, 0.35), color),
+        Some(root),
+    );
+    let visor = linear_rgba(vec4(0.05, 0.05, 0.08, 1.0));
+    // In the head's space, which is scaled down.
+    scene.add(
+        part("visor", vec3(0.0, 0.1, -0.5), vec3(0.8, 0.3, 0.1), visor),
+        Some(head),
+    );
+    Some(root)
+}
+
+fn pose_avatar(scene: &mut Scene, avatar: NodeId, pose: Pose) {
+    let Some(node) = scene.get_mut(avatar) else {
+        return;
+    };
+    node.transform.position = pose.position;
+    node.transform.rotation = Quaternion::from_angle_y(Rad(pose.yaw));
+    let children = node.children.clone();
+    for child in children {
+        match scene.get_mut(child) {
+            Some(head) if head.name == "head" => {
+                head.transform.rotation = Quaternion::from_angle_x(Rad(pose.pitch));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// sRGB color from hue in degrees, saturation and value.
+fn hsv(hue: f32, saturation: f32, value: f32) -> Vector4<f32> {
+    let channel = |n: f32| {
+        let k = (n + hue / 60.0) % 6.0;
+        value - value * saturation * k.min(4.0 - k).clamp(0.0, 1.0)
+    };
+    vec4(channel(5.0), channel(3.0), channel(1.0), 1.0)
+}
@@ -0,0 +1,117 @@
+use miniquad::*;
+
+use crate::post::{Quad, RenderTarget};
+
+/// What the scene shader writes, set through its `oit_output` uniform.
+pub const OUTPUT_BLENDED: f32 = 0.0;
+pub const OUTPUT_ACCUMULATION: f32 = 1.0;
+pub const OUTPUT_REVEALAGE: f32 = 2.0;
+
+/// Weighted blended order-independent transparency (McGuire and Bavoil,
+/// 2013). Transparent surfaces are summed into two targets instead of
+/// being sorted: their colors weighted by coverage and distance, and how
+/// much of what is behind them they let through. A fullscreen pass then
+/// lays the weighted average over the opaque scene. Nothing pops when
+/// overlapping surfaces swap order, at the cost of only approximating
+/// which of them is in front.
+///
+/// Like `VelocityPass`, the transparent objects are drawn once per target
+/// against the scene's depth buffer.
+pub struct Oit {
+    pub enabled: bool,
+    /// Premultiplied, weighted color in RGB and the weights in A.
+    accumulation: RenderTarget,
+    /// `-ln(1 - alpha)` summed over the layers, so the product of what
+    /// each lets through can be added up like everything else.
+    revealage: RenderTarget,
+    composite: Pipeline,
+}
+
+impl Oit {
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        width: u32,
+        height: u32,
+        scene_depth: TextureId,
+    ) -> Oit {
+        let composite = quad.pipeline_with(
+            ctx,
+            shader::FRAGMENT,
+            shader::meta(),
+            PipelineParams {
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                // The scene's alpha stays opaque.
+                alpha_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Zero,
+                    BlendFactor::One,
+                )),
+                ..Default::default()
+            },
+        );
+        // Sums of weights run far past 1.
+        let target = |ctx: &mut dyn RenderingBackend| {
+            RenderTarget::sharing_depth(ctx, width, height, TextureFormat::RGBA16F, scene_depth)
+        };
+        Oit {
+            enabled: false,
+            accumulation: target(ctx),
+            revealage: target(ctx),
+            composite,
+        }
+    }
+
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: u32, height: u32) {
+        self.accumulation.resize(ctx, width, height);
+        self.revealage.resize(ctx, width, height);
+    }
+
+    /// Starts the pass that sums weighted colors. The transparent objects
+    /// are drawn into it with `OUTPUT_ACCUMULATION`.
+    pub fn begin_accumulation(&self, ctx: &mut dyn RenderingBackend) {
+        begin(ctx, self.accumulation.pass);
+    }
+
+    /// Starts the pass that sums coverage. The transparent objects are
+    /// drawn into it again with `OUTPUT_REVEALAGE`.
+    pub fn begin_revealage(&self, ctx: &mut dyn RenderingBackend) {
+        begin(ctx, self.revealage.pass);
+    }
+
+    /// Blends the summed layers over the current pass.
+    pub fn composite(&self, ctx: &mut dyn RenderingBackend, quad: &Quad) {
+        ctx.apply_pipeline(&self.composite);
+        ctx.apply_bindings(&quad.bindings(vec![self.accumulation.color, self.revealage.color]));
+        quad.draw(ctx);
+    }
+}
+
+fn begin(ctx: &mut dyn RenderingBackend, pass: RenderPass) {
+    // Depth is the scene's and has to be kept.
+    ctx.begin_pass(
+        Some(pass),
+        PassAction::Clear {
+            color: Some((0.0, 0.0, 0.0, 0.0)),
+            depth: None,
+            stencil: None,
+        },
+    );
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/oit.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["accumulation".to_owned(), "revealage".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![] },
+        }
+    }
+}
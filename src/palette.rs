@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+pub type Rgba = [f32; 4];
+
+/// Sets of colors that stay distinguishable with a given kind of color
+/// vision deficiency.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorScheme {
+    Standard,
+    /// For protanopia and deuteranopia: Okabe and Ito's colors, which
+    /// differ along blue-orange and in lightness rather than red-green.
+    RedGreen,
+    /// For tritanopia: red against cyan and pink, with lightness doing the
+    /// rest, rather than blue-green or yellow-violet.
+    BlueYellow,
+}
+
+impl ColorScheme {
+    pub const ALL: [ColorScheme; 3] = [
+        ColorScheme::Standard,
+        ColorScheme::RedGreen,
+        ColorScheme::BlueYellow,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorScheme::Standard => "standard",
+            ColorScheme::RedGreen => "redgreen",
+            ColorScheme::BlueYellow => "blueyellow",
+        }
+    }
+
+    pub fn find(name: &str) -> Option<ColorScheme> {
+        ColorScheme::ALL
+            .into_iter()
+            .find(|scheme| scheme.name() == name)
+    }
+}
+
+/// The colors things are told apart by: the debug views, the editing
+/// gizmo, the minimap marker, the HUD and overlays, and the props of
+/// generated scenes. Built from the settings; nothing else picks its own
+/// colors for these.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    /// Gizmo handles for X, Y and Z.
+    pub axes: [Rgba; 3],
+    /// The gizmo handle being dragged.
+    pub active: Rgba,
+    /// Bounds of the selected object.
+    pub selection: Rgba,
+    pub bounds: Rgba,
+    pub normals: Rgba,
+    pub frustum: Rgba,
+    /// The player's arrow on the minimap...
+    pub marker: Rgba,
+    /// ...and the edges of their view.
+    pub view_edges: Rgba,
+    /// Text over the scene.
+    pub text: Rgba,
+    /// Drop shadows and outlines that keep `text` readable.
+    pub shadow: Rgba,
+    /// Backgrounds of panels and notifications.
+    pub panel: Rgba,
+    /// Names of the enabled debug views.
+    pub debug_text: Rgba,
+    /// Renderer statistics.
+    pub stats_text: Rgba,
+    /// sRGB colors the props of generated scenes are tinted around.
+    pub props: [(f32, f32, f32); 6],
+}
+
+impl Palette {
+    pub fn new(scheme: ColorScheme, high_contrast: bool) -> Palette {
+        let palette = match scheme {
+            ColorScheme::Standard => Palette {
+                axes: [
+                    [1.0, 0.25, 0.2, 1.0],
+                    [0.3, 0.9, 0.2, 1.0],
+                    [0.25, 0.45, 1.0, 1.0],
+                ],
+                active: [1.0, 0.9, 0.2, 1.0],
+                selection: [1.0, 0.6, 0.1, 1.0],
+                bounds: [1.0, 0.8, 0.0, 1.0],
+                normals: [0.1, 0.4, 1.0, 1.0],
+                frustum: [1.0, 0.2, 0.8, 1.0],
+                marker: [1.0, 0.2, 0.1, 1.0],
+                view_edges: [0.8, 0.8, 0.8, 1.0],
+                text: [1.0, 1.0, 1.0, 1.0],
+                shadow: [0.0, 0.0, 0.0, 0.6],
+                panel: [0.0, 0.0, 0.0, 0.5],
+                debug_text: [1.0, 0.85, 0.3, 1.0],
+                stats_text: [0.85, 1.0, 0.85, 1.0],
+                props: [
+                    (0.8, 0.78, 0.72),
+                    (0.55, 0.35, 0.2),
+                    (0.3, 0.45, 0.6),
+                    (0.7, 0.2, 0.15),
+                    (0.35, 0.5, 0.25),
+                    (0.85, 0.7, 0.3),
+                ],
+            },
+            ColorScheme::RedGreen => Palette {
+                axes: [
+                    [0.84, 0.37, 0.0, 1.0],
+                    [0.94, 0.89, 0.26, 1.0],
+                    [0.0, 0.45, 0.7, 1.0],
+                ],
+                active: [1.0, 1.0, 1.0, 1.0],
+                selection: [0.8, 0.47, 0.65, 1.0],
+                bounds: [0.9, 0.62, 0.0, 1.0],
+                normals: [0.34, 0.71, 0.91, 1.0],
+                frustum: [0.8, 0.47, 0.65, 1.0],
+                marker: [0.9, 0.62, 0.0, 1.0],
+                view_edges: [0.8, 0.8, 0.8, 1.0],
+                text: [1.0, 1.0, 1.0, 1.0],
+                shadow: [0.0, 0.0, 0.0, 0.6],
+                panel: [0.0, 0.0, 0.0, 0.5],
+                debug_text: [0.9, 0.62, 0.0, 1.0],
+                stats_text: [0.34, 0.71, 0.91, 1.0],
+                props: [
+                    (0.8, 0.78, 0.72),
+                    (0.9, 0.62, 0.0),
+                    (0.34, 0.71, 0.91),
+                    (0.84, 0.37, 0.0),
+                    (0.0, 0.45, 0.7),
+                    (0.8, 0.47, 0.65),
+                ],
+            },
+            ColorScheme::BlueYellow => Palette {
+                axes: [
+                    [0.9, 0.15, 0.2, 1.0],
+                    [0.2, 0.85, 0.85, 1.0],
+                    [1.0, 0.65, 0.85, 1.0],
+                ],
+                active: [1.0, 1.0, 1.0, 1.0],
+                selection: [0.2, 0.85, 0.85, 1.0],
+                bounds: [1.0, 0.65, 0.85, 1.0],
+                normals: [0.2, 0.85, 0.85, 1.0],
+                frustum: [0.9, 0.15, 0.2, 1.0],
+                marker: [0.9, 0.15, 0.2, 1.0],
+                view_edges: [0.8, 0.8, 0.8, 1.0],
+                text: [1.0, 1.0, 1.0, 1.0],
+                shadow: [0.0, 0.0, 0.0, 0.6],
+                panel: [0.0, 0.0, 0.0, 0.5],
+                debug_text: [1.0, 0.65, 0.85, 1.0],
+                stats_text: [0.2, 0.85, 0.85, 1.0],
+                props: [
+                    (0.8, 0.78, 0.72),
+                    (0.35, 0.3, 0.3),
+                    (0.2, 0.65, 0.7),
+                    (0.75, 0.15, 0.2),
+                    (0.9, 0.6, 0.75),
+                    (0.55, 0.55, 0.55),
+                ],
+            },
+        };
+        if high_contrast {
+            palette.high_contrast()
+        } else {
+            palette
+        }
+    }
+
+    /// Lines and labels at full brightness, white text, and solid black
+    /// behind it.
+    fn high_contrast(self) -> Palette {
+        Palette {
+            axes: self.axes.map(brightest),
+            active: brightest(self.active),
+            selection: brightest(self.selection),
+            bounds: brightest(self.bounds),
+            normals: brightest(self.normals),
+            frustum: brightest(self.frustum),
+            marker: brightest(self.marker),
+            view_edges: [1.0, 1.0, 1.0, 1.0],
+            text: [1.0, 1.0, 1.0, 1.0],
+            shadow: [0.0, 0.0, 0.0, 1.0],
+            panel: [0.0, 0.0, 0.0, 0.9],
+            debug_text: brightest(self.debug_text),
+            stats_text: [1.0, 1.0, 1.0, 1.0],
+            props: self.props,
+        }
+    }
+}
+
+/// Scales a color up until its largest channel is 1, keeping its hue.
+fn brightest([r, g, b, a]: Rgba) -> Rgba {
+    let max = r.max(g).max(b).max(1e-3);
+    [r / max, g / max, b / max, a]
+}
@@ -0,0 +1,61 @@
+use cgmath::{InnerSpace, Vector3};
+
+use crate::mesh::MeshLibrary;
+use crate::scene::{Instance, NodeId};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    /// Unit length.
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+/// Möller–Trumbore ray/triangle test. Returns the distance along the ray
+/// to the hit, which may be negative (behind the origin).
+pub fn ray_triangle(ray: &Ray, p: [Vector3<f32>; 3]) -> Option<f32> {
+    let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+    let pv = ray.direction.cross(e2);
+    let det = e1.dot(pv);
+    if det.abs() < 1e-8 {
+        return None;
+    }
+    let inv = 1.0 / det;
+    let s = ray.origin - p[0];
+    let u = s.dot(pv) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(e2.dot(q) * inv)
+}
+
+/// Nearest instance the ray hits in front of its origin, tested against
+/// the actual triangles.
+pub fn pick(ray: &Ray, instances: &[Instance], meshes: &MeshLibrary) -> Option<(NodeId, f32)> {
+    instances
+        .iter()
+        .filter_map(|instance| {
+            let world = instance.world;
+            meshes
+                .get(instance.mesh)
+                .mesh
+                .triangles()
+                .filter_map(|t| {
+                    let p = t.map(|v| (world * v.pos.extend(1.0)).truncate());
+                    ray_triangle(ray, p).filter(|&d| d > 0.0)
+                })
+                .min_by(f32::total_cmp)
+                .map(|d| (instance.node, d))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
@@ -0,0 +1,143 @@
+use cgmath::{vec3, EuclideanSpace, InnerSpace, Matrix4, Vector3};
+use miniquad::*;
+
+use crate::camera::{Camera, DepthMode, Projection};
+use crate::post::RenderTarget;
+use crate::scene::{NodeId, Scene};
+
+/// Size of the offscreen view, in pixels.
+const RESOLUTION: (u32, u32) = (320, 180);
+/// Height of the inset on screen, as a fraction of the window height.
+const SCREEN_FRACTION: f32 = 0.25;
+/// Gap between the inset and the top of the window, in pixels.
+const MARGIN: f32 = 16.0;
+/// How far towards the sun its view starts, and half the depth it covers.
+const SUN_DISTANCE: f32 = 200.0;
+
+/// Where the inset camera looks from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PipSource {
+    /// From the player, backwards.
+    Rear,
+    /// Down along the sunlight, orthographic and centered on the player,
+    /// showing what the sun lights.
+    Sun,
+    /// Along a scene node's -Z, with its Y up, following it as it moves.
+    Node(NodeId),
+}
+
+/// A second view of the scene, rendered into a small texture and shown
+/// at the top of the screen like a rear-view mirror.
+pub struct Pip {
+    pub enabled: bool,
+    pub source: PipSource,
+    /// Vertical field of view of the perspective sources, in degrees.
+    pub fov: f32,
+    /// Half the height of the area the sun view shows, in world units.
+    pub sun_extent: f32,
+    target: RenderTarget,
+}
+
+impl Pip {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Pip {
+        Pip {
+            enabled: false,
+            source: PipSource::Rear,
+            fov: 60.0,
+            sun_extent: 30.0,
+            target: RenderTarget::new(ctx, RESOLUTION.0, RESOLUTION.1),
+        }
+    }
+
+    pub fn pass(&self) -> RenderPass {
+        self.target.pass
+    }
+
+    pub fn texture(&self) -> TextureId {
+        self.target.color
+    }
+
+    /// Shows the view from `node` until it is detached or removed.
+    pub fn attach(&mut self, node: NodeId) {
+        self.source = PipSource::Node(node);
+        self.enabled = true;
+    }
+
+    /// Steps through off, the rear view, the sun view and the view from
+    /// `selected`, skipping the last without a selection.
+    pub fn cycle(&mut self, selected: Option<NodeId>) {
+        match (self.enabled, self.source) {
+            (false, _) => {
+                self.source = PipSource::Rear;
+                self.enabled = true;
+            }
+            (true, PipSource::Rear) => self.source = PipSource::Sun,
+            (true, PipSource::Sun) => match selected {
+                Some(node) => self.attach(node),
+                None => self.enabled = false,
+            },
+            (true, PipSource::Node(_)) => self.enabled = false,
+        }
+    }
+
+    /// The projection and view matrices to draw the inset with, or `None`
+    /// if the node it was attached to is gone. `sun` points towards the
+    /// light, like `DirectionalLight::direction`.
+    pub fn view(
+        &self,
+        player: &Camera,
+        scene: &Scene,
+        sun: Vector3<f32>,
+    ) -> Option<(Matrix4<f32>, Matrix4<f32>)> {
+        let camera = Camera {
+            projection: Projection::Perspective,
+            fov: self.fov,
+            depth_mode: DepthMode::Standard,
+            aspect: RESOLUTION.0 as f32 / RESOLUTION.1 as f32,
+            ..player.clone()
+        };
+        let eye = player.position;
+        let view = match self.source {
+            PipSource::Rear => {
+                let transform = player.transform();
+                let back = transform.z.truncate();
+                Matrix4::look_to_rh(eye, back, transform.y.truncate())
+            }
+            PipSource::Sun => {
+                let camera = Camera {
+                    projection: Projection::Orthographic,
+                    ortho_size: self.sun_extent,
+                    near: 1.0,
+                    far: SUN_DISTANCE * 2.0,
+                    ..camera
+                };
+                // Looking straight down, north is up like on the minimap.
+                let up = match sun.y.abs() > 0.99 {
+                    true => vec3(0.0, 0.0, -1.0),
+                    false => vec3(0.0, 1.0, 0.0),
+                };
+                let view = Matrix4::look_to_rh(eye + sun * SUN_DISTANCE, -sun, up);
+                return Some((camera.projection_matrix(), view));
+            }
+            PipSource::Node(node) => {
+                scene.get(node)?;
+                // Scale is dropped by only taking directions from the axes.
+                let world = scene.world_transform(node);
+                let position = EuclideanSpace::from_vec(world.w.truncate());
+                let forward = -world.z.truncate().normalize();
+                Matrix4::look_to_rh(position, forward, world.y.truncate().normalize())
+            }
+        };
+        Some((camera.projection_matrix(), view))
+    }
+
+    /// Where the inset goes on screen, as x, y, width and height in pixels
+    /// from the bottom left: centered at the top.
+    pub fn viewport(&self, screen_width: f32, screen_height: f32) -> [i32; 4] {
+        let height = screen_height * SCREEN_FRACTION;
+        let width = height * RESOLUTION.0 as f32 / RESOLUTION.1 as f32;
+        let x = (screen_width - width) / 2.0;
+        let y = screen_height - height - MARGIN;
+        [x as i32, y as i32, width as i32, height as i32]
+    }
+}
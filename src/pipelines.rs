@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use miniquad::*;
+
+use crate::mesh::vertex_attributes;
+use crate::scene::{Cull, Material, Winding};
+use crate::skin::skin_attributes;
+
+/// How a material's fragments combine with what is already drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Blend {
+    Opaque,
+    /// Over what is behind, so drawn back to front.
+    Sorted,
+    /// Added up, in any order, into the targets of `Oit`.
+    Weighted,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Variant {
+    cull: Cull,
+    winding: Winding,
+    blend: Blend,
+    skinned: bool,
+}
+
+/// The scene shader's pipelines. Materials choose which faces are culled
+/// and which winding faces front, and each combination drawn gets its
+/// own pipeline, made the first time it is asked for. Skinned meshes get
+/// their own set, with the skinning vertex shader and its extra vertex
+/// buffer.
+pub struct ScenePipelines {
+    shader: ShaderId,
+    skinned_shader: ShaderId,
+    opaque: PipelineParams,
+    /// For materials with alpha below 1: blended, without writing depth.
+    transparent: PipelineParams,
+    /// Like `transparent`, but summing everything it writes.
+    weighted: PipelineParams,
+    variants: HashMap<Variant, Pipeline>,
+    /// Draws mesh edges as lines, unskinned and skinned. Lines aren't
+    /// culled, so these serve for every material.
+    wireframe: [Pipeline; 2],
+}
+
+impl ScenePipelines {
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        shader: ShaderId,
+        skinned_shader: ShaderId,
+        opaque: PipelineParams,
+        transparent: PipelineParams,
+    ) -> ScenePipelines {
+        let lines = PipelineParams {
+            primitive_type: PrimitiveType::Lines,
+            cull_face: CullFace::Nothing,
+            ..opaque
+        };
+        let wireframe = [
+            new_pipeline(ctx, shader, false, lines),
+            new_pipeline(ctx, skinned_shader, true, lines),
+        ];
+        let add = BlendState::new(Equation::Add, BlendFactor::One, BlendFactor::One);
+        let weighted = PipelineParams {
+            color_blend: Some(add),
+            alpha_blend: Some(add),
+            ..transparent
+        };
+        ScenePipelines {
+            shader,
+            skinned_shader,
+            opaque,
+            transparent,
+            weighted,
+            variants: HashMap::new(),
+            wireframe,
+        }
+    }
+
+    pub fn wireframe(&self, skinned: bool) -> Pipeline {
+        self.wireframe[skinned as usize]
+    }
+
+    /// The pipeline for `material`. `mirrored` views, seen through a
+    /// reflection, flip its winding.
+    pub fn get(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        material: &Material,
+        blend: Blend,
+        mirrored: bool,
+        skinned: bool,
+    ) -> Pipeline {
+        let winding = match (material.winding, mirrored) {
+            (Winding::CounterClockwise, false) | (Winding::Clockwise, true) => {
+                Winding::CounterClockwise
+            }
+            _ => Winding::Clockwise,
+        };
+        let variant = Variant {
+            cull: material.cull,
+            winding,
+            blend,
+            skinned,
+        };
+        *self.variants.entry(variant).or_insert_with(|| {
+            let base = match blend {
+                Blend::Opaque => self.opaque,
+                Blend::Sorted => self.transparent,
+                Blend::Weighted => self.weighted,
+            };
+            let params = PipelineParams {
+                cull_face: match variant.cull {
+                    Cull::Back => CullFace::Back,
+                    Cull::Front => CullFace::Front,
+                    Cull::None => CullFace::Nothing,
+                },
+                front_face_order: match variant.winding {
+                    Winding::CounterClockwise => FrontFaceOrder::CounterClockwise,
+                    Winding::Clockwise => FrontFaceOrder::Clockwise,
+                },
+                ..base
+            };
+            let shader = match skinned {
+                true => self.skinned_shader,
+                false => self.shader,
+            };
+            new_pipeline(ctx, shader, skinned, params)
+        })
+    }
+}
+
+fn new_pipeline(
+    ctx: &mut dyn RenderingBackend,
+    shader: ShaderId,
+    skinned: bool,
+    params: PipelineParams,
+) -> Pipeline {
+    if skinned {
+        let attributes: Vec<VertexAttribute> = vertex_attributes()
+            .into_iter()
+            .chain(skin_attributes())
+            .collect();
+        ctx.new_pipeline(
+            &[BufferLayout::default(), BufferLayout::default()],
+            &attributes,
+            shader,
+            params,
+        )
+    } else {
+        ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &vertex_attributes(),
+            shader,
+            params,
+        )
+    }
+}
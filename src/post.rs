@@ -0,0 +1,318 @@
+use cgmath::Matrix4;
+use miniquad::*;
+
+use crate::gfx::compile_shader;
+
+/// An offscreen target. The scene is rendered into one with a depth
+/// attachment, fullscreen passes ping-pong between color-only ones. They
+/// hold linear color, in half floats unless asked otherwise, since 8 bits
+/// of linear color band the darks.
+pub struct RenderTarget {
+    pub color: TextureId,
+    pub depth: Option<TextureId>,
+    pub pass: RenderPass,
+}
+
+impl RenderTarget {
+    pub fn new(ctx: &mut dyn RenderingBackend, width: u32, height: u32) -> RenderTarget {
+        let depth = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format: TextureFormat::Depth,
+            ..Default::default()
+        });
+        RenderTarget::with_depth(ctx, width, height, Some(depth))
+    }
+
+    pub fn color_only(ctx: &mut dyn RenderingBackend, width: u32, height: u32) -> RenderTarget {
+        RenderTarget::with_depth(ctx, width, height, None)
+    }
+
+    /// Renders against another target's depth buffer. The depth texture
+    /// stays owned (and resized) by that target.
+    pub fn sharing_depth(
+        ctx: &mut dyn RenderingBackend,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        depth: TextureId,
+    ) -> RenderTarget {
+        let shared = RenderTarget::with_format(ctx, width, height, format, Some(depth));
+        RenderTarget {
+            depth: None,
+            ..shared
+        }
+    }
+
+    fn with_depth(
+        ctx: &mut dyn RenderingBackend,
+        width: u32,
+        height: u32,
+        depth: Option<TextureId>,
+    ) -> RenderTarget {
+        RenderTarget::with_format(ctx, width, height, TextureFormat::RGBA16F, depth)
+    }
+
+    fn with_format(
+        ctx: &mut dyn RenderingBackend,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        depth: Option<TextureId>,
+    ) -> RenderTarget {
+        let color = ctx.new_render_texture(TextureParams {
+            width,
+            height,
+            format,
+            ..Default::default()
+        });
+        let pass = ctx.new_render_pass(color, depth);
+        RenderTarget { color, depth, pass }
+    }
+
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: u32, height: u32) {
+        ctx.texture_resize(self.color, width, height, None);
+        if let Some(depth) = self.depth {
+            ctx.texture_resize(depth, width, height, None);
+        }
+    }
+}
+
+/// Per-frame values shared by every effect.
+pub struct Frame {
+    /// Seconds since startup.
+    pub time: f32,
+    /// Depth buffer of the scene pass.
+    pub depth: TextureId,
+    /// Packed screen-space motion, see `VelocityPass`.
+    pub velocity: TextureId,
+    pub near: f32,
+    pub far: f32,
+    /// Unjittered projection the depth buffer was rendered with.
+    pub projection: Matrix4<f32>,
+    pub view: Matrix4<f32>,
+    /// Of the camera the depth buffer was rendered with, see
+    /// `Camera::log_depth_coef`.
+    pub log_depth_coef: f32,
+    /// The sky's environment cubemap, if one is loaded.
+    pub environment: Option<TextureId>,
+}
+
+/// A fullscreen pass that reads the previous image in the chain.
+pub trait Effect {
+    fn enabled(&self) -> bool;
+    /// Separable effects run several passes, each reading the output of
+    /// the one before.
+    fn passes(&self) -> usize {
+        1
+    }
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        frame: &Frame,
+        pass: usize,
+    );
+}
+
+/// Runs the enabled effects in order, ping-ponging between two targets.
+pub struct Chain {
+    targets: [RenderTarget; 2],
+}
+
+impl Chain {
+    pub fn new(ctx: &mut dyn RenderingBackend, width: u32, height: u32) -> Chain {
+        Chain {
+            targets: [
+                RenderTarget::color_only(ctx, width, height),
+                RenderTarget::color_only(ctx, width, height),
+            ],
+        }
+    }
+
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: u32, height: u32) {
+        for target in &mut self.targets {
+            target.resize(ctx, width, height);
+        }
+    }
+
+    /// Returns the texture holding the output of the last enabled effect,
+    /// or `source` itself if none ran.
+    pub fn run(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        mut source: TextureId,
+        frame: &Frame,
+        effects: &[&dyn Effect],
+    ) -> TextureId {
+        let mut next = 0;
+        for effect in effects.iter().filter(|e| e.enabled()) {
+            for pass in 0..effect.passes() {
+                let target = &self.targets[next];
+                ctx.begin_pass(Some(target.pass), PassAction::Nothing);
+                effect.draw(ctx, quad, source, frame, pass);
+                ctx.end_render_pass();
+                source = target.color;
+                next = 1 - next;
+            }
+        }
+        source
+    }
+}
+
+/// Two triangles covering the whole screen, shared by every fullscreen pass.
+pub struct Quad {
+    vertex_buffer: BufferId,
+    index_buffer: BufferId,
+}
+
+impl Quad {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Quad {
+        #[rustfmt::skip]
+        let vertices: [[f32; 2]; 4] = [
+            [-1.0, -1.0],
+            [ 1.0, -1.0],
+            [ 1.0,  1.0],
+            [-1.0,  1.0],
+        ];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+
+        Quad {
+            vertex_buffer,
+            index_buffer,
+        }
+    }
+
+    pub fn bindings(&self, images: Vec<TextureId>) -> Bindings {
+        Bindings {
+            vertex_buffers: vec![self.vertex_buffer],
+            index_buffer: self.index_buffer,
+            images,
+        }
+    }
+
+    pub fn pipeline(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        fragment: &str,
+        meta: ShaderMeta,
+    ) -> Pipeline {
+        self.pipeline_with(ctx, fragment, meta, PipelineParams::default())
+    }
+
+    /// A fullscreen pipeline with its own state, e.g. one that writes depth.
+    pub fn pipeline_with(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        fragment: &str,
+        meta: ShaderMeta,
+        params: PipelineParams,
+    ) -> Pipeline {
+        let shader = compile_shader(ctx, shader::VERTEX, fragment, meta);
+        ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("in_pos", VertexFormat::Float2)],
+            shader,
+            params,
+        )
+    }
+
+    pub fn draw(&self, ctx: &mut dyn RenderingBackend) {
+        ctx.draw(0, 6, 1);
+    }
+}
+
+/// Final pass: the scene is lit and blended in linear space, so it is
+/// encoded to sRGB exactly once, right before it reaches the window.
+pub struct Present {
+    pipeline: Pipeline,
+}
+
+impl Present {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Present {
+        let pipeline = quad.pipeline(
+            ctx,
+            shader::PRESENT,
+            ShaderMeta {
+                images: vec!["source".to_owned()],
+                uniforms: UniformBlockLayout { uniforms: vec![] },
+            },
+        );
+        Present { pipeline }
+    }
+
+    pub fn draw(&self, ctx: &mut dyn RenderingBackend, quad: &Quad, source: TextureId) {
+        ctx.begin_default_pass(PassAction::Nothing);
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source]));
+        quad.draw(ctx);
+        ctx.end_render_pass();
+    }
+
+    /// Encodes `source` the way `draw` does, but into a texture that is
+    /// read back, and returns its pixels as RGBA rows from the top.
+    pub fn capture(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        // Already encoded, so 8 bits are enough.
+        let target = RenderTarget::with_format(ctx, width, height, TextureFormat::RGBA8, None);
+        ctx.begin_pass(Some(target.pass), PassAction::Nothing);
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source]));
+        quad.draw(ctx);
+        ctx.end_render_pass();
+
+        let mut pixels = vec![0; width as usize * height as usize * 4];
+        ctx.texture_read_pixels(target.color, &mut pixels);
+        // Deletes the color texture along with the pass.
+        ctx.delete_render_pass(target.pass);
+        // GL rows start at the bottom.
+        pixels
+            .chunks(width as usize * 4)
+            .rev()
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Encodes `source` into a rectangle of the current pass, given in
+    /// pixels from the bottom left, for insets like the minimap.
+    pub fn draw_inset(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        [x, y, width, height]: [i32; 4],
+    ) {
+        let (screen_width, screen_height) = window::screen_size();
+        ctx.apply_viewport(x, y, width, height);
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![source]));
+        quad.draw(ctx);
+        ctx.apply_viewport(0, 0, screen_width as i32, screen_height as i32);
+    }
+}
+
+mod shader {
+    pub const VERTEX: &str = include_str!("shaders/fullscreen.vert");
+
+    pub const PRESENT: &str = include_str!("shaders/present.frag");
+}
@@ -0,0 +1,264 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use cgmath::{vec3, vec4, Deg, Euler, Quaternion};
+use serde::Deserialize;
+
+use crate::animation::AnimationDef;
+use crate::color::linear_rgba;
+use crate::mesh::MeshLibrary;
+use crate::scene::{Cull, Material, Node, NodeId, Scene, Transform, Winding};
+
+const PREFAB_DIR: &str = "prefabs";
+
+/// A reusable object template, one per `.ron` file in `prefabs/`, named
+/// after the file.
+#[derive(Deserialize, Clone, Debug)]
+pub struct PrefabNode {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Name of a mesh in the `MeshLibrary`; nodes without one only group
+    /// their children.
+    #[serde(default)]
+    pub mesh: Option<String>,
+    #[serde(default)]
+    pub material: MaterialDef,
+    #[serde(default)]
+    pub transform: TransformDef,
+    /// Replaces `transform` for the properties it has keys for.
+    #[serde(default)]
+    pub animation: Option<AnimationDef>,
+    #[serde(default)]
+    pub children: Vec<PrefabNode>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MaterialDef {
+    /// sRGB color, as picked in an editor.
+    pub color: (f32, f32, f32, f32),
+    pub cull: Cull,
+    pub winding: Winding,
+}
+
+impl Default for MaterialDef {
+    fn default() -> MaterialDef {
+        MaterialDef {
+            color: (1.0, 1.0, 1.0, 1.0),
+            cull: Cull::Back,
+            winding: Winding::CounterClockwise,
+        }
+    }
+}
+
+impl MaterialDef {
+    pub fn material(&self) -> Material {
+        let (r, g, b, a) = self.color;
+        Material {
+            color: linear_rgba(vec4(r, g, b, a)),
+            cull: self.cull,
+            winding: self.winding,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct TransformDef {
+    pub position: (f32, f32, f32),
+    /// Euler angles in degrees, applied X, then Y, then Z.
+    pub rotation: (f32, f32, f32),
+    pub scale: (f32, f32, f32),
+}
+
+impl Default for TransformDef {
+    fn default() -> TransformDef {
+        TransformDef {
+            position: (0.0, 0.0, 0.0),
+            rotation: (0.0, 0.0, 0.0),
+            scale: (1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl TransformDef {
+    pub fn transform(&self) -> Transform {
+        let (x, y, z) = self.rotation;
+        Transform {
+            position: self.position.into(),
+            rotation: Quaternion::from(Euler::new(Deg(x), Deg(y), Deg(z))),
+            scale: vec3(self.scale.0, self.scale.1, self.scale.2),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PrefabLibrary {
+    prefabs: HashMap<String, PrefabNode>,
+}
+
+impl PrefabLibrary {
+    /// Loads every prefab in `prefabs/`. Broken files are reported and
+    /// skipped.
+    pub fn load() -> PrefabLibrary {
+        let mut prefabs = HashMap::new();
+        let paths = fs::read_dir(PREFAB_DIR)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|e| e == "ron"))
+                    .collect()
+            })
+            .unwrap_or_else(|err| {
+                println!("Could not read {}: {}", PREFAB_DIR, err);
+                vec![]
+            });
+        for path in paths {
+            if let Some((name, prefab)) = load_prefab(&path) {
+                prefabs.insert(name, prefab);
+            }
+        }
+        PrefabLibrary { prefabs }
+    }
+
+    /// Adds a copy of the prefab to the scene. `transform` replaces the
+    /// prefab's default root transform.
+    pub fn instantiate(
+        &self,
+        name: &str,
+        scene: &mut Scene,
+        meshes: &MeshLibrary,
+        transform: Option<Transform>,
+        parent: Option<NodeId>,
+    ) -> Result<NodeId, String> {
+        let prefab = self
+            .prefabs
+            .get(name)
+            .ok_or_else(|| format!("unknown prefab {:?}", name))?;
+        check_meshes(prefab, meshes)?;
+        let root = spawn(prefab, name, scene, meshes, parent)?;
+        if let Some(transform) = transform {
+            scene.get_mut(root).unwrap().transform = transform;
+        }
+        Ok(root)
+    }
+}
+
+fn load_prefab(path: &Path) -> Option<(String, PrefabNode)> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| println!("Could not read prefab {}: {}", path.display(), err))
+        .ok()?;
+    let prefab = ron::from_str(&text)
+        .map_err(|err| println!("Could not parse prefab {}: {}", path.display(), err))
+        .ok()?;
+    let name = path.file_stem()?.to_string_lossy().into_owned();
+    Some((name, prefab))
+}
+
+/// Fails on the first mesh the library doesn't have, so that a broken
+/// prefab adds nothing to the scene rather than the part of it before.
+fn check_meshes(def: &PrefabNode, meshes: &MeshLibrary) -> Result<(), String> {
+    if let Some(mesh) = &def.mesh {
+        meshes
+            .find(mesh)
+            .ok_or_else(|| format!("unknown mesh {:?}", mesh))?;
+    }
+    def.children
+        .iter()
+        .try_for_each(|child| check_meshes(child, meshes))
+}
+
+fn spawn(
+    def: &PrefabNode,
+    default_name: &str,
+    scene: &mut Scene,
+    meshes: &MeshLibrary,
+    parent: Option<NodeId>,
+) -> Result<NodeId, String> {
+    let mesh = match &def.mesh {
+        Some(mesh) => Some(
+            meshes
+                .find(mesh)
+                .ok_or_else(|| format!("unknown mesh {:?}", mesh))?,
+        ),
+        None => None,
+    };
+    let mut node = Node {
+        mesh,
+        material: def.material.material(),
+        transform: def.transform.transform(),
+        animation: def.animation.as_ref().map(AnimationDef::animation),
+        ..Node::new(def.name.as_deref().unwrap_or(default_name))
+    };
+    if let Some(animation) = &node.animation {
+        animation.clip.apply(animation.time, &mut node.transform);
+    }
+    let id = scene.add(node, parent);
+    for child in &def.children {
+        spawn(child, default_name, scene, meshes, Some(id))?;
+    }
+    Ok(id)
+}
+
+/// A scene file: prefabs to place, each optionally overriding where.
+#[derive(Deserialize, Debug)]
+pub struct SceneDef {
+    pub instances: Vec<PrefabInstance>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PrefabInstance {
+    pub prefab: String,
+    #[serde(default)]
+    pub transform: Option<TransformDef>,
+    /// Animates the instance's root, e.g. into a moving platform.
+    #[serde(default)]
+    pub animation: Option<AnimationDef>,
+}
+
+/// Reads and parses a scene file without touching any scene, so a caller
+/// can check it before clearing what is loaded.
+pub fn read_scene(path: &str) -> Result<SceneDef, String> {
+    let text =
+        fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    ron::from_str(&text).map_err(|err| format!("could not parse {}: {}", path, err))
+}
+
+/// Instantiates every prefab a scene file references. Instances that fail
+/// are reported and left out; a file that cannot be read or parsed is an
+/// error and leaves `scene` untouched.
+pub fn load_scene(
+    path: &str,
+    prefabs: &PrefabLibrary,
+    scene: &mut Scene,
+    meshes: &MeshLibrary,
+) -> Result<(), String> {
+    let def = read_scene(path)?;
+    place_scene(def, path, prefabs, scene, meshes);
+    Ok(())
+}
+
+/// Instantiates the prefabs of an already parsed scene file. `path` only
+/// names the file when an instance is skipped.
+pub fn place_scene(
+    def: SceneDef,
+    path: &str,
+    prefabs: &PrefabLibrary,
+    scene: &mut Scene,
+    meshes: &MeshLibrary,
+) {
+    for instance in def.instances {
+        let transform = instance.transform.map(|t| t.transform());
+        let root = match prefabs.instantiate(&instance.prefab, scene, meshes, transform, None) {
+            Ok(root) => root,
+            Err(err) => {
+                println!("Skipping instance in {}: {}", path, err);
+                continue;
+            }
+        };
+        if let Some(def) = &instance.animation {
+            let node = scene.get_mut(root).unwrap();
+            let animation = def.animation();
+            animation.clip.apply(animation.time, &mut node.transform);
+            node.animation = Some(animation);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use cgmath::{Matrix4, Vector3, Vector4};
+use miniquad::*;
+
+use crate::post::Quad;
+
+/// Signed-distance-field shapes raymarched in a fullscreen pass. Rays are
+/// cast through the camera's inverse view-projection and the hits write
+/// the depth the rasterizer would, so the shapes sit in the same world as
+/// the meshes and intersect them correctly, reflections included.
+pub struct Raymarcher {
+    pipeline: Pipeline,
+    pub enabled: bool,
+}
+
+impl Raymarcher {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Raymarcher {
+        let pipeline = quad.pipeline_with(
+            ctx,
+            shader::FRAGMENT,
+            shader::meta(),
+            PipelineParams {
+                depth_write: true,
+                depth_test: Comparison::LessOrEqual,
+                ..Default::default()
+            },
+        );
+        Raymarcher {
+            pipeline,
+            enabled: false,
+        }
+    }
+
+    /// Draws into the active pass, after the sky and alongside the meshes.
+    pub fn draw(&self, ctx: &mut dyn RenderingBackend, quad: &Quad, uniforms: &Uniforms) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![]));
+        ctx.apply_uniforms(UniformsSource::table(uniforms));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/raymarch.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("inv_view_proj", UniformType::Mat4),
+                UniformDesc::new("view_proj", UniformType::Mat4),
+                UniformDesc::new("camera_pos", UniformType::Float3),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+                UniformDesc::new("clip_plane", UniformType::Float4),
+                UniformDesc::new("sun_direction", UniformType::Float3),
+                UniformDesc::new("sun_radiance", UniformType::Float3),
+                UniformDesc::new("ambient", UniformType::Float3),
+                UniformDesc::new("irradiance", UniformType::Float3).array(9),
+                UniformDesc::new("fog_color", UniformType::Float3),
+                UniformDesc::new("fog_density", UniformType::Float1),
+                UniformDesc::new("time", UniformType::Float1),
+            ] },
+        }
+    }
+}
+
+#[repr(C)]
+pub struct Uniforms {
+    /// Inverse of `view_proj`, to turn pixels into rays.
+    pub inv_view_proj: Matrix4<f32>,
+    /// The (possibly jittered) matrix the meshes are rasterized with.
+    pub view_proj: Matrix4<f32>,
+    pub camera_pos: Vector3<f32>,
+    pub log_depth_coef: f32,
+    /// Hits on the negative side of this plane are discarded.
+    pub clip_plane: Vector4<f32>,
+    pub sun_direction: Vector3<f32>,
+    pub sun_radiance: Vector3<f32>,
+    pub ambient: Vector3<f32>,
+    pub irradiance: [Vector3<f32>; 9],
+    pub fog_color: Vector3<f32>,
+    pub fog_density: f32,
+    /// Simulation seconds, which animate the shapes.
+    pub time: f32,
+}
@@ -0,0 +1,498 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Bytes a client may send without finishing a message before it is
+/// dropped.
+const MAX_MESSAGE: usize = 1 << 20;
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// A command from a remote client. Messages are JSON objects naming the
+/// command in `cmd`; an `id`, if present, is copied into the reply.
+///
+/// ```json
+/// {"id": 1, "cmd": "camera", "position": [0, 2, 5], "yaw": 90}
+/// {"cmd": "load", "file": "test.ron", "level": true}
+/// {"cmd": "screenshot", "file": "before.png"}
+/// {"cmd": "debug", "flag": "wireframe", "on": true}
+/// {"cmd": "console", "line": "set fov 60"}
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    /// Moves the camera by whichever of the fields are given, angles in
+    /// degrees, and replies with the resulting pose.
+    Camera {
+        position: Option<[f32; 3]>,
+        yaw: Option<f32>,
+        pitch: Option<f32>,
+        fov: Option<f32>,
+    },
+    /// Replaces the scene with one from `scenes/`, or a level from
+    /// `levels/`.
+    Load {
+        file: String,
+        #[serde(default)]
+        level: bool,
+    },
+    /// Saves the next frame to `screenshots/`, replying once it is written.
+    Screenshot { file: Option<String> },
+    /// Lists the debug flags, or switches one.
+    Debug {
+        flag: Option<String>,
+        on: Option<bool>,
+    },
+    /// Runs a line in the console. Only commands that touch neither files
+    /// nor the network are allowed.
+    Console { line: String },
+}
+
+pub struct Request {
+    pub client: usize,
+    pub id: Value,
+    pub command: Result<Command, String>,
+}
+
+#[derive(PartialEq)]
+enum Protocol {
+    /// Nothing received yet.
+    Unknown,
+    /// One JSON message per line, for scripts talking plain TCP.
+    Lines,
+    /// Text frames, after an HTTP upgrade.
+    WebSocket,
+}
+
+struct Client {
+    id: usize,
+    stream: TcpStream,
+    protocol: Protocol,
+    input: Vec<u8>,
+    closed: bool,
+}
+
+/// Lets external tools and test harnesses drive the viewer: a TCP server
+/// on localhost that takes JSON commands either as WebSocket text frames
+/// or as plain lines, told apart by whether the client starts with an
+/// HTTP request. Every command gets one JSON reply, `{"ok": true, ...}`
+/// or `{"ok": false, "error": ...}`. Nothing blocks; the stage polls it
+/// once a frame. Browser pages may only connect if they were served from
+/// localhost too.
+pub struct Server {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    next_client: usize,
+    /// Clients waiting for a screenshot, with the ids to reply with.
+    screenshots: Vec<(usize, Value)>,
+}
+
+impl Server {
+    pub fn start(port: u16) -> Result<Server, String> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|err| format!("could not listen on port {}: {}", port, err))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        Ok(Server {
+            listener,
+            clients: vec![],
+            next_client: 0,
+            screenshots: vec![],
+        })
+    }
+
+    /// Parses `--remote <port>` from the command line.
+    pub fn from_args() -> Option<Server> {
+        let args: Vec<String> = std::env::args().collect();
+        let i = args.iter().position(|arg| arg == "--remote")?;
+        let Some(port) = args.get(i + 1).and_then(|arg| arg.parse().ok()) else {
+            println!("--remote needs a port");
+            return None;
+        };
+        match Server::start(port) {
+            Ok(server) => {
+                println!("{}", server.status());
+                Some(server)
+            }
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let address = self
+            .listener
+            .local_addr()
+            .map_or_else(|err| err.to_string(), |address| address.to_string());
+        format!(
+            "remote control on {}, {} clients",
+            address,
+            self.clients.len()
+        )
+    }
+
+    /// Accepts new clients and returns the commands that arrived since the
+    /// last call. Each must be answered with `reply`.
+    pub fn poll(&mut self) -> Vec<Request> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if stream.set_nonblocking(true).is_err() {
+                        continue;
+                    }
+                    self.clients.push(Client {
+                        id: self.next_client,
+                        stream,
+                        protocol: Protocol::Unknown,
+                        input: vec![],
+                        closed: false,
+                    });
+                    self.next_client += 1;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    println!("Remote control: {}", err);
+                    break;
+                }
+            }
+        }
+
+        let mut requests = vec![];
+        for client in &mut self.clients {
+            for message in client.receive() {
+                requests.push(parse(client.id, &message));
+            }
+        }
+        self.clients.retain(|client| !client.closed);
+        requests
+    }
+
+    pub fn reply(&mut self, client: usize, id: &Value, result: Result<Value, String>) {
+        let mut reply = match result {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert("ok".to_owned(), true.into());
+                Value::Object(fields)
+            }
+            Ok(Value::Null) => json!({ "ok": true }),
+            Ok(value) => json!({ "ok": true, "result": value }),
+            Err(error) => json!({ "ok": false, "error": error }),
+        };
+        if !id.is_null() {
+            reply["id"] = id.clone();
+        }
+        if let Some(client) = self.clients.iter_mut().find(|c| c.id == client) {
+            client.send(&reply.to_string());
+        }
+    }
+
+    /// Holds the reply to a screenshot command until `screenshot_taken`.
+    pub fn wait_for_screenshot(&mut self, client: usize, id: Value) {
+        self.screenshots.push((client, id));
+    }
+
+    /// Answers everyone waiting for a screenshot with where it was saved.
+    pub fn screenshot_taken(&mut self, result: &Result<String, String>) {
+        for (client, id) in std::mem::take(&mut self.screenshots) {
+            let result = result.clone().map(|path| json!({ "file": path }));
+            self.reply(client, &id, result);
+        }
+    }
+}
+
+fn parse(client: usize, message: &str) -> Request {
+    let value = match serde_json::from_str::<Value>(message) {
+        Ok(value) => value,
+        Err(err) => {
+            return Request {
+                client,
+                id: Value::Null,
+                command: Err(format!("not JSON: {}", err)),
+            }
+        }
+    };
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    Request {
+        client,
+        id,
+        command: Command::deserialize(value).map_err(|err| err.to_string()),
+    }
+}
+
+impl Client {
+    /// Reads what is available and splits off the complete messages.
+    fn receive(&mut self) -> Vec<String> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+        if self.input.len() > MAX_MESSAGE {
+            self.closed = true;
+        }
+
+        if self.protocol == Protocol::Unknown && !self.input.is_empty() {
+            if self.input.starts_with(b"GET ") {
+                if !self.handshake() {
+                    return vec![];
+                }
+            } else if let Some(end) = self.input.iter().position(|&b| b == b'\n') {
+                // Any other HTTP request is a browser posting to the port,
+                // whose body would otherwise be taken as commands.
+                if String::from_utf8_lossy(&self.input[..end]).contains(" HTTP/") {
+                    self.closed = true;
+                    return vec![];
+                }
+                self.protocol = Protocol::Lines;
+            }
+        }
+        match self.protocol {
+            Protocol::Unknown => vec![],
+            Protocol::Lines => self.lines(),
+            Protocol::WebSocket => self.frames(),
+        }
+    }
+
+    fn lines(&mut self) -> Vec<String> {
+        let mut messages = vec![];
+        while let Some(end) = self.input.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                messages.push(line.trim().to_owned());
+            }
+        }
+        messages
+    }
+
+    /// Answers the HTTP upgrade request once all of it has arrived.
+    /// Returns whether the connection is now a WebSocket.
+    fn handshake(&mut self) -> bool {
+        let Some(end) = self.input.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return false;
+        };
+        let request: Vec<u8> = self.input.drain(..end + 4).collect();
+        let request = String::from_utf8_lossy(&request);
+        let header = |wanted: &str| {
+            request.lines().find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim()
+                    .eq_ignore_ascii_case(wanted)
+                    .then(|| value.trim().to_owned())
+            })
+        };
+        // Browsers send the page's origin; tools usually send none.
+        if !header("origin").is_none_or(|origin| local_origin(&origin)) {
+            let _ = self
+                .stream
+                .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n");
+            self.closed = true;
+            return false;
+        }
+        let Some(key) = header("sec-websocket-key") else {
+            let _ = self
+                .stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+            self.closed = true;
+            return false;
+        };
+        let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        );
+        self.write(response.as_bytes());
+        self.protocol = Protocol::WebSocket;
+        true
+    }
+
+    /// Decodes the complete frames in the input. Control frames are
+    /// answered here; fragmented messages are not supported.
+    fn frames(&mut self) -> Vec<String> {
+        let mut messages = vec![];
+        while let Some((opcode, payload, length)) = decode_frame(&self.input) {
+            self.input.drain(..length);
+            match opcode {
+                0x1 => messages.push(String::from_utf8_lossy(&payload).into_owned()),
+                0x8 => {
+                    self.write(&encode_frame(0x8, &[]));
+                    self.closed = true;
+                }
+                0x9 => self.write(&encode_frame(0xA, &payload)),
+                _ => (),
+            }
+        }
+        messages
+    }
+
+    fn send(&mut self, message: &str) {
+        match self.protocol {
+            Protocol::WebSocket => self.write(&encode_frame(0x1, message.as_bytes())),
+            _ => self.write(format!("{}\n", message).as_bytes()),
+        }
+    }
+
+    /// Replies are small, so a client that can't take one whole is
+    /// dropped rather than queued for. The socket stays non-blocking, so
+    /// a client that stopped reading fails the write with `WouldBlock`
+    /// once its send buffer is full, instead of stalling the frame.
+    fn write(&mut self, bytes: &[u8]) {
+        if self.stream.write_all(bytes).is_err() {
+            self.closed = true;
+        }
+    }
+}
+
+/// Returns the opcode, unmasked payload and total length of the frame at
+/// the start of `input`, if all of it has arrived.
+/// Whether a browser origin is a page served from this machine, rather
+/// than any site the user happens to have open.
+fn local_origin(origin: &str) -> bool {
+    let Some(host) = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+    else {
+        return false;
+    };
+    let host = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or(""),
+        None => host.split(':').next().unwrap_or(""),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+fn decode_frame(input: &[u8]) -> Option<(u8, Vec<u8>, usize)> {
+    let [first, second, ..] = *input else {
+        return None;
+    };
+    let opcode = first & 0x0F;
+    let masked = second & 0x80 != 0;
+    let (length, mut offset) = match second & 0x7F {
+        126 => (
+            u16::from_be_bytes(input.get(2..4)?.try_into().ok()?) as usize,
+            4,
+        ),
+        127 => (
+            u64::from_be_bytes(input.get(2..10)?.try_into().ok()?) as usize,
+            10,
+        ),
+        n => (n as usize, 2),
+    };
+    let mask = if masked {
+        let mask: [u8; 4] = input.get(offset..offset + 4)?.try_into().ok()?;
+        offset += 4;
+        mask
+    } else {
+        [0; 4]
+    };
+    let payload = input.get(offset..offset.checked_add(length)?)?;
+    let payload = payload
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect();
+    Some((opcode, payload, offset + length))
+}
+
+/// A single unmasked frame, as servers send them.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// SHA-1, which the WebSocket handshake needs and nothing else does.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes(block[i * 4..i * 4 + 4].try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use miniquad::*;
+
+/// One draw call, with all the state it needs.
+pub struct DrawCommand<U> {
+    pub pipeline: Pipeline,
+    pub bindings: Bindings,
+    pub uniforms: U,
+    /// Indices to draw, from the start of the index buffer.
+    pub elements: i32,
+    pub instances: i32,
+    /// Distance from the camera, for ordering.
+    pub depth: f32,
+    /// Blended over what is behind it, so it has to be drawn after it.
+    pub transparent: bool,
+}
+
+/// Draw calls collected while walking the scene and submitted together,
+/// in an order that keeps state changes down and blending correct.
+pub struct RenderQueue<U> {
+    commands: Vec<DrawCommand<U>>,
+}
+
+impl<U> Default for RenderQueue<U> {
+    fn default() -> RenderQueue<U> {
+        RenderQueue { commands: vec![] }
+    }
+}
+
+impl<U> RenderQueue<U> {
+    pub fn push(&mut self, command: DrawCommand<U>) {
+        self.commands.push(command);
+    }
+
+    /// Draws and clears everything queued, into the current pass. Opaque
+    /// commands go first, grouped by pipeline and then by bindings, front
+    /// to back within a group so hidden surfaces fail the depth test early.
+    /// Transparent ones follow back to front, each blending over what is
+    /// already there.
+    pub fn submit(&mut self, ctx: &mut dyn RenderingBackend) {
+        self.draw(ctx, |_| true);
+        self.commands.clear();
+    }
+
+    /// Draws and removes only the opaque commands. The transparent ones
+    /// stay queued for passes of their own.
+    pub fn submit_opaque(&mut self, ctx: &mut dyn RenderingBackend) {
+        self.draw(ctx, |command| !command.transparent);
+        self.commands.retain(|command| command.transparent);
+    }
+
+    /// Draws everything queued again, into the current pass, after `adjust`
+    /// has changed each command's uniforms. Nothing is removed, so the same
+    /// objects can be drawn into several targets.
+    pub fn replay(&mut self, ctx: &mut dyn RenderingBackend, adjust: impl Fn(&mut U)) {
+        for command in &mut self.commands {
+            adjust(&mut command.uniforms);
+        }
+        self.draw(ctx, |_| true);
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    fn draw(&self, ctx: &mut dyn RenderingBackend, include: impl Fn(&DrawCommand<U>) -> bool) {
+        let mut pipelines = HashMap::new();
+        let mut bindings = HashMap::new();
+        let mut keys: Vec<(usize, usize)> = vec![];
+        for command in &self.commands {
+            let count = pipelines.len();
+            let pipeline = *pipelines.entry(command.pipeline).or_insert(count);
+            let count = bindings.len();
+            let binding = *bindings
+                .entry(binding_key(&command.bindings))
+                .or_insert(count);
+            keys.push((pipeline, binding));
+        }
+        let mut order: Vec<usize> = (0..self.commands.len())
+            .filter(|&i| include(&self.commands[i]))
+            .collect();
+        order.sort_by(|&a, &b| {
+            let (ca, cb) = (&self.commands[a], &self.commands[b]);
+            ca.transparent.cmp(&cb.transparent).then_with(|| {
+                if ca.transparent {
+                    cb.depth.total_cmp(&ca.depth)
+                } else {
+                    keys[a].cmp(&keys[b]).then(ca.depth.total_cmp(&cb.depth))
+                }
+            })
+        });
+
+        let mut applied: Option<(usize, usize)> = None;
+        for i in order {
+            let command = &self.commands[i];
+            let (pipeline, binding) = keys[i];
+            if applied.map(|a| a.0) != Some(pipeline) {
+                ctx.apply_pipeline(&command.pipeline);
+                // Bindings don't carry over to a new pipeline.
+                applied = None;
+            }
+            if applied.map(|a| a.1) != Some(binding) {
+                ctx.apply_bindings(&command.bindings);
+            }
+            applied = Some((pipeline, binding));
+            ctx.apply_uniforms(UniformsSource::table(&command.uniforms));
+            ctx.draw(0, command.elements, command.instances);
+        }
+    }
+}
+
+fn binding_key(bindings: &Bindings) -> (Vec<BufferId>, BufferId, Vec<TextureId>) {
+    (
+        bindings.vertex_buffers.clone(),
+        bindings.index_buffer,
+        bindings.images.clone(),
+    )
+}
@@ -0,0 +1,303 @@
+use std::f32::consts::TAU;
+
+use cgmath::{vec2, vec3, vec4, InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use miniquad::*;
+
+use crate::chunks::{self, ChunkManager, CHUNK_SIZE};
+use crate::color::linear_rgba;
+use crate::culling::Frustum;
+use crate::gfx::compile_shader;
+use crate::mesh::{vertex_attributes, GpuMesh, Mesh, Vertex};
+
+/// Kinds of props scattered: grass tufts, then rocks.
+pub const KINDS: usize = 2;
+const GRASS: usize = 0;
+/// Distance between candidate spots of each kind. One jittered candidate
+/// per cell is kept or dropped by the density map.
+const SPACING: [f32; KINDS] = [1.0, 4.0];
+/// Instances of one kind a chunk can hold, which sizes its instance
+/// buffers: one per grass cell.
+pub const MAX_PER_CHUNK: usize = (CHUNK_SIZE as usize).pow(2);
+
+/// Per-instance data, read by the vertex shader from an instance buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterInstance {
+    /// World position in xyz, scale in w.
+    pub offset: Vector4<f32>,
+    /// Cosine and sine of the rotation about Y, then a brightness factor.
+    pub orient: Vector4<f32>,
+}
+
+/// Places the props of one terrain chunk. Where each kind grows is decided
+/// by a density map built from the terrain's slope and height and a
+/// patchy noise layer: grass covers gentle slopes below the snow line in
+/// clumps, rocks are sparse but gather on steep ground.
+pub fn place(seed: u64, (cx, cz): (i32, i32)) -> [Vec<ScatterInstance>; KINDS] {
+    let mut placed = [vec![], vec![]];
+    for (kind, instances) in placed.iter_mut().enumerate() {
+        let spacing = SPACING[kind];
+        let cells = (CHUNK_SIZE / spacing) as i32;
+        // Salted apart from the terrain and from the other kinds.
+        let salt = seed ^ (0x5ca7_7e2d_0000_0000 + kind as u64);
+        for z in 0..cells {
+            for x in 0..cells {
+                let (ix, iz) = (cx * cells + x, cz * cells + z);
+                let random = |i: u64| chunks::hash(salt.wrapping_add(i << 8), ix, iz) * 0.5 + 0.5;
+                let wx = (ix as f32 + random(0)) * spacing;
+                let wz = (iz as f32 + random(1)) * spacing;
+                let y = chunks::height(seed, wx, wz);
+                let e = 0.5;
+                let normal = vec3(
+                    chunks::height(seed, wx - e, wz) - chunks::height(seed, wx + e, wz),
+                    2.0 * e,
+                    chunks::height(seed, wx, wz - e) - chunks::height(seed, wx, wz + e),
+                )
+                .normalize();
+                if random(2) >= density(kind, salt, wx, wz, y, normal.y) {
+                    continue;
+                }
+                let angle = random(3) * TAU;
+                let (scale, sink) = match kind {
+                    GRASS => (0.6 + random(4) * 0.6, 0.0),
+                    _ => (0.3 + random(4).powi(2) * 1.2, 0.15),
+                };
+                instances.push(ScatterInstance {
+                    offset: vec4(wx, y - sink * scale, wz, scale),
+                    orient: vec4(angle.cos(), angle.sin(), 0.75 + random(5) * 0.5, 0.0),
+                });
+            }
+        }
+    }
+    placed
+}
+
+/// Chance in [0, 1] that a candidate spot gets a prop of `kind`.
+fn density(kind: usize, salt: u64, x: f32, z: f32, height: f32, up: f32) -> f32 {
+    let patches = chunks::value_noise(salt, x / 12.0, z / 12.0);
+    match kind {
+        GRASS => {
+            let flat = chunks::smoothstep(0.75, 0.9, up);
+            let below_snow = chunks::smoothstep(11.0, 8.0, height);
+            chunks::smoothstep(-0.3, 0.3, patches) * flat * below_snow
+        }
+        // Rocks.
+        _ => {
+            let steep = chunks::smoothstep(0.9, 0.7, up);
+            (0.15 + 0.5 * steep) * chunks::smoothstep(-0.6, 0.4, patches)
+        }
+    }
+}
+
+/// Grass and rocks scattered over the streamed terrain and drawn with
+/// hardware instancing: each chunk keeps one instance buffer per kind, so
+/// every kind in a chunk is a single draw call however many props it has.
+/// Props shrink into the ground between `fade_start` and `fade_end` from
+/// the camera; chunks beyond that, or outside the view, aren't drawn.
+pub struct Scatter {
+    pub enabled: bool,
+    pub fade_start: f32,
+    pub fade_end: f32,
+    pipeline: Pipeline,
+    meshes: [GpuMesh; KINDS],
+}
+
+impl Scatter {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Scatter {
+        let shader = compile_shader(ctx, shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let mut attributes = vertex_attributes().to_vec();
+        attributes.extend([
+            VertexAttribute::with_buffer("in_offset", VertexFormat::Float4, 1),
+            VertexAttribute::with_buffer("in_orient", VertexFormat::Float4, 1),
+        ]);
+        let pipeline = ctx.new_pipeline(
+            &[
+                BufferLayout::default(),
+                BufferLayout {
+                    step_func: VertexStep::PerInstance,
+                    ..Default::default()
+                },
+            ],
+            &attributes,
+            shader,
+            PipelineParams {
+                depth_write: true,
+                depth_test: Comparison::LessOrEqual,
+                // Grass blades are single quads, seen from both sides, and
+                // reflections flip the winding anyway.
+                cull_face: CullFace::Nothing,
+                ..Default::default()
+            },
+        );
+        Scatter {
+            enabled: false,
+            fade_start: 40.0,
+            fade_end: 60.0,
+            pipeline,
+            meshes: [GpuMesh::new(ctx, tuft()), GpuMesh::new(ctx, rock())],
+        }
+    }
+
+    /// Draws the props of every loaded chunk in view into the current
+    /// pass. `uniforms` describe the view; the fade distances are filled
+    /// in here.
+    pub fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        chunks: &ChunkManager,
+        image: TextureId,
+        mut uniforms: Uniforms,
+    ) {
+        if !self.enabled {
+            return;
+        }
+        uniforms.fade_start = self.fade_start;
+        uniforms.fade_end = self.fade_end;
+        let frustum = Frustum::new(uniforms.perspective * uniforms.view);
+        let camera = uniforms.camera_pos;
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_uniforms(UniformsSource::table(&uniforms));
+        for chunk in chunks.iter() {
+            let (min, max) = chunk.bounds;
+            let nearest = vec3(
+                camera.x.clamp(min.x, max.x),
+                camera.y.clamp(min.y, max.y),
+                camera.z.clamp(min.z, max.z),
+            );
+            if (nearest - camera).magnitude() > self.fade_end
+                || !frustum.intersects(chunk.bounds, Matrix4::identity())
+            {
+                continue;
+            }
+            for (kind, mesh) in self.meshes.iter().enumerate() {
+                if chunk.scatter_counts[kind] == 0 {
+                    continue;
+                }
+                let mut bindings = mesh.bindings(image);
+                bindings.vertex_buffers.push(chunk.buffers.scatter[kind]);
+                ctx.apply_bindings(&bindings);
+                ctx.draw(0, mesh.index_count(), chunk.scatter_counts[kind]);
+            }
+        }
+    }
+}
+
+/// Three crossed blades, darker at the root.
+fn tuft() -> Mesh {
+    let root = linear_rgba(vec4(0.12, 0.22, 0.06, 1.0));
+    let tip = linear_rgba(vec4(0.45, 0.62, 0.22, 1.0));
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for blade in 0..3 {
+        let angle = blade as f32 * TAU / 6.0;
+        let side = vec3(angle.cos(), 0.0, angle.sin());
+        let lean = vec3(-angle.sin(), 0.0, angle.cos()) * 0.08;
+        let base = vertices.len() as u16;
+        for (pos, color) in [
+            (-side * 0.12, root),
+            (side * 0.12, root),
+            (side * 0.03 + lean + vec3(0.0, 0.5, 0.0), tip),
+            (-side * 0.03 + lean + vec3(0.0, 0.5, 0.0), tip),
+        ] {
+            vertices.push(Vertex {
+                pos,
+                // Lit like the ground they grow from.
+                normal: vec3(0.0, 1.0, 0.0),
+                color,
+                uv2: vec2(0.0, 0.0),
+            });
+        }
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    Mesh { vertices, indices }
+}
+
+/// A flat-shaded boulder: a wide ring of six points at the bottom, a
+/// narrower, twisted one above it and a cap.
+fn rock() -> Mesh {
+    let ring = |i: usize, radius: f32, y: f32, twist: f32| {
+        let angle = (i as f32 + twist) * TAU / 6.0;
+        // Irregular but fixed, so every rock has the same silhouette
+        // before scaling and rotation.
+        let bump = 1.0 + 0.15 * ((i * 7 + 3) % 5) as f32 / 4.0;
+        vec3(angle.cos() * radius * bump, y, angle.sin() * radius * bump)
+    };
+    let cap = vec3(0.05, 0.5, -0.03);
+    let mut triangles = vec![];
+    for i in 0..6 {
+        let j = (i + 1) % 6;
+        let (a, b) = (ring(i, 0.5, 0.0, 0.0), ring(j, 0.5, 0.0, 0.0));
+        let (c, d) = (ring(i, 0.35, 0.35, 0.5), ring(j, 0.35, 0.35, 0.5));
+        triangles.extend([[a, c, b], [b, c, d], [c, cap, d]]);
+    }
+    let color = linear_rgba(vec4(0.45, 0.43, 0.4, 1.0));
+    let center = vec3(0.0, 0.2, 0.0);
+    let mut vertices = vec![];
+    for [a, b, c] in triangles {
+        let mut normal = (b - a).cross(c - a).normalize();
+        let mut corners = [a, b, c];
+        if normal.dot((a + b + c) / 3.0 - center) < 0.0 {
+            normal = -normal;
+            corners.swap(1, 2);
+        }
+        vertices.extend(corners.map(|pos| Vertex {
+            pos,
+            normal,
+            color,
+            uv2: vec2(0.0, 0.0),
+        }));
+    }
+    let indices = (0..vertices.len() as u16).collect();
+    Mesh { vertices, indices }
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const VERTEX: &str = include_str!("shaders/scatter.vert");
+    /// Lit like the rest of the scene.
+    pub const FRAGMENT: &str = include_str!("shaders/basic.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["lightmap".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("perspective", UniformType::Mat4),
+                UniformDesc::new("view", UniformType::Mat4),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+                UniformDesc::new("camera_pos", UniformType::Float3),
+                UniformDesc::new("fade_start", UniformType::Float1),
+                UniformDesc::new("fade_end", UniformType::Float1),
+                UniformDesc::new("use_lightmap", UniformType::Float1),
+                UniformDesc::new("lightmap_range", UniformType::Float1),
+                UniformDesc::new("clip_plane", UniformType::Float4),
+                UniformDesc::new("sun_direction", UniformType::Float3),
+                UniformDesc::new("sun_radiance", UniformType::Float3),
+                UniformDesc::new("ambient", UniformType::Float3),
+                UniformDesc::new("irradiance", UniformType::Float3).array(9),
+                UniformDesc::new("fog_color", UniformType::Float3),
+                UniformDesc::new("fog_density", UniformType::Float1),
+            ] },
+        }
+    }
+}
+
+#[repr(C)]
+pub struct Uniforms {
+    pub perspective: Matrix4<f32>,
+    pub view: Matrix4<f32>,
+    pub log_depth_coef: f32,
+    pub camera_pos: Vector3<f32>,
+    pub fade_start: f32,
+    pub fade_end: f32,
+    /// Always 0: props aren't part of the lightmap.
+    pub use_lightmap: f32,
+    pub lightmap_range: f32,
+    pub clip_plane: Vector4<f32>,
+    pub sun_direction: Vector3<f32>,
+    pub sun_radiance: Vector3<f32>,
+    pub ambient: Vector3<f32>,
+    pub irradiance: [Vector3<f32>; 9],
+    pub fog_color: Vector3<f32>,
+    pub fog_density: f32,
+}
@@ -0,0 +1,364 @@
+use cgmath::{vec3, vec4, ElementWise, Matrix4, One, Quaternion, Vector3, Vector4};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::animation::Animation;
+use crate::mesh::MeshId;
+
+/// Upper bound on instances drawn per call; sized to fit the per-instance
+/// uniform arrays within the GL 3 minimum uniform budget.
+pub const MAX_INSTANCES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn from_position(position: Vector3<f32>) -> Transform {
+        Transform {
+            position,
+            ..Transform::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.position)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            position: vec3(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    /// Linear color, multiplied with the vertex colors.
+    pub color: Vector4<f32>,
+    pub cull: Cull,
+    pub winding: Winding,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            color: vec4(1.0, 1.0, 1.0, 1.0),
+            cull: Cull::Back,
+            winding: Winding::CounterClockwise,
+        }
+    }
+}
+
+/// Which side of a material's triangles is left out. Thin geometry like
+/// foliage cards wants `None`; back faces are then lit as seen from
+/// behind.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Cull {
+    Back,
+    Front,
+    None,
+}
+
+/// The order a triangle's corners go round in, seen from its front. Meshes
+/// authored for the other convention show inside out unless their
+/// material says so.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Winding {
+    CounterClockwise,
+    Clockwise,
+}
+
+/// Per-object values applied on top of the material when drawing, so one
+/// instance can be recolored or highlighted without a material of its own.
+/// They aren't scene edits and don't go into the history.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawParams {
+    /// Multiplied with the material color, alpha included.
+    pub tint: Vector4<f32>,
+    /// How much of the object's color is added on top of the lighting.
+    pub emissive: f32,
+    /// Free for experiments; the built-in shaders ignore them.
+    pub custom: Vector3<f32>,
+}
+
+impl DrawParams {
+    /// Emissive factor in x, the custom values in yzw, as the shader gets
+    /// them.
+    pub fn packed(&self) -> Vector4<f32> {
+        vec4(self.emissive, self.custom.x, self.custom.y, self.custom.z)
+    }
+}
+
+impl Default for DrawParams {
+    fn default() -> DrawParams {
+        DrawParams {
+            tint: vec4(1.0, 1.0, 1.0, 1.0),
+            emissive: 0.0,
+            custom: vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<MeshId>,
+    pub material: Material,
+    pub params: DrawParams,
+    /// Relative to the parent.
+    pub transform: Transform,
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    /// Drives `transform` while playing.
+    pub animation: Option<Animation>,
+}
+
+impl Node {
+    pub fn new(name: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            mesh: None,
+            material: Material::default(),
+            params: DrawParams::default(),
+            transform: Transform::default(),
+            parent: None,
+            children: vec![],
+            animation: None,
+        }
+    }
+}
+
+/// A drawable node, resolved to world space.
+#[derive(Clone, Copy)]
+pub struct Instance {
+    pub node: NodeId,
+    pub mesh: MeshId,
+    pub world: Matrix4<f32>,
+    pub material: Material,
+    pub params: DrawParams,
+}
+
+impl Instance {
+    /// The material color with the tint applied.
+    pub fn color(&self) -> Vector4<f32> {
+        self.material.color.mul_element_wise(self.params.tint)
+    }
+}
+
+/// Node hierarchy. Ids stay valid for the lifetime of a node; removed slots
+/// are left empty rather than reused.
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Option<Node>>,
+}
+
+impl Scene {
+    pub fn add(&mut self, mut node: Node, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        node.parent = parent;
+        if let Some(parent) = parent {
+            self.nodes[parent.0].as_mut().unwrap().children.push(id);
+        }
+        self.nodes.push(Some(node));
+        id
+    }
+
+    /// Removes a node along with all of its descendants.
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn remove(&mut self, id: NodeId) {
+        self.detach(id);
+    }
+
+    /// Removes a node and its descendants, handing them back (the node
+    /// first) so `restore` can put them back in place.
+    pub fn detach(&mut self, id: NodeId) -> Vec<(NodeId, Node)> {
+        let Some(node) = self.nodes.get_mut(id.0).and_then(Option::take) else {
+            return vec![];
+        };
+        if let Some(parent) = node.parent.and_then(|p| self.get_mut(p)) {
+            parent.children.retain(|&child| child != id);
+        }
+        // Children can't reach their already detached parent, so its
+        // child list stays intact for `restore`.
+        let children = node.children.clone();
+        let mut detached = vec![(id, node)];
+        for child in children {
+            detached.extend(self.detach(child));
+        }
+        detached
+    }
+
+    /// Puts nodes returned by `detach` back under their old ids. Ids are
+    /// never reused, so the slots are still free.
+    pub fn restore(&mut self, nodes: Vec<(NodeId, Node)>) {
+        let Some(&(root, ref first)) = nodes.first() else {
+            return;
+        };
+        if let Some(parent) = first.parent.and_then(|p| self.get_mut(p)) {
+            parent.children.push(root);
+        }
+        for (id, node) in nodes {
+            self.nodes[id.0] = Some(node);
+        }
+    }
+
+    /// Moves a node under another parent, or to the top level. The node
+    /// keeps its local transform, so it follows its new parent around.
+    /// Refuses to make a node its own ancestor.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        let Some(old) = self.get(id).map(|node| node.parent) else {
+            return false;
+        };
+        if let Some(parent) = parent {
+            if self.get(parent).is_none() || self.is_ancestor(id, parent) {
+                return false;
+            }
+        }
+        if let Some(old) = old.and_then(|p| self.get_mut(p)) {
+            old.children.retain(|&child| child != id);
+        }
+        if let Some(new) = parent.and_then(|p| self.get_mut(p)) {
+            new.children.push(id);
+        }
+        self.get_mut(id).unwrap().parent = parent;
+        true
+    }
+
+    /// Whether `ancestor` is `id` or one of its ancestors.
+    pub fn is_ancestor(&self, ancestor: NodeId, mut id: NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.get(id).and_then(|node| node.parent) {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Removes every node. Ids handed out so far stay dead rather than
+    /// coming back as new nodes.
+    pub fn clear(&mut self) {
+        self.nodes.iter_mut().for_each(|node| *node = None);
+    }
+
+    /// Looks up a live node by its index, as handed out to scripts.
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn id(&self, index: usize) -> Option<NodeId> {
+        self.get(NodeId(index)).map(|_| NodeId(index))
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id.0)?.as_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, node)| Some((NodeId(i), node.as_ref()?)))
+    }
+
+    /// Topmost ancestor of a node, or the node itself.
+    pub fn root(&self, mut id: NodeId) -> NodeId {
+        while let Some(parent) = self.get(id).and_then(|node| node.parent) {
+            id = parent;
+        }
+        id
+    }
+
+    /// Advances every playing animation by `dt` seconds and poses its node.
+    /// Nodes are animated in parallel.
+    pub fn animate(&mut self, dt: f32) {
+        self.nodes.par_iter_mut().flatten().for_each(|node| {
+            let Some(animation) = &mut node.animation else {
+                return;
+            };
+            if animation.advance(dt) {
+                animation.clip.apply(animation.time, &mut node.transform);
+            }
+        });
+    }
+
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    pub fn animation_mut(&mut self, id: NodeId) -> Option<&mut Animation> {
+        self.get_mut(id)?.animation.as_mut()
+    }
+
+    pub fn world_transform(&self, id: NodeId) -> Matrix4<f32> {
+        let node = self.get(id).unwrap();
+        let local = node.transform.matrix();
+        match node.parent {
+            Some(parent) => self.world_transform(parent) * local,
+            None => local,
+        }
+    }
+
+    /// Every node with a mesh, grouped by mesh so consecutive entries can
+    /// be drawn instanced. World transforms are resolved in parallel.
+    pub fn instances(&self) -> Vec<Instance> {
+        let mut instances: Vec<Instance> = self
+            .nodes
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, node)| {
+                let node = node.as_ref()?;
+                Some(Instance {
+                    node: NodeId(i),
+                    mesh: node.mesh?,
+                    world: self.world_transform(NodeId(i)),
+                    material: node.material,
+                    params: node.params,
+                })
+            })
+            .collect();
+        // Keys are unique, so an unstable sort gives the same order.
+        instances.par_sort_unstable_by_key(|instance| {
+            let material = &instance.material;
+            (
+                instance.mesh,
+                material.cull,
+                material.winding,
+                instance.node,
+            )
+        });
+        instances
+    }
+}
+
+/// Splits sorted instances into runs that share a mesh and the faces drawn
+/// of it, and fit in one draw call.
+pub fn batches(instances: &[Instance]) -> impl Iterator<Item = &[Instance]> {
+    instances
+        .chunk_by(|a, b| {
+            a.mesh == b.mesh
+                && a.material.cull == b.material.cull
+                && a.material.winding == b.material.winding
+        })
+        .flat_map(|run| run.chunks(MAX_INSTANCES))
+}
@@ -0,0 +1,408 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs, mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use cgmath::{vec3, Deg, Euler, InnerSpace, Quaternion, Vector3};
+use miniquad::KeyCode;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope, AST, FLOAT, INT};
+
+use crate::animation::Animation;
+use crate::light::Lighting;
+use crate::mesh::MeshLibrary;
+use crate::prefab::PrefabLibrary;
+use crate::scene::{DrawParams, NodeId, Scene, Transform};
+
+const SCRIPT_DIR: &str = "scripts";
+/// How often `scripts/` is checked for added, changed or removed files.
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Everything scripts can touch. The stage's state is moved in here for
+/// the duration of a script run and moved back out afterwards, so the
+/// registered functions can reach it without borrowing the stage.
+#[derive(Default)]
+struct World {
+    scene: Scene,
+    prefabs: PrefabLibrary,
+    meshes: MeshLibrary,
+    lighting: Lighting,
+    keys_down: HashSet<KeyCode>,
+    /// Nodes spawned by the script currently running.
+    spawned: Vec<NodeId>,
+}
+
+type Shared = Rc<RefCell<World>>;
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+struct Script {
+    path: PathBuf,
+    modified: SystemTime,
+    ast: AST,
+    scope: Scope<'static>,
+    /// Removed again when the script is reloaded, so reloading doesn't
+    /// pile up copies of whatever the script creates at startup.
+    spawned: Vec<NodeId>,
+    /// Set after a runtime error, until the file changes.
+    failed: bool,
+}
+
+/// Rhai scripts from `scripts/`, hot-reloaded when their files change.
+///
+/// The top level of a script runs once when it is (re)loaded; a
+/// `fn update(dt)` in it is then called every frame. Scripts run after the
+/// day/night cycle, so lights set from `update` override it.
+pub struct Scripts {
+    engine: Engine,
+    world: Shared,
+    scripts: Vec<Script>,
+    last_scan: Instant,
+}
+
+/// The stage state scripts are run against.
+pub struct Context<'a> {
+    pub scene: &'a mut Scene,
+    pub prefabs: &'a mut PrefabLibrary,
+    pub meshes: &'a mut MeshLibrary,
+    pub lighting: &'a mut Lighting,
+    pub keys_down: &'a HashSet<KeyCode>,
+}
+
+impl Scripts {
+    pub fn new() -> Scripts {
+        let world = Shared::default();
+        let mut engine = Engine::new();
+        register_api(&mut engine, &world);
+        Scripts {
+            engine,
+            world,
+            scripts: vec![],
+            // Forces a scan on the first update.
+            last_scan: Instant::now() - RELOAD_INTERVAL,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, mut ctx: Context) {
+        self.swap(&mut ctx);
+        self.world.borrow_mut().keys_down.clone_from(ctx.keys_down);
+        if self.last_scan.elapsed() >= RELOAD_INTERVAL {
+            self.last_scan = Instant::now();
+            self.reload_changed();
+        }
+        for script in self.scripts.iter_mut().filter(|s| !s.failed) {
+            let has_update = script
+                .ast
+                .iter_functions()
+                .any(|f| f.name == "update" && f.params.len() == 1);
+            if !has_update {
+                continue;
+            }
+            let result = self.engine.call_fn::<Dynamic>(
+                &mut script.scope,
+                &script.ast,
+                "update",
+                (dt as FLOAT,),
+            );
+            script.spawned.append(&mut self.world.borrow_mut().spawned);
+            if let Err(err) = result {
+                println!("Script {} failed: {}", script.path.display(), err);
+                script.failed = true;
+            }
+        }
+        self.swap(&mut ctx);
+    }
+
+    /// Exchanges the stage's state with the scripts' world; called once to
+    /// hand it over before running scripts and once to give it back.
+    fn swap(&self, ctx: &mut Context) {
+        let mut world = self.world.borrow_mut();
+        mem::swap(&mut world.scene, ctx.scene);
+        mem::swap(&mut world.prefabs, ctx.prefabs);
+        mem::swap(&mut world.meshes, ctx.meshes);
+        mem::swap(&mut world.lighting, ctx.lighting);
+    }
+
+    fn reload_changed(&mut self) {
+        let mut paths: Vec<PathBuf> = fs::read_dir(SCRIPT_DIR)
+            .map(|dir| {
+                dir.filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|p| p.extension().is_some_and(|e| e == "rhai"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.sort();
+
+        let mut kept = vec![];
+        for script in mem::take(&mut self.scripts) {
+            let unchanged =
+                paths.contains(&script.path) && modified(&script.path) == Some(script.modified);
+            if unchanged {
+                kept.push(script);
+            } else {
+                self.unload(script);
+            }
+        }
+        self.scripts = kept;
+
+        for path in paths {
+            if self.scripts.iter().any(|s| s.path == path) {
+                continue;
+            }
+            if let Some(script) = self.load(path) {
+                self.scripts.push(script);
+            }
+        }
+        self.scripts.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    fn load(&self, path: PathBuf) -> Option<Script> {
+        let modified = modified(&path)?;
+        let ast = self
+            .engine
+            .compile_file(path.clone())
+            .map_err(|err| println!("Could not compile script {}: {}", path.display(), err))
+            .ok()?;
+        let mut scope = Scope::new();
+        let result = self.engine.run_ast_with_scope(&mut scope, &ast);
+        let spawned = mem::take(&mut self.world.borrow_mut().spawned);
+        let failed = match result {
+            Ok(()) => {
+                println!("Loaded script {}", path.display());
+                false
+            }
+            Err(err) => {
+                println!("Script {} failed: {}", path.display(), err);
+                true
+            }
+        };
+        Some(Script {
+            path,
+            modified,
+            ast,
+            scope,
+            spawned,
+            failed,
+        })
+    }
+
+    fn unload(&self, script: Script) {
+        let mut world = self.world.borrow_mut();
+        for id in script.spawned {
+            world.scene.remove(id);
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn register_api(engine: &mut Engine, world: &Shared) {
+    // Scene
+    let w = world.clone();
+    engine.register_fn("instantiate", move |prefab: &str| -> ScriptResult<INT> {
+        spawn(&w, prefab, None)
+    });
+    let w = world.clone();
+    engine.register_fn(
+        "instantiate",
+        move |prefab: &str, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<INT> {
+            spawn(&w, prefab, Some(Transform::from_position(vector(x, y, z))))
+        },
+    );
+    let w = world.clone();
+    engine.register_fn("destroy", move |node: INT| -> ScriptResult<()> {
+        let mut world = w.borrow_mut();
+        let id = node_id(&world.scene, node)?;
+        world.scene.remove(id);
+        Ok(())
+    });
+    let w = world.clone();
+    engine.register_fn("find", move |name: &str| -> INT {
+        w.borrow()
+            .scene
+            .iter()
+            .find(|(_, node)| node.name == name)
+            .map_or(-1, |(id, _)| id.index() as INT)
+    });
+
+    // Transforms, relative to the node's parent. Angles are in degrees.
+    let w = world.clone();
+    engine.register_fn("position", move |node: INT| -> ScriptResult<Array> {
+        with_transform(&w, node, |t| array(t.position))
+    });
+    let w = world.clone();
+    engine.register_fn(
+        "set_position",
+        move |node: INT, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+            with_transform(&w, node, |t| t.position = vector(x, y, z))
+        },
+    );
+    let w = world.clone();
+    engine.register_fn(
+        "translate",
+        move |node: INT, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+            with_transform(&w, node, |t| t.position += vector(x, y, z))
+        },
+    );
+    let w = world.clone();
+    engine.register_fn(
+        "set_rotation",
+        move |node: INT, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+            with_transform(&w, node, |t| t.rotation = euler(x, y, z))
+        },
+    );
+    let w = world.clone();
+    engine.register_fn(
+        "rotate",
+        move |node: INT, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+            with_transform(&w, node, |t| t.rotation = t.rotation * euler(x, y, z))
+        },
+    );
+    let w = world.clone();
+    engine.register_fn(
+        "set_scale",
+        move |node: INT, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+            with_transform(&w, node, |t| t.scale = vector(x, y, z))
+        },
+    );
+
+    // Animations authored on the node.
+    let w = world.clone();
+    engine.register_fn("play_animation", move |node: INT| -> ScriptResult<()> {
+        with_animation(&w, node, Animation::play)
+    });
+    let w = world.clone();
+    engine.register_fn("pause_animation", move |node: INT| -> ScriptResult<()> {
+        with_animation(&w, node, Animation::pause)
+    });
+    let w = world.clone();
+    engine.register_fn(
+        "set_animation_looping",
+        move |node: INT, looping: bool| -> ScriptResult<()> {
+            with_animation(&w, node, |a| a.looping = looping)
+        },
+    );
+
+    // Per-object draw parameters. Colors are linear.
+    let w = world.clone();
+    engine.register_fn(
+        "set_tint",
+        move |node: INT, r: FLOAT, g: FLOAT, b: FLOAT, a: FLOAT| -> ScriptResult<()> {
+            with_params(&w, node, |p| p.tint = vector(r, g, b).extend(a as f32))
+        },
+    );
+    let w = world.clone();
+    engine.register_fn(
+        "set_emissive",
+        move |node: INT, emissive: FLOAT| -> ScriptResult<()> {
+            with_params(&w, node, |p| p.emissive = emissive as f32)
+        },
+    );
+    let w = world.clone();
+    engine.register_fn(
+        "set_params",
+        move |node: INT, x: FLOAT, y: FLOAT, z: FLOAT| -> ScriptResult<()> {
+            with_params(&w, node, |p| p.custom = vector(x, y, z))
+        },
+    );
+
+    // Lights. Colors are linear.
+    let w = world.clone();
+    engine.register_fn("set_sun_direction", move |x: FLOAT, y: FLOAT, z: FLOAT| {
+        w.borrow_mut().lighting.sun.direction = vector(x, y, z).normalize();
+    });
+    let w = world.clone();
+    engine.register_fn("set_sun_color", move |r: FLOAT, g: FLOAT, b: FLOAT| {
+        w.borrow_mut().lighting.sun.color = vector(r, g, b);
+    });
+    let w = world.clone();
+    engine.register_fn("set_sun_intensity", move |intensity: FLOAT| {
+        w.borrow_mut().lighting.sun.intensity = intensity as f32;
+    });
+    let w = world.clone();
+    engine.register_fn("set_ambient", move |r: FLOAT, g: FLOAT, b: FLOAT| {
+        w.borrow_mut().lighting.ambient = vector(r, g, b);
+    });
+    let w = world.clone();
+    engine.register_fn(
+        "set_fog",
+        move |r: FLOAT, g: FLOAT, b: FLOAT, density: FLOAT| {
+            let lighting = &mut w.borrow_mut().lighting;
+            lighting.fog_color = vector(r, g, b);
+            lighting.fog_density = density as f32;
+        },
+    );
+
+    // Input. Keys are named as in miniquad's `KeyCode`, e.g. "Space", "Up".
+    let w = world.clone();
+    engine.register_fn("key_down", move |key: &str| -> bool {
+        w.borrow()
+            .keys_down
+            .iter()
+            .any(|k| format!("{:?}", k).eq_ignore_ascii_case(key))
+    });
+}
+
+fn spawn(world: &Shared, prefab: &str, transform: Option<Transform>) -> ScriptResult<INT> {
+    let world = &mut *world.borrow_mut();
+    let id = world
+        .prefabs
+        .instantiate(prefab, &mut world.scene, &world.meshes, transform, None)?;
+    world.spawned.push(id);
+    Ok(id.index() as INT)
+}
+
+fn node_id(scene: &Scene, node: INT) -> ScriptResult<NodeId> {
+    usize::try_from(node)
+        .ok()
+        .and_then(|index| scene.id(index))
+        .ok_or_else(|| format!("no node {}", node).into())
+}
+
+fn with_transform<T>(
+    world: &Shared,
+    node: INT,
+    f: impl FnOnce(&mut Transform) -> T,
+) -> ScriptResult<T> {
+    let mut world = world.borrow_mut();
+    let id = node_id(&world.scene, node)?;
+    Ok(f(&mut world.scene.get_mut(id).unwrap().transform))
+}
+
+fn with_params(world: &Shared, node: INT, f: impl FnOnce(&mut DrawParams)) -> ScriptResult<()> {
+    let mut world = world.borrow_mut();
+    let id = node_id(&world.scene, node)?;
+    f(&mut world.scene.get_mut(id).unwrap().params);
+    Ok(())
+}
+
+fn with_animation(world: &Shared, node: INT, f: impl FnOnce(&mut Animation)) -> ScriptResult<()> {
+    let mut world = world.borrow_mut();
+    let id = node_id(&world.scene, node)?;
+    let animation = world
+        .scene
+        .animation_mut(id)
+        .ok_or_else(|| format!("node {} has no animation", node))?;
+    f(animation);
+    Ok(())
+}
+
+fn vector(x: FLOAT, y: FLOAT, z: FLOAT) -> Vector3<f32> {
+    vec3(x as f32, y as f32, z as f32)
+}
+
+fn euler(x: FLOAT, y: FLOAT, z: FLOAT) -> Quaternion<f32> {
+    Quaternion::from(Euler::new(Deg(x as f32), Deg(y as f32), Deg(z as f32)))
+}
+
+fn array(v: Vector3<f32>) -> Array {
+    vec![
+        Dynamic::from_float(v.x as FLOAT),
+        Dynamic::from_float(v.y as FLOAT),
+        Dynamic::from_float(v.z as FLOAT),
+    ]
+}
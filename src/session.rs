@@ -0,0 +1,49 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+const PATH: &str = "session.ron";
+
+/// What one run leaves for the next, so that restarting doesn't start
+/// over at the origin with everything reset.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Session {
+    pub camera: Option<CameraState>,
+    /// Saved even when `restore_session` is off, so that choice sticks.
+    pub settings: Settings,
+    /// Names of the enabled debug flags.
+    pub debug: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CameraState {
+    /// As written by `Camera::pose`.
+    pub pose: String,
+    pub speed: f32,
+    /// Missing from sessions saved before it could be changed.
+    #[serde(default)]
+    pub ortho_size: Option<f32>,
+}
+
+impl Session {
+    /// The last run's session, or `None` if there was none. A broken file
+    /// is reported and ignored.
+    pub fn load() -> Option<Session> {
+        let text = fs::read_to_string(PATH).ok()?;
+        ron::from_str(&text)
+            .map_err(|err| println!("Could not parse {}: {}", PATH, err))
+            .ok()
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| fs::write(PATH, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            println!("Could not save {}: {}", PATH, err);
+        }
+    }
+}
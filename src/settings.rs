@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::palette::ColorScheme;
+
+/// Options that can be changed while running. They are kept between runs
+/// as part of the `Session`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub antialiasing: Antialiasing,
+    /// Radians turned per pixel of mouse motion.
+    pub mouse_sensitivity: f32,
+    /// Pick up the camera, settings and debug views of the last run at
+    /// startup.
+    pub restore_session: bool,
+    /// Spread the per-object scene update over all cores.
+    pub parallel_update: bool,
+    /// Vertical field of view in degrees. Zooming and jumping to a pasted
+    /// pose change the camera's for a while, but not this.
+    pub fov: f32,
+    /// Colors of the debug views, gizmo, HUD and generated props.
+    pub color_scheme: ColorScheme,
+    /// Brighter lines and text on solid backgrounds.
+    pub high_contrast: bool,
+    pub transparency: Transparency,
+}
+
+/// MSAA is not offered: the scene is rendered offscreen and miniquad has
+/// no multisampled render textures to resolve from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Antialiasing {
+    Off,
+    Fxaa,
+    Taa,
+}
+
+impl Antialiasing {
+    pub fn next(self) -> Antialiasing {
+        match self {
+            Antialiasing::Off => Antialiasing::Fxaa,
+            Antialiasing::Fxaa => Antialiasing::Taa,
+            Antialiasing::Taa => Antialiasing::Off,
+        }
+    }
+}
+
+/// How transparent materials are combined with what is behind them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transparency {
+    /// Blended back to front, object by object. Exact for separate
+    /// objects, but overlapping ones pop as their order changes.
+    Sorted,
+    /// Order-independent, see `Oit`: stable, but only approximately
+    /// layered.
+    Weighted,
+}
+
+impl Transparency {
+    pub fn find(name: &str) -> Option<Transparency> {
+        match name {
+            "sorted" => Some(Transparency::Sorted),
+            "weighted" => Some(Transparency::Weighted),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            antialiasing: Antialiasing::Fxaa,
+            mouse_sensitivity: 0.01,
+            restore_session: true,
+            parallel_update: true,
+            fov: 80.0,
+            color_scheme: ColorScheme::Standard,
+            high_contrast: false,
+            transparency: Transparency::Sorted,
+        }
+    }
+}
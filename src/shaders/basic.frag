@@ -1,6 +1,64 @@
-#version 100
+#version 140
 in lowp vec4 color;
+in vec3 world_pos;
+in vec3 normal;
+in float view_distance;
+in vec2 lightmap_uv;
+in vec4 params;
+
+out vec4 frag_color;
+
+uniform sampler2D lightmap;
+uniform float use_lightmap;
+uniform float lightmap_range;
+uniform vec4 clip_plane;
+uniform vec3 sun_direction;
+uniform vec3 sun_radiance;
+uniform vec3 ambient;
+// Environment map irradiance as spherical harmonics, zero without one.
+uniform vec3 irradiance[9];
+uniform vec3 fog_color;
+uniform float fog_density;
+// 0 to blend over the target, 1 for order-independent accumulation and
+// 2 for its revealage, see `Oit`.
+uniform float oit_output;
+
+vec3 environment_light(vec3 n) {
+    return irradiance[0]
+        + irradiance[1]*n.y + irradiance[2]*n.z + irradiance[3]*n.x
+        + irradiance[4]*n.x*n.y + irradiance[5]*n.y*n.z
+        + irradiance[6]*(3.0*n.z*n.z - 1.0) + irradiance[7]*n.x*n.z
+        + irradiance[8]*(n.x*n.x - n.y*n.y);
+}
+
+// How much a transparent surface counts towards the weighted average:
+// more when it covers more, and much more when it is close.
+float oit_weight(float alpha, float z) {
+    return alpha*clamp(10.0/(1e-5 + pow(z/5.0, 2.0) + pow(z/200.0, 6.0)), 1e-2, 3e3);
+}
 
 void main() {
-    gl_FragColor = color;
+    if (dot(vec4(world_pos, 1.0), clip_plane) < 0.0) {
+        discard;
+    }
+    // Back faces only show on double-sided or front-culled materials,
+    // and are lit from their own side.
+    vec3 n = normalize(gl_FrontFacing ? normal : -normal);
+    vec3 direct;
+    if (use_lightmap > 0.5) {
+        direct = texture(lightmap, lightmap_uv).rgb*lightmap_range;
+    } else {
+        direct = sun_radiance*max(dot(n, sun_direction), 0.0);
+    }
+    vec3 lit = color.rgb*(ambient + max(environment_light(n), 0.0) + direct + params.x);
+    float d = fog_density*view_distance;
+    float fog = 1.0 - exp(-d*d);
+    vec3 shaded = mix(lit, fog_color, fog);
+    if (oit_output > 1.5) {
+        frag_color = vec4(-log(1.0 - min(color.a, 0.999)));
+    } else if (oit_output > 0.5) {
+        frag_color = vec4(shaded*color.a, color.a)*oit_weight(color.a, view_distance);
+    } else {
+        frag_color = vec4(shaded, color.a);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,8 @@
+#version 140
+in vec4 color;
+
+out vec4 frag_color;
+
+void main() {
+    frag_color = color;
+}
\ No newline at end of file
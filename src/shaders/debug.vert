@@ -0,0 +1,16 @@
+#version 140
+in vec3 in_pos;
+in vec4 in_color;
+
+out vec4 color;
+
+uniform mat4 view_proj;
+uniform float log_depth_coef;
+
+void main() {
+    gl_Position = view_proj*vec4(in_pos, 1.0);
+    if (log_depth_coef > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w))*log_depth_coef - 1.0)*gl_Position.w;
+    }
+    color = in_color;
+}
\ No newline at end of file
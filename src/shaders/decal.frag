@@ -0,0 +1,37 @@
+#version 140
+out vec4 frag_color;
+
+uniform sampler2D depth;
+uniform sampler2D decal;
+uniform mat4 inv_view_proj;
+uniform mat4 projection;
+uniform float log_depth_coef;
+uniform mat4 inv_world;
+uniform vec2 screen_size;
+// Where the image is in the texture: scale in xy, offset in zw.
+uniform vec4 scale_offset;
+
+// The NDC depth `projection` gives the point in the depth buffer at
+// `d`, which with logarithmic depth holds log2 of view depth instead.
+float ndc_depth(float d) {
+    float z = d*2.0 - 1.0;
+    if (log_depth_coef > 0.0) {
+        float w = exp2((z + 1.0)/log_depth_coef) - 1.0;
+        return (projection[3][2] - projection[2][2]*w)/w;
+    }
+    return z;
+}
+
+void main() {
+    vec2 uv = gl_FragCoord.xy/screen_size;
+    float d = texture(depth, uv).r;
+    if (d >= 1.0) {
+        discard;
+    }
+    vec4 world_pos = inv_view_proj*vec4(uv*2.0 - 1.0, ndc_depth(d), 1.0);
+    vec3 local = (inv_world*vec4(world_pos.xyz/world_pos.w, 1.0)).xyz;
+    if (any(greaterThan(abs(local), vec3(0.5)))) {
+        discard;
+    }
+    frag_color = texture(decal, (local.xy + 0.5)*scale_offset.xy + scale_offset.zw);
+}
\ No newline at end of file
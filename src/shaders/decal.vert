@@ -0,0 +1,9 @@
+#version 140
+in vec3 in_pos;
+
+uniform mat4 view_proj;
+uniform mat4 world;
+
+void main() {
+    gl_Position = view_proj*world*vec4(in_pos, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,55 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+uniform sampler2D depth;
+uniform vec2 direction;
+uniform float near;
+uniform float far;
+uniform float log_depth_coef;
+uniform float focus_distance;
+uniform float autofocus;
+uniform float aperture;
+uniform float max_radius;
+
+const int TAPS = 8;
+
+float linear_depth(vec2 at) {
+    float z = texture(depth, at).r*2.0 - 1.0;
+    if (log_depth_coef > 0.0) {
+        // Logarithmic depth holds log2 of view depth instead.
+        return exp2((z + 1.0)/log_depth_coef) - 1.0;
+    }
+    return 2.0*near*far/(far + near - z*(far - near));
+}
+
+// Circle of confusion radius in pixels.
+float coc(float d, float focus) {
+    return clamp(aperture*abs(d - focus)/d, 0.0, 1.0)*max_radius;
+}
+
+void main() {
+    vec2 px = direction/vec2(textureSize(source, 0));
+    float focus = autofocus > 0.5 ? linear_depth(vec2(0.5)) : focus_distance;
+
+    float radius = coc(linear_depth(uv), focus);
+    vec4 center = texture(source, uv);
+    vec3 sum = center.rgb;
+    float weight = 1.0;
+    for (int i = -TAPS; i <= TAPS; i++) {
+        if (i == 0) {
+            continue;
+        }
+        float offset = float(i)/float(TAPS)*max_radius;
+        vec2 at = uv + px*offset;
+        // A sample contributes if its own blur reaches this pixel, so sharp
+        // foreground objects don't smear over the background they cover.
+        float reach = min(radius, coc(linear_depth(at), focus));
+        float w = step(abs(offset), reach);
+        sum += texture(source, at).rgb*w;
+        weight += w;
+    }
+    frag_color = vec4(sum/weight, center.a);
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+#version 140
+in vec2 in_pos;
+
+out vec2 uv;
+
+void main() {
+    gl_Position = vec4(in_pos, 0.0, 1.0);
+    uv = in_pos*0.5 + 0.5;
+}
\ No newline at end of file
@@ -0,0 +1,44 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+
+const float REDUCE_MIN = 1.0/128.0;
+const float REDUCE_MUL = 1.0/8.0;
+const float SPAN_MAX = 8.0;
+
+// Edges are judged perceptually, so luma is taken from roughly
+// gamma-encoded values rather than the linear ones stored in the chain.
+float luma(vec3 c) {
+    return sqrt(dot(c, vec3(0.299, 0.587, 0.114)));
+}
+
+void main() {
+    vec2 px = 1.0/vec2(textureSize(source, 0));
+
+    vec4 center = texture(source, uv);
+    float nw = luma(texture(source, uv + vec2(-1.0, -1.0)*px).rgb);
+    float ne = luma(texture(source, uv + vec2( 1.0, -1.0)*px).rgb);
+    float sw = luma(texture(source, uv + vec2(-1.0,  1.0)*px).rgb);
+    float se = luma(texture(source, uv + vec2( 1.0,  1.0)*px).rgb);
+    float m = luma(center.rgb);
+
+    float luma_min = min(m, min(min(nw, ne), min(sw, se)));
+    float luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+    vec2 dir = vec2(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+    float dir_reduce = max((nw + ne + sw + se)*0.25*REDUCE_MUL, REDUCE_MIN);
+    float rcp_dir_min = 1.0/(min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir*rcp_dir_min, vec2(-SPAN_MAX), vec2(SPAN_MAX))*px;
+
+    vec3 a = 0.5*(texture(source, uv + dir*(1.0/3.0 - 0.5)).rgb
+                + texture(source, uv + dir*(2.0/3.0 - 0.5)).rgb);
+    vec3 b = a*0.5 + 0.25*(texture(source, uv - dir*0.5).rgb
+                         + texture(source, uv + dir*0.5).rgb);
+
+    float luma_b = luma(b);
+    vec3 color = (luma_b < luma_min || luma_b > luma_max) ? a : b;
+    frag_color = vec4(color, center.a);
+}
\ No newline at end of file
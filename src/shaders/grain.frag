@@ -0,0 +1,18 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+uniform float intensity;
+uniform float time;
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(12.9898, 78.233)))*43758.5453);
+}
+
+void main() {
+    vec4 color = texture(source, uv);
+    float noise = hash(gl_FragCoord.xy + fract(time)*vec2(113.0, 271.0)) - 0.5;
+    frag_color = vec4(max(color.rgb + noise*intensity, 0.0), color.a);
+}
\ No newline at end of file
@@ -1,14 +1,37 @@
 #version 140
 in vec3 in_pos;
+in vec3 in_normal;
 in vec4 in_color;
+in vec2 in_uv2;
 
 out lowp vec4 color;
+out vec3 world_pos;
+out vec3 normal;
+out float view_distance;
+out vec2 lightmap_uv;
+out vec4 params;
 
 uniform mat4 perspective;
 uniform mat4 view;
-uniform mat4 world[2];
+uniform mat4 world[16];
+uniform vec4 lightmap_scale_offset[16];
+uniform vec4 instance_color[16];
+// Emissive factor in x, custom values in yzw.
+uniform vec4 instance_params[16];
+uniform float log_depth_coef;
 
 void main() {
-    gl_Position = perspective*view*world[gl_InstanceID]*vec4(in_pos, 1.0);
-    color = in_color;
+    vec4 pos = world[gl_InstanceID]*vec4(in_pos, 1.0);
+    vec4 view_pos = view*pos;
+    gl_Position = perspective*view_pos;
+    if (log_depth_coef > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w))*log_depth_coef - 1.0)*gl_Position.w;
+    }
+    color = in_color*instance_color[gl_InstanceID];
+    params = instance_params[gl_InstanceID];
+    world_pos = pos.xyz;
+    normal = mat3(world[gl_InstanceID])*in_normal;
+    view_distance = length(view_pos.xyz);
+    vec4 so = lightmap_scale_offset[gl_InstanceID];
+    lightmap_uv = in_uv2*so.xy + so.zw;
 }
\ No newline at end of file
@@ -0,0 +1,37 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+uniform sampler2D lut;
+
+vec3 linear_to_srgb(vec3 c) {
+    vec3 lo = c*12.92;
+    vec3 hi = 1.055*pow(c, vec3(1.0/2.4)) - 0.055;
+    return mix(lo, hi, step(vec3(0.0031308), c));
+}
+
+vec3 srgb_to_linear(vec3 c) {
+    vec3 lo = c/12.92;
+    vec3 hi = pow((c + 0.055)/1.055, vec3(2.4));
+    return mix(lo, hi, step(vec3(0.04045), c));
+}
+
+// 16 slices of 16x16 laid out left to right, blue selects the slice.
+vec3 lookup(vec3 c) {
+    float slice = c.b*15.0;
+    float s0 = floor(slice);
+    float s1 = min(s0 + 1.0, 15.0);
+    vec2 rg = (c.rg*15.0 + 0.5)/vec2(256.0, 16.0);
+    vec3 a = texture(lut, rg + vec2(s0/16.0, 0.0)).rgb;
+    vec3 b = texture(lut, rg + vec2(s1/16.0, 0.0)).rgb;
+    return mix(a, b, slice - s0);
+}
+
+void main() {
+    vec4 color = texture(source, uv);
+    // LUTs are authored against display-encoded values.
+    vec3 graded = lookup(linear_to_srgb(clamp(color.rgb, 0.0, 1.0)));
+    frag_color = vec4(srgb_to_linear(graded), color.a);
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+uniform sampler2D velocity;
+uniform float shutter;
+
+const int SAMPLES = 12;
+
+float unpack(vec2 e) {
+    return (e.x*255.0*256.0 + e.y*255.0)/65535.0*2.0 - 1.0;
+}
+
+void main() {
+    vec4 packed = texture(velocity, uv);
+    vec2 motion = vec2(unpack(packed.rg), unpack(packed.ba))*shutter;
+
+    vec4 center = texture(source, uv);
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < SAMPLES; i++) {
+        float t = float(i)/float(SAMPLES - 1) - 0.5;
+        sum += texture(source, clamp(uv - motion*t, 0.0, 1.0)).rgb;
+    }
+    frag_color = vec4(sum/float(SAMPLES), center.a);
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D accumulation;
+uniform sampler2D revealage;
+
+void main() {
+    // Fraction of the opaque scene still showing through every layer.
+    float revealed = exp(-texture(revealage, uv).r);
+    if (revealed > 0.999) {
+        discard;
+    }
+    vec4 sum = texture(accumulation, uv);
+    frag_color = vec4(sum.rgb/max(sum.a, 1e-5), 1.0 - revealed);
+}
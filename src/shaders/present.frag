@@ -0,0 +1,17 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+
+vec3 linear_to_srgb(vec3 c) {
+    vec3 lo = c*12.92;
+    vec3 hi = 1.055*pow(c, vec3(1.0/2.4)) - 0.055;
+    return mix(lo, hi, step(vec3(0.0031308), c));
+}
+
+void main() {
+    vec4 color = texture(source, uv);
+    frag_color = vec4(linear_to_srgb(clamp(color.rgb, 0.0, 1.0)), color.a);
+}
\ No newline at end of file
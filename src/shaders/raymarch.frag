@@ -0,0 +1,138 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform mat4 inv_view_proj;
+uniform mat4 view_proj;
+uniform vec3 camera_pos;
+uniform float log_depth_coef;
+uniform vec4 clip_plane;
+uniform vec3 sun_direction;
+uniform vec3 sun_radiance;
+uniform vec3 ambient;
+// Environment map irradiance as spherical harmonics, zero without one.
+uniform vec3 irradiance[9];
+uniform vec3 fog_color;
+uniform float fog_density;
+uniform float time;
+
+const int MAX_STEPS = 128;
+const float MAX_DISTANCE = 200.0;
+const float EPSILON = 0.001;
+// Where the shapes sit in the world.
+const vec3 CENTER = vec3(0.0, 1.0, -8.0);
+
+vec3 environment_light(vec3 n) {
+    return irradiance[0]
+        + irradiance[1]*n.y + irradiance[2]*n.z + irradiance[3]*n.x
+        + irradiance[4]*n.x*n.y + irradiance[5]*n.y*n.z
+        + irradiance[6]*(3.0*n.z*n.z - 1.0) + irradiance[7]*n.x*n.z
+        + irradiance[8]*(n.x*n.x - n.y*n.y);
+}
+
+float sd_sphere(vec3 p, float r) {
+    return length(p) - r;
+}
+
+float sd_round_box(vec3 p, vec3 b, float r) {
+    vec3 q = abs(p) - b;
+    return length(max(q, 0.0)) + min(max(q.x, max(q.y, q.z)), 0.0) - r;
+}
+
+float sd_torus(vec3 p, vec2 t) {
+    vec2 q = vec2(length(p.xz) - t.x, p.y);
+    return length(q) - t.y;
+}
+
+float smooth_min(float a, float b, float k) {
+    float h = clamp(0.5 + 0.5*(b - a)/k, 0.0, 1.0);
+    return mix(b, a, h) - k*h*(1.0 - h);
+}
+
+mat2 rotate(float a) {
+    float c = cos(a);
+    float s = sin(a);
+    return mat2(c, s, -s, c);
+}
+
+// Distance to the scene in x, material in y.
+vec2 map(vec3 p) {
+    p -= CENTER;
+    vec3 b = p;
+    b.xz *= rotate(time*0.5);
+    float blob = smooth_min(
+        sd_round_box(b, vec3(0.6), 0.15),
+        sd_sphere(p - vec3(0.0, 0.9 + 0.3*sin(time*1.3), 0.0), 0.55),
+        0.3
+    );
+    vec3 t = p - vec3(2.5, 0.0, 0.0);
+    t.xy *= rotate(time*0.7);
+    float torus = sd_torus(t, vec2(0.7, 0.2));
+    return blob < torus ? vec2(blob, 0.0) : vec2(torus, 1.0);
+}
+
+vec3 estimate_normal(vec3 p) {
+    vec2 e = vec2(EPSILON, 0.0);
+    return normalize(vec3(
+        map(p + e.xyy).x - map(p - e.xyy).x,
+        map(p + e.yxy).x - map(p - e.yxy).x,
+        map(p + e.yyx).x - map(p - e.yyx).x
+    ));
+}
+
+float soft_shadow(vec3 p, vec3 dir) {
+    float light = 1.0;
+    float t = 0.02;
+    for (int i = 0; i < 48 && t < 20.0; i++) {
+        float d = map(p + dir*t).x;
+        if (d < EPSILON) {
+            return 0.0;
+        }
+        light = min(light, 8.0*d/t);
+        t += d;
+    }
+    return light;
+}
+
+void main() {
+    vec4 near = inv_view_proj*vec4(uv*2.0 - 1.0, -1.0, 1.0);
+    vec4 far = inv_view_proj*vec4(uv*2.0 - 1.0, 1.0, 1.0);
+    // Starting on the near plane rather than at the camera also works
+    // for orthographic projections.
+    vec3 origin = near.xyz/near.w;
+    // Not divided by w, which is 0 with an infinite far plane.
+    vec3 dir = normalize(far.xyz - origin*far.w);
+
+    float t = 0.0;
+    vec2 hit = vec2(MAX_DISTANCE, -1.0);
+    for (int i = 0; i < MAX_STEPS && t < MAX_DISTANCE; i++) {
+        vec2 d = map(origin + dir*t);
+        if (d.x < EPSILON*t) {
+            hit = vec2(t, d.y);
+            break;
+        }
+        t += d.x;
+    }
+    vec3 p = origin + dir*hit.x;
+    if (hit.y < 0.0 || dot(vec4(p, 1.0), clip_plane) < 0.0) {
+        discard;
+    }
+
+    // Same depth the rasterizer would write for this point.
+    vec4 clip = view_proj*vec4(p, 1.0);
+    float ndc_depth = clip.z/clip.w;
+    if (log_depth_coef > 0.0) {
+        ndc_depth = log2(max(1e-6, 1.0 + clip.w))*log_depth_coef - 1.0;
+    }
+    gl_FragDepth = ndc_depth*0.5 + 0.5;
+
+    vec3 n = estimate_normal(p);
+    vec3 albedo = hit.y < 0.5 ? vec3(0.7, 0.3, 0.2) : vec3(0.2, 0.45, 0.7);
+    float shadow = soft_shadow(p + n*0.01, sun_direction);
+    vec3 direct = sun_radiance*max(dot(n, sun_direction), 0.0)*shadow;
+    vec3 lit = albedo*(ambient + max(environment_light(n), 0.0) + direct);
+    float d = fog_density*distance(p, camera_pos);
+    float fog = 1.0 - exp(-d*d);
+    frag_color = vec4(mix(lit, fog_color, fog), 1.0);
+}
\ No newline at end of file
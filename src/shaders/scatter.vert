@@ -0,0 +1,45 @@
+#version 140
+in vec3 in_pos;
+in vec3 in_normal;
+in vec4 in_color;
+in vec2 in_uv2;
+// Per instance: world position and scale...
+in vec4 in_offset;
+// ...and cosine and sine of the rotation about Y, then brightness.
+in vec4 in_orient;
+
+out lowp vec4 color;
+out vec3 world_pos;
+out vec3 normal;
+out float view_distance;
+out vec2 lightmap_uv;
+out vec4 params;
+
+uniform mat4 perspective;
+uniform mat4 view;
+uniform float log_depth_coef;
+uniform vec3 camera_pos;
+uniform float fade_start;
+uniform float fade_end;
+
+vec3 rotate_y(vec3 v) {
+    return vec3(in_orient.x*v.x + in_orient.y*v.z, v.y, -in_orient.y*v.x + in_orient.x*v.z);
+}
+
+void main() {
+    // Props shrink into the ground with distance, down to nothing past
+    // fade_end, where their triangles collapse and aren't rasterized.
+    float fade = 1.0 - smoothstep(fade_start, fade_end, distance(in_offset.xyz, camera_pos));
+    vec4 pos = vec4(rotate_y(in_pos*in_offset.w*fade) + in_offset.xyz, 1.0);
+    vec4 view_pos = view*pos;
+    gl_Position = perspective*view_pos;
+    if (log_depth_coef > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w))*log_depth_coef - 1.0)*gl_Position.w;
+    }
+    color = in_color*vec4(vec3(in_orient.z), 1.0);
+    world_pos = pos.xyz;
+    normal = rotate_y(in_normal);
+    view_distance = length(view_pos.xyz);
+    lightmap_uv = vec2(0.0);
+    params = vec4(0.0);
+}
\ No newline at end of file
@@ -0,0 +1,57 @@
+#version 140
+in vec3 in_pos;
+in vec3 in_normal;
+in vec4 in_color;
+in vec2 in_uv2;
+in vec4 in_joints;
+in vec4 in_weights;
+
+out lowp vec4 color;
+out vec3 world_pos;
+out vec3 normal;
+out float view_distance;
+out vec2 lightmap_uv;
+out vec4 params;
+
+uniform mat4 perspective;
+uniform mat4 view;
+// Skinned meshes are drawn one at a time, so only the first entry of the
+// per-instance arrays is declared; GL ignores the rest when uploading.
+uniform mat4 world[1];
+uniform vec4 lightmap_scale_offset[1];
+uniform vec4 instance_color[1];
+// Emissive factor in x, custom values in yzw.
+uniform vec4 instance_params[1];
+uniform float log_depth_coef;
+// Top three rows of each joint matrix, see `skin::JointRows`.
+uniform vec4 joints[192];
+
+mat4 joint(float index) {
+    int row = int(index)*3;
+    return transpose(mat4(joints[row], joints[row + 1], joints[row + 2], vec4(0.0, 0.0, 0.0, 1.0)));
+}
+
+void main() {
+    mat4 skin = mat4(1.0);
+    // Parts of the model that aren't bound to the skeleton have no weights.
+    if (dot(in_weights, vec4(1.0)) > 0.0) {
+        skin = joint(in_joints.x)*in_weights.x
+            + joint(in_joints.y)*in_weights.y
+            + joint(in_joints.z)*in_weights.z
+            + joint(in_joints.w)*in_weights.w;
+    }
+    mat4 model = world[0]*skin;
+    vec4 pos = model*vec4(in_pos, 1.0);
+    vec4 view_pos = view*pos;
+    gl_Position = perspective*view_pos;
+    if (log_depth_coef > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w))*log_depth_coef - 1.0)*gl_Position.w;
+    }
+    color = in_color*instance_color[0];
+    params = instance_params[0];
+    world_pos = pos.xyz;
+    normal = mat3(model)*in_normal;
+    view_distance = length(view_pos.xyz);
+    vec4 so = lightmap_scale_offset[0];
+    lightmap_uv = in_uv2*so.xy + so.zw;
+}
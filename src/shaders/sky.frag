@@ -0,0 +1,42 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform mat4 inv_view_proj;
+uniform vec3 camera_pos;
+uniform vec3 sun_direction;
+uniform vec3 sun_radiance;
+
+const vec3 DAY_ZENITH = vec3(0.08, 0.2, 0.6);
+const vec3 DAY_HORIZON = vec3(0.5, 0.65, 0.85);
+const vec3 SUNSET_HORIZON = vec3(0.9, 0.4, 0.15);
+const vec3 NIGHT = vec3(0.002, 0.004, 0.01);
+const vec3 GROUND = vec3(0.05, 0.045, 0.04);
+// Angular radius of the sun, in radians.
+const float SUN_RADIUS = 0.0093;
+
+void main() {
+    vec4 far = inv_view_proj*vec4(uv*2.0 - 1.0, 1.0, 1.0);
+    // Not divided by w, which is 0 with an infinite far plane.
+    vec3 dir = normalize(far.xyz - camera_pos*far.w);
+
+    float elevation = sun_direction.y;
+    // 0 with the sun high up, 1 around sunrise/sunset.
+    float sunset = 1.0 - smoothstep(0.0, 0.35, abs(elevation));
+    float daylight = smoothstep(-0.15, 0.1, elevation);
+
+    vec3 horizon = mix(DAY_HORIZON, SUNSET_HORIZON, sunset);
+    float t = pow(1.0 - max(dir.y, 0.0), 4.0);
+    vec3 sky = mix(DAY_ZENITH, horizon, t);
+    sky = mix(NIGHT, sky, daylight);
+
+    float cos_sun = max(dot(dir, sun_direction), 0.0);
+    vec3 glow = sun_radiance*(0.25*pow(cos_sun, 8.0) + 0.5*pow(cos_sun, 256.0));
+    float disk = smoothstep(cos(SUN_RADIUS*1.2), cos(SUN_RADIUS), cos_sun);
+
+    vec3 color = sky + glow*daylight + sun_radiance*disk*20.0;
+    // Below the horizon, fade into a dark ground color.
+    color = mix(color, GROUND*max(daylight, 0.05), smoothstep(0.0, -0.05, dir.y));
+    frag_color = vec4(color, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform samplerCube environment;
+uniform mat4 inv_view_proj;
+uniform vec3 camera_pos;
+
+void main() {
+    vec4 far = inv_view_proj*vec4(uv*2.0 - 1.0, 1.0, 1.0);
+    // Not divided by w, which is 0 with an infinite far plane.
+    vec3 dir = normalize(far.xyz - camera_pos*far.w);
+    frag_color = vec4(texture(environment, dir).rgb, 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,99 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+uniform sampler2D depth;
+uniform samplerCube environment;
+uniform mat4 projection;
+uniform mat4 inv_projection;
+uniform mat4 inv_view;
+uniform float log_depth_coef;
+uniform float strength;
+uniform float thickness;
+uniform float use_environment;
+
+const int STEPS = 48;
+const int REFINE_STEPS = 6;
+
+// The NDC depth `projection` gives the point in the depth buffer at
+// `d`, which with logarithmic depth holds log2 of view depth instead.
+float ndc_depth(float d) {
+    float z = d*2.0 - 1.0;
+    if (log_depth_coef > 0.0) {
+        float w = exp2((z + 1.0)/log_depth_coef) - 1.0;
+        return (projection[3][2] - projection[2][2]*w)/w;
+    }
+    return z;
+}
+
+vec3 view_pos(vec2 at) {
+    float z = ndc_depth(texture(depth, at).r);
+    vec4 p = inv_projection*vec4(at*2.0 - 1.0, z, 1.0);
+    return p.xyz/p.w;
+}
+
+vec2 project(vec3 p) {
+    vec4 clip = projection*vec4(p, 1.0);
+    return clip.xy/clip.w*0.5 + 0.5;
+}
+
+bool on_screen(vec2 at) {
+    return all(greaterThanEqual(at, vec2(0.0))) && all(lessThanEqual(at, vec2(1.0)));
+}
+
+void main() {
+    vec4 color = texture(source, uv);
+    if (texture(depth, uv).r >= 1.0) {
+        frag_color = color;
+        return;
+    }
+
+    vec3 p = view_pos(uv);
+    vec3 n = normalize(cross(dFdx(p), dFdy(p)));
+    vec3 v = normalize(p);
+    vec3 r = reflect(v, n);
+
+    float step_len = 0.02*length(p);
+    float t = step_len;
+    vec2 hit_uv = vec2(-1.0);
+    for (int i = 0; i < STEPS; i++) {
+        vec3 q = p + r*t;
+        vec2 at = project(q);
+        if (!on_screen(at) || q.z > 0.0) {
+            break;
+        }
+        float behind = view_pos(at).z - q.z;
+        if (behind > 0.0 && behind < thickness*t) {
+            // Binary search between the last step and this one.
+            float lo = t - step_len;
+            float hi = t;
+            for (int j = 0; j < REFINE_STEPS; j++) {
+                float mid = 0.5*(lo + hi);
+                vec3 m = p + r*mid;
+                if (view_pos(project(m)).z - m.z > 0.0) {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            hit_uv = project(p + r*hi);
+            break;
+        }
+        t += step_len;
+        step_len *= 1.05;
+    }
+
+    // Missed rays see the sky, if there is a map of it.
+    vec3 missed = use_environment > 0.0 ? texture(environment, mat3(inv_view)*r).rgb : color.rgb;
+    vec3 reflected = missed;
+    if (on_screen(hit_uv)) {
+        vec2 edge = smoothstep(vec2(0.0), vec2(0.1), hit_uv)*smoothstep(vec2(1.0), vec2(0.9), hit_uv);
+        reflected = mix(missed, texture(source, hit_uv).rgb, edge.x*edge.y);
+    }
+
+    float fresnel = pow(1.0 - max(dot(-v, n), 0.0), 5.0);
+    float amount = strength*mix(0.04, 1.0, fresnel);
+    frag_color = vec4(mix(color.rgb, reflected, amount), color.a);
+}
\ No newline at end of file
@@ -0,0 +1,39 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D current;
+uniform sampler2D history;
+uniform sampler2D velocity;
+uniform float history_weight;
+
+float unpack(vec2 e) {
+    return (e.x*255.0*256.0 + e.y*255.0)/65535.0*2.0 - 1.0;
+}
+
+void main() {
+    vec2 px = 1.0/vec2(textureSize(current, 0));
+
+    vec3 color = texture(current, uv).rgb;
+    vec3 lo = color;
+    vec3 hi = color;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec3 s = texture(current, uv + vec2(x, y)*px).rgb;
+            lo = min(lo, s);
+            hi = max(hi, s);
+        }
+    }
+
+    vec4 packed = texture(velocity, uv);
+    vec2 motion = vec2(unpack(packed.rg), unpack(packed.ba));
+    vec2 prev_uv = uv - motion;
+
+    vec3 past = clamp(texture(history, prev_uv).rgb, lo, hi);
+    float weight = history_weight;
+    if (any(lessThan(prev_uv, vec2(0.0))) || any(greaterThan(prev_uv, vec2(1.0)))) {
+        weight = 0.0;
+    }
+    frag_color = vec4(mix(color, past, weight), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,11 @@
+#version 140
+in vec2 uv;
+in vec4 color;
+
+out vec4 frag_color;
+
+uniform sampler2D atlas;
+
+void main() {
+    frag_color = color*texture(atlas, uv);
+}
\ No newline at end of file
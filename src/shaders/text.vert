@@ -0,0 +1,16 @@
+#version 140
+in vec2 in_pos;
+in vec2 in_uv;
+in vec4 in_color;
+
+out vec2 uv;
+out vec4 color;
+
+uniform vec2 screen_size;
+
+void main() {
+    vec2 ndc = in_pos/screen_size*2.0 - 1.0;
+    gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+    uv = in_uv;
+    color = in_color;
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+#version 140
+in vec4 curr_clip;
+in vec4 prev_clip;
+
+out vec4 frag_color;
+
+// 16 bits per component spread over two 8 bit channels, covering [-1, 1].
+vec2 pack(float v) {
+    float x = floor(clamp(v*0.5 + 0.5, 0.0, 1.0)*65535.0);
+    return vec2(floor(x/256.0), mod(x, 256.0))/255.0;
+}
+
+void main() {
+    vec2 curr = curr_clip.xy/curr_clip.w*0.5;
+    vec2 prev = prev_clip.xy/prev_clip.w*0.5;
+    vec2 velocity = curr - prev;
+    frag_color = vec4(pack(velocity.x), pack(velocity.y));
+}
\ No newline at end of file
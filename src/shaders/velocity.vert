@@ -0,0 +1,22 @@
+#version 140
+in vec3 in_pos;
+
+out vec4 curr_clip;
+out vec4 prev_clip;
+
+uniform mat4 projection_view;
+uniform mat4 view_proj;
+uniform mat4 prev_view_proj;
+uniform mat4 world[16];
+uniform mat4 prev_world[16];
+uniform float log_depth_coef;
+
+void main() {
+    vec4 pos = vec4(in_pos, 1.0);
+    gl_Position = projection_view*world[gl_InstanceID]*pos;
+    if (log_depth_coef > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w))*log_depth_coef - 1.0)*gl_Position.w;
+    }
+    curr_clip = view_proj*world[gl_InstanceID]*pos;
+    prev_clip = prev_view_proj*prev_world[gl_InstanceID]*pos;
+}
\ No newline at end of file
@@ -0,0 +1,15 @@
+#version 140
+in vec2 uv;
+
+out vec4 frag_color;
+
+uniform sampler2D source;
+uniform float intensity;
+uniform float time;
+
+void main() {
+    vec4 color = texture(source, uv);
+    vec2 d = uv - 0.5;
+    float falloff = smoothstep(0.8, 0.2, length(d)*1.4);
+    frag_color = vec4(color.rgb*mix(1.0, falloff, intensity), color.a);
+}
\ No newline at end of file
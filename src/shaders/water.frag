@@ -0,0 +1,48 @@
+#version 140
+in vec3 world_pos;
+in vec4 clip_pos;
+
+out vec4 frag_color;
+
+uniform sampler2D reflection;
+uniform sampler2D refraction;
+uniform vec3 camera_pos;
+uniform float time;
+
+const vec3 DEEP_COLOR = vec3(0.01, 0.05, 0.06);
+const float DISTORTION = 0.02;
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(127.1, 311.7)))*43758.5453);
+}
+
+float noise(vec2 p) {
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f*f*(3.0 - 2.0*f);
+    return mix(mix(hash(i), hash(i + vec2(1.0, 0.0)), u.x),
+               mix(hash(i + vec2(0.0, 1.0)), hash(i + vec2(1.0, 1.0)), u.x), u.y);
+}
+
+// Two layers of noise scrolling in different directions.
+float waves(vec2 p) {
+    return noise(p*4.0 + vec2(time*0.3, time*0.2))
+         + 0.5*noise(p*9.0 - vec2(time*0.4, -time*0.1));
+}
+
+void main() {
+    vec2 p = world_pos.xz;
+    float e = 0.01;
+    float dx = waves(p + vec2(e, 0.0)) - waves(p - vec2(e, 0.0));
+    float dz = waves(p + vec2(0.0, e)) - waves(p - vec2(0.0, e));
+    vec3 normal = normalize(vec3(-dx, 8.0*e, -dz));
+
+    vec2 uv = clip_pos.xy/clip_pos.w*0.5 + 0.5;
+    vec2 offset = normal.xz*DISTORTION;
+    vec3 reflected = texture(reflection, clamp(uv + offset, 0.001, 0.999)).rgb;
+    vec3 refracted = mix(texture(refraction, clamp(uv - offset, 0.001, 0.999)).rgb, DEEP_COLOR, 0.3);
+
+    vec3 to_eye = normalize(camera_pos - world_pos);
+    float fresnel = 0.02 + 0.98*pow(1.0 - max(dot(to_eye, normal), 0.0), 5.0);
+    frag_color = vec4(mix(refracted, reflected, fresnel), 1.0);
+}
\ No newline at end of file
@@ -0,0 +1,18 @@
+#version 140
+in vec2 in_pos;
+
+out vec3 world_pos;
+out vec4 clip_pos;
+
+uniform mat4 view_proj;
+uniform float height;
+uniform float log_depth_coef;
+
+void main() {
+    world_pos = vec3(in_pos.x, height, in_pos.y);
+    clip_pos = view_proj*vec4(world_pos, 1.0);
+    gl_Position = clip_pos;
+    if (log_depth_coef > 0.0) {
+        gl_Position.z = (log2(max(1e-6, 1.0 + gl_Position.w))*log_depth_coef - 1.0)*gl_Position.w;
+    }
+}
\ No newline at end of file
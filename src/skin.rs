@@ -0,0 +1,95 @@
+use cgmath::{Matrix, Matrix4, SquareMatrix};
+use miniquad::*;
+
+use crate::animation::Clip;
+use crate::scene::Transform;
+
+/// Most joints a skin can have. Joints go to the shader as the top three
+/// rows of their matrices, and 64 of them still fit the GL 3 minimum
+/// vertex uniform budget next to the per-instance arrays.
+pub const MAX_JOINTS: usize = 64;
+
+/// The rows the shader gets for every joint: `JointRows[3 * j..3 * j + 3]`
+/// is the top of joint `j`'s matrix, the bottom row being `0 0 0 1`.
+pub type JointRows = [[f32; 4]; 3 * MAX_JOINTS];
+
+/// Which joints move a vertex, and by how much. Kept out of `Vertex`, in
+/// a second vertex buffer, so meshes without a skin don't carry it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkinVertex {
+    /// Indices into `Skeleton::joints`, as floats for GL 3 attributes.
+    pub joints: [f32; 4],
+    /// Sum to 1, or are all 0 for vertices that don't follow the skeleton.
+    pub weights: [f32; 4],
+}
+
+/// Layout of `SkinVertex`, bound as the second vertex buffer next to
+/// `mesh::vertex_attributes`.
+pub fn skin_attributes() -> [VertexAttribute; 2] {
+    [
+        VertexAttribute::with_buffer("in_joints", VertexFormat::Float4, 1),
+        VertexAttribute::with_buffer("in_weights", VertexFormat::Float4, 1),
+    ]
+}
+
+#[derive(Clone, Debug)]
+pub struct Joint {
+    pub parent: Option<usize>,
+    /// Fixed transform from the parent joint, or the model origin, to the
+    /// node the joint hangs from. Identity unless the file has nodes that
+    /// aren't joints between them.
+    pub offset: Matrix4<f32>,
+    /// Local transform when no clip moves the joint.
+    pub rest: Transform,
+    /// Takes the mesh into the joint's space as it was bound.
+    pub inverse_bind: Matrix4<f32>,
+}
+
+/// A joint hierarchy that deforms a mesh, posed on the GPU. Animation
+/// clips move it through `Clip::joints`, indexed like `joints`.
+#[derive(Clone, Debug)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    /// Joint indices with every parent before its children.
+    pub order: Vec<usize>,
+}
+
+impl Skeleton {
+    /// The joint matrices at `time` into `clip`, as the skinning shader
+    /// takes them. Joints the clip has no tracks for keep their rest pose.
+    pub fn pose(&self, clip: Option<&Clip>, time: f32) -> JointRows {
+        let mut global = vec![Matrix4::identity(); self.joints.len()];
+        for &i in &self.order {
+            let joint = &self.joints[i];
+            let mut local = joint.rest;
+            if let Some(track) = clip.and_then(|clip| clip.joints.get(i)) {
+                track.apply(time, &mut local);
+            }
+            let parent = joint.parent.map_or(Matrix4::identity(), |p| global[p]);
+            global[i] = parent * joint.offset * local.matrix();
+        }
+
+        let mut rows = [[0.0; 4]; 3 * MAX_JOINTS];
+        for (i, joint) in self.joints.iter().enumerate() {
+            // Rows of the matrix are columns of its transpose.
+            let matrix = (global[i] * joint.inverse_bind).transpose();
+            rows[3 * i] = matrix.x.into();
+            rows[3 * i + 1] = matrix.y.into();
+            rows[3 * i + 2] = matrix.z.into();
+        }
+        rows
+    }
+
+    /// Every joint at rest, for a skinned mesh without an animation.
+    pub fn rest_pose(&self) -> JointRows {
+        self.pose(None, 0.0)
+    }
+}
+
+/// What a mesh needs to be skinned: the per-vertex joints and weights,
+/// parallel to `Mesh::vertices`, and the skeleton they refer to.
+pub struct Skin {
+    pub vertices: Vec<SkinVertex>,
+    pub skeleton: Skeleton,
+}
@@ -0,0 +1,122 @@
+use cgmath::{Matrix4, Point3, SquareMatrix, Vector3};
+use miniquad::*;
+
+use crate::environment::Environment;
+use crate::light::DirectionalLight;
+use crate::post::Quad;
+
+/// Analytic sky: a zenith-to-horizon gradient tinted by the sun elevation,
+/// a forward-scattering glow and the sun disk itself. It is drawn first in
+/// a pass, without touching depth, so geometry covers it. A loaded
+/// environment map is drawn instead, if there is one.
+pub struct Sky {
+    pipeline: Pipeline,
+    skybox: Pipeline,
+    environment: Option<Environment>,
+}
+
+impl Sky {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Sky {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+        let skybox = quad.pipeline(ctx, shader::SKYBOX, shader::skybox_meta());
+        Sky {
+            pipeline,
+            skybox,
+            environment: None,
+        }
+    }
+
+    /// Replaces the environment map, or goes back to the analytic sky.
+    pub fn set_environment(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        environment: Option<Environment>,
+    ) {
+        if let Some(old) = std::mem::replace(&mut self.environment, environment) {
+            old.delete(ctx);
+        }
+    }
+
+    /// The environment map's cubemap, for effects that reflect the sky.
+    pub fn environment(&self) -> Option<TextureId> {
+        self.environment.as_ref().map(|e| e.cubemap)
+    }
+
+    /// The environment map's diffuse light, which stands in for the flat
+    /// ambient term while one is loaded.
+    pub fn irradiance(&self) -> Option<[Vector3<f32>; 9]> {
+        self.environment.as_ref().map(|e| e.irradiance)
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        view_proj: Matrix4<f32>,
+        camera_pos: Point3<f32>,
+        sun: &DirectionalLight,
+    ) {
+        if let Some(environment) = &self.environment {
+            ctx.apply_pipeline(&self.skybox);
+            ctx.apply_bindings(&quad.bindings(vec![environment.cubemap]));
+            ctx.apply_uniforms(UniformsSource::table(&shader::SkyboxUniforms {
+                inv_view_proj: view_proj.invert().unwrap(),
+                camera_pos: camera_pos.into(),
+            }));
+            quad.draw(ctx);
+            return;
+        }
+        let sun_radiance = sun.radiance();
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            inv_view_proj: view_proj.invert().unwrap(),
+            camera_pos: camera_pos.into(),
+            sun_direction: sun.direction.into(),
+            sun_radiance: sun_radiance.into(),
+        }));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use cgmath::Matrix4;
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/sky.frag");
+    pub const SKYBOX: &str = include_str!("shaders/skybox.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("inv_view_proj", UniformType::Mat4),
+                UniformDesc::new("camera_pos", UniformType::Float3),
+                UniformDesc::new("sun_direction", UniformType::Float3),
+                UniformDesc::new("sun_radiance", UniformType::Float3),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub inv_view_proj: Matrix4<f32>,
+        pub camera_pos: [f32; 3],
+        pub sun_direction: [f32; 3],
+        pub sun_radiance: [f32; 3],
+    }
+
+    pub fn skybox_meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["environment".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("inv_view_proj", UniformType::Mat4),
+                UniformDesc::new("camera_pos", UniformType::Float3),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct SkyboxUniforms {
+        pub inv_view_proj: Matrix4<f32>,
+        pub camera_pos: [f32; 3],
+    }
+}
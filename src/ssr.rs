@@ -0,0 +1,114 @@
+use cgmath::SquareMatrix;
+use miniquad::*;
+
+use crate::post::{Effect, Frame, Quad};
+
+/// Screen-space reflections, ray-marched against the scene depth buffer.
+///
+/// The renderer is forward-only, so there is no G-buffer to read normals or
+/// glossiness from: normals are reconstructed from depth derivatives and
+/// `strength` stands in for a per-material reflectivity. Rays that leave
+/// the screen reflect the sky's environment map, or without one keep the
+/// color already there.
+pub struct Reflections {
+    pipeline: Pipeline,
+    /// Bound in place of an environment map while none is loaded.
+    no_environment: TextureId,
+    pub enabled: bool,
+    pub strength: f32,
+    /// How far behind the depth buffer a ray may pass and still count as a
+    /// hit, in view-space units.
+    pub thickness: f32,
+}
+
+impl Reflections {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad) -> Reflections {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+        let black = [0u8; 4];
+        let faces = [[&black[..]]; 6];
+        let faces: Vec<&[&[u8]]> = faces.iter().map(|face| &face[..]).collect();
+        let no_environment = ctx.new_texture(
+            TextureAccess::Static,
+            TextureSource::Array(&faces),
+            TextureParams {
+                kind: TextureKind::CubeMap,
+                width: 1,
+                height: 1,
+                format: TextureFormat::RGBA8,
+                ..Default::default()
+            },
+        );
+        Reflections {
+            pipeline,
+            no_environment,
+            enabled: false,
+            strength: 0.5,
+            thickness: 0.05,
+        }
+    }
+}
+
+impl Effect for Reflections {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        source: TextureId,
+        frame: &Frame,
+        _pass: usize,
+    ) {
+        ctx.apply_pipeline(&self.pipeline);
+        let environment = frame.environment.unwrap_or(self.no_environment);
+        ctx.apply_bindings(&quad.bindings(vec![source, frame.depth, environment]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            projection: frame.projection,
+            inv_projection: frame.projection.invert().unwrap(),
+            inv_view: frame.view.invert().unwrap(),
+            log_depth_coef: frame.log_depth_coef,
+            strength: self.strength,
+            thickness: self.thickness,
+            use_environment: if frame.environment.is_some() {
+                1.0
+            } else {
+                0.0
+            },
+        }));
+        quad.draw(ctx);
+    }
+}
+
+mod shader {
+    use cgmath::Matrix4;
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/ssr.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["source".to_owned(), "depth".to_owned(), "environment".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("projection", UniformType::Mat4),
+                UniformDesc::new("inv_projection", UniformType::Mat4),
+                UniformDesc::new("inv_view", UniformType::Mat4),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+                UniformDesc::new("strength", UniformType::Float1),
+                UniformDesc::new("thickness", UniformType::Float1),
+                UniformDesc::new("use_environment", UniformType::Float1),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: Matrix4<f32>,
+        pub inv_projection: Matrix4<f32>,
+        pub inv_view: Matrix4<f32>,
+        pub log_depth_coef: f32,
+        pub strength: f32,
+        pub thickness: f32,
+        pub use_environment: f32,
+    }
+}
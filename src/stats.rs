@@ -0,0 +1,397 @@
+use std::{cell::Cell, collections::HashMap, time::Duration};
+
+use miniquad::*;
+
+use crate::palette::Palette;
+use crate::text::TextRenderer;
+
+/// What the renderer did during one frame, plus the GPU memory held at its
+/// end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub triangles: u32,
+    pub lines: u32,
+    pub buffers_created: u32,
+    pub textures_created: u32,
+    /// Vertex, index, uniform and texture data sent to the GPU. Buffer
+    /// updates count the whole buffer, since the backend interface doesn't
+    /// reveal how much of it a partial update covers.
+    pub bytes_uploaded: u64,
+    pub buffer_memory: u64,
+    pub texture_memory: u64,
+}
+
+/// CPU time taken by the per-object scene phases in the last frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SceneTimings {
+    pub animation: Duration,
+    /// Resolving world transforms and sorting the instances.
+    pub transforms: Duration,
+    /// Frustum culling against the culling camera.
+    pub culling: Duration,
+    /// Objects culling left out.
+    pub culled: usize,
+    /// Threads the phases were spread over.
+    pub threads: usize,
+}
+
+impl RenderStats {
+    /// Lists the counters, and the time the scene took on the CPU, down
+    /// the top left corner.
+    pub fn draw(&self, scene: &SceneTimings, text: &mut TextRenderer, palette: &Palette) {
+        let margin = text.scale * 2.0;
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let lines = [
+            format!("draw calls {}", self.draw_calls),
+            format!("instances  {}", self.instances),
+            format!("triangles  {}", self.triangles),
+            format!("lines      {}", self.lines),
+            format!(
+                "created    {} buffers, {} textures",
+                self.buffers_created, self.textures_created
+            ),
+            format!("uploaded   {}", bytes(self.bytes_uploaded)),
+            format!("buffers    {}", bytes(self.buffer_memory)),
+            format!("textures   {}", bytes(self.texture_memory)),
+            format!("animation  {:.2} ms", ms(scene.animation)),
+            format!(
+                "transforms {:.2} ms on {} threads",
+                ms(scene.transforms),
+                scene.threads
+            ),
+            format!(
+                "culling    {:.2} ms, {} culled",
+                ms(scene.culling),
+                scene.culled
+            ),
+        ];
+        text.rect(
+            0.0,
+            0.0,
+            margin * 2.0 + 38.0 * text.char_width(),
+            margin * 2.0 + lines.len() as f32 * text.line_height(),
+            palette.panel,
+        );
+        for (i, line) in lines.iter().enumerate() {
+            let y = margin + i as f32 * text.line_height();
+            text.print(margin, y, palette.stats_text, line);
+        }
+    }
+}
+
+fn bytes(n: u64) -> String {
+    match n {
+        0..=9_999 => format!("{} B", n),
+        10_000..=9_999_999 => format!("{:.1} KiB", n as f64 / 1024.0),
+        _ => format!("{:.1} MiB", n as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// Wraps the real backend and counts what passes through it. Counters are
+/// reset when a frame is committed; `stats` returns the last full frame.
+pub struct CountingBackend {
+    inner: Box<dyn RenderingBackend>,
+    /// `draw` only gets `&self`.
+    current: Cell<RenderStats>,
+    last: RenderStats,
+    primitives: HashMap<Pipeline, PrimitiveType>,
+    primitive: PrimitiveType,
+    buffer_sizes: HashMap<BufferId, u64>,
+    texture_sizes: HashMap<TextureId, u64>,
+}
+
+impl CountingBackend {
+    pub fn new(inner: Box<dyn RenderingBackend>) -> CountingBackend {
+        CountingBackend {
+            inner,
+            current: Cell::default(),
+            last: RenderStats::default(),
+            primitives: HashMap::new(),
+            primitive: PrimitiveType::Triangles,
+            buffer_sizes: HashMap::new(),
+            texture_sizes: HashMap::new(),
+        }
+    }
+
+    pub fn stats(&self) -> RenderStats {
+        self.last
+    }
+
+    fn count(&self, f: impl FnOnce(&mut RenderStats)) {
+        let mut stats = self.current.get();
+        f(&mut stats);
+        self.current.set(stats);
+    }
+
+    fn track_texture(&mut self, texture: TextureId) {
+        let params = self.inner.texture_params(texture);
+        let mut size = params.format.size(params.width, params.height) as u64;
+        if params.kind == TextureKind::CubeMap {
+            size *= 6;
+        }
+        if params.allocate_mipmaps {
+            size = size * 4 / 3;
+        }
+        let old = self.texture_sizes.insert(texture, size).unwrap_or(0);
+        self.count(|s| s.texture_memory = s.texture_memory - old + size);
+    }
+}
+
+impl RenderingBackend for CountingBackend {
+    fn info(&self) -> ContextInfo {
+        self.inner.info()
+    }
+
+    fn new_shader(
+        &mut self,
+        shader: ShaderSource,
+        meta: ShaderMeta,
+    ) -> Result<ShaderId, ShaderError> {
+        self.inner.new_shader(shader, meta)
+    }
+
+    fn new_texture(
+        &mut self,
+        access: TextureAccess,
+        data: TextureSource,
+        params: TextureParams,
+    ) -> TextureId {
+        let uploaded: usize = match &data {
+            TextureSource::Empty => 0,
+            TextureSource::Bytes(bytes) => bytes.len(),
+            TextureSource::Array(faces) => {
+                faces.iter().flat_map(|f| f.iter()).map(|l| l.len()).sum()
+            }
+        };
+        let texture = self.inner.new_texture(access, data, params);
+        self.count(|s| {
+            s.textures_created += 1;
+            s.bytes_uploaded += uploaded as u64;
+        });
+        self.track_texture(texture);
+        texture
+    }
+
+    fn texture_params(&self, texture: TextureId) -> TextureParams {
+        self.inner.texture_params(texture)
+    }
+
+    unsafe fn texture_raw_id(&self, texture: TextureId) -> RawId {
+        self.inner.texture_raw_id(texture)
+    }
+
+    fn texture_set_min_filter(
+        &mut self,
+        texture: TextureId,
+        filter: FilterMode,
+        mipmap_filter: MipmapFilterMode,
+    ) {
+        self.inner
+            .texture_set_min_filter(texture, filter, mipmap_filter)
+    }
+
+    fn texture_set_mag_filter(&mut self, texture: TextureId, filter: FilterMode) {
+        self.inner.texture_set_mag_filter(texture, filter)
+    }
+
+    fn texture_set_wrap(&mut self, texture: TextureId, wrap_x: TextureWrap, wrap_y: TextureWrap) {
+        self.inner.texture_set_wrap(texture, wrap_x, wrap_y)
+    }
+
+    fn texture_generate_mipmaps(&mut self, texture: TextureId) {
+        self.inner.texture_generate_mipmaps(texture)
+    }
+
+    fn texture_resize(
+        &mut self,
+        texture: TextureId,
+        width: u32,
+        height: u32,
+        bytes: Option<&[u8]>,
+    ) {
+        let uploaded = bytes.map_or(0, |b| b.len()) as u64;
+        self.inner.texture_resize(texture, width, height, bytes);
+        self.count(|s| s.bytes_uploaded += uploaded);
+        self.track_texture(texture);
+    }
+
+    fn texture_read_pixels(&mut self, texture: TextureId, bytes: &mut [u8]) {
+        self.inner.texture_read_pixels(texture, bytes)
+    }
+
+    fn texture_update_part(
+        &mut self,
+        texture: TextureId,
+        x_offset: i32,
+        y_offset: i32,
+        width: i32,
+        height: i32,
+        bytes: &[u8],
+    ) {
+        self.count(|s| s.bytes_uploaded += bytes.len() as u64);
+        self.inner
+            .texture_update_part(texture, x_offset, y_offset, width, height, bytes)
+    }
+
+    fn new_render_pass_mrt(
+        &mut self,
+        color_img: &[TextureId],
+        resolve_img: Option<&[TextureId]>,
+        depth_img: Option<TextureId>,
+    ) -> RenderPass {
+        self.inner
+            .new_render_pass_mrt(color_img, resolve_img, depth_img)
+    }
+
+    fn render_pass_color_attachments(&self, render_pass: RenderPass) -> &[TextureId] {
+        self.inner.render_pass_color_attachments(render_pass)
+    }
+
+    fn delete_render_pass(&mut self, render_pass: RenderPass) {
+        self.inner.delete_render_pass(render_pass)
+    }
+
+    fn new_pipeline(
+        &mut self,
+        buffer_layout: &[BufferLayout],
+        attributes: &[VertexAttribute],
+        shader: ShaderId,
+        params: PipelineParams,
+    ) -> Pipeline {
+        let pipeline = self
+            .inner
+            .new_pipeline(buffer_layout, attributes, shader, params);
+        self.primitives.insert(pipeline, params.primitive_type);
+        pipeline
+    }
+
+    fn apply_pipeline(&mut self, pipeline: &Pipeline) {
+        self.primitive = self.primitives[pipeline];
+        self.inner.apply_pipeline(pipeline)
+    }
+
+    fn delete_pipeline(&mut self, pipeline: Pipeline) {
+        self.primitives.remove(&pipeline);
+        self.inner.delete_pipeline(pipeline)
+    }
+
+    fn new_buffer(
+        &mut self,
+        type_: BufferType,
+        usage: BufferUsage,
+        data: BufferSource,
+    ) -> BufferId {
+        let filled = matches!(data, BufferSource::Slice(_));
+        let buffer = self.inner.new_buffer(type_, usage, data);
+        let size = self.inner.buffer_size(buffer) as u64;
+        self.buffer_sizes.insert(buffer, size);
+        self.count(|s| {
+            s.buffers_created += 1;
+            s.buffer_memory += size;
+            if filled {
+                s.bytes_uploaded += size;
+            }
+        });
+        buffer
+    }
+
+    fn buffer_update(&mut self, buffer: BufferId, data: BufferSource) {
+        let size = self.buffer_sizes.get(&buffer).copied().unwrap_or(0);
+        self.count(|s| s.bytes_uploaded += size);
+        self.inner.buffer_update(buffer, data)
+    }
+
+    fn buffer_size(&mut self, buffer: BufferId) -> usize {
+        self.inner.buffer_size(buffer)
+    }
+
+    fn delete_buffer(&mut self, buffer: BufferId) {
+        let size = self.buffer_sizes.remove(&buffer).unwrap_or(0);
+        self.count(|s| s.buffer_memory -= size);
+        self.inner.delete_buffer(buffer)
+    }
+
+    fn delete_texture(&mut self, texture: TextureId) {
+        let size = self.texture_sizes.remove(&texture).unwrap_or(0);
+        self.count(|s| s.texture_memory -= size);
+        self.inner.delete_texture(texture)
+    }
+
+    fn delete_shader(&mut self, program: ShaderId) {
+        self.inner.delete_shader(program)
+    }
+
+    fn apply_viewport(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.inner.apply_viewport(x, y, w, h)
+    }
+
+    fn apply_scissor_rect(&mut self, x: i32, y: i32, w: i32, h: i32) {
+        self.inner.apply_scissor_rect(x, y, w, h)
+    }
+
+    fn apply_bindings_from_slice(
+        &mut self,
+        vertex_buffers: &[BufferId],
+        index_buffer: BufferId,
+        textures: &[TextureId],
+    ) {
+        self.inner
+            .apply_bindings_from_slice(vertex_buffers, index_buffer, textures)
+    }
+
+    fn apply_uniforms_from_bytes(&mut self, uniform_ptr: *const u8, size: usize) {
+        self.count(|s| s.bytes_uploaded += size as u64);
+        self.inner.apply_uniforms_from_bytes(uniform_ptr, size)
+    }
+
+    fn clear(
+        &mut self,
+        color: Option<(f32, f32, f32, f32)>,
+        depth: Option<f32>,
+        stencil: Option<i32>,
+    ) {
+        self.inner.clear(color, depth, stencil)
+    }
+
+    fn begin_default_pass(&mut self, action: PassAction) {
+        self.inner.begin_default_pass(action)
+    }
+
+    fn begin_pass(&mut self, pass: Option<RenderPass>, action: PassAction) {
+        self.inner.begin_pass(pass, action)
+    }
+
+    fn end_render_pass(&mut self) {
+        self.inner.end_render_pass()
+    }
+
+    fn commit_frame(&mut self) {
+        self.inner.commit_frame();
+        let mut stats = self.current.get();
+        self.last = stats;
+        // Memory carries over; everything else is per frame.
+        stats = RenderStats {
+            buffer_memory: stats.buffer_memory,
+            texture_memory: stats.texture_memory,
+            ..RenderStats::default()
+        };
+        self.current.set(stats);
+    }
+
+    fn draw(&self, base_element: i32, num_elements: i32, num_instances: i32) {
+        let primitive = self.primitive;
+        self.count(|s| {
+            s.draw_calls += 1;
+            s.instances += num_instances as u32;
+            let primitives = num_elements as u32 * num_instances as u32;
+            match primitive {
+                PrimitiveType::Triangles => s.triangles += primitives / 3,
+                PrimitiveType::Lines => s.lines += primitives / 2,
+                PrimitiveType::Points => (),
+            }
+        });
+        self.inner.draw(base_element, num_elements, num_instances)
+    }
+}
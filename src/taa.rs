@@ -0,0 +1,114 @@
+use cgmath::{vec3, Matrix4};
+use miniquad::*;
+
+use crate::post::{Quad, RenderTarget};
+
+/// Temporal anti-aliasing. The projection is shifted by a different
+/// sub-pixel offset every frame and the results are accumulated in a
+/// history buffer, reprojected through the velocity buffer and clamped to
+/// the current neighborhood to reject stale samples.
+pub struct Taa {
+    pipeline: Pipeline,
+    history: [RenderTarget; 2],
+    current: usize,
+    frame_index: u32,
+    /// False until a frame has been resolved into the history.
+    valid: bool,
+    pub enabled: bool,
+}
+
+const HISTORY_WEIGHT: f32 = 0.9;
+const JITTER_PHASES: u32 = 8;
+
+impl Taa {
+    pub fn new(ctx: &mut dyn RenderingBackend, quad: &Quad, width: u32, height: u32) -> Taa {
+        let pipeline = quad.pipeline(ctx, shader::FRAGMENT, shader::meta());
+        Taa {
+            pipeline,
+            history: [
+                RenderTarget::color_only(ctx, width, height),
+                RenderTarget::color_only(ctx, width, height),
+            ],
+            current: 0,
+            frame_index: 0,
+            valid: false,
+            enabled: false,
+        }
+    }
+
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: u32, height: u32) {
+        for target in &mut self.history {
+            target.resize(ctx, width, height);
+        }
+        self.valid = false;
+    }
+
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// Clip-space translation to apply on top of the projection matrix.
+    pub fn jitter(&self, width: f32, height: f32) -> Matrix4<f32> {
+        let i = self.frame_index % JITTER_PHASES + 1;
+        let x = (halton(i, 2) - 0.5) * 2.0 / width;
+        let y = (halton(i, 3) - 0.5) * 2.0 / height;
+        Matrix4::from_translation(vec3(x, y, 0.0))
+    }
+
+    /// Blends `current` into the history and returns the resolved image.
+    pub fn resolve(
+        &mut self,
+        ctx: &mut dyn RenderingBackend,
+        quad: &Quad,
+        current: TextureId,
+        velocity: TextureId,
+    ) -> TextureId {
+        let previous = self.history[self.current].color;
+        self.current = 1 - self.current;
+        let target = &self.history[self.current];
+
+        ctx.begin_pass(Some(target.pass), PassAction::Nothing);
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&quad.bindings(vec![current, previous, velocity]));
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            history_weight: if self.valid { HISTORY_WEIGHT } else { 0.0 },
+        }));
+        quad.draw(ctx);
+        ctx.end_render_pass();
+
+        self.valid = true;
+        self.frame_index = self.frame_index.wrapping_add(1);
+        target.color
+    }
+}
+
+/// Low-discrepancy sequence, so the jitter covers the pixel evenly.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut r = 0.0;
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+    r
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const FRAGMENT: &str = include_str!("shaders/taa.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["current".to_owned(), "history".to_owned(), "velocity".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("history_weight", UniformType::Float1),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub history_weight: f32,
+    }
+}
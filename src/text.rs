@@ -0,0 +1,193 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+use miniquad::*;
+
+use crate::dynamic_buffer::DynamicBuffer;
+use crate::gfx::compile_shader;
+
+/// Glyph size in font pixels.
+const GLYPH: usize = 8;
+/// Atlas layout: printable ASCII in a 16x6 grid, the last cell is solid
+/// and used to fill rectangles.
+const COLUMNS: usize = 16;
+const ROWS: usize = 6;
+const FIRST: u8 = b' ';
+const SOLID: u8 = 0x7f;
+/// Quads drawn in one batch; more are drawn in several.
+const MAX_QUADS: usize = 4096;
+
+#[repr(C)]
+struct Vertex {
+    pos: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Screen-space text and rectangles in an 8x8 bitmap font, batched and
+/// drawn over the final image. Positions are in pixels from the top left;
+/// colors are sRGB, since they go straight to the window.
+pub struct TextRenderer {
+    pipeline: Pipeline,
+    bindings: Bindings,
+    vertices: DynamicBuffer<Vertex>,
+    /// Screen pixels per font pixel.
+    pub scale: f32,
+}
+
+impl TextRenderer {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> TextRenderer {
+        let (width, height) = (COLUMNS * GLYPH, ROWS * GLYPH);
+        let mut texels = vec![0u8; width * height * 4];
+        for code in FIRST..=SOLID {
+            let glyph = if code == SOLID {
+                [0xff; GLYPH]
+            } else {
+                BASIC_FONTS.get(code as char).unwrap_or([0; GLYPH])
+            };
+            let cell = (code - FIRST) as usize;
+            let (cx, cy) = (cell % COLUMNS * GLYPH, cell / COLUMNS * GLYPH);
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..GLYPH {
+                    if row & (1 << x) != 0 {
+                        let at = ((cy + y) * width + cx + x) * 4;
+                        texels[at..at + 4].copy_from_slice(&[255; 4]);
+                    }
+                }
+            }
+        }
+        let atlas = ctx.new_texture_from_rgba8(width as u16, height as u16, &texels);
+        ctx.texture_set_filter(atlas, FilterMode::Nearest, MipmapFilterMode::None);
+
+        let vertices = DynamicBuffer::new(ctx, 1024 * 4, MAX_QUADS * 4, 4);
+        let indices: Vec<u16> = (0..MAX_QUADS as u16)
+            .flat_map(|q| [0, 1, 2, 0, 2, 3].map(|i| q * 4 + i))
+            .collect();
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+
+        let shader = compile_shader(ctx, shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("in_pos", VertexFormat::Float2),
+                VertexAttribute::new("in_uv", VertexFormat::Float2),
+                VertexAttribute::new("in_color", VertexFormat::Float4),
+            ],
+            shader,
+            PipelineParams {
+                color_blend: Some(BlendState::new(
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                ..Default::default()
+            },
+        );
+
+        TextRenderer {
+            pipeline,
+            bindings: Bindings {
+                vertex_buffers: vec![],
+                index_buffer,
+                images: vec![atlas],
+            },
+            vertices,
+            scale: 2.0 * window::dpi_scale(),
+        }
+    }
+
+    /// Height of one line of text, in pixels.
+    pub fn line_height(&self) -> f32 {
+        (GLYPH + 2) as f32 * self.scale
+    }
+
+    /// Width of one character, in pixels.
+    pub fn char_width(&self) -> f32 {
+        GLYPH as f32 * self.scale
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        // Sample the middle of the solid cell, clear of its edges.
+        let (u, v) = cell_uv(SOLID);
+        let mid = [u + 0.5 / COLUMNS as f32, v + 0.5 / ROWS as f32];
+        self.quad([x, y, width, height], [mid[0], mid[1], 0.0, 0.0], color);
+    }
+
+    /// Queues a single line of text; characters outside printable ASCII
+    /// are drawn as `?`.
+    pub fn print(&mut self, x: f32, y: f32, color: [f32; 4], text: &str) {
+        let size = self.char_width();
+        for (i, c) in text.chars().enumerate() {
+            let code = match c {
+                ' '..='~' => c as u8,
+                _ => b'?',
+            };
+            let (u, v) = cell_uv(code);
+            self.quad(
+                [x + i as f32 * size, y, size, size],
+                [u, v, 1.0 / COLUMNS as f32, 1.0 / ROWS as f32],
+                color,
+            );
+        }
+    }
+
+    /// Draws everything queued since the last call into the current pass.
+    pub fn draw(&mut self, ctx: &mut dyn RenderingBackend) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let (width, height) = window::screen_size();
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            screen_size: [width, height],
+        }));
+        let bindings = &mut self.bindings;
+        self.vertices.flush(ctx, |ctx, buffer, count| {
+            bindings.vertex_buffers = vec![buffer];
+            ctx.apply_bindings(bindings);
+            ctx.draw(0, (count / 4 * 6) as i32, 1);
+        });
+    }
+
+    fn quad(&mut self, [x, y, w, h]: [f32; 4], [u, v, du, dv]: [f32; 4], color: [f32; 4]) {
+        for (cx, cy) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            self.vertices.push(Vertex {
+                pos: [x + w * cx, y + h * cy],
+                uv: [u + du * cx, v + dv * cy],
+                color,
+            });
+        }
+    }
+}
+
+fn cell_uv(code: u8) -> (f32, f32) {
+    let cell = (code - FIRST) as usize;
+    (
+        (cell % COLUMNS) as f32 / COLUMNS as f32,
+        (cell / COLUMNS) as f32 / ROWS as f32,
+    )
+}
+
+mod shader {
+    use miniquad::*;
+
+    pub const VERTEX: &str = include_str!("shaders/text.vert");
+
+    pub const FRAGMENT: &str = include_str!("shaders/text.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["atlas".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("screen_size", UniformType::Float2),
+            ] },
+        }
+    }
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub screen_size: [f32; 2],
+    }
+}
@@ -0,0 +1,157 @@
+use miniquad::*;
+
+use crate::color::srgb_to_linear;
+
+/// How the texels of an image are encoded. Color textures (albedo, UI,
+/// photos) are authored in sRGB, while data textures (LUTs, normal maps,
+/// masks) must be sampled as-is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<Image, png::DecodingError> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buf
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => unreachable!("palette is expanded by normalize_to_color8"),
+    };
+
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
+
+/// OpenGL's sRGB-encoded 8-bit internal format, which miniquad doesn't
+/// expose. Core since OpenGL 3.0.
+const GL_SRGB8_ALPHA8: u32 = 0x8C43;
+const GL_TEXTURE_BINDING_2D: u32 = 0x8069;
+const GL_ACTIVE_TEXTURE: u32 = 0x84E0;
+
+/// Uploads an RGBA8 image. sRGB images stay encoded in an sRGB texture,
+/// which the GPU decodes to linear when sampling, so every shader can
+/// assume it samples linear values without 8 bits of linear storage
+/// banding the darks.
+pub fn upload(
+    ctx: &mut dyn RenderingBackend,
+    mut image: Image,
+    color_space: ColorSpace,
+) -> TextureId {
+    let texture = ctx.new_texture_from_data_and_format(
+        &image.rgba,
+        TextureParams {
+            width: image.width,
+            height: image.height,
+            format: TextureFormat::RGBA8,
+            ..Default::default()
+        },
+    );
+    if color_space == ColorSpace::Srgb
+        && !store_srgb(ctx, texture, image.width, image.height, &image.rgba)
+    {
+        linearize(&mut image.rgba);
+        ctx.texture_update(texture, &image.rgba);
+    }
+    texture
+}
+
+/// Reallocates `texture` as sRGB-encoded storage holding `rgba`, for the
+/// GPU to decode when sampling. miniquad still believes it is RGBA8, and
+/// later updates through it upload RGBA8 texels, which is what sRGB
+/// storage takes. Returns false, leaving the texture alone, on backends
+/// other than OpenGL; texels then have to be decoded with `linearize`.
+pub fn store_srgb(
+    ctx: &mut dyn RenderingBackend,
+    texture: TextureId,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> bool {
+    if ctx.info().backend != Backend::OpenGl {
+        return false;
+    }
+    // Only Apple targets have other kinds of id.
+    #[allow(irrefutable_let_patterns)]
+    let RawId::OpenGl(raw) = (unsafe { ctx.texture_raw_id(texture) }) else {
+        return false;
+    };
+    unsafe {
+        // Leave the bindings as miniquad's state cache remembers them.
+        let (mut active, mut bound) = (0, 0);
+        gl::glGetIntegerv(GL_ACTIVE_TEXTURE, &mut active);
+        gl::glActiveTexture(gl::GL_TEXTURE0);
+        gl::glGetIntegerv(GL_TEXTURE_BINDING_2D, &mut bound);
+
+        gl::glBindTexture(gl::GL_TEXTURE_2D, raw);
+        gl::glTexImage2D(
+            gl::GL_TEXTURE_2D,
+            0,
+            GL_SRGB8_ALPHA8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            gl::GL_RGBA,
+            gl::GL_UNSIGNED_BYTE,
+            rgba.as_ptr() as *const _,
+        );
+
+        gl::glBindTexture(gl::GL_TEXTURE_2D, bound as u32);
+        gl::glActiveTexture(active as u32);
+    }
+    true
+}
+
+/// Decodes the color channels of sRGB RGBA8 texels to linear, in place,
+/// where the GPU can't be given sRGB storage.
+pub fn linearize(rgba: &mut [u8]) {
+    for px in rgba.chunks_exact_mut(4) {
+        for c in &mut px[..3] {
+            *c = (srgb_to_linear(*c as f32 / 255.0) * 255.0).round() as u8;
+        }
+    }
+}
+
+/// Writes 8-bit RGBA rows, top first, to a PNG file.
+pub fn save_png(path: &str, width: u32, height: u32, rgba: &[u8]) -> Result<(), String> {
+    let file =
+        std::fs::File::create(path).map_err(|err| format!("could not create {}: {}", path, err))?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|err| format!("could not write {}: {}", path, err))
+}
+
+pub fn load_png(
+    ctx: &mut dyn RenderingBackend,
+    bytes: &[u8],
+    color_space: ColorSpace,
+) -> Result<TextureId, png::DecodingError> {
+    Ok(upload(ctx, decode_png(bytes)?, color_space))
+}
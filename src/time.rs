@@ -0,0 +1,98 @@
+use std::time::Instant;
+
+/// Seconds of simulation a single frame step advances.
+const STEP: f32 = 1.0 / 60.0;
+
+/// Frame timing, kept in two clocks. Real time always runs and drives
+/// what the user controls directly: flying the camera, transitions and
+/// film grain. Simulation time runs at `scale` times real time and stops
+/// while paused; the scene's animations, scripts, the day/night cycle and
+/// the water consume it.
+pub struct Time {
+    last_frame: Instant,
+    real_delta: f32,
+    real_elapsed: f32,
+    delta: f32,
+    elapsed: f32,
+    /// How fast simulation time runs compared to real time. 0 pauses it
+    /// too.
+    pub scale: f32,
+    paused: bool,
+    /// Set when one frame step is requested while paused.
+    step: bool,
+}
+
+impl Time {
+    pub fn new() -> Time {
+        Time {
+            last_frame: Instant::now(),
+            real_delta: 0.0,
+            real_elapsed: 0.0,
+            delta: 0.0,
+            elapsed: 0.0,
+            scale: 1.0,
+            paused: false,
+            step: false,
+        }
+    }
+
+    /// Starts a frame. The real time it covers is measured since the last
+    /// one, unless `fixed` gives it.
+    pub fn tick(&mut self, fixed: Option<f32>) {
+        let now = Instant::now();
+        self.real_delta = fixed.unwrap_or_else(|| (now - self.last_frame).as_secs_f32());
+        self.last_frame = now;
+        self.real_elapsed += self.real_delta;
+        self.delta = if !self.is_paused() {
+            self.real_delta * self.scale
+        } else if self.step {
+            STEP
+        } else {
+            0.0
+        };
+        self.step = false;
+        self.elapsed += self.delta;
+    }
+
+    /// Simulation seconds this frame.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Simulation seconds since startup.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    /// Real seconds this frame.
+    pub fn real_delta(&self) -> f32 {
+        self.real_delta
+    }
+
+    /// Real seconds since startup.
+    pub fn real_elapsed(&self) -> f32 {
+        self.real_elapsed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused || self.scale == 0.0
+    }
+
+    /// Pauses or resumes. Resuming from a scale of 0 goes back to normal
+    /// speed.
+    pub fn toggle_pause(&mut self) {
+        if self.scale == 0.0 {
+            self.scale = 1.0;
+            self.paused = false;
+        } else {
+            self.paused = !self.paused;
+        }
+    }
+
+    /// Advances the simulation by one step on the next frame, if paused.
+    pub fn step(&mut self) {
+        if self.is_paused() {
+            self.step = true;
+        }
+    }
+}
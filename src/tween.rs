@@ -0,0 +1,182 @@
+use std::f32::consts::TAU;
+
+use cgmath::{Quaternion, Vector3, VectorSpace};
+
+/// Shapes the progress of a tween.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ease {
+    Linear,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    /// Overshoots and springs back into place.
+    Elastic,
+}
+
+impl Ease {
+    /// Maps linear progress in 0..1 to eased progress, which starts at 0
+    /// and ends at 1 but may leave that range in between.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+            Ease::CubicInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+            Ease::Elastic if t == 0.0 || t == 1.0 => t,
+            Ease::Elastic => 1.0 + 2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * TAU / 3.0).sin(),
+        }
+    }
+}
+
+/// Values that can be animated.
+pub trait Lerp: Copy {
+    /// `self` at `t` = 0, `to` at `t` = 1. Must extrapolate sensibly for
+    /// overshooting eases.
+    fn lerp(self, to: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, to: f32, t: f32) -> f32 {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for Vector3<f32> {
+    fn lerp(self, to: Vector3<f32>, t: f32) -> Vector3<f32> {
+        VectorSpace::lerp(self, to, t)
+    }
+}
+
+impl Lerp for Quaternion<f32> {
+    fn lerp(self, to: Quaternion<f32>, t: f32) -> Quaternion<f32> {
+        self.slerp(to, t)
+    }
+}
+
+/// One value moving from `from` to `to` over `duration` seconds.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween<V> {
+    pub from: V,
+    pub to: V,
+    pub duration: f32,
+    pub ease: Ease,
+    elapsed: f32,
+}
+
+impl<V: Lerp> Tween<V> {
+    pub fn new(from: V, to: V, duration: f32, ease: Ease) -> Tween<V> {
+        Tween {
+            from,
+            to,
+            duration,
+            ease,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn value(&self) -> V {
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        self.from.lerp(self.to, self.ease.apply(t))
+    }
+
+    /// Moves `dt` seconds on and returns the new value.
+    pub fn advance(&mut self, dt: f32) -> V {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        self.value()
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+type Callback<T> = Box<dyn FnOnce(&mut T)>;
+/// Advances a tween and stores the value; returns whether it has finished.
+type Step<T> = Box<dyn FnMut(&mut T, f32) -> bool>;
+
+/// A tween writing into a property of `T`.
+pub struct Animation<T> {
+    property: &'static str,
+    step: Step<T>,
+    on_complete: Option<Callback<T>>,
+}
+
+impl<T> Animation<T> {
+    /// Runs `f` once the tween has reached its end. Not run if the
+    /// animation is replaced or cancelled first.
+    pub fn then(&mut self, f: impl FnOnce(&mut T) + 'static) -> &mut Animation<T> {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+}
+
+/// Running animations of properties of `T`, each named so that animating
+/// a property again takes over from the tween already moving it.
+pub struct Tweens<T> {
+    active: Vec<Animation<T>>,
+}
+
+impl<T> Default for Tweens<T> {
+    fn default() -> Tweens<T> {
+        Tweens { active: vec![] }
+    }
+}
+
+impl<T> Tweens<T> {
+    /// Starts moving `property` along `tween`, writing each value with
+    /// `set`.
+    pub fn animate<V: Lerp + 'static>(
+        &mut self,
+        property: &'static str,
+        mut tween: Tween<V>,
+        mut set: impl FnMut(&mut T, V) + 'static,
+    ) -> &mut Animation<T> {
+        self.cancel(property);
+        self.active.push(Animation {
+            property,
+            step: Box::new(move |target, dt| {
+                set(target, tween.advance(dt));
+                tween.finished()
+            }),
+            on_complete: None,
+        });
+        self.active.last_mut().unwrap()
+    }
+
+    /// Stops animating `property`, leaving it where it is.
+    pub fn cancel(&mut self, property: &'static str) {
+        self.active.retain(|a| a.property != property);
+    }
+
+    pub fn is_animating(&self, property: &str) -> bool {
+        self.active.iter().any(|a| a.property == property)
+    }
+
+    /// Advances every animation on `target`, which owns the `Tweens`
+    /// reached through `tweens`. Completion callbacks run last and may
+    /// start new animations.
+    pub fn update(target: &mut T, tweens: fn(&mut T) -> &mut Tweens<T>, dt: f32) {
+        let mut active = std::mem::take(&mut tweens(target).active);
+        let mut completed = vec![];
+        active.retain_mut(|a| {
+            if (a.step)(target, dt) {
+                completed.extend(a.on_complete.take());
+                false
+            } else {
+                true
+            }
+        });
+        // Anything started from a setter goes after what was running.
+        let added = std::mem::replace(&mut tweens(target).active, active);
+        tweens(target).active.extend(added);
+        for f in completed {
+            f(target);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use cgmath::Matrix4;
+use miniquad::*;
+
+use crate::gfx::compile_shader;
+use crate::mesh::vertex_attributes;
+use crate::post::RenderTarget;
+use crate::scene::MAX_INSTANCES;
+
+/// Screen-space motion of every pixel between the previous and the current
+/// frame, in UV units. miniquad passes have a single color attachment, so
+/// instead of an extra output on the geometry pass the scene is drawn a
+/// second time against its own depth buffer.
+pub struct VelocityPass {
+    pipeline: Pipeline,
+    pub target: RenderTarget,
+}
+
+impl VelocityPass {
+    pub fn new(
+        ctx: &mut dyn RenderingBackend,
+        width: u32,
+        height: u32,
+        scene_depth: TextureId,
+    ) -> VelocityPass {
+        let shader = compile_shader(ctx, shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &vertex_attributes(),
+            shader,
+            PipelineParams {
+                depth_write: false,
+                depth_test: Comparison::LessOrEqual,
+                // Materials may be double-sided or wound the other way;
+                // the depth test keeps only the surfaces the scene shows.
+                cull_face: CullFace::Nothing,
+                ..Default::default()
+            },
+        );
+
+        let target =
+            RenderTarget::sharing_depth(ctx, width, height, TextureFormat::RGBA8, scene_depth);
+        // Packed values must not be blended between texels.
+        ctx.texture_set_filter(target.color, FilterMode::Nearest, MipmapFilterMode::None);
+
+        VelocityPass { pipeline, target }
+    }
+
+    /// Starts the pass; each instance batch is then drawn with `draw`.
+    pub fn begin(&self, ctx: &mut dyn RenderingBackend) {
+        // Zero motion, as packed by the fragment shader.
+        let zero = 0x7f as f32 / 255.0;
+        ctx.begin_pass(
+            Some(self.target.pass),
+            PassAction::Clear {
+                color: Some((zero, 1.0, zero, 1.0)),
+                depth: None,
+                stencil: None,
+            },
+        );
+        ctx.apply_pipeline(&self.pipeline);
+    }
+
+    pub fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        bindings: &Bindings,
+        uniforms: &Uniforms,
+        index_count: i32,
+        instances: i32,
+    ) {
+        ctx.apply_bindings(bindings);
+        ctx.apply_uniforms(UniformsSource::table(uniforms));
+        ctx.draw(0, index_count, instances);
+    }
+}
+
+mod shader {
+    use miniquad::*;
+
+    use crate::scene::MAX_INSTANCES;
+
+    pub const VERTEX: &str = include_str!("shaders/velocity.vert");
+
+    pub const FRAGMENT: &str = include_str!("shaders/velocity.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec![],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("projection_view", UniformType::Mat4),
+                UniformDesc::new("view_proj", UniformType::Mat4),
+                UniformDesc::new("prev_view_proj", UniformType::Mat4),
+                UniformDesc::new("world", UniformType::Mat4).array(MAX_INSTANCES),
+                UniformDesc::new("prev_world", UniformType::Mat4).array(MAX_INSTANCES),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+            ] },
+        }
+    }
+}
+
+#[repr(C)]
+pub struct Uniforms {
+    /// The (possibly jittered) matrix the scene was rasterized with, so the
+    /// depth test against the scene depth buffer lines up exactly.
+    pub projection_view: Matrix4<f32>,
+    pub view_proj: Matrix4<f32>,
+    pub prev_view_proj: Matrix4<f32>,
+    pub world: [Matrix4<f32>; MAX_INSTANCES],
+    pub prev_world: [Matrix4<f32>; MAX_INSTANCES],
+    pub log_depth_coef: f32,
+}
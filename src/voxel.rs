@@ -0,0 +1,453 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{vec2, vec3, vec4, ElementWise, Matrix4, Vector3, Vector4};
+use miniquad::*;
+
+use crate::color::linear_rgba;
+use crate::mesh::{Mesh, Vertex};
+use crate::picking::Ray;
+
+/// Blocks along each side of a chunk.
+const SIZE: i32 = 16;
+/// Width of a block in world units.
+const BLOCK_SIZE: f32 = 0.25;
+/// Chunks in the world along X, Y and Z.
+const WORLD_CHUNKS: [i32; 3] = [4, 2, 4];
+/// Where the corner of the world is. The hills rise a little above the
+/// hand-made scenes' floor, behind where the camera starts.
+const ORIGIN: Vector3<f32> = vec3(-8.0, -2.0, -24.0);
+/// Blocks a ray passes through before giving up.
+const MAX_REACH: usize = 64;
+
+pub type Block = u8;
+pub const AIR: Block = 0;
+const STONE: Block = 1;
+const DIRT: Block = 2;
+const GRASS: Block = 3;
+/// sRGB colors by block id.
+const COLORS: [(f32, f32, f32); 4] = [
+    (0.0, 0.0, 0.0),
+    (0.5, 0.5, 0.52),
+    (0.45, 0.32, 0.2),
+    (0.35, 0.6, 0.25),
+];
+/// Brightness by how many of a vertex's three neighbours are solid,
+/// fewest last.
+const AO_LEVELS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+type Coord = [i32; 3];
+
+struct Chunk {
+    blocks: Vec<Block>,
+    gpu: Option<ChunkMesh>,
+    dirty: bool,
+}
+
+/// A chunk's mesh on the GPU. Buffers only grow, so edits that don't add
+/// faces past their size are plain updates.
+pub struct ChunkMesh {
+    vertex_buffer: GrowingBuffer,
+    index_buffer: GrowingBuffer,
+    edge_buffer: GrowingBuffer,
+    index_count: i32,
+    edge_count: i32,
+    pub world: Matrix4<f32>,
+}
+
+impl ChunkMesh {
+    pub fn index_count(&self) -> i32 {
+        self.index_count
+    }
+
+    pub fn edge_count(&self) -> i32 {
+        self.edge_count
+    }
+
+    pub fn bindings(&self, image: TextureId) -> Bindings {
+        Bindings {
+            vertex_buffers: vec![self.vertex_buffer.id],
+            index_buffer: self.index_buffer.id,
+            images: vec![image],
+        }
+    }
+
+    pub fn edge_bindings(&self, image: TextureId) -> Bindings {
+        Bindings {
+            index_buffer: self.edge_buffer.id,
+            ..self.bindings(image)
+        }
+    }
+}
+
+struct GrowingBuffer {
+    id: BufferId,
+    /// In elements.
+    capacity: usize,
+}
+
+impl GrowingBuffer {
+    fn new<T>(ctx: &mut dyn RenderingBackend, kind: BufferType, data: &[T]) -> GrowingBuffer {
+        let capacity = data.len().next_power_of_two().max(64);
+        let id = ctx.new_buffer(
+            kind,
+            BufferUsage::Dynamic,
+            BufferSource::empty::<T>(capacity),
+        );
+        ctx.buffer_update(id, BufferSource::slice(data));
+        GrowingBuffer { id, capacity }
+    }
+
+    fn write<T>(&mut self, ctx: &mut dyn RenderingBackend, kind: BufferType, data: &[T]) {
+        if data.len() > self.capacity {
+            ctx.delete_buffer(self.id);
+            *self = GrowingBuffer::new(ctx, kind, data);
+        } else {
+            ctx.buffer_update(self.id, BufferSource::slice(data));
+        }
+    }
+}
+
+/// A small world of blocks, split into chunks that are greedily meshed
+/// (coplanar faces of the same block and shading merged into larger
+/// quads) with ambient occlusion baked into the vertex colors. Chunks are
+/// remeshed when a block in or next to them changes.
+pub struct VoxelWorld {
+    pub enabled: bool,
+    chunks: HashMap<Coord, Chunk>,
+}
+
+impl VoxelWorld {
+    /// Rolling hills of stone under dirt and grass.
+    pub fn new() -> VoxelWorld {
+        let mut world = VoxelWorld {
+            enabled: false,
+            chunks: HashMap::new(),
+        };
+        for cx in 0..WORLD_CHUNKS[0] {
+            for cy in 0..WORLD_CHUNKS[1] {
+                for cz in 0..WORLD_CHUNKS[2] {
+                    world.chunks.insert(
+                        [cx, cy, cz],
+                        Chunk {
+                            blocks: vec![AIR; (SIZE * SIZE * SIZE) as usize],
+                            gpu: None,
+                            dirty: true,
+                        },
+                    );
+                }
+            }
+        }
+        let width = WORLD_CHUNKS[0] * SIZE;
+        let depth = WORLD_CHUNKS[2] * SIZE;
+        for x in 0..width {
+            for z in 0..depth {
+                let (fx, fz) = (x as f32, z as f32);
+                let height = 8.0
+                    + 3.0 * (fx * 0.2).sin() * (fz * 0.15).cos()
+                    + 2.0 * (fx * 0.07 + fz * 0.11).sin();
+                let top = height as i32;
+                for y in 0..=top {
+                    let block = match top - y {
+                        0 => GRASS,
+                        1..=2 => DIRT,
+                        _ => STONE,
+                    };
+                    world.set_raw([x, y, z], block);
+                }
+            }
+        }
+        world
+    }
+
+    pub fn get(&self, [x, y, z]: Coord) -> Block {
+        let chunk = [x.div_euclid(SIZE), y.div_euclid(SIZE), z.div_euclid(SIZE)];
+        self.chunks.get(&chunk).map_or(AIR, |c| {
+            c.blocks[index([x.rem_euclid(SIZE), y.rem_euclid(SIZE), z.rem_euclid(SIZE)])]
+        })
+    }
+
+    fn solid(&self, p: Coord) -> bool {
+        self.get(p) != AIR
+    }
+
+    /// Sets a block without marking anything for remeshing. Returns false
+    /// outside the world.
+    fn set_raw(&mut self, [x, y, z]: Coord, block: Block) -> bool {
+        let chunk = [x.div_euclid(SIZE), y.div_euclid(SIZE), z.div_euclid(SIZE)];
+        let Some(c) = self.chunks.get_mut(&chunk) else {
+            return false;
+        };
+        c.blocks[index([x.rem_euclid(SIZE), y.rem_euclid(SIZE), z.rem_euclid(SIZE)])] = block;
+        true
+    }
+
+    /// Changes a block and marks every chunk whose faces or shading it
+    /// touches for remeshing.
+    pub fn set(&mut self, p: Coord, block: Block) {
+        if !self.set_raw(p, block) {
+            return;
+        }
+        let mut touched = HashSet::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let q = [p[0] + dx, p[1] + dy, p[2] + dz];
+                    touched.insert(q.map(|v| v.div_euclid(SIZE)));
+                }
+            }
+        }
+        for chunk in touched {
+            if let Some(c) = self.chunks.get_mut(&chunk) {
+                c.dirty = true;
+            }
+        }
+    }
+
+    /// The first solid block along the ray and the empty cell in front of
+    /// it, walking the grid cell by cell.
+    pub fn raycast(&self, ray: &Ray) -> Option<(Coord, Coord)> {
+        let origin = (ray.origin - ORIGIN) / BLOCK_SIZE;
+        let dir = ray.direction;
+        let mut cell = [origin.x, origin.y, origin.z].map(|v| v.floor() as i32);
+        let step = [dir.x, dir.y, dir.z].map(|d| if d > 0.0 { 1 } else { -1 });
+        let o = [origin.x, origin.y, origin.z];
+        let d = [dir.x, dir.y, dir.z];
+        // Distance along the ray to the next boundary on each axis, and
+        // between boundaries.
+        let mut next = [0.0; 3];
+        let mut delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if d[axis] != 0.0 {
+                delta[axis] = 1.0 / d[axis].abs();
+                let boundary = cell[axis] as f32 + if step[axis] > 0 { 1.0 } else { 0.0 };
+                next[axis] = (boundary - o[axis]) / d[axis];
+            } else {
+                next[axis] = f32::INFINITY;
+            }
+        }
+        let mut previous = cell;
+        for _ in 0..MAX_REACH {
+            if self.solid(cell) {
+                return Some((cell, previous));
+            }
+            previous = cell;
+            let axis = (0..3).min_by(|&a, &b| next[a].total_cmp(&next[b])).unwrap();
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+        None
+    }
+
+    /// Remeshes and uploads the chunks changed since the last call.
+    pub fn update(&mut self, ctx: &mut dyn RenderingBackend) {
+        if !self.enabled {
+            return;
+        }
+        let dirty: Vec<Coord> = self
+            .chunks
+            .iter()
+            .filter(|(_, c)| c.dirty)
+            .map(|(&coord, _)| coord)
+            .collect();
+        for coord in dirty {
+            let mesh = self.mesh(coord);
+            let edges = mesh.edges();
+            let chunk = self.chunks.get_mut(&coord).unwrap();
+            chunk.dirty = false;
+            match &mut chunk.gpu {
+                Some(gpu) => {
+                    gpu.vertex_buffer
+                        .write(ctx, BufferType::VertexBuffer, &mesh.vertices);
+                    gpu.index_buffer
+                        .write(ctx, BufferType::IndexBuffer, &mesh.indices);
+                    gpu.edge_buffer.write(ctx, BufferType::IndexBuffer, &edges);
+                    gpu.index_count = mesh.indices.len() as i32;
+                    gpu.edge_count = edges.len() as i32;
+                }
+                None => {
+                    let corner = ORIGIN
+                        + vec3(coord[0] as f32, coord[1] as f32, coord[2] as f32)
+                            * SIZE as f32
+                            * BLOCK_SIZE;
+                    chunk.gpu = Some(ChunkMesh {
+                        vertex_buffer: GrowingBuffer::new(
+                            ctx,
+                            BufferType::VertexBuffer,
+                            &mesh.vertices,
+                        ),
+                        index_buffer: GrowingBuffer::new(
+                            ctx,
+                            BufferType::IndexBuffer,
+                            &mesh.indices,
+                        ),
+                        edge_buffer: GrowingBuffer::new(ctx, BufferType::IndexBuffer, &edges),
+                        index_count: mesh.indices.len() as i32,
+                        edge_count: edges.len() as i32,
+                        world: Matrix4::from_translation(corner) * Matrix4::from_scale(BLOCK_SIZE),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Meshed chunks with something to draw.
+    pub fn meshes(&self) -> impl Iterator<Item = &ChunkMesh> {
+        self.chunks
+            .values()
+            .filter_map(|c| c.gpu.as_ref())
+            .filter(|gpu| gpu.index_count > 0)
+    }
+
+    /// Greedy mesh of one chunk, in block units relative to its corner.
+    fn mesh(&self, chunk: Coord) -> Mesh {
+        let base = chunk.map(|c| c * SIZE);
+        let mut mesh = Mesh {
+            vertices: vec![],
+            indices: vec![],
+        };
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for sign in [1, -1] {
+                for slice in 0..SIZE {
+                    // The visible faces of this slice: block and corner
+                    // shading, which must match for faces to merge.
+                    let mut mask: Vec<Option<(Block, [usize; 4])>> =
+                        vec![None; (SIZE * SIZE) as usize];
+                    for j in 0..SIZE {
+                        for i in 0..SIZE {
+                            let mut p = base;
+                            p[axis] += slice;
+                            p[u] += i;
+                            p[v] += j;
+                            let block = self.get(p);
+                            let mut front = p;
+                            front[axis] += sign;
+                            if block != AIR && !self.solid(front) {
+                                mask[(j * SIZE + i) as usize] =
+                                    Some((block, self.face_ao(front, u, v)));
+                            }
+                        }
+                    }
+                    self.merge(&mut mesh, &mut mask, axis, sign, slice);
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Occlusion at the corners of a face whose front is the empty cell
+    /// `front`, in quad order: (-u, -v), (+u, -v), (+u, +v), (-u, +v).
+    fn face_ao(&self, front: Coord, u: usize, v: usize) -> [usize; 4] {
+        [(-1, -1), (1, -1), (1, 1), (-1, 1)].map(|(du, dv)| {
+            let offset = |a: i32, b: i32| {
+                let mut q = front;
+                q[u] += a;
+                q[v] += b;
+                self.solid(q)
+            };
+            let (side1, side2, corner) = (offset(du, 0), offset(0, dv), offset(du, dv));
+            if side1 && side2 {
+                0
+            } else {
+                3 - side1 as usize - side2 as usize - corner as usize
+            }
+        })
+    }
+
+    /// Turns a slice's face mask into as few quads as it greedily can:
+    /// each run along `u` is extended along `v` while the rows match.
+    fn merge(
+        &self,
+        mesh: &mut Mesh,
+        mask: &mut [Option<(Block, [usize; 4])>],
+        axis: usize,
+        sign: i32,
+        slice: i32,
+    ) {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        for j in 0..SIZE {
+            let mut i = 0;
+            while i < SIZE {
+                let Some(face) = mask[(j * SIZE + i) as usize] else {
+                    i += 1;
+                    continue;
+                };
+                let mut width = 1;
+                while i + width < SIZE && mask[(j * SIZE + i + width) as usize] == Some(face) {
+                    width += 1;
+                }
+                let mut height = 1;
+                'grow: while j + height < SIZE {
+                    for k in 0..width {
+                        if mask[((j + height) * SIZE + i + k) as usize] != Some(face) {
+                            break 'grow;
+                        }
+                    }
+                    height += 1;
+                }
+                for dj in 0..height {
+                    for k in 0..width {
+                        mask[((j + dj) * SIZE + i + k) as usize] = None;
+                    }
+                }
+                self.quad(mesh, face, axis, u, v, sign, [slice, i, j], [width, height]);
+                i += width;
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn quad(
+        &self,
+        mesh: &mut Mesh,
+        (block, ao): (Block, [usize; 4]),
+        axis: usize,
+        u: usize,
+        v: usize,
+        sign: i32,
+        [slice, i, j]: [i32; 3],
+        [width, height]: [i32; 2],
+    ) {
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+        normal[axis] = sign as f32;
+        let (r, g, b) = COLORS[block as usize];
+        let color = linear_rgba(vec4(r, g, b, 1.0));
+        let base = mesh.vertices.len() as u16;
+        for (corner, (du, dv)) in [(0, 0), (width, 0), (width, height), (0, height)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut pos = Vector3::new(0.0, 0.0, 0.0);
+            pos[axis] = (slice + if sign > 0 { 1 } else { 0 }) as f32;
+            pos[u] = (i + du) as f32;
+            pos[v] = (j + dv) as f32;
+            let shade = AO_LEVELS[ao[corner]];
+            mesh.vertices.push(Vertex {
+                pos,
+                normal,
+                color: color.mul_element_wise(Vector4::new(shade, shade, shade, 1.0)),
+                uv2: vec2(0.0, 0.0),
+            });
+        }
+        // Split along the diagonal through the darker corners, so the
+        // occlusion fades evenly instead of showing the triangles.
+        let corners = if ao[0] + ao[2] <= ao[1] + ao[3] {
+            [0, 1, 2, 0, 2, 3]
+        } else {
+            [1, 2, 3, 1, 3, 0]
+        };
+        // Faces toward -axis wind the other way round.
+        let corners = if sign > 0 {
+            corners
+        } else {
+            [
+                corners[0], corners[2], corners[1], corners[3], corners[5], corners[4],
+            ]
+        };
+        mesh.indices.extend(corners.map(|c| base + c));
+    }
+}
+
+fn index([x, y, z]: Coord) -> usize {
+    (x + SIZE * (y + SIZE * z)) as usize
+}
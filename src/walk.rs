@@ -0,0 +1,368 @@
+use cgmath::{vec2, vec3, Array, EuclideanSpace, InnerSpace, Point3, Vector3, Zero};
+
+use crate::camera::Camera;
+use crate::chunks::ChunkManager;
+use crate::mesh::MeshLibrary;
+use crate::scene::Instance;
+
+/// Radius of the capsule.
+const RADIUS: f32 = 0.3;
+/// Height of the capsule, from the feet to the top of the head.
+const HEIGHT: f32 = 1.8;
+/// Height of the camera above the feet.
+pub const EYE_HEIGHT: f32 = 1.6;
+/// Spheres the capsule is tested as, spread evenly from bottom to top.
+const SPHERES: usize = 4;
+/// Ledges up to this high are stepped onto rather than blocking.
+const STEP_HEIGHT: f32 = 0.4;
+/// Units per second.
+const WALK_SPEED: f32 = 4.0;
+const GRAVITY: f32 = 20.0;
+/// Steepest ground that can be stood on, as the cosine of its slope
+/// (about 50°). Anything steeper is slid down.
+const MAX_SLOPE: f32 = 0.64;
+/// Moves are cut into substeps at most this long, so that thin walls
+/// can't be passed through between frames.
+const MAX_SUBSTEP: f32 = RADIUS * 0.5;
+/// Penetrations resolved per substep.
+const ITERATIONS: usize = 4;
+/// Falling below this puts the walker back where walking started.
+const KILL_DEPTH: f32 = -100.0;
+
+type Triangle = [Vector3<f32>; 3];
+
+/// Walks the camera over the scene and the terrain as a capsule under
+/// gravity. Running into geometry keeps the part of the move along the
+/// contact plane, so walls and slopes are slid along rather than stopping
+/// the walker dead, and ledges up to `STEP_HEIGHT` are climbed.
+pub struct Walker {
+    pub enabled: bool,
+    /// Vertical speed, up being positive.
+    fall_speed: f32,
+    grounded: bool,
+    /// Feet position walking started from.
+    spawn: Vector3<f32>,
+}
+
+impl Default for Walker {
+    fn default() -> Walker {
+        Walker {
+            enabled: false,
+            fall_speed: 0.0,
+            grounded: false,
+            spawn: Vector3::zero(),
+        }
+    }
+}
+
+impl Walker {
+    /// Starts walking from where the camera is, or goes back to flying.
+    pub fn set(&mut self, enabled: bool, camera: &Camera) {
+        if enabled && !self.enabled {
+            self.reset(camera);
+        }
+        self.enabled = enabled;
+    }
+
+    /// Starts over from where the camera is, after it was moved there.
+    pub fn reset(&mut self, camera: &Camera) {
+        self.spawn = feet(camera);
+        self.fall_speed = 0.0;
+        self.grounded = false;
+    }
+
+    /// Moves the camera `direction` (horizontal, unit length or zero) for
+    /// `delta` seconds, colliding with `instances` and the terrain.
+    pub fn update(
+        &mut self,
+        camera: &mut Camera,
+        direction: Vector3<f32>,
+        delta: f32,
+        instances: &[Instance],
+        meshes: &MeshLibrary,
+        terrain: &ChunkManager,
+    ) {
+        let start = feet(camera);
+        let walk = direction * WALK_SPEED * delta;
+        let fall = (self.fall_speed - GRAVITY * delta) * delta;
+        let reach = walk.magnitude() + fall.abs() + STEP_HEIGHT * 2.0;
+        let world = Collider::gather(start, reach, instances, meshes, terrain);
+
+        let progress = |to: Vector3<f32>| (to - start).dot(walk);
+        let (mut position, _) = world.slide(start, walk);
+        // Blocked while walking on the ground: try climbing over.
+        if self.grounded && progress(position) < walk.magnitude2() * 0.9 {
+            let (raised, _) = world.slide(start, vec3(0.0, STEP_HEIGHT, 0.0));
+            let (across, _) = world.slide(raised, walk);
+            let (lowered, landed) = world.slide(across, vec3(0.0, start.y - raised.y, 0.0));
+            if landed.grounded && progress(lowered) > progress(position) + 1e-4 {
+                position = lowered;
+            }
+        }
+
+        self.fall_speed -= GRAVITY * delta;
+        let (mut position, contacts) = world.slide(position, vec3(0.0, fall, 0.0));
+        let mut grounded = contacts.grounded;
+        for normal in contacts.normals {
+            // Only the speed into the surface is lost, as with the moves.
+            self.fall_speed -= normal.y * (self.fall_speed * normal.y).min(0.0);
+        }
+        // Walking down slopes and stairs keeps to the ground rather than
+        // launching off every edge.
+        if self.grounded && !grounded && self.fall_speed <= 0.0 {
+            let (snapped, contacts) = world.slide(position, vec3(0.0, -STEP_HEIGHT, 0.0));
+            if contacts.grounded {
+                position = snapped;
+                grounded = true;
+            }
+        }
+        if grounded {
+            self.fall_speed = 0.0;
+        }
+        self.grounded = grounded;
+
+        if position.y < KILL_DEPTH {
+            position = self.spawn;
+            self.fall_speed = 0.0;
+        }
+        camera.position = Point3::from_vec(position + vec3(0.0, EYE_HEIGHT, 0.0));
+    }
+}
+
+fn feet(camera: &Camera) -> Vector3<f32> {
+    camera.position.to_vec() - vec3(0.0, EYE_HEIGHT, 0.0)
+}
+
+/// What a move ran into.
+struct Contacts {
+    /// Whether any of it was ground that can be stood on.
+    grounded: bool,
+    normals: Vec<Vector3<f32>>,
+}
+
+/// The deepest overlap of the capsule with the geometry.
+struct Penetration {
+    /// Out of the surface, towards the capsule.
+    normal: Vector3<f32>,
+    depth: f32,
+    /// For ground that can be stood on, the distance straight up that
+    /// clears it. Edges of walkable surfaces count, so that the capsule
+    /// can stand on the rim of a ledge it just stepped onto.
+    lift: Option<f32>,
+}
+
+/// The geometry around the walker, in world space.
+struct Collider<'a> {
+    triangles: Vec<Triangle>,
+    terrain: &'a ChunkManager,
+}
+
+impl<'a> Collider<'a> {
+    /// Collects the triangles of instances whose bounds come within
+    /// `reach` of the capsule at `feet`.
+    fn gather(
+        feet: Vector3<f32>,
+        reach: f32,
+        instances: &[Instance],
+        meshes: &MeshLibrary,
+        terrain: &'a ChunkManager,
+    ) -> Collider<'a> {
+        let margin = vec3(RADIUS, 0.0, RADIUS) + vec3(reach, reach, reach);
+        let (low, high) = (feet - margin, feet + vec3(0.0, HEIGHT, 0.0) + margin);
+        let mut triangles = vec![];
+        for instance in instances {
+            let mesh = meshes.get(instance.mesh);
+            let (min, max) = mesh.bounds;
+            let corners = (0..8).map(|i| {
+                let corner = vec3(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                );
+                (instance.world * corner.extend(1.0)).truncate()
+            });
+            let (lo, hi) = corners.fold(
+                (Vector3::from_value(f32::MAX), Vector3::from_value(f32::MIN)),
+                |(lo, hi), c| {
+                    (
+                        vec3(lo.x.min(c.x), lo.y.min(c.y), lo.z.min(c.z)),
+                        vec3(hi.x.max(c.x), hi.y.max(c.y), hi.z.max(c.z)),
+                    )
+                },
+            );
+            let apart = hi.x < low.x
+                || lo.x > high.x
+                || hi.y < low.y
+                || lo.y > high.y
+                || hi.z < low.z
+                || lo.z > high.z;
+            if apart {
+                continue;
+            }
+            triangles.extend(
+                mesh.mesh
+                    .triangles()
+                    .map(|t| t.map(|v| (instance.world * v.pos.extend(1.0)).truncate())),
+            );
+        }
+        Collider { triangles, terrain }
+    }
+
+    /// Moves the capsule from `feet` by `motion` in substeps, pushing it
+    /// out of whatever it overlaps after each one and dropping the part of
+    /// the remaining motion that heads into the surface.
+    fn slide(&self, mut feet: Vector3<f32>, motion: Vector3<f32>) -> (Vector3<f32>, Contacts) {
+        let mut contacts = Contacts {
+            grounded: false,
+            normals: vec![],
+        };
+        // Falling onto ground is resolved straight up, so that standing
+        // on a slope doesn't creep downhill.
+        let vertical = motion.x == 0.0 && motion.z == 0.0;
+        let substeps = (motion.magnitude() / MAX_SUBSTEP).ceil().max(1.0) as usize;
+        let mut step = motion / substeps as f32;
+        for _ in 0..substeps {
+            feet += step;
+            for _ in 0..ITERATIONS {
+                let Some(Penetration {
+                    normal,
+                    depth,
+                    lift,
+                }) = self.penetration(feet)
+                else {
+                    break;
+                };
+                match (vertical, lift) {
+                    (true, Some(lift)) => {
+                        feet.y += lift;
+                        step.y = step.y.max(0.0);
+                    }
+                    _ => {
+                        feet += normal * depth;
+                        step -= normal * step.dot(normal).min(0.0);
+                    }
+                }
+                contacts.grounded |= lift.is_some();
+                contacts.normals.push(normal);
+            }
+        }
+        (feet, contacts)
+    }
+
+    /// The deepest overlap of the capsule at `feet`, if any. Of equally
+    /// deep ones, such as the two faces meeting at the rim of a ledge,
+    /// ground wins.
+    fn penetration(&self, feet: Vector3<f32>) -> Option<Penetration> {
+        let mut deepest: Option<Penetration> = None;
+        let mut consider = |found: Penetration| {
+            let replace = match &deepest {
+                None => true,
+                Some(current) => {
+                    found.depth > current.depth + 1e-5
+                        || (found.depth > current.depth - 1e-5
+                            && found.lift.is_some()
+                            && current.lift.is_none())
+                }
+            };
+            if replace {
+                deepest = Some(found);
+            }
+        };
+        let spacing = (HEIGHT - RADIUS * 2.0) / (SPHERES - 1) as f32;
+        for i in 0..SPHERES {
+            let center = feet + vec3(0.0, RADIUS + spacing * i as f32, 0.0);
+            for triangle in &self.triangles {
+                let point = closest_point(center, triangle);
+                let offset = center - point;
+                let distance = offset.magnitude();
+                if distance >= RADIUS {
+                    continue;
+                }
+                let mut face = (triangle[1] - triangle[0])
+                    .cross(triangle[2] - triangle[0])
+                    .normalize();
+                // Triangles are solid from both sides.
+                if face.dot(offset) < 0.0 {
+                    face = -face;
+                }
+                let normal = match distance > 1e-6 {
+                    true => offset / distance,
+                    false => face,
+                };
+                let lift = (face.y >= MAX_SLOPE && normal.y > 0.0).then(|| {
+                    if (normal - face).magnitude2() < 1e-4 {
+                        // On the face itself.
+                        (RADIUS - distance) / normal.y
+                    } else {
+                        // On an edge or corner: rise until the point is
+                        // on the sphere.
+                        let h = vec2(offset.x, offset.z).magnitude();
+                        (RADIUS * RADIUS - h * h).sqrt() - offset.y
+                    }
+                });
+                consider(Penetration {
+                    normal,
+                    depth: RADIUS - distance,
+                    lift,
+                });
+            }
+        }
+
+        // The terrain is a heightfield: measure against its tangent plane
+        // under the bottom sphere.
+        let center = feet + vec3(0.0, RADIUS, 0.0);
+        if let Some(height) = self.terrain.ground(center.x, center.z) {
+            let e = 0.25;
+            let ground = |x: f32, z: f32| self.terrain.ground(x, z).unwrap_or(height);
+            let normal = vec3(
+                ground(center.x - e, center.z) - ground(center.x + e, center.z),
+                2.0 * e,
+                ground(center.x, center.z - e) - ground(center.x, center.z + e),
+            )
+            .normalize();
+            let distance = (center.y - height) * normal.y;
+            if distance < RADIUS {
+                consider(Penetration {
+                    normal,
+                    depth: RADIUS - distance,
+                    lift: (normal.y >= MAX_SLOPE).then(|| (RADIUS - distance) / normal.y),
+                });
+            }
+        }
+        deepest
+    }
+}
+
+/// The point of a triangle nearest to `p`, after Ericson's "Real-Time
+/// Collision Detection".
+fn closest_point(p: Vector3<f32>, [a, b, c]: &Triangle) -> Vector3<f32> {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
@@ -0,0 +1,158 @@
+use cgmath::{vec3, vec4, Matrix4, Vector4};
+use miniquad::*;
+
+use crate::camera::Camera;
+use crate::gfx::compile_shader;
+use crate::post::RenderTarget;
+
+/// Offscreen targets are rendered at a fraction of the screen resolution.
+const TARGET_SCALE: f32 = 0.5;
+const HALF_EXTENT: f32 = 10.0;
+
+/// A horizontal water plane. Before the main pass the scene is rendered
+/// twice more: mirrored about the plane into `reflection`, and as-is into
+/// `refraction`, each clipped to its side of the surface. The water shader
+/// mixes the two with a Fresnel term, distorted by scrolling noise normals.
+pub struct Water {
+    pipeline: Pipeline,
+    bindings: Bindings,
+    pub reflection: RenderTarget,
+    pub refraction: RenderTarget,
+    pub height: f32,
+    pub enabled: bool,
+}
+
+impl Water {
+    pub fn new(ctx: &mut dyn RenderingBackend, width: f32, height: f32) -> Water {
+        #[rustfmt::skip]
+        let vertices: [[f32; 2]; 4] = [
+            [-HALF_EXTENT, -HALF_EXTENT],
+            [-HALF_EXTENT,  HALF_EXTENT],
+            [ HALF_EXTENT,  HALF_EXTENT],
+            [ HALF_EXTENT, -HALF_EXTENT],
+        ];
+        let vertex_buffer = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&vertices),
+        );
+
+        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
+        let index_buffer = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Immutable,
+            BufferSource::slice(&indices),
+        );
+
+        let (w, h) = target_size(width, height);
+        let reflection = RenderTarget::new(ctx, w, h);
+        let refraction = RenderTarget::new(ctx, w, h);
+
+        let bindings = Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![reflection.color, refraction.color],
+        };
+
+        let shader = compile_shader(ctx, shader::VERTEX, shader::FRAGMENT, shader::meta());
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("in_pos", VertexFormat::Float2)],
+            shader,
+            PipelineParams {
+                depth_write: true,
+                depth_test: Comparison::LessOrEqual,
+                ..Default::default()
+            },
+        );
+
+        Water {
+            pipeline,
+            bindings,
+            reflection,
+            refraction,
+            height: -0.2,
+            enabled: false,
+        }
+    }
+
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: f32, height: f32) {
+        let (w, h) = target_size(width, height);
+        self.reflection.resize(ctx, w, h);
+        self.refraction.resize(ctx, w, h);
+    }
+
+    /// Reflects world space about the water plane.
+    pub fn mirror(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(vec3(0.0, self.height, 0.0))
+            * Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0)
+            * Matrix4::from_translation(vec3(0.0, -self.height, 0.0))
+    }
+
+    /// Keeps what is above the surface.
+    pub fn above(&self) -> Vector4<f32> {
+        vec4(0.0, 1.0, 0.0, -self.height)
+    }
+
+    /// Keeps what is below the surface.
+    pub fn below(&self) -> Vector4<f32> {
+        vec4(0.0, -1.0, 0.0, self.height)
+    }
+
+    /// Draws the surface into the currently active pass.
+    pub fn draw(
+        &self,
+        ctx: &mut dyn RenderingBackend,
+        view_proj: Matrix4<f32>,
+        camera: &Camera,
+        time: f32,
+    ) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&self.bindings);
+        ctx.apply_uniforms(UniformsSource::table(&shader::Uniforms {
+            view_proj,
+            camera_pos: camera.position.into(),
+            height: self.height,
+            log_depth_coef: camera.log_depth_coef(),
+            time,
+        }));
+        ctx.draw(0, 6, 1);
+    }
+}
+
+fn target_size(width: f32, height: f32) -> (u32, u32) {
+    (
+        ((width * TARGET_SCALE) as u32).max(1),
+        ((height * TARGET_SCALE) as u32).max(1),
+    )
+}
+
+mod shader {
+    use cgmath::Matrix4;
+    use miniquad::*;
+
+    pub const VERTEX: &str = include_str!("shaders/water.vert");
+
+    pub const FRAGMENT: &str = include_str!("shaders/water.frag");
+
+    pub fn meta() -> ShaderMeta {
+        ShaderMeta {
+            images: vec!["reflection".to_owned(), "refraction".to_owned()],
+            uniforms: UniformBlockLayout { uniforms: vec![
+                UniformDesc::new("view_proj", UniformType::Mat4),
+                UniformDesc::new("camera_pos", UniformType::Float3),
+                UniformDesc::new("height", UniformType::Float1),
+                UniformDesc::new("log_depth_coef", UniformType::Float1),
+                UniformDesc::new("time", UniformType::Float1),
+            ] },
+        }
+    }
+    #[repr(C)]
+    pub struct Uniforms {
+        pub view_proj: Matrix4<f32>,
+        pub camera_pos: [f32; 3],
+        pub height: f32,
+        pub log_depth_coef: f32,
+        pub time: f32,
+    }
+}